@@ -3,9 +3,17 @@
 /// - src/chainparams.cpp in Bitcoin Core
 /// - https://github.com/bitcoin/bitcoin/blob/master/src/chainparams.cpp
 
-use bitcoin::{BlockHash, Network};
+use bitcoin::consensus::encode::serialize;
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::p2p::Magic;
+use bitcoin::{BlockHash, Network, Script, ScriptBuf};
 use std::str::FromStr;
 
+use crate::seeds;
+
+/// Block script of the default signet: 1-of-2 multisig
+pub const DEFAULT_SIGNET_CHALLENGE: &str = "512103ad5e0edad18cb1f0fc0d28a3d4f1f3e445640337489abb10404f2d1e086be430210359ef5021964fe22d6f8e05b2463c9540ce96883fe3b278760f048f5189f2e6c452ae";
+
 /// Checkpoint: (height, block_hash)
 pub type Checkpoint = (u32, &'static str);
 
@@ -23,6 +31,31 @@ pub struct ChainParams {
     /// Minimum cumulative chain work required
     /// Prevents very low-work chains from wasting our time
     pub minimum_chain_work: Option<[u8; 32]>,
+
+    /// Message start bytes; a custom signet derives its own
+    pub magic: Magic,
+
+    /// Seed nodes as host[:port] (Bitcoin Core: vSeeds, -signetseednode)
+    pub seeds: Vec<String>,
+
+    /// Script every signet block must satisfy (BIP 325)
+    pub signet_challenge: Option<ScriptBuf>,
+}
+
+/// Default signet challenge script
+pub fn default_signet_challenge() -> ScriptBuf {
+    ScriptBuf::from_hex(DEFAULT_SIGNET_CHALLENGE).expect("valid hex")
+}
+
+/// Message start of the signet with `challenge`: the first four bytes of
+/// the double SHA256 of the serialized challenge (BIP 325)
+pub fn signet_magic(challenge: &Script) -> Magic {
+    let hash = sha256d::Hash::hash(&serialize(challenge));
+    Magic::from_bytes(hash[..4].try_into().expect("4 bytes"))
+}
+
+fn dns_seeds(net: Network) -> Vec<String> {
+    seeds::dns_seeds(net).iter().map(|s| s.to_string()).collect()
 }
 
 impl ChainParams {
@@ -33,8 +66,25 @@ impl ChainParams {
             Network::Testnet => Self::testnet(),
             Network::Signet => Self::signet(),
             Network::Regtest => Self::regtest(),
-            _ => Self::regtest(), // fallback
+            // fallback: no checkpoints, but the network's own magic and seeds
+            _ => Self { magic: net.magic(), seeds: dns_seeds(net), ..Self::regtest() },
+        }
+    }
+
+    /// Signet with `-signetchallenge` and `-signetseednode` (Bitcoin Core:
+    /// SigNetParams). A custom challenge gets its own magic and no DNS
+    /// seeds; seed nodes, when given, replace the seeds either way.
+    pub fn for_signet(challenge: Option<ScriptBuf>, seed_nodes: Vec<String>) -> Self {
+        let mut params = Self::signet();
+        if let Some(challenge) = challenge {
+            params.magic = signet_magic(&challenge);
+            params.seeds.clear();
+            params.signet_challenge = Some(challenge);
+        }
+        if !seed_nodes.is_empty() {
+            params.seeds = seed_nodes;
         }
+        params
     }
 
     /// Bitcoin mainnet parameters
@@ -82,6 +132,9 @@ impl ChainParams {
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x9a, 0x3c, 0x1e,
                 0x6f, 0x7e, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e, 0x6f,
             ]),
+            magic: Magic::BITCOIN,
+            seeds: dns_seeds(Network::Bitcoin),
+            signet_challenge: None,
         }
     }
 
@@ -105,6 +158,9 @@ impl ChainParams {
                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
                 0x00, 0x00, 0x00, 0x01, 0x74, 0x76, 0xa7, 0x21,
             ]),
+            magic: Magic::TESTNET3,
+            seeds: dns_seeds(Network::Testnet),
+            signet_challenge: None,
         }
     }
 
@@ -119,6 +175,9 @@ impl ChainParams {
             assume_valid: None,
             // Signet has very low difficulty, no minimum work requirement
             minimum_chain_work: None,
+            magic: Magic::SIGNET,
+            seeds: dns_seeds(Network::Signet),
+            signet_challenge: Some(default_signet_challenge()),
         }
     }
 
@@ -128,6 +187,9 @@ impl ChainParams {
             checkpoints: &[],
             assume_valid: None,
             minimum_chain_work: None,
+            magic: Magic::REGTEST,
+            seeds: Vec::new(),
+            signet_challenge: None,
         }
     }
 
@@ -194,6 +256,22 @@ mod tests {
         assert!(cp_50k.is_some());
     }
 
+    #[test]
+    fn test_custom_signet() {
+        let default = ChainParams::for_network(Network::Signet);
+        assert_eq!(signet_magic(default.signet_challenge.as_ref().unwrap()), Network::Signet.magic());
+
+        // OP_TRUE signet with one seed node
+        let params = ChainParams::for_signet(Some(ScriptBuf::from_bytes(vec![0x51])), vec!["10.0.0.1:38333".into()]);
+        assert_ne!(params.magic, Magic::SIGNET);
+        assert_eq!(params.seeds, vec!["10.0.0.1:38333".to_string()]);
+        assert!(ChainParams::for_signet(Some(ScriptBuf::from_bytes(vec![0x51])), vec![]).seeds.is_empty());
+
+        let params = ChainParams::for_signet(None, vec![]);
+        assert_eq!(params.magic, Magic::SIGNET);
+        assert_eq!(params.seeds, default.seeds);
+    }
+
     #[test]
     fn test_regtest_no_checkpoints() {
         let params = ChainParams::for_network(Network::Regtest);
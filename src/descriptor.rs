@@ -0,0 +1,375 @@
+//! Output descriptors that stand for a single script (BIP 380-386), enough
+//! to name a coinbase destination or an address to look up: `pk`, `pkh`,
+//! `wpkh`, `sh`, `wsh`, `multi`, `sortedmulti`, key-path `tr`, `combo`,
+//! `addr` and `raw`.
+//!
+//! Keys may be hex public keys, WIF private keys, or extended keys with a
+//! fixed derivation path. Ranged and multipath descriptors are refused, as
+//! generatetodescriptor does in Bitcoin Core.
+
+use bitcoin::bip32::{ChildNumber, Xpriv, Xpub};
+use bitcoin::opcodes::all::OP_CHECKMULTISIG;
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{All, Secp256k1, XOnlyPublicKey};
+use bitcoin::{Address, Network, NetworkKind, PrivateKey, PublicKey, ScriptBuf};
+use std::str::FromStr;
+
+/// Characters a descriptor may contain, grouped by the checksum's symbol classes
+const INPUT_CHARSET: &str =
+    "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Largest redeem script a P2SH output can spend (Bitcoin Core: MAX_SCRIPT_ELEMENT_SIZE)
+const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+/// Most keys in a `multi` outside tapscript (Bitcoin Core: MAX_PUBKEYS_PER_MULTISIG)
+const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+
+/// Where an expression sits, which decides what it may contain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Context {
+    Top,
+    P2sh,
+    P2wsh,
+}
+
+fn poly_mod(mut c: u64, val: u64) -> u64 {
+    let c0 = c >> 35;
+    c = ((c & 0x7_ffff_ffff) << 5) ^ val;
+    for (bit, gen) in [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd].into_iter().enumerate() {
+        if c0 >> bit & 1 != 0 {
+            c ^= gen;
+        }
+    }
+    c
+}
+
+/// The 8-character checksum of `desc` (Bitcoin Core: DescriptorChecksum),
+/// or None if it has characters outside the descriptor alphabet
+fn descriptor_checksum(desc: &str) -> Option<String> {
+    let (mut c, mut cls, mut cls_count) = (1u64, 0u64, 0);
+    for ch in desc.chars() {
+        let pos = INPUT_CHARSET.find(ch)? as u64;
+        c = poly_mod(c, pos & 31);
+        cls = cls * 3 + (pos >> 5);
+        cls_count += 1;
+        if cls_count == 3 {
+            c = poly_mod(c, cls);
+            cls = 0;
+            cls_count = 0;
+        }
+    }
+    if cls_count > 0 {
+        c = poly_mod(c, cls);
+    }
+    for _ in 0..8 {
+        c = poly_mod(c, 0);
+    }
+    c ^= 1;
+    Some((0..8).map(|j| CHECKSUM_CHARSET[(c >> (5 * (7 - j)) & 31) as usize] as char).collect())
+}
+
+/// Strip and verify an optional `#checksum` suffix
+fn check_checksum(desc: &str) -> Result<&str, String> {
+    let (payload, checksum) = match desc.split_once('#') {
+        Some((payload, checksum)) => (payload, Some(checksum)),
+        None => (desc, None),
+    };
+    let computed = descriptor_checksum(payload).ok_or("Invalid characters in payload")?;
+    match checksum {
+        None => {}
+        Some(c) if c.contains('#') => return Err("Multiple '#' symbols".into()),
+        Some(c) if c.len() != 8 => return Err(format!("Expected 8 character checksum, not {} characters", c.len())),
+        Some(c) if c != computed => {
+            return Err(format!("Provided checksum '{}' does not match computed checksum '{}'", c, computed))
+        }
+        Some(_) => {}
+    }
+    Ok(payload)
+}
+
+/// Split `name(args)` into its name and argument string
+fn split_call(expr: &str) -> Option<(&str, &str)> {
+    let open = expr.find('(')?;
+    let args = expr[open + 1..].strip_suffix(')')?;
+    Some((&expr[..open], args))
+}
+
+/// Split on commas that are not nested in brackets
+fn split_args(args: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let (mut depth, mut start) = (0i32, 0);
+    for (i, ch) in args.char_indices() {
+        match ch {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                out.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    out.push(&args[start..]);
+    out
+}
+
+fn derive_path(path: &[&str], hardened_ok: bool, key: &str) -> Result<Vec<ChildNumber>, String> {
+    path.iter()
+        .map(|step| {
+            if step.starts_with('*') {
+                return Err("Ranged descriptor not accepted. Maybe pass through deriveaddresses first?".into());
+            }
+            if step.starts_with('<') {
+                return Err("Multipath descriptor not accepted".into());
+            }
+            let (index, hardened) = match step.strip_suffix('\'').or_else(|| step.strip_suffix('h')) {
+                Some(index) => (index, true),
+                None => (*step, false),
+            };
+            if hardened && !hardened_ok {
+                return Err("Cannot derive script without private keys".into());
+            }
+            let index: u32 = index.parse().map_err(|_| format!("Key path value '{}' is not a valid uint32", step))?;
+            let child = if hardened { ChildNumber::from_hardened_idx(index) } else { ChildNumber::from_normal_idx(index) };
+            child.map_err(|_| format!("Key path value {} is out of range in '{}'", index, key))
+        })
+        .collect()
+}
+
+fn parse_key(expr: &str, ctx: Context, network: Network, secp: &Secp256k1<All>) -> Result<PublicKey, String> {
+    // Key origin information only matters for signing
+    let key = match expr.strip_prefix('[') {
+        Some(rest) => rest
+            .split_once(']')
+            .map(|(_, key)| key)
+            .ok_or_else(|| format!("Key origin start '[' character expected but not found in '{}'", expr))?,
+        None => expr,
+    };
+    let invalid = || format!("key '{}' is not valid", key);
+    let mut parts = key.split('/');
+    let base = parts.next().unwrap_or_default();
+    let path: Vec<&str> = parts.collect();
+    let network = NetworkKind::from(network);
+
+    let public = if let Ok(xpub) = Xpub::from_str(base) {
+        if xpub.network != network {
+            return Err(invalid());
+        }
+        let path = derive_path(&path, false, key)?;
+        PublicKey::new(xpub.derive_pub(secp, &path).map_err(|_| invalid())?.public_key)
+    } else if let Ok(xpriv) = Xpriv::from_str(base) {
+        if xpriv.network != network {
+            return Err(invalid());
+        }
+        let path = derive_path(&path, true, key)?;
+        let derived = xpriv.derive_priv(secp, &path).map_err(|_| invalid())?;
+        PublicKey::new(Xpub::from_priv(secp, &derived).public_key)
+    } else if !path.is_empty() {
+        return Err(invalid());
+    } else if let Ok(public) = PublicKey::from_str(base) {
+        public
+    } else {
+        let private = PrivateKey::from_wif(base).map_err(|_| invalid())?;
+        if private.network != network {
+            return Err(invalid());
+        }
+        private.public_key(secp)
+    };
+
+    if !public.compressed && ctx == Context::P2wsh {
+        return Err("Uncompressed keys are not allowed".into());
+    }
+    Ok(public)
+}
+
+fn parse_xonly_key(expr: &str, network: Network, secp: &Secp256k1<All>) -> Result<XOnlyPublicKey, String> {
+    let key = expr.rsplit(']').next().unwrap_or(expr);
+    if key.len() == 64 {
+        return XOnlyPublicKey::from_str(key).map_err(|_| format!("key '{}' is not valid", key));
+    }
+    let public = parse_key(expr, Context::Top, network, secp)?;
+    if !public.compressed {
+        return Err("Uncompressed keys are not allowed".into());
+    }
+    Ok(public.inner.x_only_public_key().0)
+}
+
+fn multisig_script(args: &[&str], sorted: bool, ctx: Context, network: Network, secp: &Secp256k1<All>) -> Result<ScriptBuf, String> {
+    let (threshold, keys) = args.split_first().ok_or("Multi: expected threshold")?;
+    let threshold: usize = threshold.parse().map_err(|_| format!("Multi threshold '{}' is not valid", threshold))?;
+    if keys.is_empty() || keys.len() > MAX_PUBKEYS_PER_MULTISIG {
+        return Err(format!(
+            "Cannot have {} pubkeys in multisig; must have between 1 and {} keys, inclusive",
+            keys.len(),
+            MAX_PUBKEYS_PER_MULTISIG
+        ));
+    }
+    if threshold < 1 {
+        return Err(format!("Multisig threshold cannot be {}, must be at least 1", threshold));
+    }
+    if threshold > keys.len() {
+        return Err(format!(
+            "Multisig threshold cannot be larger than the number of keys; threshold is {} but only {} keys specified",
+            threshold,
+            keys.len()
+        ));
+    }
+    let mut keys = keys.iter().map(|k| parse_key(k, ctx, network, secp)).collect::<Result<Vec<_>, _>>()?;
+    if sorted {
+        keys.sort_by_key(|k| k.to_bytes());
+    }
+    let mut builder = Builder::new().push_int(threshold as i64);
+    for key in &keys {
+        builder = builder.push_key(key);
+    }
+    Ok(builder.push_int(keys.len() as i64).push_opcode(OP_CHECKMULTISIG).into_script())
+}
+
+fn parse_script(expr: &str, ctx: Context, network: Network, secp: &Secp256k1<All>) -> Result<ScriptBuf, String> {
+    let (name, args) = split_call(expr).ok_or_else(|| format!("'{}' is not a valid descriptor function", expr))?;
+    let top_only = |what: &str| {
+        if ctx == Context::Top {
+            Ok(())
+        } else {
+            Err(format!("Can only have {} at top level", what))
+        }
+    };
+
+    match name {
+        "pk" => Ok(ScriptBuf::new_p2pk(&parse_key(args, ctx, network, secp)?)),
+        "pkh" => Ok(ScriptBuf::new_p2pkh(&parse_key(args, ctx, network, secp)?.pubkey_hash())),
+        "wpkh" => {
+            if ctx == Context::P2wsh {
+                return Err("Can only have wpkh() at top level or inside sh()".into());
+            }
+            let key = parse_key(args, Context::P2wsh, network, secp)?;
+            Ok(ScriptBuf::new_p2wpkh(&key.wpubkey_hash().map_err(|_| "Uncompressed keys are not allowed")?))
+        }
+        "multi" | "sortedmulti" => {
+            let script = multisig_script(&split_args(args), name == "sortedmulti", ctx, network, secp)?;
+            if ctx == Context::P2sh && script.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                return Err(format!(
+                    "P2SH script is too large, {} bytes is larger than {} bytes",
+                    script.len(),
+                    MAX_SCRIPT_ELEMENT_SIZE
+                ));
+            }
+            Ok(script)
+        }
+        "sh" => {
+            top_only("sh()")?;
+            let inner = parse_script(args, Context::P2sh, network, secp)?;
+            Ok(ScriptBuf::new_p2sh(&inner.script_hash()))
+        }
+        "wsh" => {
+            if ctx == Context::P2wsh {
+                return Err("Can only have wsh() at top level or inside sh()".into());
+            }
+            let inner = parse_script(args, Context::P2wsh, network, secp)?;
+            Ok(ScriptBuf::new_p2wsh(&inner.wscript_hash()))
+        }
+        "tr" => {
+            top_only("tr()")?;
+            let args = split_args(args);
+            if args.len() > 1 {
+                return Err("tr() script paths are not supported".into());
+            }
+            let internal_key = parse_xonly_key(args[0], network, secp)?;
+            Ok(ScriptBuf::new_p2tr(secp, internal_key, None))
+        }
+        "combo" => {
+            top_only("combo()")?;
+            // Bitcoin Core takes P2WPKH for compressed keys and P2PKH otherwise
+            let key = parse_key(args, ctx, network, secp)?;
+            Ok(match key.wpubkey_hash() {
+                Ok(hash) => ScriptBuf::new_p2wpkh(&hash),
+                Err(_) => ScriptBuf::new_p2pkh(&key.pubkey_hash()),
+            })
+        }
+        "addr" => {
+            top_only("addr()")?;
+            let address = Address::from_str(args)
+                .ok()
+                .and_then(|a| a.require_network(network).ok())
+                .ok_or("Address is not valid")?;
+            Ok(address.script_pubkey())
+        }
+        "raw" => {
+            top_only("raw()")?;
+            let bytes = hex::decode(args).map_err(|_| format!("Raw script is not hex: '{}'", args))?;
+            Ok(ScriptBuf::from_bytes(bytes))
+        }
+        _ => Err(format!("'{}' is not a valid descriptor function", name)),
+    }
+}
+
+/// The one output script `descriptor` describes on `network` (Bitcoin
+/// Core: DescriptorScript in rpc/mining.cpp). The checksum is optional.
+pub fn descriptor_script(descriptor: &str, network: Network) -> Result<ScriptBuf, String> {
+    let payload = check_checksum(descriptor)?;
+    let secp = Secp256k1::new();
+    parse_script(payload, Context::Top, network, &secp)
+}
+
+/// Coinbase destination given as a descriptor or an address, the way
+/// generateblock reads its `output` argument
+pub fn output_script(output: &str, network: Network) -> Option<ScriptBuf> {
+    descriptor_script(output, network).ok().or_else(|| {
+        let address = Address::from_str(output).ok()?.require_network(network).ok()?;
+        Some(address.script_pubkey())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+
+    #[test]
+    fn test_checksum() {
+        // BIP 380 test vector
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert!(descriptor_script("raw(deadbeef)#89f8spxm", Network::Regtest).is_ok());
+        assert!(descriptor_script("raw(deadbeef)#89f8spxn", Network::Regtest)
+            .unwrap_err()
+            .starts_with("Provided checksum"));
+        assert!(descriptor_script("raw(deadbeef)#89f8", Network::Regtest).is_err());
+    }
+
+    #[test]
+    fn test_scripts() {
+        let key = PublicKey::from_str(KEY).unwrap();
+        let wpkh = ScriptBuf::new_p2wpkh(&key.wpubkey_hash().unwrap());
+        let script = |d: &str| descriptor_script(d, Network::Regtest);
+
+        assert_eq!(script(&format!("pk({})", KEY)).unwrap(), ScriptBuf::new_p2pk(&key));
+        assert_eq!(script(&format!("pkh({})", KEY)).unwrap(), ScriptBuf::new_p2pkh(&key.pubkey_hash()));
+        assert_eq!(script(&format!("wpkh([d34db33f/84h/1h/0h]{})", KEY)).unwrap(), wpkh);
+        assert_eq!(script(&format!("sh(wpkh({}))", KEY)).unwrap(), ScriptBuf::new_p2sh(&wpkh.script_hash()));
+        assert_eq!(script(&format!("combo({})", KEY)).unwrap(), wpkh);
+
+        let multi = script(&format!("wsh(multi(1,{}))", KEY)).unwrap();
+        assert!(multi.is_p2wsh());
+        assert_eq!(script(&format!("tr({})", KEY)).unwrap().len(), 34);
+        let address = Address::from_script(&wpkh, Network::Regtest).unwrap();
+        assert_eq!(script(&format!("addr({})", address)).unwrap(), wpkh);
+    }
+
+    #[test]
+    fn test_rejects() {
+        let err = |d: &str| descriptor_script(d, Network::Regtest).unwrap_err();
+        let secp = Secp256k1::new();
+        let tpub = Xpub::from_priv(&secp, &Xpriv::new_master(NetworkKind::Test, &[1; 32]).unwrap()).to_string();
+
+        assert!(descriptor_script(&format!("pkh({}/0/1)", tpub), Network::Regtest).is_ok());
+        assert!(err(&format!("pkh({}/0/*)", tpub)).starts_with("Ranged descriptor"));
+        assert_eq!(err(&format!("pkh({}/<0;1>)", tpub)), "Multipath descriptor not accepted");
+        assert_eq!(err(&format!("pkh({}/0h)", tpub)), "Cannot derive script without private keys");
+        assert_eq!(err(&format!("wsh(sh(pk({})))", KEY)), "Can only have sh() at top level");
+        assert!(err(&format!("multi(2,{})", KEY)).starts_with("Multisig threshold cannot be larger"));
+        assert!(err("foo(00)").contains("not a valid descriptor function"));
+        // A testnet key on mainnet
+        assert!(descriptor_script(&format!("pkh({})", tpub), Network::Bitcoin).is_err());
+    }
+}
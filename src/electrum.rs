@@ -0,0 +1,717 @@
+//! Electrum protocol server (protocol 1.4, as served by ElectrumX and electrs).
+//!
+//! Wallets connect over TCP and speak newline-delimited JSON-RPC 2.0. Script
+//! queries (`blockchain.scripthash.*`) are answered from the address index,
+//! `blockchain.transaction.get` from the mempool and the transaction index,
+//! and headers and merkle proofs from the kernel's blocks, so `--electrum`
+//! turns on `--addrindex` and `--txindex`.
+//!
+//! A script's status is the SHA256 of its history as `txid:height:` strings
+//! (height 0 for unconfirmed transactions, -1 when they have unconfirmed
+//! parents). Subscribed clients get `blockchain.headers.subscribe` on every
+//! tip change and `blockchain.scripthash.subscribe` whenever a new block or
+//! a mempool change alters a status.
+
+use anyhow::{Context, Result};
+use bitcoin::consensus::{deserialize, encode::serialize_hex};
+use bitcoin::hashes::{sha256, sha256d, Hash, HashEngine};
+use bitcoin::{BlockHash, Transaction, Txid};
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio_util::codec::{FramedRead, LinesCodec};
+
+use crate::index::addrindex::{AddrIndex, ScriptActivity, ScriptHash};
+use crate::index::coins::MEMPOOL_HEIGHT;
+use crate::index::txindex::TxIndex;
+use crate::index::BlockIndex;
+use crate::kernel::Kernel;
+use crate::mempool::fees::FeeEstimateHorizon;
+use crate::mempool::projection::{self, DEFAULT_HISTOGRAM_BIN_VSIZE};
+use crate::mempool::Mempool;
+use crate::p2p::control::{P2PCommand, P2PHandle};
+use crate::rpc::util::tx_to_json;
+
+const SERVER_VERSION: &str = concat!("btck-rust-node ", env!("CARGO_PKG_VERSION"));
+const PROTOCOL_VERSION: &str = "1.4";
+
+/// How often the tip, the index and the mempool are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Most headers one blockchain.block.headers request returns
+const MAX_HEADERS: u32 = 2016;
+
+/// Longest request line a client may send (a large transaction.broadcast)
+const MAX_LINE_LENGTH: usize = 1024 * 1024;
+
+// JSON-RPC error codes, and ElectrumX's for bad requests and daemon errors
+const ERR_PARSE: i64 = -32700;
+const ERR_METHOD_NOT_FOUND: i64 = -32601;
+const ERR_INVALID_PARAMS: i64 = -32602;
+const ERR_BAD_REQUEST: i64 = 1;
+const ERR_DAEMON: i64 = 2;
+
+type MethodResult = Result<Value, (i64, String)>;
+
+fn invalid_params(message: impl Into<String>) -> (i64, String) {
+    (ERR_INVALID_PARAMS, message.into())
+}
+
+fn daemon_error(e: anyhow::Error) -> (i64, String) {
+    (ERR_DAEMON, format!("{:#}", e))
+}
+
+/// Scripthash as Electrum writes it: the SHA256 byte-reversed, in hex
+fn parse_scripthash(s: &str) -> Option<ScriptHash> {
+    let mut bytes: [u8; 32] = hex::decode(s).ok()?.try_into().ok()?;
+    bytes.reverse();
+    Some(ScriptHash::from_byte_array(bytes))
+}
+
+fn scripthash_hex(scripthash: &ScriptHash) -> String {
+    let mut bytes = scripthash.to_byte_array();
+    bytes.reverse();
+    hex::encode(bytes)
+}
+
+/// Status of a history given as (txid, height) pairs; None when it is empty
+fn status(history: &[(Txid, i64)]) -> Option<String> {
+    if history.is_empty() {
+        return None;
+    }
+    let mut engine = sha256::Hash::engine();
+    for (txid, height) in history {
+        engine.input(format!("{}:{}:", txid, height).as_bytes());
+    }
+    Some(hex::encode(sha256::Hash::from_engine(engine).as_byte_array()))
+}
+
+/// Hashes that prove the transaction at `pos` is under the merkle root of
+/// a block with `txids`, bottom up
+fn merkle_branch(txids: &[Txid], mut pos: usize) -> Vec<sha256d::Hash> {
+    let mut branch = Vec::new();
+    let mut level: Vec<sha256d::Hash> = txids.iter().map(|txid| txid.to_raw_hash()).collect();
+    while level.len() > 1 {
+        if level.len() % 2 == 1 {
+            level.push(*level.last().unwrap());
+        }
+        branch.push(level[pos ^ 1]);
+        level = level
+            .chunks(2)
+            .map(|pair| {
+                let mut engine = sha256d::Hash::engine();
+                engine.input(pair[0].as_byte_array());
+                engine.input(pair[1].as_byte_array());
+                sha256d::Hash::from_engine(engine)
+            })
+            .collect();
+        pos /= 2;
+    }
+    branch
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+fn reply(id: Value, result: MethodResult) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } }),
+    }
+}
+
+fn param<'a>(params: &'a [Value], i: usize, name: &str) -> Result<&'a Value, (i64, String)> {
+    params.get(i).ok_or_else(|| invalid_params(format!("missing parameter {}", name)))
+}
+
+fn str_param<'a>(params: &'a [Value], i: usize, name: &str) -> Result<&'a str, (i64, String)> {
+    param(params, i, name)?.as_str().ok_or_else(|| invalid_params(format!("{} must be a string", name)))
+}
+
+fn u32_param(params: &[Value], i: usize, name: &str) -> Result<u32, (i64, String)> {
+    param(params, i, name)?
+        .as_u64()
+        .and_then(|n| u32::try_from(n).ok())
+        .ok_or_else(|| invalid_params(format!("{} must be a non-negative integer", name)))
+}
+
+fn scripthash_param(params: &[Value]) -> Result<ScriptHash, (i64, String)> {
+    let s = str_param(params, 0, "scripthash")?;
+    parse_scripthash(s).ok_or_else(|| invalid_params(format!("invalid scripthash {}", s)))
+}
+
+fn txid_param(params: &[Value]) -> Result<Txid, (i64, String)> {
+    let s = str_param(params, 0, "tx_hash")?;
+    s.parse().map_err(|_| invalid_params(format!("invalid tx_hash {}", s)))
+}
+
+/// What connections are told about: the kernel's tip, the address index's
+/// best block and the mempool's sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ChainState {
+    height: i32,
+    tip: BlockHash,
+    index_best: Option<BlockHash>,
+    mempool_sequence: u64,
+}
+
+/// Per-connection subscriptions
+struct Connection {
+    addr: SocketAddr,
+    /// Tip last sent as a header notification, once subscribed to headers
+    headers: Option<BlockHash>,
+    /// Subscribed scripts and the status last sent for each
+    scripthashes: HashMap<ScriptHash, Option<String>>,
+}
+
+struct Server {
+    kernel: Arc<Kernel>,
+    mempool: Arc<Mempool>,
+    addrindex: Arc<AddrIndex>,
+    txindex: Arc<TxIndex>,
+    p2p: Option<P2PHandle>,
+    state: watch::Sender<ChainState>,
+    stop: watch::Sender<bool>,
+    /// Dropped with the server, once every task holding it has exited
+    _running: mpsc::Sender<()>,
+}
+
+/// Running Electrum server; `shutdown` must be awaited before the kernel is dropped
+pub struct ElectrumServer {
+    server: Arc<Server>,
+    stopped: mpsc::Receiver<()>,
+}
+
+/// Resolves once shutdown was requested, even before `stop` was subscribed
+async fn stopped(stop: &mut watch::Receiver<bool>) {
+    let _ = stop.wait_for(|stopped| *stopped).await;
+}
+
+impl ElectrumServer {
+    /// Bind `listen` and start following the tip and the mempool
+    pub async fn start(
+        listen: &str,
+        kernel: Arc<Kernel>,
+        mempool: Arc<Mempool>,
+        addrindex: Arc<AddrIndex>,
+        txindex: Arc<TxIndex>,
+        p2p: Option<P2PHandle>,
+    ) -> Result<Self> {
+        let (stop, _) = watch::channel(false);
+        let (running, stopped) = mpsc::channel(1);
+        let initial = Server::chain_state(&kernel, &mempool, &addrindex)?;
+        let (state, _) = watch::channel(initial);
+        let server = Arc::new(Server { kernel, mempool, addrindex, txindex, p2p, state, stop, _running: running });
+
+        let listener = TcpListener::bind(listen).await.with_context(|| format!("cannot bind electrum on {}", listen))?;
+        eprintln!("[electrum] serving on {} (protocol {})", listen, PROTOCOL_VERSION);
+        tokio::spawn(server.clone().accept_loop(listener));
+        tokio::spawn(server.clone().follow_chain());
+
+        Ok(Self { server, stopped })
+    }
+
+    /// Close every connection and wait until no task holds the kernel
+    pub async fn shutdown(self) {
+        let Self { server, mut stopped } = self;
+        server.stop.send_replace(true);
+        drop(server);
+        let _ = stopped.recv().await;
+    }
+}
+
+impl Server {
+    fn chain_state(kernel: &Kernel, mempool: &Mempool, addrindex: &AddrIndex) -> Result<ChainState> {
+        Ok(ChainState {
+            height: kernel.get_height()?,
+            tip: kernel.get_best_block_hash()?,
+            index_best: addrindex.best_block().map(|(_, hash)| hash),
+            mempool_sequence: mempool.sequence(),
+        })
+    }
+
+    /// Serialized header of the active chain's block at `height`. Read from
+    /// the address index, which stores every header it has connected; only
+    /// blocks the index has not reached yet are read from disk by the kernel.
+    fn header_hex(&self, height: u32) -> Result<String, (i64, String)> {
+        let tip = self.kernel.get_height().map_err(daemon_error)?;
+        if height as i64 > tip as i64 {
+            return Err((ERR_BAD_REQUEST, format!("height {} out of range", height)));
+        }
+        let hash = self.kernel.get_block_hash(height as i32).map_err(daemon_error)?;
+        // A header the index has not disconnected yet after a reorg is not the active chain's
+        let indexed = self.addrindex.header(height).map_err(daemon_error)?.filter(|h| h.block_hash() == hash);
+        let header = match indexed {
+            Some(header) => header,
+            None => self
+                .kernel
+                .get_block_header(&hash)
+                .map_err(daemon_error)?
+                .ok_or_else(|| (ERR_DAEMON, format!("header of block {} is not available", hash)))?,
+        };
+        Ok(serialize_hex(&header))
+    }
+
+    fn tip_header(&self, height: i32) -> MethodResult {
+        Ok(json!({ "height": height, "hex": self.header_hex(height as u32)? }))
+    }
+
+    /// Confirmed and unconfirmed activity of each of `scripthashes`
+    fn activities(&self, scripthashes: &HashSet<ScriptHash>) -> Result<HashMap<ScriptHash, ScriptActivity>> {
+        let mut unconfirmed = self.addrindex.mempool_activity(&self.mempool, scripthashes)?;
+        let mut result = HashMap::with_capacity(scripthashes.len());
+        for scripthash in scripthashes {
+            let mut activity = self.addrindex.activity(scripthash)?;
+            if let Some(unconfirmed) = unconfirmed.remove(scripthash) {
+                activity.extend(unconfirmed);
+            }
+            result.insert(*scripthash, activity);
+        }
+        Ok(result)
+    }
+
+    fn activity(&self, scripthash: &ScriptHash) -> Result<ScriptActivity> {
+        let mut activities = self.activities(&HashSet::from([*scripthash]))?;
+        Ok(activities.remove(scripthash).unwrap_or_default())
+    }
+
+    /// Electrum height of a history entry: its block's, or 0 in the mempool
+    /// and -1 with unconfirmed parents
+    fn electrum_height(&self, txid: &Txid, height: u32) -> i64 {
+        if height != MEMPOOL_HEIGHT {
+            return height as i64;
+        }
+        let has_unconfirmed_parent = self
+            .mempool
+            .get_tx(txid)
+            .is_some_and(|tx| tx.input.iter().any(|input| self.mempool.contains(&input.previous_output.txid)));
+        if has_unconfirmed_parent {
+            -1
+        } else {
+            0
+        }
+    }
+
+    fn history(&self, activity: &ScriptActivity) -> Vec<(Txid, i64)> {
+        activity.history().iter().map(|e| (e.txid, self.electrum_height(&e.txid, e.height))).collect()
+    }
+
+    fn history_json(&self, activity: &ScriptActivity, mempool_only: bool) -> Value {
+        let entries = self
+            .history(activity)
+            .into_iter()
+            .filter(|(_, height)| !mempool_only || *height <= 0)
+            .map(|(txid, height)| {
+                let mut entry = json!({ "tx_hash": txid.to_string(), "height": height });
+                if height <= 0 {
+                    if let Some(fee) = self.mempool.get_entry(&txid).map(|e| e.fee) {
+                        entry["fee"] = json!(fee);
+                    }
+                }
+                entry
+            })
+            .collect();
+        Value::Array(entries)
+    }
+
+    /// Status of each of `scripthashes`
+    fn statuses(&self, scripthashes: &HashSet<ScriptHash>) -> Result<HashMap<ScriptHash, Option<String>>> {
+        Ok(self
+            .activities(scripthashes)?
+            .into_iter()
+            .map(|(scripthash, activity)| (scripthash, status(&self.history(&activity))))
+            .collect())
+    }
+
+    /// Answer a request that does not touch the connection's subscriptions
+    fn call(&self, method: &str, params: &[Value]) -> MethodResult {
+        match method {
+            "server.version" => Ok(json!([SERVER_VERSION, PROTOCOL_VERSION])),
+            "server.banner" => Ok(json!(format!("Welcome to {}", SERVER_VERSION))),
+            "server.donation_address" => Ok(json!("")),
+            "server.peers.subscribe" => Ok(json!([])),
+            "server.ping" => Ok(Value::Null),
+            "server.features" => {
+                let genesis = self.kernel.get_block_hash(0).map_err(daemon_error)?;
+                Ok(json!({
+                    "genesis_hash": genesis.to_string(),
+                    "hosts": {},
+                    "protocol_max": PROTOCOL_VERSION,
+                    "protocol_min": PROTOCOL_VERSION,
+                    "pruning": null,
+                    "server_version": SERVER_VERSION,
+                    "hash_function": "sha256",
+                }))
+            }
+
+            "blockchain.block.header" => {
+                let height = u32_param(params, 0, "height")?;
+                Ok(json!(self.header_hex(height)?))
+            }
+            "blockchain.block.headers" => {
+                let start = u32_param(params, 0, "start_height")?;
+                let count = u32_param(params, 1, "count")?.min(MAX_HEADERS);
+                // Only what the address index has headers for, so a batch never reads full blocks
+                let tip = self.kernel.get_height().map_err(daemon_error)?;
+                let indexed = self.addrindex.best_block().map_or(-1, |(height, _)| height);
+                let end = (start as i64 + count as i64).min(tip.min(indexed) as i64 + 1);
+                let mut hex = String::new();
+                for height in start as i64..end {
+                    hex.push_str(&self.header_hex(height as u32)?);
+                }
+                Ok(json!({ "count": (end - start as i64).max(0), "hex": hex, "max": MAX_HEADERS }))
+            }
+            "blockchain.estimatefee" => {
+                let target = u32_param(params, 0, "number")?;
+                let estimator = self.mempool.fee_estimator();
+                let estimator = estimator.read();
+                let max_target = estimator.highest_target_tracked(FeeEstimateHorizon::Long).max(1);
+                // Never below what the mempool accepts, as estimatesmartfee
+                let min_fee_rate = self.mempool.min_fee_rate().max(self.mempool.policy().min_relay_fee);
+                match estimator.estimate_smart_fee(target.clamp(1, max_target), false).0 {
+                    Some(fee_rate) => Ok(json!(fee_rate.max(min_fee_rate).as_btc_per_kvb())),
+                    None => Ok(json!(-1)),
+                }
+            }
+            "blockchain.relayfee" => Ok(json!(self.mempool.policy().min_relay_fee.as_btc_per_kvb())),
+            "mempool.get_fee_histogram" => {
+                let bins = projection::fee_histogram(&self.mempool, DEFAULT_HISTOGRAM_BIN_VSIZE);
+                Ok(bins.iter().map(|bin| json!([bin.fee_rate.as_sat_per_vb_f64(), bin.vsize])).collect())
+            }
+
+            "blockchain.scripthash.get_balance" => {
+                let activity = self.activity(&scripthash_param(params)?).map_err(daemon_error)?;
+                let (confirmed, unconfirmed) = activity.balance();
+                Ok(json!({ "confirmed": confirmed, "unconfirmed": unconfirmed }))
+            }
+            "blockchain.scripthash.get_history" => {
+                let activity = self.activity(&scripthash_param(params)?).map_err(daemon_error)?;
+                Ok(self.history_json(&activity, false))
+            }
+            "blockchain.scripthash.get_mempool" => {
+                let activity = self.activity(&scripthash_param(params)?).map_err(daemon_error)?;
+                Ok(self.history_json(&activity, true))
+            }
+            "blockchain.scripthash.listunspent" => {
+                let activity = self.activity(&scripthash_param(params)?).map_err(daemon_error)?;
+                let utxos = activity
+                    .unspent()
+                    .iter()
+                    .map(|f| {
+                        json!({
+                            "tx_hash": f.outpoint.txid.to_string(),
+                            "tx_pos": f.outpoint.vout,
+                            "height": if f.height == MEMPOOL_HEIGHT { 0 } else { f.height },
+                            "value": f.value,
+                        })
+                    })
+                    .collect();
+                Ok(Value::Array(utxos))
+            }
+
+            "blockchain.transaction.get" => {
+                let txid = txid_param(params)?;
+                let verbose = params.get(1).and_then(Value::as_bool).unwrap_or(false);
+                self.get_transaction(&txid, verbose)
+            }
+            "blockchain.transaction.get_merkle" => {
+                let txid = txid_param(params)?;
+                let height = u32_param(params, 1, "height")?;
+                self.get_merkle(&txid, height)
+            }
+            "blockchain.transaction.broadcast" => {
+                let raw = str_param(params, 0, "raw_tx")?;
+                self.broadcast(raw)
+            }
+            _ => Err((ERR_METHOD_NOT_FOUND, format!("unknown method {}", method))),
+        }
+    }
+
+    fn get_transaction(&self, txid: &Txid, verbose: bool) -> MethodResult {
+        let (tx, block) = match self.mempool.get_tx(txid) {
+            Some(tx) => ((*tx).clone(), None),
+            None => match self.txindex.get_transaction(&self.kernel, txid).map_err(daemon_error)? {
+                Some((tx, location)) => (tx, Some(location)),
+                None => return Err((ERR_BAD_REQUEST, format!("no transaction {} in the mempool or the chain", txid))),
+            },
+        };
+        if !verbose {
+            return Ok(json!(serialize_hex(&tx)));
+        }
+
+        let mut result = tx_to_json(&tx, self.kernel.network());
+        result["hex"] = json!(serialize_hex(&tx));
+        if let Some(location) = block {
+            let tip = self.kernel.get_height().map_err(daemon_error)?;
+            result["blockhash"] = json!(location.block_hash.to_string());
+            result["confirmations"] = json!(tip - location.height + 1);
+            if let Some(header) = self.kernel.get_block_header(&location.block_hash).map_err(daemon_error)? {
+                result["time"] = json!(header.time);
+                result["blocktime"] = json!(header.time);
+            }
+        }
+        Ok(result)
+    }
+
+    fn get_merkle(&self, txid: &Txid, height: u32) -> MethodResult {
+        let hash = self
+            .kernel
+            .get_block_hash(height as i32)
+            .map_err(|_| (ERR_BAD_REQUEST, format!("height {} out of range", height)))?;
+        let block = self
+            .kernel
+            .get_block(&hash)
+            .map_err(daemon_error)?
+            .ok_or_else(|| (ERR_DAEMON, format!("block {} is not available", hash)))?;
+        let txids: Vec<Txid> = block.txdata.iter().map(Transaction::compute_txid).collect();
+        let pos = txids
+            .iter()
+            .position(|t| t == txid)
+            .ok_or_else(|| (ERR_BAD_REQUEST, format!("tx {} not in block at height {}", txid, height)))?;
+        // Hashes are shown byte-reversed, like txids
+        let branch: Vec<String> =
+            merkle_branch(&txids, pos).iter().map(|h| Txid::from_raw_hash(*h).to_string()).collect();
+        Ok(json!({ "block_height": height, "merkle": branch, "pos": pos }))
+    }
+
+    /// Accept a transaction into the mempool and relay it, like sendrawtransaction
+    fn broadcast(&self, raw: &str) -> MethodResult {
+        let tx: Transaction = hex::decode(raw)
+            .ok()
+            .and_then(|bytes| deserialize(&bytes).ok())
+            .ok_or_else(|| (ERR_BAD_REQUEST, "TX decode failed".to_string()))?;
+        let txid = tx.compute_txid();
+        if !self.mempool.contains(&txid) {
+            let accepted = self.mempool.accept_tx(tx.clone()).map_err(|e| (ERR_BAD_REQUEST, e.to_string()))?;
+            eprintln!("[electrum] broadcast accepted {} (fee {} sat)", accepted.txid, accepted.fee);
+        }
+        self.mempool.add_unbroadcast(txid);
+        if let Some(p2p) = &self.p2p {
+            p2p.send(P2PCommand::RelayTransaction(Arc::new(tx)));
+        }
+        Ok(json!(txid.to_string()))
+    }
+
+    async fn accept_loop(self: Arc<Self>, listener: TcpListener) {
+        let mut stop = self.stop.subscribe();
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, addr)) => {
+                        tokio::spawn(self.clone().serve(stream, addr));
+                    }
+                    Err(e) => eprintln!("[electrum] accept failed: {}", e),
+                },
+                _ = stopped(&mut stop) => break,
+            }
+        }
+    }
+
+    /// Publish tip, index and mempool changes to the connections
+    async fn follow_chain(self: Arc<Self>) {
+        let mut stop = self.stop.subscribe();
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = stopped(&mut stop) => break,
+            }
+            match Self::chain_state(&self.kernel, &self.mempool, &self.addrindex) {
+                Ok(state) => {
+                    self.state.send_if_modified(|current| std::mem::replace(current, state) != state);
+                }
+                Err(e) => eprintln!("[electrum] cannot read the tip: {:#}", e),
+            }
+        }
+    }
+
+    async fn serve(self: Arc<Self>, stream: TcpStream, addr: SocketAddr) {
+        let mut conn = Connection { addr, headers: None, scripthashes: HashMap::new() };
+        eprintln!("[electrum] {} connected", addr);
+        match self.run_connection(stream, &mut conn).await {
+            Ok(()) => eprintln!("[electrum] {} disconnected", addr),
+            Err(e) => eprintln!("[electrum] {} disconnected: {:#}", addr, e),
+        }
+    }
+
+    async fn run_connection(self: &Arc<Self>, stream: TcpStream, conn: &mut Connection) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = FramedRead::new(reader, LinesCodec::new_with_max_length(MAX_LINE_LENGTH));
+        let mut state = self.state.subscribe();
+        let mut stop = self.stop.subscribe();
+
+        loop {
+            let messages = tokio::select! {
+                line = lines.next() => match line {
+                    Some(line) => self.handle_line(conn, &line?).await,
+                    None => return Ok(()),
+                },
+                changed = state.changed() => match changed {
+                    Ok(()) => {
+                        let current = *state.borrow_and_update();
+                        self.notifications(conn, current).await
+                    }
+                    Err(_) => return Ok(()),
+                },
+                _ = stopped(&mut stop) => return Ok(()),
+            };
+            for message in messages {
+                writer.write_all(format!("{}\n", message).as_bytes()).await?;
+            }
+        }
+    }
+
+    /// Run `f` on a blocking thread
+    async fn blocking<T, F>(self: &Arc<Self>, f: F) -> Result<T, (i64, String)>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Server) -> Result<T, (i64, String)> + Send + 'static,
+    {
+        let server = self.clone();
+        tokio::task::spawn_blocking(move || f(&server))
+            .await
+            .unwrap_or_else(|e| Err((ERR_DAEMON, format!("request failed: {}", e))))
+    }
+
+    /// Notifications for what changed in `state` since the last ones
+    async fn notifications(self: &Arc<Self>, conn: &mut Connection, state: ChainState) -> Vec<Value> {
+        let mut messages = Vec::new();
+        if conn.headers.is_some_and(|notified| notified != state.tip) {
+            match self.blocking(move |server| server.tip_header(state.height)).await {
+                Ok(header) => {
+                    conn.headers = Some(state.tip);
+                    messages.push(notification("blockchain.headers.subscribe", json!([header])));
+                }
+                Err((_, e)) => eprintln!("[electrum] cannot notify {} of the tip: {}", conn.addr, e),
+            }
+        }
+
+        if conn.scripthashes.is_empty() {
+            return messages;
+        }
+        let scripthashes: HashSet<ScriptHash> = conn.scripthashes.keys().copied().collect();
+        let statuses = match self.blocking(move |server| server.statuses(&scripthashes).map_err(daemon_error)).await {
+            Ok(statuses) => statuses,
+            Err((_, e)) => {
+                eprintln!("[electrum] cannot compute statuses for {}: {}", conn.addr, e);
+                return messages;
+            }
+        };
+        for (scripthash, status) in statuses {
+            let Some(last) = conn.scripthashes.get_mut(&scripthash) else {
+                continue;
+            };
+            if *last != status {
+                let params = json!([scripthash_hex(&scripthash), status]);
+                messages.push(notification("blockchain.scripthash.subscribe", params));
+                *last = status;
+            }
+        }
+        messages
+    }
+
+    /// Answer one request line, a single request or a batch
+    async fn handle_line(self: &Arc<Self>, conn: &mut Connection, line: &str) -> Vec<Value> {
+        match serde_json::from_str::<Value>(line) {
+            Ok(Value::Array(requests)) => {
+                let mut replies = Vec::with_capacity(requests.len());
+                for request in &requests {
+                    replies.push(self.handle_request(conn, request).await);
+                }
+                vec![Value::Array(replies)]
+            }
+            Ok(request) => vec![self.handle_request(conn, &request).await],
+            Err(_) => vec![reply(Value::Null, Err((ERR_PARSE, "Parse error".to_string())))],
+        }
+    }
+
+    async fn handle_request(self: &Arc<Self>, conn: &mut Connection, request: &Value) -> Value {
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = request.get("method").and_then(Value::as_str).unwrap_or_default().to_string();
+        let params = request.get("params").and_then(Value::as_array).cloned().unwrap_or_default();
+
+        let result = match method.as_str() {
+            "blockchain.headers.subscribe" => {
+                let state = *self.state.borrow();
+                let header = self.blocking(move |server| server.tip_header(state.height)).await;
+                if header.is_ok() {
+                    conn.headers = Some(state.tip);
+                }
+                header
+            }
+            "blockchain.scripthash.subscribe" => match scripthash_param(&params) {
+                Ok(scripthash) => {
+                    let current = self
+                        .blocking(move |server| {
+                            let activity = server.activity(&scripthash).map_err(daemon_error)?;
+                            Ok(status(&server.history(&activity)))
+                        })
+                        .await;
+                    current.map(|current| {
+                        conn.scripthashes.insert(scripthash, current.clone());
+                        json!(current)
+                    })
+                }
+                Err(e) => Err(e),
+            },
+            "blockchain.scripthash.unsubscribe" => {
+                scripthash_param(&params).map(|scripthash| json!(conn.scripthashes.remove(&scripthash).is_some()))
+            }
+            _ => self.blocking(move |server| server.call(&method, &params)).await,
+        };
+        reply(id, result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{merkle_tree, Network, ScriptBuf};
+
+    #[test]
+    fn test_scripthash_encoding() {
+        // Electrum's documented example: the P2PKH script of 1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa
+        let script = ScriptBuf::from_hex("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac").unwrap();
+        let scripthash = crate::index::addrindex::script_hash(&script);
+        let encoded = scripthash_hex(&scripthash);
+        assert_eq!(encoded, "8b01df4e368ea28f8dc0423bcf7a4923e3a12d307c875e47a0cfbf90b5c39161");
+        assert_eq!(parse_scripthash(&encoded), Some(scripthash));
+        assert_eq!(parse_scripthash("00"), None);
+    }
+
+    #[test]
+    fn test_status() {
+        assert_eq!(status(&[]), None);
+        let txid = bitcoin::constants::genesis_block(Network::Bitcoin).txdata[0].compute_txid();
+        let expected = sha256::Hash::hash(format!("{}:0:", txid).as_bytes());
+        assert_eq!(status(&[(txid, 0)]), Some(hex::encode(expected.as_byte_array())));
+    }
+
+    #[test]
+    fn test_merkle_branch() {
+        for n in 1..10u8 {
+            let txids: Vec<Txid> = (0..n).map(|i| Txid::hash(&[i])).collect();
+            let root = merkle_tree::calculate_root(txids.iter().map(|t| t.to_raw_hash())).unwrap();
+            for pos in 0..n as usize {
+                let mut hash = txids[pos].to_raw_hash();
+                let mut index = pos;
+                for sibling in merkle_branch(&txids, pos) {
+                    let mut engine = sha256d::Hash::engine();
+                    let (left, right) = if index % 2 == 0 { (hash, sibling) } else { (sibling, hash) };
+                    engine.input(left.as_byte_array());
+                    engine.input(right.as_byte_array());
+                    hash = sha256d::Hash::from_engine(engine);
+                    index /= 2;
+                }
+                assert_eq!(hash, root, "{} transactions, position {}", n, pos);
+            }
+        }
+    }
+}
@@ -0,0 +1,614 @@
+//! Address index (electrs/Insight style; Bitcoin Core has none).
+//!
+//! Maps the SHA256 of each output script, the Electrum "scripthash", to the
+//! outputs that paid it and the inputs that spent them, so the balance,
+//! UTXOs and history of an address can be read without a wallet. Every
+//! output is also kept by outpoint, which traces a spend back to the script
+//! it came from and lets a block be disconnected without undo data. The
+//! header of every indexed block is kept too, so Electrum clients can sync
+//! headers without the kernel reading each full block from disk.
+//!
+//! Rows of a script sort in block order: height, then position of the
+//! transaction in the block, then output or input index.
+//!
+//! Layout:
+//! - `o` + txid + vout (BE)                          -> scripthash, value (LE)
+//! - `f` + scripthash + height, position, vout (BE)  -> txid, value (LE), coinbase
+//! - `s` + scripthash + height, position, vin (BE)   -> txid, prevout, value (LE)
+//! - `h` + height (BE)                               -> block header (80 bytes)
+//! - `B`                                             -> best block (height LE, hash)
+
+use anyhow::{anyhow, Result};
+use bitcoin::block::Header;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::{consensus, Block, BlockHash, OutPoint, Script, Txid};
+use parking_lot::RwLock;
+use rocksdb::{Options, WriteBatch, DB};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use super::coins::MEMPOOL_HEIGHT;
+use super::BlockIndex;
+use crate::mempool::Mempool;
+
+const PREFIX_OUTPUT: u8 = b'o';
+const PREFIX_FUNDING: u8 = b'f';
+const PREFIX_SPENDING: u8 = b's';
+const PREFIX_HEADER: u8 = b'h';
+const KEY_BEST: &[u8] = b"B";
+
+/// SHA256 of an output script; Electrum shows it byte-reversed in hex
+pub type ScriptHash = sha256::Hash;
+
+pub fn script_hash(script: &Script) -> ScriptHash {
+    sha256::Hash::hash(script.as_bytes())
+}
+
+/// An output paying a script. Unconfirmed ones have height `MEMPOOL_HEIGHT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Funding {
+    pub outpoint: OutPoint,
+    pub height: u32,
+    /// Position of the transaction in its block (0 in the mempool)
+    pub pos: u32,
+    pub value: u64,
+    pub is_coinbase: bool,
+}
+
+/// An input spending an output of a script. Unconfirmed ones have height
+/// `MEMPOOL_HEIGHT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spending {
+    pub txid: Txid,
+    pub vin: u32,
+    pub height: u32,
+    /// Position of the transaction in its block (0 in the mempool)
+    pub pos: u32,
+    pub prevout: OutPoint,
+    /// Value of the spent output
+    pub value: u64,
+}
+
+/// What one transaction did to a script
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub txid: Txid,
+    pub height: u32,
+    pub pos: u32,
+    pub received: u64,
+    pub sent: u64,
+}
+
+impl HistoryEntry {
+    pub fn is_confirmed(&self) -> bool {
+        self.height != MEMPOOL_HEIGHT
+    }
+}
+
+/// Outputs paying a script and inputs spending them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptActivity {
+    pub fundings: Vec<Funding>,
+    pub spendings: Vec<Spending>,
+}
+
+impl ScriptActivity {
+    pub fn extend(&mut self, other: ScriptActivity) {
+        self.fundings.extend(other.fundings);
+        self.spendings.extend(other.spendings);
+    }
+
+    /// Outputs that no input here spends, in block order
+    pub fn unspent(&self) -> Vec<Funding> {
+        let spent: HashSet<OutPoint> = self.spendings.iter().map(|s| s.prevout).collect();
+        let mut unspent: Vec<Funding> =
+            self.fundings.iter().filter(|f| !spent.contains(&f.outpoint)).copied().collect();
+        unspent.sort_by_key(|f| (f.height, f.pos, f.outpoint.txid, f.outpoint.vout));
+        unspent
+    }
+
+    /// Confirmed balance, and the net change of unconfirmed transactions
+    pub fn balance(&self) -> (u64, i64) {
+        let mut confirmed: i64 = 0;
+        let mut unconfirmed: i64 = 0;
+        for f in &self.fundings {
+            let total = if f.height == MEMPOOL_HEIGHT { &mut unconfirmed } else { &mut confirmed };
+            *total += f.value as i64;
+        }
+        for s in &self.spendings {
+            let total = if s.height == MEMPOOL_HEIGHT { &mut unconfirmed } else { &mut confirmed };
+            *total -= s.value as i64;
+        }
+        (confirmed.max(0) as u64, unconfirmed)
+    }
+
+    /// One entry per transaction, in block order and then unconfirmed ones
+    /// by txid
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        let mut entries: HashMap<Txid, HistoryEntry> = HashMap::new();
+        for f in &self.fundings {
+            let txid = f.outpoint.txid;
+            let entry = entries.entry(txid).or_insert(HistoryEntry {
+                txid,
+                height: f.height,
+                pos: f.pos,
+                received: 0,
+                sent: 0,
+            });
+            entry.received += f.value;
+        }
+        for s in &self.spendings {
+            let entry = entries.entry(s.txid).or_insert(HistoryEntry {
+                txid: s.txid,
+                height: s.height,
+                pos: s.pos,
+                received: 0,
+                sent: 0,
+            });
+            entry.sent += s.value;
+        }
+        let mut history: Vec<HistoryEntry> = entries.into_values().collect();
+        history.sort_by_key(|e| (e.height, e.pos, e.txid));
+        history
+    }
+}
+
+fn output_key(outpoint: &OutPoint) -> [u8; 37] {
+    let mut key = [0u8; 37];
+    key[0] = PREFIX_OUTPUT;
+    key[1..33].copy_from_slice(outpoint.txid.as_byte_array());
+    key[33..37].copy_from_slice(&outpoint.vout.to_be_bytes());
+    key
+}
+
+fn header_key(height: u32) -> [u8; 5] {
+    let mut key = [0u8; 5];
+    key[0] = PREFIX_HEADER;
+    key[1..5].copy_from_slice(&height.to_be_bytes());
+    key
+}
+
+fn script_prefix(prefix: u8, scripthash: &ScriptHash) -> [u8; 33] {
+    let mut key = [0u8; 33];
+    key[0] = prefix;
+    key[1..33].copy_from_slice(scripthash.as_byte_array());
+    key
+}
+
+fn row_key(prefix: u8, scripthash: &ScriptHash, height: u32, pos: u32, index: u32) -> [u8; 45] {
+    let mut key = [0u8; 45];
+    key[0..33].copy_from_slice(&script_prefix(prefix, scripthash));
+    key[33..37].copy_from_slice(&height.to_be_bytes());
+    key[37..41].copy_from_slice(&pos.to_be_bytes());
+    key[41..45].copy_from_slice(&index.to_be_bytes());
+    key
+}
+
+/// Height, position and output/input index from a row key
+fn decode_row_key(key: &[u8]) -> Result<(u32, u32, u32)> {
+    if key.len() != 45 {
+        return Err(anyhow!("bad addrindex key length {}", key.len()));
+    }
+    Ok((
+        u32::from_be_bytes(key[33..37].try_into().unwrap()),
+        u32::from_be_bytes(key[37..41].try_into().unwrap()),
+        u32::from_be_bytes(key[41..45].try_into().unwrap()),
+    ))
+}
+
+fn encode_output(scripthash: &ScriptHash, value: u64) -> [u8; 40] {
+    let mut buf = [0u8; 40];
+    buf[0..32].copy_from_slice(scripthash.as_byte_array());
+    buf[32..40].copy_from_slice(&value.to_le_bytes());
+    buf
+}
+
+fn decode_output(data: &[u8]) -> Result<(ScriptHash, u64)> {
+    if data.len() != 40 {
+        return Err(anyhow!("bad addrindex output length {}", data.len()));
+    }
+    Ok((ScriptHash::from_slice(&data[0..32])?, u64::from_le_bytes(data[32..40].try_into().unwrap())))
+}
+
+impl Funding {
+    fn encode_value(&self) -> [u8; 41] {
+        let mut buf = [0u8; 41];
+        buf[0..32].copy_from_slice(self.outpoint.txid.as_byte_array());
+        buf[32..40].copy_from_slice(&self.value.to_le_bytes());
+        buf[40] = self.is_coinbase as u8;
+        buf
+    }
+
+    fn decode(key: &[u8], data: &[u8]) -> Result<Self> {
+        let (height, pos, vout) = decode_row_key(key)?;
+        if data.len() != 41 {
+            return Err(anyhow!("bad addrindex funding length {}", data.len()));
+        }
+        Ok(Self {
+            outpoint: OutPoint::new(Txid::from_slice(&data[0..32])?, vout),
+            height,
+            pos,
+            value: u64::from_le_bytes(data[32..40].try_into().unwrap()),
+            is_coinbase: data[40] != 0,
+        })
+    }
+}
+
+impl Spending {
+    fn encode_value(&self) -> [u8; 76] {
+        let mut buf = [0u8; 76];
+        buf[0..32].copy_from_slice(self.txid.as_byte_array());
+        buf[32..64].copy_from_slice(self.prevout.txid.as_byte_array());
+        buf[64..68].copy_from_slice(&self.prevout.vout.to_be_bytes());
+        buf[68..76].copy_from_slice(&self.value.to_le_bytes());
+        buf
+    }
+
+    fn decode(key: &[u8], data: &[u8]) -> Result<Self> {
+        let (height, pos, vin) = decode_row_key(key)?;
+        if data.len() != 76 {
+            return Err(anyhow!("bad addrindex spending length {}", data.len()));
+        }
+        Ok(Self {
+            txid: Txid::from_slice(&data[0..32])?,
+            vin,
+            height,
+            pos,
+            prevout: OutPoint::new(
+                Txid::from_slice(&data[32..64])?,
+                u32::from_be_bytes(data[64..68].try_into().unwrap()),
+            ),
+            value: u64::from_le_bytes(data[68..76].try_into().unwrap()),
+        })
+    }
+}
+
+/// scripthash -> funding and spending outpoints of the active chain, stored in RocksDB
+pub struct AddrIndex {
+    db: DB,
+    best: RwLock<Option<(i32, BlockHash)>>,
+}
+
+impl AddrIndex {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        let db = DB::open(&opts, path)?;
+
+        let best = match db.get(KEY_BEST)? {
+            Some(v) if v.len() == 36 => Some((
+                i32::from_le_bytes(v[0..4].try_into().unwrap()),
+                BlockHash::from_slice(&v[4..36])?,
+            )),
+            _ => None,
+        };
+        match best {
+            Some((height, hash)) => eprintln!("[index] addrindex: opened {:?} at height {} ({})", path, height, hash),
+            None => eprintln!("[index] addrindex: opened {:?}, indexing from genesis", path),
+        }
+        Ok(Self { db, best: RwLock::new(best) })
+    }
+
+    /// Script hash and value of a confirmed output, spent or not
+    pub fn get_output(&self, outpoint: &OutPoint) -> Result<Option<(ScriptHash, u64)>> {
+        match self.db.get(output_key(outpoint))? {
+            Some(v) => Ok(Some(decode_output(&v)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Header of the indexed block at `height`
+    pub fn header(&self, height: u32) -> Result<Option<Header>> {
+        match self.db.get(header_key(height))? {
+            Some(v) => Ok(Some(consensus::deserialize(&v)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Confirmed outputs paying `scripthash` and the inputs spending them
+    pub fn activity(&self, scripthash: &ScriptHash) -> Result<ScriptActivity> {
+        let activity = ScriptActivity {
+            fundings: self.rows(PREFIX_FUNDING, scripthash, Funding::decode)?,
+            spendings: self.rows(PREFIX_SPENDING, scripthash, Spending::decode)?,
+        };
+        Ok(activity)
+    }
+
+    /// Unconfirmed activity of each of `scripthashes` that the mempool
+    /// touches. Walks the whole mempool, so callers batch their scripts.
+    pub fn mempool_activity(
+        &self,
+        mempool: &Mempool,
+        scripthashes: &HashSet<ScriptHash>,
+    ) -> Result<HashMap<ScriptHash, ScriptActivity>> {
+        let mut result: HashMap<ScriptHash, ScriptActivity> = HashMap::new();
+        if scripthashes.is_empty() {
+            return Ok(result);
+        }
+        for txid in mempool.get_all_txids() {
+            let Some(tx) = mempool.get_tx(&txid) else {
+                continue;
+            };
+            for (vin, input) in tx.input.iter().enumerate() {
+                let prevout = input.previous_output;
+                let prev = match mempool.get_tx(&prevout.txid) {
+                    Some(parent) => parent
+                        .output
+                        .get(prevout.vout as usize)
+                        .map(|output| (script_hash(&output.script_pubkey), output.value.to_sat())),
+                    None => self.get_output(&prevout)?,
+                };
+                let Some((scripthash, value)) = prev else {
+                    continue;
+                };
+                if scripthashes.contains(&scripthash) {
+                    result.entry(scripthash).or_default().spendings.push(Spending {
+                        txid,
+                        vin: vin as u32,
+                        height: MEMPOOL_HEIGHT,
+                        pos: 0,
+                        prevout,
+                        value,
+                    });
+                }
+            }
+            for (vout, output) in tx.output.iter().enumerate() {
+                let scripthash = script_hash(&output.script_pubkey);
+                if scripthashes.contains(&scripthash) {
+                    result.entry(scripthash).or_default().fundings.push(Funding {
+                        outpoint: OutPoint::new(txid, vout as u32),
+                        height: MEMPOOL_HEIGHT,
+                        pos: 0,
+                        value: output.value.to_sat(),
+                        is_coinbase: false,
+                    });
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Rows of `scripthash` under `prefix`, in block order
+    fn rows<T>(&self, prefix: u8, scripthash: &ScriptHash, decode: fn(&[u8], &[u8]) -> Result<T>) -> Result<Vec<T>> {
+        let start = script_prefix(prefix, scripthash);
+        let mut rows = Vec::new();
+        for item in self.db.prefix_iterator(start) {
+            let (key, value) = item?;
+            if !key.starts_with(&start) {
+                break;
+            }
+            rows.push(decode(&key, &value)?);
+        }
+        Ok(rows)
+    }
+
+    fn best_value(height: i32, hash: &BlockHash) -> Vec<u8> {
+        let mut v = Vec::with_capacity(36);
+        v.extend_from_slice(&height.to_le_bytes());
+        v.extend_from_slice(hash.as_byte_array());
+        v
+    }
+}
+
+impl BlockIndex for AddrIndex {
+    fn name(&self) -> &'static str {
+        "addrindex"
+    }
+
+    fn best_block(&self) -> Option<(i32, BlockHash)> {
+        *self.best.read()
+    }
+
+    fn connect_block(&self, height: i32, block: &Block) -> Result<()> {
+        let block_hash = block.block_hash();
+        let mut batch = WriteBatch::default();
+        // Outputs of this block, which the batch has not written yet
+        let mut created: HashMap<OutPoint, (ScriptHash, u64)> = HashMap::new();
+
+        // The genesis coinbase is not spendable and is not indexed
+        if height > 0 {
+            let height = height as u32;
+            for (pos, tx) in block.txdata.iter().enumerate() {
+                let pos = pos as u32;
+                let txid = tx.compute_txid();
+                let is_coinbase = tx.is_coinbase();
+                if !is_coinbase {
+                    for (vin, input) in tx.input.iter().enumerate() {
+                        let prevout = input.previous_output;
+                        let (scripthash, value) = match created.get(&prevout) {
+                            Some(output) => *output,
+                            None => self
+                                .get_output(&prevout)?
+                                .ok_or_else(|| anyhow!("block {} spends unknown output {}", block_hash, prevout))?,
+                        };
+                        let spending = Spending { txid, vin: vin as u32, height, pos, prevout, value };
+                        let key = row_key(PREFIX_SPENDING, &scripthash, height, pos, vin as u32);
+                        batch.put(key, spending.encode_value());
+                    }
+                }
+
+                for (vout, output) in tx.output.iter().enumerate() {
+                    if output.script_pubkey.is_op_return() {
+                        continue;
+                    }
+                    let scripthash = script_hash(&output.script_pubkey);
+                    let outpoint = OutPoint::new(txid, vout as u32);
+                    let value = output.value.to_sat();
+                    let funding = Funding { outpoint, height, pos, value, is_coinbase };
+                    batch.put(output_key(&outpoint), encode_output(&scripthash, value));
+                    batch.put(row_key(PREFIX_FUNDING, &scripthash, height, pos, vout as u32), funding.encode_value());
+                    created.insert(outpoint, (scripthash, value));
+                }
+            }
+        }
+        batch.put(header_key(height as u32), consensus::serialize(&block.header));
+        batch.put(KEY_BEST, Self::best_value(height, &block_hash));
+        self.db.write(batch)?;
+
+        *self.best.write() = Some((height, block_hash));
+        Ok(())
+    }
+
+    fn disconnect_block(&self, height: i32, block: &Block) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        if height > 0 {
+            let height = height as u32;
+            for (pos, tx) in block.txdata.iter().enumerate() {
+                let pos = pos as u32;
+                let txid = tx.compute_txid();
+                if !tx.is_coinbase() {
+                    for (vin, input) in tx.input.iter().enumerate() {
+                        // Spent outputs keep their `o` record, so the script is still known
+                        if let Some((scripthash, _)) = self.get_output(&input.previous_output)? {
+                            batch.delete(row_key(PREFIX_SPENDING, &scripthash, height, pos, vin as u32));
+                        }
+                    }
+                }
+                for (vout, output) in tx.output.iter().enumerate() {
+                    if output.script_pubkey.is_op_return() {
+                        continue;
+                    }
+                    let scripthash = script_hash(&output.script_pubkey);
+                    batch.delete(output_key(&OutPoint::new(txid, vout as u32)));
+                    batch.delete(row_key(PREFIX_FUNDING, &scripthash, height, pos, vout as u32));
+                }
+            }
+        }
+
+        batch.delete(header_key(height as u32));
+        let new_best = if height > 0 { Some((height - 1, block.header.prev_blockhash)) } else { None };
+        match new_best {
+            Some((h, ref hash)) => batch.put(KEY_BEST, Self::best_value(h, hash)),
+            None => batch.delete(KEY_BEST),
+        }
+        self.db.write(batch)?;
+
+        *self.best.write() = new_best;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Amount, Network, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness};
+
+    fn spend(prevout: OutPoint, outputs: Vec<TxOut>) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: prevout,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: outputs,
+        }
+    }
+
+    #[test]
+    fn test_row_roundtrip() {
+        let scripthash = script_hash(&ScriptBuf::from(vec![0x51]));
+        let funding = Funding {
+            outpoint: OutPoint::new(Txid::all_zeros(), 3),
+            height: 840_000,
+            pos: 12,
+            value: 50_000,
+            is_coinbase: true,
+        };
+        let key = row_key(PREFIX_FUNDING, &scripthash, funding.height, funding.pos, funding.outpoint.vout);
+        assert_eq!(Funding::decode(&key, &funding.encode_value()).unwrap(), funding);
+
+        let spending = Spending {
+            txid: Txid::all_zeros(),
+            vin: 1,
+            height: 840_001,
+            pos: 2,
+            prevout: funding.outpoint,
+            value: funding.value,
+        };
+        let key = row_key(PREFIX_SPENDING, &scripthash, spending.height, spending.pos, spending.vin);
+        assert_eq!(Spending::decode(&key, &spending.encode_value()).unwrap(), spending);
+        assert!(Spending::decode(&key[..44], &spending.encode_value()).is_err());
+    }
+
+    #[test]
+    fn test_activity_balance_and_history() {
+        let funded = Funding {
+            outpoint: OutPoint::new(Txid::all_zeros(), 0),
+            height: 5,
+            pos: 1,
+            value: 1000,
+            is_coinbase: false,
+        };
+        // An unconfirmed payment from it with change back
+        let change_txid = Txid::from_byte_array([1; 32]);
+        let change = Funding {
+            outpoint: OutPoint::new(change_txid, 1),
+            height: MEMPOOL_HEIGHT,
+            pos: 0,
+            value: 300,
+            is_coinbase: false,
+        };
+        let spent =
+            Spending { txid: change_txid, vin: 0, height: MEMPOOL_HEIGHT, pos: 0, prevout: funded.outpoint, value: 1000 };
+        let activity = ScriptActivity { fundings: vec![change, funded], spendings: vec![spent] };
+
+        assert_eq!(activity.balance(), (1000, -700));
+        assert_eq!(activity.unspent(), vec![change]);
+        let history = activity.history();
+        assert_eq!(history.len(), 2);
+        assert_eq!((history[0].txid, history[0].received, history[0].sent), (Txid::all_zeros(), 1000, 0));
+        assert_eq!((history[1].txid, history[1].received, history[1].sent), (change_txid, 300, 1000));
+        assert!(history[0].is_confirmed() && !history[1].is_confirmed());
+    }
+
+    #[test]
+    fn test_connect_and_disconnect() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = AddrIndex::open(dir.path()).unwrap();
+        let alice = ScriptBuf::from(vec![0x51]);
+        let bob = ScriptBuf::from(vec![0x52]);
+
+        let genesis = bitcoin::constants::genesis_block(Network::Regtest);
+        let mut block = genesis.clone();
+        block.header.prev_blockhash = genesis.block_hash();
+        block.txdata[0].output = vec![TxOut { value: Amount::from_sat(5000), script_pubkey: alice.clone() }];
+        let coinbase = OutPoint::new(block.txdata[0].compute_txid(), 0);
+        // Spends an output created earlier in the same block
+        let payment = spend(
+            coinbase,
+            vec![
+                TxOut { value: Amount::from_sat(3000), script_pubkey: bob.clone() },
+                TxOut { value: Amount::from_sat(1500), script_pubkey: alice.clone() },
+            ],
+        );
+        let payment_txid = payment.compute_txid();
+        block.txdata.push(payment);
+
+        index.connect_block(0, &genesis).unwrap();
+        index.connect_block(1, &block).unwrap();
+
+        let alice_activity = index.activity(&script_hash(&alice)).unwrap();
+        assert_eq!(alice_activity.fundings.len(), 2);
+        assert_eq!(alice_activity.spendings.len(), 1);
+        assert_eq!(alice_activity.balance(), (1500, 0));
+        assert_eq!(alice_activity.unspent()[0].outpoint, OutPoint::new(payment_txid, 1));
+        assert!(alice_activity.fundings[0].is_coinbase);
+        let bob_activity = index.activity(&script_hash(&bob)).unwrap();
+        assert_eq!(bob_activity.balance(), (3000, 0));
+        assert_eq!(index.get_output(&OutPoint::new(payment_txid, 0)).unwrap(), Some((script_hash(&bob), 3000)));
+        assert_eq!(index.header(0).unwrap(), Some(genesis.header));
+        assert_eq!(index.header(1).unwrap(), Some(block.header));
+
+        index.disconnect_block(1, &block).unwrap();
+        assert_eq!(index.header(1).unwrap(), None);
+        assert_eq!(index.activity(&script_hash(&alice)).unwrap(), ScriptActivity::default());
+        assert_eq!(index.activity(&script_hash(&bob)).unwrap(), ScriptActivity::default());
+        assert_eq!(index.get_output(&coinbase).unwrap(), None);
+        assert_eq!(index.best_block(), Some((0, genesis.block_hash())));
+    }
+}
@@ -0,0 +1,254 @@
+//! UTXO set index.
+//!
+//! Mirrors the unspent outputs of the active chain in RocksDB so that
+//! `gettxout`, REST `getutxos` and the mempool can look up coins, which the
+//! kernel API does not expose.
+//!
+//! Layout:
+//! - `c` + txid + vout (BE)  -> coin
+//! - `u` + height (BE)       -> coins spent by that block (for rollback)
+//! - `B`                     -> best block (height LE, hash)
+
+use anyhow::{anyhow, Result};
+use bitcoin::consensus::{deserialize, Decodable, Encodable};
+use bitcoin::hashes::Hash;
+use bitcoin::{Block, BlockHash, OutPoint, TxOut, VarInt};
+use parking_lot::RwLock;
+use rocksdb::{Options, WriteBatch, DB};
+use std::collections::HashSet;
+use std::path::Path;
+
+use super::BlockIndex;
+
+/// Height reported for outputs of unconfirmed transactions (Bitcoin Core: MEMPOOL_HEIGHT)
+pub const MEMPOOL_HEIGHT: u32 = 0x7FFF_FFFF;
+
+/// Undo data is kept this many blocks deep; deeper reorgs require a reindex
+const MAX_UNDO_DEPTH: i32 = 288;
+
+const PREFIX_COIN: u8 = b'c';
+const PREFIX_UNDO: u8 = b'u';
+const KEY_BEST: &[u8] = b"B";
+
+/// An unspent transaction output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coin {
+    pub output: TxOut,
+    pub height: u32,
+    pub is_coinbase: bool,
+}
+
+impl Coin {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(5 + self.output.script_pubkey.len() + 9);
+        buf.extend_from_slice(&self.height.to_le_bytes());
+        buf.push(self.is_coinbase as u8);
+        self.output.consensus_encode(&mut buf).expect("vec write");
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < 5 {
+            return Err(anyhow!("coin record too short"));
+        }
+        Ok(Self {
+            height: u32::from_le_bytes(data[0..4].try_into().unwrap()),
+            is_coinbase: data[4] != 0,
+            output: deserialize(&data[5..])?,
+        })
+    }
+}
+
+fn coin_key(outpoint: &OutPoint) -> [u8; 37] {
+    let mut key = [0u8; 37];
+    key[0] = PREFIX_COIN;
+    key[1..33].copy_from_slice(outpoint.txid.as_byte_array());
+    key[33..37].copy_from_slice(&outpoint.vout.to_be_bytes());
+    key
+}
+
+fn undo_key(height: i32) -> [u8; 5] {
+    let mut key = [0u8; 5];
+    key[0] = PREFIX_UNDO;
+    key[1..5].copy_from_slice(&(height as u32).to_be_bytes());
+    key
+}
+
+fn encode_undo(spent: &[(OutPoint, Vec<u8>)]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    VarInt(spent.len() as u64).consensus_encode(&mut buf).expect("vec write");
+    for (outpoint, coin) in spent {
+        outpoint.consensus_encode(&mut buf).expect("vec write");
+        coin.consensus_encode(&mut buf).expect("vec write");
+    }
+    buf
+}
+
+fn decode_undo(mut data: &[u8]) -> Result<Vec<(OutPoint, Vec<u8>)>> {
+    let count = VarInt::consensus_decode(&mut data)?.0;
+    let mut spent = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let outpoint = OutPoint::consensus_decode(&mut data)?;
+        let coin = Vec::<u8>::consensus_decode(&mut data)?;
+        spent.push((outpoint, coin));
+    }
+    Ok(spent)
+}
+
+/// UTXO set of the active chain, stored in RocksDB
+pub struct CoinsIndex {
+    db: DB,
+    best: RwLock<Option<(i32, BlockHash)>>,
+}
+
+impl CoinsIndex {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        let db = DB::open(&opts, path)?;
+
+        let best = match db.get(KEY_BEST)? {
+            Some(v) if v.len() == 36 => Some((
+                i32::from_le_bytes(v[0..4].try_into().unwrap()),
+                BlockHash::from_slice(&v[4..36])?,
+            )),
+            _ => None,
+        };
+        if let Some((height, hash)) = best {
+            eprintln!("[index] coins: opened {:?} at height {} ({})", path, height, hash);
+        }
+
+        Ok(Self { db, best: RwLock::new(best) })
+    }
+
+    pub fn get_coin(&self, outpoint: &OutPoint) -> Result<Option<Coin>> {
+        match self.db.get(coin_key(outpoint))? {
+            Some(v) => Ok(Some(Coin::decode(&v)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn best_value(height: i32, hash: &BlockHash) -> Vec<u8> {
+        let mut v = Vec::with_capacity(36);
+        v.extend_from_slice(&height.to_le_bytes());
+        v.extend_from_slice(hash.as_byte_array());
+        v
+    }
+}
+
+impl BlockIndex for CoinsIndex {
+    fn name(&self) -> &'static str {
+        "coins"
+    }
+
+    fn best_block(&self) -> Option<(i32, BlockHash)> {
+        *self.best.read()
+    }
+
+    fn connect_block(&self, height: i32, block: &Block) -> Result<()> {
+        let hash = block.block_hash();
+        let mut batch = WriteBatch::default();
+        let mut created: HashSet<OutPoint> = HashSet::new();
+        let mut spent: Vec<(OutPoint, Vec<u8>)> = Vec::new();
+
+        // The genesis coinbase is not spendable and never enters the UTXO set
+        if height > 0 {
+            for tx in &block.txdata {
+                let is_coinbase = tx.is_coinbase();
+                if !is_coinbase {
+                    for input in &tx.input {
+                        let prevout = input.previous_output;
+                        if !created.remove(&prevout) {
+                            match self.db.get(coin_key(&prevout))? {
+                                Some(coin) => spent.push((prevout, coin)),
+                                None => return Err(anyhow!("block {} spends unknown coin {}", hash, prevout)),
+                            }
+                        }
+                        batch.delete(coin_key(&prevout));
+                    }
+                }
+
+                let txid = tx.compute_txid();
+                for (vout, output) in tx.output.iter().enumerate() {
+                    if output.script_pubkey.is_op_return() {
+                        continue;
+                    }
+                    let outpoint = OutPoint::new(txid, vout as u32);
+                    let coin = Coin { output: output.clone(), height: height as u32, is_coinbase };
+                    batch.put(coin_key(&outpoint), coin.encode());
+                    created.insert(outpoint);
+                }
+            }
+        }
+
+        batch.put(undo_key(height), encode_undo(&spent));
+        if height > MAX_UNDO_DEPTH {
+            batch.delete(undo_key(height - MAX_UNDO_DEPTH));
+        }
+        batch.put(KEY_BEST, Self::best_value(height, &hash));
+        self.db.write(batch)?;
+
+        *self.best.write() = Some((height, hash));
+        Ok(())
+    }
+
+    fn disconnect_block(&self, height: i32, block: &Block) -> Result<()> {
+        let undo = self
+            .db
+            .get(undo_key(height))?
+            .ok_or_else(|| anyhow!("no undo data for height {}; reindex required", height))?;
+
+        let mut batch = WriteBatch::default();
+        for tx in &block.txdata {
+            let txid = tx.compute_txid();
+            for vout in 0..tx.output.len() {
+                batch.delete(coin_key(&OutPoint::new(txid, vout as u32)));
+            }
+        }
+        for (outpoint, coin) in decode_undo(&undo)? {
+            batch.put(coin_key(&outpoint), coin);
+        }
+        batch.delete(undo_key(height));
+
+        let new_best = if height > 0 { Some((height - 1, block.header.prev_blockhash)) } else { None };
+        match new_best {
+            Some((h, ref hash)) => batch.put(KEY_BEST, Self::best_value(h, hash)),
+            None => batch.delete(KEY_BEST),
+        }
+        self.db.write(batch)?;
+
+        *self.best.write() = new_best;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{Amount, ScriptBuf, Txid};
+
+    #[test]
+    fn test_coin_roundtrip() {
+        let coin = Coin {
+            output: TxOut { value: Amount::from_sat(50_000), script_pubkey: ScriptBuf::from(vec![0x51]) },
+            height: 123,
+            is_coinbase: true,
+        };
+        assert_eq!(Coin::decode(&coin.encode()).unwrap(), coin);
+        assert!(Coin::decode(&[1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_undo_roundtrip() {
+        let outpoint = OutPoint::new(Txid::all_zeros(), 7);
+        let spent = vec![(outpoint, vec![1, 2, 3]), (OutPoint::null(), vec![])];
+        assert_eq!(decode_undo(&encode_undo(&spent)).unwrap(), spent);
+    }
+
+    #[test]
+    fn test_coin_key_layout() {
+        let key = coin_key(&OutPoint::new(Txid::all_zeros(), 1));
+        assert_eq!(key[0], b'c');
+        assert_eq!(&key[33..], &[0, 0, 0, 1]);
+    }
+}
@@ -0,0 +1,164 @@
+//! Optional block indexes maintained next to the kernel's chainstate.
+//!
+//! libbitcoinkernel does not expose its UTXO set or a transaction index, so
+//! anything that needs them keeps its own database and follows the active
+//! chain: a background thread reads each newly connected block through the
+//! kernel and hands it to the index, and rolls blocks back on a reorg
+//! (Bitcoin Core: BaseIndex).
+
+pub mod addrindex;
+pub mod coins;
+pub mod txindex;
+
+use anyhow::Result;
+use bitcoin::{Block, BlockHash};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::kernel::Kernel;
+
+/// How often an index that caught up with the tip polls for new blocks
+const SYNC_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A database or notifier that is updated block by block along the active chain
+pub trait BlockIndex: Send + Sync + 'static {
+    fn name(&self) -> &'static str;
+
+    /// Last block applied to the index
+    fn best_block(&self) -> Option<(i32, BlockHash)>;
+
+    /// Apply the block at `height`, which extends `best_block()`
+    fn connect_block(&self, height: i32, block: &Block) -> Result<()>;
+
+    /// Undo the block at `height`, which is `best_block()`
+    fn disconnect_block(&self, height: i32, block: &Block) -> Result<()>;
+}
+
+/// Whether `index` has caught up with the kernel's tip
+pub fn is_synced(index: &dyn BlockIndex, kernel: &Kernel) -> bool {
+    index.best_block().map(|(_, hash)| hash) == kernel.get_best_block_hash().ok()
+}
+
+/// Give `index` up to `timeout` to catch up with the kernel's tip, so that a
+/// query right after a new block sees it (Bitcoin Core:
+/// BlockUntilSyncedToCurrentChain). Returns whether it did.
+pub fn wait_until_synced(index: &dyn BlockIndex, kernel: &Kernel, timeout: Duration) -> bool {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        if is_synced(index, kernel) {
+            return true;
+        }
+        if std::time::Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Background threads that keep indexes in sync with the kernel
+#[derive(Default)]
+pub struct IndexRunner {
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl IndexRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start(&mut self, kernel: Arc<Kernel>, index: Arc<dyn BlockIndex>) {
+        let stop = self.stop.clone();
+        let name = index.name();
+        let handle = std::thread::Builder::new()
+            .name(format!("index-{}", name))
+            .spawn(move || sync_loop(&kernel, index.as_ref(), &stop))
+            .expect("failed to spawn index thread");
+        self.threads.push(handle);
+    }
+
+    /// Stop all sync threads; must be called before the kernel is dropped
+    pub fn shutdown(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        for t in self.threads {
+            let _ = t.join();
+        }
+    }
+}
+
+fn sync_loop(kernel: &Kernel, index: &dyn BlockIndex, stop: &AtomicBool) {
+    eprintln!("[index] {} sync started", index.name());
+    let mut logged_synced = false;
+
+    while !stop.load(Ordering::SeqCst) {
+        match sync_step(kernel, index) {
+            Ok(true) => {
+                logged_synced = false;
+                if let Some((height, _)) = index.best_block() {
+                    if height % 10_000 == 0 {
+                        eprintln!("[index] {} synced to height {}", index.name(), height);
+                    }
+                }
+            }
+            Ok(false) => {
+                if !logged_synced {
+                    if let Some((height, hash)) = index.best_block() {
+                        eprintln!("[index] {} is up to date at height {} ({})", index.name(), height, hash);
+                    }
+                    logged_synced = true;
+                }
+                std::thread::sleep(SYNC_POLL_INTERVAL);
+            }
+            Err(e) => {
+                eprintln!("[index] {} sync error: {:#}", index.name(), e);
+                std::thread::sleep(Duration::from_secs(5));
+            }
+        }
+    }
+    eprintln!("[index] {} sync stopped", index.name());
+}
+
+/// Connect or disconnect one block; returns false when already at the tip
+fn sync_step(kernel: &Kernel, index: &dyn BlockIndex) -> Result<bool> {
+    let tip = kernel.get_height()?;
+    if tip < 0 {
+        return Ok(false);
+    }
+
+    let next_height = match index.best_block() {
+        None => 0,
+        Some((height, hash)) => {
+            // Our best block left the active chain: roll it back first
+            let on_chain = height <= tip && kernel.get_block_hash(height).ok() == Some(hash);
+            if !on_chain {
+                let block = kernel
+                    .get_block(&hash)?
+                    .ok_or_else(|| anyhow::anyhow!("block {} needed for rollback is not available", hash))?;
+                eprintln!("[index] {} disconnecting block {} at height {}", index.name(), hash, height);
+                index.disconnect_block(height, &block)?;
+                return Ok(true);
+            }
+            if height >= tip {
+                return Ok(false);
+            }
+            height + 1
+        }
+    };
+
+    let hash = kernel.get_block_hash(next_height)?;
+    let block = kernel
+        .get_block(&hash)?
+        .ok_or_else(|| anyhow::anyhow!("block {} at height {} is not available", hash, next_height))?;
+
+    // The tip may have moved under us; retry on the next step
+    if let Some((_, best)) = index.best_block() {
+        if block.header.prev_blockhash != best {
+            return Ok(true);
+        }
+    }
+
+    index.connect_block(next_height, &block)?;
+    Ok(true)
+}
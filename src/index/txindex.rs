@@ -0,0 +1,200 @@
+//! Transaction index (Bitcoin Core: index/txindex.cpp).
+//!
+//! Maps the txid of every transaction in the active chain to the block it
+//! was confirmed in, so `getrawtransaction` can find transactions that left
+//! the mempool. Blocks themselves stay in the kernel's block files; a lookup
+//! reads the block back and takes the transaction at its position.
+//!
+//! Layout:
+//! - `t` + txid -> height (LE), block hash, position in the block (LE)
+//! - `B`        -> best block (height LE, hash)
+
+use anyhow::{anyhow, Result};
+use bitcoin::hashes::Hash;
+use bitcoin::{Block, BlockHash, Transaction, Txid};
+use parking_lot::RwLock;
+use rocksdb::{Options, WriteBatch, DB};
+use std::path::Path;
+
+use super::BlockIndex;
+use crate::kernel::Kernel;
+
+const PREFIX_TX: u8 = b't';
+const KEY_BEST: &[u8] = b"B";
+
+/// Where a transaction was confirmed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxLocation {
+    pub height: i32,
+    pub block_hash: BlockHash,
+    /// Index of the transaction in the block, the coinbase being 0
+    pub position: u32,
+}
+
+impl TxLocation {
+    fn encode(&self) -> [u8; 40] {
+        let mut buf = [0u8; 40];
+        buf[0..4].copy_from_slice(&self.height.to_le_bytes());
+        buf[4..36].copy_from_slice(self.block_hash.as_byte_array());
+        buf[36..40].copy_from_slice(&self.position.to_le_bytes());
+        buf
+    }
+
+    fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() != 40 {
+            return Err(anyhow!("bad txindex record length {}", data.len()));
+        }
+        Ok(Self {
+            height: i32::from_le_bytes(data[0..4].try_into().unwrap()),
+            block_hash: BlockHash::from_slice(&data[4..36])?,
+            position: u32::from_le_bytes(data[36..40].try_into().unwrap()),
+        })
+    }
+}
+
+fn tx_key(txid: &Txid) -> [u8; 33] {
+    let mut key = [0u8; 33];
+    key[0] = PREFIX_TX;
+    key[1..33].copy_from_slice(txid.as_byte_array());
+    key
+}
+
+/// txid -> block position for the active chain, stored in RocksDB
+pub struct TxIndex {
+    db: DB,
+    best: RwLock<Option<(i32, BlockHash)>>,
+}
+
+impl TxIndex {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        let db = DB::open(&opts, path)?;
+
+        let best = match db.get(KEY_BEST)? {
+            Some(v) if v.len() == 36 => Some((
+                i32::from_le_bytes(v[0..4].try_into().unwrap()),
+                BlockHash::from_slice(&v[4..36])?,
+            )),
+            _ => None,
+        };
+        match best {
+            Some((height, hash)) => eprintln!("[index] txindex: opened {:?} at height {} ({})", path, height, hash),
+            None => eprintln!("[index] txindex: opened {:?}, indexing from genesis", path),
+        }
+        Ok(Self { db, best: RwLock::new(best) })
+    }
+
+    pub fn find(&self, txid: &Txid) -> Result<Option<TxLocation>> {
+        match self.db.get(tx_key(txid))? {
+            Some(v) => Ok(Some(TxLocation::decode(&v)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The transaction and the block it is in, read back through the kernel
+    pub fn get_transaction(&self, kernel: &Kernel, txid: &Txid) -> Result<Option<(Transaction, TxLocation)>> {
+        let Some(location) = self.find(txid)? else {
+            return Ok(None);
+        };
+        let block = kernel
+            .get_block(&location.block_hash)?
+            .ok_or_else(|| anyhow!("block {} of indexed tx {} is not available", location.block_hash, txid))?;
+        match block.txdata.into_iter().nth(location.position as usize) {
+            Some(tx) if tx.compute_txid() == *txid => Ok(Some((tx, location))),
+            _ => Err(anyhow!("txindex entry of {} does not match block {}", txid, location.block_hash)),
+        }
+    }
+
+    fn best_value(height: i32, hash: &BlockHash) -> Vec<u8> {
+        let mut v = Vec::with_capacity(36);
+        v.extend_from_slice(&height.to_le_bytes());
+        v.extend_from_slice(hash.as_byte_array());
+        v
+    }
+}
+
+impl BlockIndex for TxIndex {
+    fn name(&self) -> &'static str {
+        "txindex"
+    }
+
+    fn best_block(&self) -> Option<(i32, BlockHash)> {
+        *self.best.read()
+    }
+
+    fn connect_block(&self, height: i32, block: &Block) -> Result<()> {
+        let block_hash = block.block_hash();
+        let mut batch = WriteBatch::default();
+        // The genesis coinbase is not an ordinary transaction and is not indexed
+        if height > 0 {
+            for (position, tx) in block.txdata.iter().enumerate() {
+                let location = TxLocation { height, block_hash, position: position as u32 };
+                batch.put(tx_key(&tx.compute_txid()), location.encode());
+            }
+        }
+        batch.put(KEY_BEST, Self::best_value(height, &block_hash));
+        self.db.write(batch)?;
+
+        *self.best.write() = Some((height, block_hash));
+        Ok(())
+    }
+
+    fn disconnect_block(&self, height: i32, block: &Block) -> Result<()> {
+        let block_hash = block.block_hash();
+        let mut batch = WriteBatch::default();
+        for tx in &block.txdata {
+            let txid = tx.compute_txid();
+            // A duplicate txid (BIP30) may point at an earlier block; leave it
+            if self.find(&txid)?.is_some_and(|location| location.block_hash == block_hash) {
+                batch.delete(tx_key(&txid));
+            }
+        }
+
+        let new_best = if height > 0 { Some((height - 1, block.header.prev_blockhash)) } else { None };
+        match new_best {
+            Some((h, ref hash)) => batch.put(KEY_BEST, Self::best_value(h, hash)),
+            None => batch.delete(KEY_BEST),
+        }
+        self.db.write(batch)?;
+
+        *self.best.write() = new_best;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::Network;
+
+    #[test]
+    fn test_location_roundtrip() {
+        let location = TxLocation { height: 840_000, block_hash: BlockHash::all_zeros(), position: 17 };
+        assert_eq!(TxLocation::decode(&location.encode()).unwrap(), location);
+        assert!(TxLocation::decode(&[0; 36]).is_err());
+    }
+
+    #[test]
+    fn test_connect_and_disconnect() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = TxIndex::open(dir.path()).unwrap();
+
+        let genesis = bitcoin::constants::genesis_block(Network::Regtest);
+        let mut block = genesis.clone();
+        block.header.prev_blockhash = genesis.block_hash();
+        let txid = block.txdata[0].compute_txid();
+
+        index.connect_block(0, &genesis).unwrap();
+        assert_eq!(index.find(&txid).unwrap(), None);
+
+        index.connect_block(1, &block).unwrap();
+        let location = index.find(&txid).unwrap().unwrap();
+        assert_eq!((location.height, location.block_hash, location.position), (1, block.block_hash(), 0));
+        assert_eq!(index.best_block(), Some((1, block.block_hash())));
+
+        index.disconnect_block(1, &block).unwrap();
+        assert_eq!(index.find(&txid).unwrap(), None);
+        assert_eq!(index.best_block(), Some((0, genesis.block_hash())));
+    }
+}
@@ -0,0 +1,96 @@
+//! Least-recently-used cache of block headers by hash.
+//!
+//! The kernel only hands out whole blocks, so without it every median time
+//! past (11 headers) and every retarget walk (up to 2016 headers) would read
+//! and deserialize that many full blocks from disk. A header never changes
+//! for a given hash, so entries are only ever evicted, never invalidated.
+
+use bitcoin::block::Header;
+use bitcoin::BlockHash;
+use std::collections::{BTreeMap, HashMap};
+
+/// Enough for a full retarget interval plus the median-time-past window
+pub const HEADER_CACHE_SIZE: usize = 4096;
+
+pub struct HeaderCache {
+    capacity: usize,
+    /// Header and the tick it was last used at
+    entries: HashMap<BlockHash, (Header, u64)>,
+    /// Last-use tick -> hash, oldest first
+    order: BTreeMap<u64, BlockHash>,
+    tick: u64,
+}
+
+impl HeaderCache {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), entries: HashMap::new(), order: BTreeMap::new(), tick: 0 }
+    }
+
+    pub fn get(&mut self, hash: &BlockHash) -> Option<Header> {
+        self.tick += 1;
+        let (header, used) = self.entries.get_mut(hash)?;
+        self.order.remove(used);
+        *used = self.tick;
+        self.order.insert(self.tick, *hash);
+        Some(*header)
+    }
+
+    pub fn insert(&mut self, hash: BlockHash, header: Header) {
+        self.tick += 1;
+        if let Some((_, used)) = self.entries.insert(hash, (header, self.tick)) {
+            self.order.remove(&used);
+        }
+        self.order.insert(self.tick, hash);
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::block::Version;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{CompactTarget, TxMerkleNode};
+
+    fn header(time: u32) -> Header {
+        Header {
+            version: Version::from_consensus(4),
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        }
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = HeaderCache::new(2);
+        let (a, b, c) = (header(1), header(2), header(3));
+        cache.insert(a.block_hash(), a);
+        cache.insert(b.block_hash(), b);
+        // Touching `a` leaves `b` as the oldest entry
+        assert_eq!(cache.get(&a.block_hash()), Some(a));
+        cache.insert(c.block_hash(), c);
+
+        assert_eq!(cache.entries.len(), 2);
+        assert_eq!(cache.get(&b.block_hash()), None);
+        assert_eq!(cache.get(&a.block_hash()), Some(a));
+        assert_eq!(cache.get(&c.block_hash()), Some(c));
+    }
+
+    #[test]
+    fn test_reinsert_keeps_one_entry() {
+        let mut cache = HeaderCache::new(2);
+        let a = header(1);
+        cache.insert(a.block_hash(), a);
+        cache.insert(a.block_hash(), a);
+        assert_eq!(cache.entries.len(), 1);
+        assert_eq!(cache.order.len(), 1);
+    }
+}
@@ -1,9 +1,14 @@
+mod headers;
+
+use crate::chainparams::default_signet_challenge;
 use crate::ffi;
 use anyhow::Result;
 use bitcoin::hashes::Hash;
-use bitcoin::BlockHash;
+use bitcoin::{BlockHash, Script, ScriptBuf};
+use headers::{HeaderCache, HEADER_CACHE_SIZE};
+use parking_lot::Mutex;
 use std::ffi::{c_void, CStr, CString};
-use std::os::raw::c_char;
+use std::os::raw::{c_char, c_int};
 use std::path::PathBuf;
 
 // Chain type constants (matching bitcoinkernel.h)
@@ -13,12 +18,29 @@ const CHAIN_TESTNET4: u8 = 2;
 const CHAIN_SIGNET: u8 = 3;
 const CHAIN_REGTEST: u8 = 4;
 
+// Script verification flags (matching bitcoinkernel.h btck_ScriptVerificationFlags)
+const SCRIPT_VERIFY_P2SH: u32 = 1 << 0;
+const SCRIPT_VERIFY_DERSIG: u32 = 1 << 2;
+const SCRIPT_VERIFY_NULLDUMMY: u32 = 1 << 4;
+const SCRIPT_VERIFY_CHECKLOCKTIMEVERIFY: u32 = 1 << 9;
+const SCRIPT_VERIFY_CHECKSEQUENCEVERIFY: u32 = 1 << 10;
+const SCRIPT_VERIFY_WITNESS: u32 = 1 << 11;
+const SCRIPT_VERIFY_TAPROOT: u32 = 1 << 17;
+const SCRIPT_VERIFY_ALL: u32 = SCRIPT_VERIFY_P2SH
+    | SCRIPT_VERIFY_DERSIG
+    | SCRIPT_VERIFY_NULLDUMMY
+    | SCRIPT_VERIFY_CHECKLOCKTIMEVERIFY
+    | SCRIPT_VERIFY_CHECKSEQUENCEVERIFY
+    | SCRIPT_VERIFY_WITNESS
+    | SCRIPT_VERIFY_TAPROOT;
+
 type CChainstateManager = ffi::btck_ChainstateManager;
 type CChain = ffi::btck_Chain;
 type CContext = ffi::btck_Context;
 type CChainParameters = ffi::btck_ChainParameters;
 type CContextOptions = ffi::btck_ContextOptions;
 type CChainstateManagerOptions = ffi::btck_ChainstateManagerOptions;
+type CBlockTreeEntry = ffi::btck_BlockTreeEntry;
 
 /// Kernel log callback: output kernel logs to stderr
 unsafe extern "C" fn log_cb(_ud: *mut c_void, msg: *const c_char, _len: usize) {
@@ -29,18 +51,38 @@ unsafe extern "C" fn log_cb(_ud: *mut c_void, msg: *const c_char, _len: usize) {
     }
 }
 
+/// btck_block_to_bytes writer: append to the Vec<u8> passed as user data
+unsafe extern "C" fn write_bytes_cb(bytes: *const c_void, size: usize, userdata: *mut c_void) -> c_int {
+    let buf = &mut *(userdata as *mut Vec<u8>);
+    buf.extend_from_slice(std::slice::from_raw_parts(bytes as *const u8, size));
+    0
+}
+
+/// Position of a block in the block index
+#[derive(Debug, Clone)]
+pub struct BlockInfo {
+    pub hash: BlockHash,
+    pub height: i32,
+    pub prev: Option<BlockHash>,
+    /// Whether the block is part of the active chain
+    pub in_active_chain: bool,
+}
+
 /// Kernel wrapper for libbitcoinkernel
 pub struct Kernel {
     ctx: *mut CContext,
     chain_params: *mut CChainParameters,
     pub chainman: *mut CChainstateManager,
+    network: bitcoin::Network,
+    signet_challenge: Option<ScriptBuf>,
+    headers: Mutex<HeaderCache>,
 }
 
 unsafe impl Send for Kernel {}
 unsafe impl Sync for Kernel {}
 
 impl Kernel {
-    pub fn new(chain: &str, datadir: &PathBuf, blocksdir: &PathBuf) -> Result<Self> {
+    pub fn new(chain: &str, datadir: &PathBuf, blocksdir: &PathBuf, signet_challenge: Option<&Script>) -> Result<Self> {
         eprintln!("[kernel] Initializing kernel for chain: {}", chain);
         eprintln!("[kernel] Data directory: {:?}", datadir);
         eprintln!("[kernel] Blocks directory: {:?}", blocksdir);
@@ -74,6 +116,17 @@ impl Kernel {
             _ => CHAIN_REGTEST,
         };
 
+        // btck_chain_parameters_create only takes a chain type, so the kernel
+        // checks every signet block against the default challenge
+        let signet_challenge = (chain_type == CHAIN_SIGNET)
+            .then(|| signet_challenge.map(Script::to_owned).unwrap_or_else(default_signet_challenge));
+        if signet_challenge.as_ref().is_some_and(|c| *c != default_signet_challenge()) {
+            anyhow::bail!(
+                "kernel cannot validate custom signet: libbitcoinkernel's chain parameters only \
+                 support the default signet challenge, so -signetchallenge must be left unset"
+            );
+        }
+
         eprintln!("[kernel] Creating context options...");
         let ctx_opts = unsafe { ffi::btck_context_options_create() };
         if ctx_opts.is_null() {
@@ -191,7 +244,15 @@ impl Kernel {
         // The "already known" messages during sync are NORMAL and CORRECT behavior.
         // They indicate blocks are already in the index and don't need reprocessing.
 
-        let kernel = Self { ctx, chain_params, chainman };
+        let network = match chain_type {
+            CHAIN_MAIN => bitcoin::Network::Bitcoin,
+            CHAIN_TESTNET => bitcoin::Network::Testnet,
+            CHAIN_TESTNET4 => bitcoin::Network::Testnet4,
+            CHAIN_SIGNET => bitcoin::Network::Signet,
+            _ => bitcoin::Network::Regtest,
+        };
+        let headers = Mutex::new(HeaderCache::new(HEADER_CACHE_SIZE));
+        let kernel = Self { ctx, chain_params, chainman, network, signet_challenge, headers };
 
         // Initialize or re-process genesis block
         // Bitcoin Core does this in LoadBlockIndex()
@@ -342,6 +403,136 @@ impl Kernel {
         }
     }
 
+    /// Network this kernel validates
+    pub fn network(&self) -> bitcoin::Network {
+        self.network
+    }
+
+    /// Block challenge on signet (BIP 325)
+    pub fn signet_challenge(&self) -> Option<&Script> {
+        self.signet_challenge.as_deref()
+    }
+
+    /// Chain name as reported by Bitcoin Core (getblockchaininfo "chain")
+    pub fn chain_name(&self) -> &'static str {
+        match self.network {
+            bitcoin::Network::Bitcoin => "main",
+            bitcoin::Network::Testnet => "test",
+            bitcoin::Network::Testnet4 => "testnet4",
+            bitcoin::Network::Signet => "signet",
+            bitcoin::Network::Regtest => "regtest",
+        }
+    }
+
+    fn entry_hash(entry: *const CBlockTreeEntry) -> Option<BlockHash> {
+        unsafe {
+            let hash_ptr = ffi::btck_block_tree_entry_get_block_hash(entry);
+            if hash_ptr.is_null() {
+                return None;
+            }
+            let hash_bytes = std::slice::from_raw_parts(hash_ptr as *const u8, 32);
+            Some(BlockHash::from_byte_array(hash_bytes.try_into().unwrap()))
+        }
+    }
+
+    fn entry_by_hash(&self, hash: &BlockHash) -> Option<*const CBlockTreeEntry> {
+        let c_hash = ffi::btck_BlockHash { hash: hash.to_byte_array() };
+        let entry = unsafe {
+            ffi::btck_chainstate_manager_get_block_tree_entry_by_hash(self.chainman, &c_hash)
+        };
+        (!entry.is_null()).then_some(entry)
+    }
+
+    /// Look up a block in the block index (active chain or not)
+    pub fn get_block_info(&self, hash: &BlockHash) -> Result<Option<BlockInfo>> {
+        let Some(entry) = self.entry_by_hash(hash) else {
+            return Ok(None);
+        };
+        unsafe {
+            let chain = ffi::btck_chainstate_manager_get_active_chain(self.chainman);
+            let in_active_chain = !chain.is_null() && ffi::btck_chain_contains(chain, entry) == 1;
+            let prev_entry = ffi::btck_block_tree_entry_get_previous(entry);
+            let prev = if prev_entry.is_null() { None } else { Self::entry_hash(prev_entry) };
+
+            Ok(Some(BlockInfo {
+                hash: *hash,
+                height: ffi::btck_block_tree_entry_get_height(entry),
+                prev,
+                in_active_chain,
+            }))
+        }
+    }
+
+    /// Read a serialized block from disk; None if unknown or not stored
+    pub fn read_block(&self, hash: &BlockHash) -> Result<Option<Vec<u8>>> {
+        let Some(entry) = self.entry_by_hash(hash) else {
+            return Ok(None);
+        };
+        unsafe {
+            let block = ffi::btck_block_read(self.chainman, entry);
+            if block.is_null() {
+                return Ok(None);
+            }
+            let mut raw: Vec<u8> = Vec::new();
+            let rc = ffi::btck_block_to_bytes(
+                block,
+                Some(write_bytes_cb),
+                &mut raw as *mut Vec<u8> as *mut c_void,
+            );
+            ffi::btck_block_destroy(block);
+            if rc != 0 {
+                anyhow::bail!("btck_block_to_bytes failed: rc={}", rc);
+            }
+            Ok(Some(raw))
+        }
+    }
+
+    /// Read and deserialize a block from disk
+    pub fn get_block(&self, hash: &BlockHash) -> Result<Option<bitcoin::Block>> {
+        match self.read_block(hash)? {
+            Some(raw) => Ok(Some(bitcoin::consensus::deserialize(&raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Header of a stored block. Served from the header cache when possible,
+    /// otherwise decoded from the first 80 bytes on disk and cached.
+    pub fn get_block_header(&self, hash: &BlockHash) -> Result<Option<bitcoin::block::Header>> {
+        if let Some(header) = self.headers.lock().get(hash) {
+            return Ok(Some(header));
+        }
+        match self.read_block(hash)? {
+            Some(raw) if raw.len() >= 80 => {
+                let header: bitcoin::block::Header = bitcoin::consensus::deserialize(&raw[..80])?;
+                self.headers.lock().insert(*hash, header);
+                Ok(Some(header))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Median timestamp of a block and its 10 predecessors
+    /// (Bitcoin Core: CBlockIndex::GetMedianTimePast)
+    pub fn median_time_past(&self, hash: &BlockHash) -> Result<Option<u32>> {
+        let mut times = Vec::with_capacity(11);
+        let mut next = Some(*hash);
+        while let Some(hash) = next {
+            if times.len() == 11 {
+                break;
+            }
+            let Some(header) = self.get_block_header(&hash)? else {
+                break;
+            };
+            times.push(header.time);
+            next = (header.prev_blockhash != BlockHash::all_zeros()).then_some(header.prev_blockhash);
+        }
+        if times.is_empty() {
+            return Ok(None);
+        }
+        times.sort_unstable();
+        Ok(Some(times[times.len() / 2]))
+    }
+
     pub fn import_blocks(&self, paths: &[String]) -> Result<i32> {
         let c_paths: Vec<CString> = paths
             .iter()
@@ -363,8 +554,6 @@ impl Kernel {
     }
 
     pub fn process_block(&self, raw: &[u8]) -> Result<()> {
-        use std::os::raw::c_int;
-
         // Get height BEFORE processing
         let height_before = self.active_height().unwrap_or(-1);
 
@@ -524,18 +713,88 @@ impl Kernel {
             }
         }
 
-        // TODO: Full consensus validation through Kernel FFI
-        // This requires additional FFI bindings for:
-        // - btck_chainstate_manager_process_transaction
-        // - btck_transaction_check_inputs (UTXO validation)
-        // - Script execution and signature validation
-        //
-        // For now, we only do basic structural checks above.
-        // The mempool will do additional policy checks.
+        // Scripts and signatures need the spent outputs: see verify_scripts
 
         Ok((true, None))
     }
 
+    /// Run every input's script against the output it spends, with all
+    /// consensus flags (Bitcoin Core: CheckInputScripts)
+    pub fn verify_scripts(
+        &self,
+        tx: &bitcoin::Transaction,
+        spent_outputs: &[bitcoin::TxOut],
+    ) -> Result<(bool, Option<String>)> {
+        if spent_outputs.len() != tx.input.len() {
+            anyhow::bail!("{} spent outputs for {} inputs", spent_outputs.len(), tx.input.len());
+        }
+
+        let raw = bitcoin::consensus::serialize(tx);
+        let tx_ptr = unsafe { ffi::btck_transaction_create(raw.as_ptr() as *const c_void, raw.len()) };
+        if tx_ptr.is_null() {
+            anyhow::bail!("btck_transaction_create failed");
+        }
+
+        let scripts: Vec<*mut ffi::btck_ScriptPubkey> = spent_outputs
+            .iter()
+            .map(|out| {
+                let script = out.script_pubkey.as_bytes();
+                unsafe { ffi::btck_script_pubkey_create(script.as_ptr() as *const c_void, script.len()) }
+            })
+            .collect();
+        let outputs: Vec<*const ffi::btck_TransactionOutput> = scripts
+            .iter()
+            .zip(spent_outputs)
+            .map(|(script, out)| {
+                if script.is_null() {
+                    return std::ptr::null();
+                }
+                unsafe { ffi::btck_transaction_output_create(*script, out.value.to_sat() as i64) as *const _ }
+            })
+            .collect();
+
+        let result = if outputs.iter().any(|o| o.is_null()) {
+            Err(anyhow::anyhow!("btck_transaction_output_create failed"))
+        } else {
+            let mut result = Ok((true, None));
+            for (index, (script, out)) in scripts.iter().zip(spent_outputs).enumerate() {
+                let mut status: ffi::btck_ScriptVerifyStatus = 0;
+                let valid = unsafe {
+                    ffi::btck_script_pubkey_verify(
+                        *script,
+                        out.value.to_sat() as i64,
+                        tx_ptr,
+                        outputs.as_ptr() as *mut *const ffi::btck_TransactionOutput,
+                        outputs.len(),
+                        index as u32,
+                        SCRIPT_VERIFY_ALL as ffi::btck_ScriptVerificationFlags,
+                        &mut status,
+                    )
+                };
+                if valid != 1 {
+                    result = if status != 0 {
+                        Err(anyhow::anyhow!("btck_script_pubkey_verify failed with status {}", status))
+                    } else {
+                        Ok((false, Some(format!("mandatory-script-verify-flag-failed (input {})", index))))
+                    };
+                    break;
+                }
+            }
+            result
+        };
+
+        unsafe {
+            for output in outputs.into_iter().filter(|o| !o.is_null()) {
+                ffi::btck_transaction_output_destroy(output as *mut _);
+            }
+            for script in scripts.into_iter().filter(|s| !s.is_null()) {
+                ffi::btck_script_pubkey_destroy(script);
+            }
+            ffi::btck_transaction_destroy(tx_ptr);
+        }
+        result
+    }
+
     /// Check if a transaction's inputs are available in UTXO set
     /// Returns (all_available, missing_count)
     pub fn check_tx_inputs(&self, _tx: &bitcoin::Transaction) -> Result<(bool, usize)> {
@@ -4,23 +4,47 @@ use std::{
     net::SocketAddr,
     path::PathBuf,
     sync::Arc,
+    time::Duration,
 };
 
 mod addrman;     // Address manager
 mod chainparams; // Chain parameters (checkpoints, AssumeValid, etc.)
+mod descriptor;  // Single-script output descriptors
+mod electrum;    // Electrum protocol server
 mod ffi;         // bindgen이 생성한 btck_* FFI
+mod index;       // Block indexes (UTXO set)
 mod kernel;      // Kernel wrapper
 mod mempool;     // Mempool 구현
+#[cfg(feature = "mining")]
+mod mining;      // Block templates, coinbase, PoW
 // mod network;  // Network 구현 (temporarily disabled)
 mod p2p;         // P2P 구현
 mod rpc;         // RPC 서버
 mod seeds;       // DNS seeds
+#[cfg(feature = "mining")]
+mod signet;      // BIP 325 block signing
+#[cfg(feature = "mining")]
+mod stratum;     // Stratum v1 job server
+#[cfg(feature = "zmq")]
+mod zmq;         // ZMQ notifications
 
 use kernel::Kernel;
-use mempool::{Mempool, MempoolPolicy};
+use mempool::{fees, persist, Mempool, MempoolPolicy};
+
+/// How often locally submitted transactions no peer has fetched are announced again
+const UNBROADCAST_RETRY: Duration = Duration::from_secs(10 * 60);
+/// How often loading mempool.dat reports that it is still waiting for the coins index
+const COINS_SYNC_WAIT: Duration = Duration::from_secs(60);
 
 #[derive(Parser, Debug, Clone)]
-#[command(name = "btck-mini-node", version, about = "Mini node powered by libbitcoinkernel")]
+#[command(
+    name = "btck-mini-node",
+    version,
+    about = "Mini node powered by libbitcoinkernel",
+    after_help = "The node always keeps a full copy of the UTXO set in <datadir>/indexes/coins, because the \
+mempool looks up transaction inputs there. It needs at least as much disk again as the kernel's chainstate \
+(well over 10 GB on mainnet), and every block is applied to it a second time during initial block download."
+)]
 struct Args {
     /// chain: mainnet/testnet/signet/regtest
     #[arg(long, default_value = "signet")]
@@ -47,6 +71,71 @@ struct Args {
     /// optional: peers to connect (can be repeated)
     #[arg(long)]
     peer: Vec<String>,
+
+    /// signet only: hex script blocks must satisfy. The kernel only validates the global
+    /// signet's challenge, so any other value stops the node at startup
+    #[arg(long)]
+    signetchallenge: Option<String>,
+
+    /// signet only: seed node as host[:port], replacing the default seeds (can be repeated)
+    #[arg(long)]
+    signetseednode: Vec<String>,
+
+    /// RPC username (with --rpcpassword); a .cookie file is used otherwise
+    #[arg(long)]
+    rpcuser: Option<String>,
+
+    /// RPC password (with --rpcuser)
+    #[arg(long)]
+    rpcpassword: Option<String>,
+
+    /// RPC credentials as <user>:<salt>$<hash> (can be repeated, see share/rpcauth)
+    #[arg(long)]
+    rpcauth: Vec<String>,
+
+    /// location of the RPC auth cookie (default: <datadir>/.cookie)
+    #[arg(long)]
+    rpccookiefile: Option<PathBuf>,
+
+    /// allowed RPC methods per user as <user>:<method>,<method> (can be repeated)
+    #[arg(long)]
+    rpcwhitelist: Vec<String>,
+
+    /// users without a --rpcwhitelist entry may call nothing (default: on if any whitelist is set)
+    #[arg(long)]
+    rpcwhitelistdefault: Option<bool>,
+
+    /// serve the unauthenticated REST interface under /rest/ on the RPC port
+    #[arg(long)]
+    rest: bool,
+
+    /// accept replacements of transactions that do not signal BIP125 replaceability
+    #[arg(long)]
+    mempoolfullrbf: bool,
+
+    /// maintain a txid -> block index in the background, for getrawtransaction
+    #[arg(long)]
+    txindex: bool,
+
+    /// maintain a scripthash -> outputs and spends index, for getaddress* RPCs
+    #[arg(long)]
+    addrindex: bool,
+
+    /// serve the Electrum protocol on <address>, e.g. 127.0.0.1:50001 (implies --txindex and --addrindex)
+    #[arg(long, value_name = "ADDRESS")]
+    electrum: Option<String>,
+
+    /// save the mempool to <datadir>/mempool.dat on shutdown and reload it on startup
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    persistmempool: bool,
+
+    #[cfg(feature = "zmq")]
+    #[command(flatten)]
+    zmq: zmq::ZmqArgs,
+
+    #[cfg(feature = "mining")]
+    #[command(flatten)]
+    stratum: stratum::StratumArgs,
 }
 
 // ------------------------------
@@ -68,8 +157,40 @@ async fn main() -> Result<()> {
     eprintln!("[main]   Data:   {:?}", args.datadir);
     eprintln!("[main]   Blocks: {:?}", blocksdir);
 
+    let signet_challenge = args
+        .signetchallenge
+        .as_deref()
+        .map(|hex| bitcoin::ScriptBuf::from_hex(hex).map_err(|e| anyhow::anyhow!("invalid -signetchallenge: {e}")))
+        .transpose()?;
+    if args.chain != "signet" && (signet_challenge.is_some() || !args.signetseednode.is_empty()) {
+        eprintln!("[main] --signetchallenge and --signetseednode only apply to signet; ignoring");
+    }
+
     // 커널 초기화
-    let kernel = Arc::new(Kernel::new(&args.chain, &args.datadir, &blocksdir)?);
+    let kernel = Arc::new(Kernel::new(&args.chain, &args.datadir, &blocksdir, signet_challenge.as_deref())?);
+
+    // UTXO 인덱스 (gettxout, REST getutxos, mempool 수수료 계산)
+    let coins = Arc::new(index::coins::CoinsIndex::open(&args.datadir.join("indexes").join("coins"))?);
+    let mut index_runner = index::IndexRunner::new();
+    index_runner.start(kernel.clone(), coins.clone());
+
+    // (옵션) 트랜잭션 인덱스 (getrawtransaction)
+    let txindex = if args.txindex || args.electrum.is_some() {
+        let txindex = Arc::new(index::txindex::TxIndex::open(&args.datadir.join("indexes").join("txindex"))?);
+        index_runner.start(kernel.clone(), txindex.clone());
+        Some(txindex)
+    } else {
+        None
+    };
+
+    // (옵션) 주소 인덱스 (getaddressbalance, getaddressutxos, getaddresshistory)
+    let addrindex = if args.addrindex || args.electrum.is_some() {
+        let addrindex = Arc::new(index::addrindex::AddrIndex::open(&args.datadir.join("indexes").join("addrindex"))?);
+        index_runner.start(kernel.clone(), addrindex.clone());
+        Some(addrindex)
+    } else {
+        None
+    };
 
     // Mempool 초기화
     let policy = match args.chain.as_str() {
@@ -77,9 +198,21 @@ async fn main() -> Result<()> {
         "testnet" | "signet" => MempoolPolicy::testnet(),
         _ => MempoolPolicy::regtest(),
     };
-    let mempool = Arc::new(Mempool::with_kernel(policy, kernel.clone()));
+    let policy = MempoolPolicy { full_rbf: args.mempoolfullrbf, ..policy };
+    let mut mempool = Mempool::with_kernel(policy, kernel.clone());
+    mempool.set_coins(coins.clone());
+    let mempool = Arc::new(mempool);
     eprintln!("[mempool] initialized with policy: {}", args.chain);
 
+    // ZMQ 알림 (--zmqpub*)
+    #[cfg(feature = "zmq")]
+    let zmq_notifier = zmq::ZmqNotifier::start(&args.zmq, &kernel).await?;
+    #[cfg(feature = "zmq")]
+    if let Some(notifier) = &zmq_notifier {
+        notifier.spawn_mempool_listener(&mempool);
+        index_runner.start(kernel.clone(), notifier.clone());
+    }
+
     // Graceful shutdown signal
     let shutdown_signal = async {
         tokio::signal::ctrl_c()
@@ -104,6 +237,51 @@ async fn main() -> Result<()> {
         }
     }
 
+    // fee_estimates.dat 복원
+    let fee_estimates_path = args.datadir.join(fees::FEE_ESTIMATES_FILENAME);
+    if fee_estimates_path.exists() {
+        match mempool.fee_estimator().write().load(&fee_estimates_path) {
+            Ok(()) => eprintln!("[fees] loaded fee estimates from {}", fee_estimates_path.display()),
+            Err(e) => eprintln!("[fees] {e:#}"),
+        }
+    }
+    {
+        let estimator = mempool.fee_estimator();
+        let path = fee_estimates_path.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(fees::FEE_FLUSH_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = estimator.read().save(&path) {
+                    eprintln!("[fees] failed to write fee estimates: {e:#}");
+                }
+            }
+        });
+    }
+
+    // mempool.dat 복원: 현재 tip 기준으로 재검증
+    let mempool_path = args.datadir.join(persist::MEMPOOL_FILENAME);
+    {
+        let m = mempool.clone();
+        let path = mempool_path.clone();
+        let persist_enabled = args.persistmempool;
+        let coins = coins.clone();
+        let k = kernel.clone();
+        tokio::task::spawn_blocking(move || {
+            if persist_enabled && path.exists() {
+                // 코인 인덱스가 tip을 따라잡기 전에는 입력을 찾지 못해 전부 버려짐
+                while !index::wait_until_synced(&*coins, &k, COINS_SYNC_WAIT) {
+                    eprintln!("[mempool] waiting for the coins index to sync before loading {}", path.display());
+                }
+                if let Err(e) = persist::load_mempool(&m, &path, persist::LoadOptions::default()) {
+                    eprintln!("[mempool] failed to load {}: {e:#}", path.display());
+                }
+            }
+            m.set_loaded(true);
+        });
+    }
+
     // (옵션) P2P 기동
     let p2p_handle = if !args.peer.is_empty() || matches!(args.chain.as_str(), "main" | "mainnet" | "testnet" | "signet") {
         let net = match args.chain.as_str() {
@@ -120,40 +298,63 @@ async fn main() -> Result<()> {
         let peers_cli = args.peer.clone();
         let k = kernel.clone();
         let m = mempool.clone();
+        let mempool_fee = mempool.clone();
+        let mempool_served = mempool.clone();
+        let mempool_rate = mempool.clone();
+
+        let chain_params = if net == bitcoin::Network::Signet {
+            chainparams::ChainParams::for_signet(signet_challenge.clone(), args.signetseednode.clone())
+        } else {
+            chainparams::ChainParams::for_network(net)
+        };
+        let pm = p2p::PeerManager::with_start_height(net, "/btck-mini-node:0.1/", current_height)
+            .with_chain_params(chain_params);
+        let handle = pm.handle();
+        let h = handle.clone();
 
-        Some(tokio::spawn(async move {
+        let task = tokio::spawn(async move {
             // 블록 처리 콜백: libbitcoinkernel 검증/적용
+            let mb = m.clone();
             let process_block = move |raw: &[u8]| -> anyhow::Result<()> {
-                k.process_block(raw)
+                k.process_block(raw)?;
+                let height = k.get_height().ok();
+                if let Ok(block) = bitcoin::consensus::deserialize::<bitcoin::Block>(raw) {
+                    mb.remove_for_block(&block, height.unwrap_or(0).max(0) as u32);
+                }
+                if let Some(height) = height {
+                    h.set_block_height(height);
+                }
+                Ok(())
             };
 
             // 트랜잭션 처리 콜백: Mempool에 추가
-            let process_tx = move |tx: &bitcoin::Transaction| -> anyhow::Result<()> {
-                // Get current height (default to 0 if unavailable)
-                let height = 0u32; // TODO: get actual height from kernel
-
-                // Estimate fee (for now use dummy value, should calculate from inputs/outputs)
-                let fee = 1000u64; // TODO: calculate actual fee
-
-                match m.add_tx(tx.clone(), fee, height) {
-                    Ok(txid) => {
-                        eprintln!("[mempool] accepted tx: {}", txid);
-                        Ok(())
+            let process_tx = move |tx: &bitcoin::Transaction| -> p2p::TxOutcome {
+                match m.accept_tx(tx.clone()) {
+                    Ok(accepted) => {
+                        eprintln!("[mempool] accepted tx: {} (fee {} sat)", accepted.txid, accepted.fee);
+                        p2p::TxOutcome::Accepted
+                    }
+                    // 부모를 모르는 트랜잭션은 고아 풀로
+                    Err(e) if e.result == mempool::TxValidationResult::MissingInputs => {
+                        p2p::TxOutcome::MissingInputs(m.missing_parents(tx))
                     }
                     Err(e) => {
                         eprintln!("[mempool] rejected tx {}: {}", tx.compute_txid(), e);
-                        Err(e)
+                        p2p::TxOutcome::Rejected(e.to_string())
                     }
                 }
             };
 
-            let mut pm = p2p::PeerManager::with_start_height(net, "/btck-mini-node:0.1/", current_height)
+            let mut pm = pm
                 .with_block_processor(process_block)
-                .with_tx_processor(process_tx);
+                .with_tx_processor(process_tx)
+                .with_fee_filter(move || mempool_fee.min_fee_rate().as_sat_per_kvb())
+                .with_tx_served(move |txid| mempool_served.remove_unbroadcast(txid))
+                .with_tx_fee_rate(move |txid| mempool_rate.get_entry(txid).map(|e| e.fee_rate.as_sat_per_kvb()));
 
             for p in peers_cli {
                 if let Ok(addr) = p.parse::<SocketAddr>() {
-                    let _ = pm.add_outbound(addr).await;
+                    let _ = pm.add_manual(addr).await;
                 }
             }
             if pm.peers_len() < 2 {
@@ -163,19 +364,82 @@ async fn main() -> Result<()> {
             if let Err(e) = pm.event_loop().await {
                 eprintln!("[p2p] loop error: {e:#}");
             }
-        }))
+        });
+        Some((task, handle))
     } else {
         None
     };
+    let p2p_control = p2p_handle.as_ref().map(|(_, handle)| handle.clone());
+
+    // 아직 아무 피어도 요청하지 않은 로컬 트랜잭션 재전파
+    if let Some(p2p) = p2p_control.clone() {
+        let m = mempool.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(UNBROADCAST_RETRY);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                for txid in m.unbroadcast_txids() {
+                    if let Some(tx) = m.get_tx(&txid) {
+                        p2p.send(p2p::control::P2PCommand::RelayTransaction(tx));
+                    }
+                }
+            }
+        });
+    }
+
+    // (옵션) Stratum 마이닝 서버
+    #[cfg(feature = "mining")]
+    let stratum_server =
+        stratum::StratumServer::start(&args.stratum, kernel.clone(), mempool.clone(), p2p_control.clone()).await?;
+
+    // (옵션) Electrum 서버 (주소 인덱스 + 트랜잭션 인덱스)
+    let electrum_server = match (&args.electrum, &addrindex, &txindex) {
+        (Some(listen), Some(addrindex), Some(txindex)) => Some(
+            electrum::ElectrumServer::start(
+                listen,
+                kernel.clone(),
+                mempool.clone(),
+                addrindex.clone(),
+                txindex.clone(),
+                p2p_control.clone(),
+            )
+            .await?,
+        ),
+        _ => None,
+    };
 
     // RPC 서버 시작 (shutdown signal과 함께)
     let rpc_addr: SocketAddr = args.rpc.parse().context("bad --rpc addr")?;
 
+    // RPC 인증 (cookie / rpcuser / rpcauth)
+    let rpc_auth = Arc::new(rpc::auth::RpcAuth::new(&rpc::auth::RpcAuthConfig {
+        datadir: args.datadir.clone(),
+        cookie_file: args.rpccookiefile.clone(),
+        rpcuser: args.rpcuser.clone(),
+        rpcpassword: args.rpcpassword.clone(),
+        rpcauth: args.rpcauth.clone(),
+        rpcwhitelist: args.rpcwhitelist.clone(),
+        rpcwhitelistdefault: args.rpcwhitelistdefault,
+    })?);
+
     // Create oneshot channel for RPC-triggered shutdown
     let (shutdown_tx, mut shutdown_rx) = tokio::sync::oneshot::channel();
+    let rpc_state = rpc::AppState::new(
+        kernel.clone(),
+        mempool.clone(),
+        coins.clone(),
+        p2p_control,
+        shutdown_tx,
+        rpc_auth.clone(),
+        args.datadir.clone(),
+    );
+    let rpc_state = rpc::AppState { txindex: txindex.clone(), addrindex: addrindex.clone(), ..rpc_state };
+    #[cfg(feature = "zmq")]
+    let rpc_state = rpc::AppState { zmq: zmq_notifier, ..rpc_state };
 
     tokio::select! {
-        result = rpc::start_rpc_server(rpc_addr, kernel.clone(), mempool.clone(), shutdown_tx) => {
+        result = rpc::start_rpc_server(rpc_addr, rpc_state, args.rest) => {
             if let Err(e) = result {
                 eprintln!("[main] RPC server error: {:#}", e);
             }
@@ -191,11 +455,50 @@ async fn main() -> Result<()> {
     // Graceful shutdown: drop all references to kernel
     eprintln!("[main] Shutting down services...");
 
-    if let Some(handle) = p2p_handle {
-        handle.abort();
+    if let Some((task, _)) = p2p_handle {
+        task.abort();
         eprintln!("[main] P2P service stopped");
     }
 
+    rpc_auth.remove_cookie();
+
+    #[cfg(feature = "mining")]
+    if let Some(server) = stratum_server {
+        server.shutdown().await;
+        eprintln!("[main] Stratum server stopped");
+    }
+
+    if let Some(server) = electrum_server {
+        server.shutdown().await;
+        eprintln!("[main] Electrum server stopped");
+    }
+
+    // Index threads read blocks through the kernel; stop them first
+    index_runner.shutdown();
+    drop(coins);
+    drop(txindex);
+    drop(addrindex);
+    eprintln!("[main] Index threads stopped");
+
+    // Only dump a fully loaded mempool, or a partial load would overwrite the file
+    if args.persistmempool && mempool.is_loaded() {
+        match persist::dump_mempool(&mempool, &mempool_path) {
+            Ok(count) => eprintln!("[mempool] dumped {} transactions to {}", count, mempool_path.display()),
+            Err(e) => eprintln!("[mempool] failed to dump mempool: {e:#}"),
+        }
+    }
+
+    // Unconfirmed transactions count as failures in the saved estimates
+    {
+        let estimator = mempool.fee_estimator();
+        let mut estimator = estimator.write();
+        let flushed = estimator.flush_unconfirmed();
+        match estimator.save(&fee_estimates_path) {
+            Ok(()) => eprintln!("[fees] flushed {} unconfirmed txs, wrote {}", flushed, fee_estimates_path.display()),
+            Err(e) => eprintln!("[fees] failed to write fee estimates: {e:#}"),
+        }
+    }
+
     // Force drop kernel to trigger btck_chainstate_manager_destroy()
     drop(kernel);
     drop(mempool);
@@ -3,29 +3,83 @@ use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
-/// Fee rate in satoshis per virtual byte
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub struct FeeRate(pub u64);
+/// Fee rate in satoshis per 1000 virtual bytes (Bitcoin Core: CFeeRate).
+/// Displays and serializes as BTC/kvB, the unit RPCs use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FeeRate(u64);
 
 impl FeeRate {
-    pub fn from_sat_per_vb(sat_per_vb: u64) -> Self {
-        FeeRate(sat_per_vb)
+    pub const ZERO: FeeRate = FeeRate(0);
+
+    pub const fn from_sat_per_vb(sat_per_vb: u64) -> Self {
+        FeeRate(sat_per_vb.saturating_mul(1000))
+    }
+
+    pub const fn from_sat_per_kvb(sat_per_kvb: u64) -> Self {
+        FeeRate(sat_per_kvb)
+    }
+
+    /// Feerate of `fee` paid for `vsize`, rounded down; zero for an empty size
+    pub fn from_fee_and_vsize(fee: u64, vsize: u64) -> Self {
+        if vsize == 0 {
+            return FeeRate::ZERO;
+        }
+        FeeRate((fee as u128 * 1000 / vsize as u128).min(u64::MAX as u128) as u64)
     }
 
-    pub fn from_sat_per_kvb(sat_per_kvb: u64) -> Self {
-        FeeRate(sat_per_kvb / 1000)
+    /// Parse a BTC/kvB amount, rounded to the nearest sat/kvB
+    pub fn from_btc_per_kvb(btc_per_kvb: f64) -> Option<Self> {
+        let sat_per_kvb = (btc_per_kvb * 100_000_000.0).round();
+        (btc_per_kvb.is_finite() && sat_per_kvb >= 0.0 && sat_per_kvb <= u64::MAX as f64)
+            .then(|| FeeRate(sat_per_kvb as u64))
     }
 
+    /// Whole sat/vB, rounded down
     pub fn as_sat_per_vb(&self) -> u64 {
-        self.0
+        self.0 / 1000
+    }
+
+    pub fn as_sat_per_vb_f64(&self) -> f64 {
+        self.0 as f64 / 1000.0
     }
 
     pub fn as_sat_per_kvb(&self) -> u64 {
-        self.0 * 1000
+        self.0
+    }
+
+    pub fn as_btc_per_kvb(&self) -> f64 {
+        self.0 as f64 / 100_000_000.0
     }
 
+    /// Fee for `vsize` at this rate, rounded up so the rate is always met
+    /// (Bitcoin Core: CFeeRate::GetFee)
     pub fn fee_for_vsize(&self, vsize: u64) -> u64 {
-        self.0.saturating_mul(vsize)
+        let fee = (self.0 as u128 * vsize as u128).div_ceil(1000);
+        fee.min(u64::MAX as u128) as u64
+    }
+
+    pub fn saturating_add(self, other: FeeRate) -> FeeRate {
+        FeeRate(self.0.saturating_add(other.0))
+    }
+}
+
+impl std::fmt::Display for FeeRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{:08} BTC/kvB", self.0 / 100_000_000, self.0 % 100_000_000)
+    }
+}
+
+impl serde::Serialize for FeeRate {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.as_btc_per_kvb())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for FeeRate {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let btc_per_kvb = f64::deserialize(deserializer)?;
+        FeeRate::from_btc_per_kvb(btc_per_kvb)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid feerate {}", btc_per_kvb)))
     }
 }
 
@@ -44,7 +98,7 @@ pub struct MempoolEntry {
     /// Total fee paid
     pub fee: u64,
 
-    /// Fee rate (satoshis per virtual byte)
+    /// Fee rate (satoshis per 1000 virtual bytes)
     pub fee_rate: FeeRate,
 
     /// Time when added to mempool
@@ -79,6 +133,12 @@ pub struct MempoolEntry {
 
     /// Signals replacement (BIP 125)
     pub signals_replacement: bool,
+
+    /// Fee adjustment from prioritisation, counted for mining priority
+    pub fee_delta: i64,
+
+    /// Sigop cost (legacy sigops only until the spent outputs are known)
+    pub sigop_cost: u64,
 }
 
 impl MempoolEntry {
@@ -89,8 +149,9 @@ impl MempoolEntry {
     ) -> Self {
         let txid = tx.compute_txid();
         let vsize = tx.vsize() as u64;
-        let fee_rate = FeeRate::from_sat_per_vb(fee / vsize.max(1));
+        let fee_rate = FeeRate::from_fee_and_vsize(fee, vsize);
         let signals_replacement = Self::check_rbf_signaling(&tx);
+        let sigop_cost = tx.total_sigop_cost(|_| None) as u64;
 
         Self {
             tx: Arc::new(tx),
@@ -109,6 +170,8 @@ impl MempoolEntry {
             descendant_count: 1,
             descendant_fees: fee,
             signals_replacement,
+            fee_delta: 0,
+            sigop_cost,
         }
     }
 
@@ -119,17 +182,22 @@ impl MempoolEntry {
 
     /// Get the ancestor fee rate
     pub fn ancestor_fee_rate(&self) -> FeeRate {
-        FeeRate::from_sat_per_vb(self.ancestor_fees / self.ancestor_size.max(1))
+        FeeRate::from_fee_and_vsize(self.ancestor_fees, self.ancestor_size)
     }
 
     /// Get the descendant fee rate
     pub fn descendant_fee_rate(&self) -> FeeRate {
-        FeeRate::from_sat_per_vb(self.descendant_fees / self.descendant_size.max(1))
+        FeeRate::from_fee_and_vsize(self.descendant_fees, self.descendant_size)
+    }
+
+    /// Fee including the prioritisation delta
+    pub fn modified_fee(&self) -> u64 {
+        (self.fee as i64).saturating_add(self.fee_delta).max(0) as u64
     }
 
     /// Get the modified fee rate (for mining priority)
     pub fn modified_fee_rate(&self) -> FeeRate {
-        self.fee_rate
+        FeeRate::from_fee_and_vsize(self.modified_fee(), self.vsize)
     }
 
     /// Get age in seconds
@@ -203,6 +271,19 @@ mod tests {
         assert_eq!(rate.fee_for_vsize(100), 1000);
     }
 
+    #[test]
+    fn test_sub_sat_fee_rate() {
+        let rate = FeeRate::from_fee_and_vsize(150, 100);
+        assert_eq!(rate.as_sat_per_kvb(), 1_500);
+        assert!(rate > FeeRate::from_sat_per_vb(1));
+        assert_eq!(FeeRate::from_fee_and_vsize(10, 100).as_sat_per_kvb(), 100);
+        // Fees round up, so the rate is always met
+        assert_eq!(FeeRate::from_sat_per_kvb(100).fee_for_vsize(141), 15);
+        assert_eq!(FeeRate::from_sat_per_kvb(1_500).to_string(), "0.00001500 BTC/kvB");
+        assert_eq!(serde_json::to_value(FeeRate::from_sat_per_kvb(1_500)).unwrap(), serde_json::json!(0.000015));
+        assert_eq!(serde_json::from_value::<FeeRate>(serde_json::json!(0.0001)).unwrap(), FeeRate::from_sat_per_vb(10));
+    }
+
     #[test]
     fn test_ancestor_update() {
         let tx = create_dummy_tx();
@@ -0,0 +1,93 @@
+//! Ephemeral dust (Bitcoin Core: policy/ephemeral_policy.cpp).
+//!
+//! A transaction may carry one dust output, such as a zero-value pay-to-anchor,
+//! as long as it pays no fee itself. It can then only be mined through a child
+//! that spends the dust, so the dust never lingers in the UTXO set.
+
+use super::policy::MempoolPolicy;
+use super::validation::{TxValidationError, TxValidationResult};
+use bitcoin::{OutPoint, Transaction};
+
+/// Most dust outputs a standard transaction may have
+pub const MAX_DUST_OUTPUTS_PER_TX: usize = 1;
+
+/// Dust limits on a transaction by itself (Bitcoin Core: PreCheckEphemeralTx)
+pub fn pre_check_ephemeral_tx(tx: &Transaction, fee: u64, policy: &MempoolPolicy) -> Result<(), TxValidationError> {
+    let dust = tx.output.iter().filter(|o| policy.is_dust(o)).count();
+    if dust > MAX_DUST_OUTPUTS_PER_TX {
+        return Err(TxValidationError::new(TxValidationResult::NotStandard, "dust", ""));
+    }
+    if dust > 0 && fee != 0 {
+        return Err(TxValidationError::new(TxValidationResult::NotStandard, "dust", "tx with dust output must be 0-fee"));
+    }
+    Ok(())
+}
+
+/// A transaction spending from a parent with dust must spend all of that
+/// dust (Bitcoin Core: CheckEphemeralSpends). `parents` may include
+/// transactions `tx` does not spend; those are ignored.
+pub fn check_ephemeral_spends(tx: &Transaction, parents: &[&Transaction], policy: &MempoolPolicy) -> Result<(), String> {
+    for parent in parents {
+        let parent_txid = parent.compute_txid();
+        if !tx.input.iter().any(|i| i.previous_output.txid == parent_txid) {
+            continue;
+        }
+        let unspent_dust = parent.output.iter().enumerate().any(|(vout, output)| {
+            let outpoint = OutPoint::new(parent_txid, vout as u32);
+            policy.is_dust(output) && !tx.input.iter().any(|i| i.previous_output == outpoint)
+        });
+        if unspent_dust {
+            return Err(format!("tx {} did not spend parent's ephemeral dust", tx.compute_txid()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mempool::standard::P2A_SCRIPT;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{absolute, transaction, Amount, ScriptBuf, TxIn, TxOut, Txid};
+
+    fn tx_with_outputs(prevouts: &[OutPoint], outputs: Vec<TxOut>) -> Transaction {
+        Transaction {
+            version: transaction::Version(3),
+            lock_time: absolute::LockTime::ZERO,
+            input: prevouts.iter().map(|p| TxIn { previous_output: *p, ..Default::default() }).collect(),
+            output: outputs,
+        }
+    }
+
+    fn anchor(value: u64) -> TxOut {
+        TxOut { value: Amount::from_sat(value), script_pubkey: ScriptBuf::from(P2A_SCRIPT.to_vec()) }
+    }
+
+    fn payment(value: u64) -> TxOut {
+        TxOut { value: Amount::from_sat(value), script_pubkey: ScriptBuf::from(vec![0x51]) }
+    }
+
+    #[test]
+    fn test_dust_requires_zero_fee() {
+        let policy = MempoolPolicy::default();
+        let parent = tx_with_outputs(&[OutPoint::new(Txid::all_zeros(), 0)], vec![payment(50_000), anchor(0)]);
+        assert!(pre_check_ephemeral_tx(&parent, 0, &policy).is_ok());
+        assert_eq!(pre_check_ephemeral_tx(&parent, 100, &policy).unwrap_err().reason, "dust");
+
+        let two_dust = tx_with_outputs(&[OutPoint::new(Txid::all_zeros(), 0)], vec![anchor(0), anchor(0)]);
+        assert!(pre_check_ephemeral_tx(&two_dust, 0, &policy).is_err());
+    }
+
+    #[test]
+    fn test_child_must_spend_dust() {
+        let policy = MempoolPolicy::default();
+        let parent = tx_with_outputs(&[OutPoint::new(Txid::all_zeros(), 0)], vec![payment(50_000), anchor(0)]);
+        let txid = parent.compute_txid();
+
+        let skips_dust = tx_with_outputs(&[OutPoint::new(txid, 0)], vec![payment(40_000)]);
+        assert!(check_ephemeral_spends(&skips_dust, &[&parent], &policy).is_err());
+
+        let spends_dust = tx_with_outputs(&[OutPoint::new(txid, 0), OutPoint::new(txid, 1)], vec![payment(40_000)]);
+        assert!(check_ephemeral_spends(&spends_dust, &[&parent], &policy).is_ok());
+    }
+}
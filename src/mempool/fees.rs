@@ -1,6 +1,90 @@
-use super::entry::FeeRate;
-use std::collections::VecDeque;
-use std::time::{Duration, SystemTime};
+//! Fee estimation from confirmation history (Bitcoin Core: policy/fees.cpp).
+//!
+//! Every transaction entering the mempool with no unconfirmed parents is
+//! tracked in a feerate bucket until it confirms or leaves. Confirmations are
+//! recorded per bucket as exponentially decaying averages over three horizons
+//! (short, medium and long), and an estimate for a target is the median
+//! feerate of the cheapest range of buckets that still confirmed within the
+//! target often enough.
+
+use anyhow::{bail, Context, Result};
+use bitcoin::consensus::{Decodable, Encodable};
+use bitcoin::io::{self, Read, Write};
+use bitcoin::{Txid, VarInt};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use super::entry::{FeeRate, MempoolEntry};
+
+/// File name under the data directory
+pub const FEE_ESTIMATES_FILENAME: &str = "fee_estimates.dat";
+
+/// Oldest client version able to read the files we write
+const CURRENT_FEES_FILE_VERSION: i32 = 149900;
+
+/// Estimates older than this are not loaded (Bitcoin Core: MAX_FILE_AGE)
+pub const MAX_FILE_AGE: Duration = Duration::from_secs(60 * 60 * 60);
+
+/// How often the estimates are written while running
+pub const FEE_FLUSH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Track confirm delays up to 12 blocks for the short horizon
+const SHORT_BLOCK_PERIODS: usize = 12;
+const SHORT_SCALE: u32 = 1;
+/// Track confirm delays up to 48 blocks for the medium horizon
+const MED_BLOCK_PERIODS: usize = 24;
+const MED_SCALE: u32 = 2;
+/// Track confirm delays up to 1008 blocks for the long horizon
+const LONG_BLOCK_PERIODS: usize = 42;
+const LONG_SCALE: u32 = 24;
+/// Historical estimates older than this aren't valid
+const OLDEST_ESTIMATE_HISTORY: u32 = 6 * 1008;
+
+/// Decay of .962 is a half-life of 18 blocks or about 3 hours
+const SHORT_DECAY: f64 = 0.962;
+/// Decay of .9952 is a half-life of 144 blocks or about 1 day
+const MED_DECAY: f64 = 0.9952;
+/// Decay of .99931 is a half-life of 1008 blocks or about 1 week
+const LONG_DECAY: f64 = 0.99931;
+
+/// Require greater than 60% of X feerate transactions to be confirmed within Y/2 blocks
+const HALF_SUCCESS_PCT: f64 = 0.6;
+/// Require greater than 85% of X feerate transactions to be confirmed within Y blocks
+const SUCCESS_PCT: f64 = 0.85;
+/// Require greater than 95% of X feerate transactions to be confirmed within 2 * Y blocks
+const DOUBLE_SUCCESS_PCT: f64 = 0.95;
+
+/// Require an avg of 0.1 tx in the combined feerate bucket per block to have stat significance
+const SUFFICIENT_FEETXS: f64 = 0.1;
+/// Require an avg of 0.5 tx when using short decay since there are fewer blocks considered
+const SUFFICIENT_TXS_SHORT: f64 = 0.5;
+
+/// Lowest and highest tracked feerates, in sat/kvB, and the spacing between buckets
+const MIN_BUCKET_FEERATE: f64 = 1000.0;
+const MAX_BUCKET_FEERATE: f64 = 1e7;
+const FEE_SPACING: f64 = 1.05;
+const INF_FEERATE: f64 = 1e99;
+
+/// Confirmation statistics kept over one time horizon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeEstimateHorizon {
+    Short,
+    Medium,
+    Long,
+}
+
+impl FeeEstimateHorizon {
+    pub const ALL: [FeeEstimateHorizon; 3] = [Self::Short, Self::Medium, Self::Long];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Short => "short",
+            Self::Medium => "medium",
+            Self::Long => "long",
+        }
+    }
+}
 
 /// Priority level for fee estimation
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -26,191 +110,779 @@ impl FeePriority {
     }
 }
 
-/// Historical transaction entry for fee estimation
+/// Counts for a range of feerate buckets (Bitcoin Core: EstimatorBucket)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EstimatorBucket {
+    /// Lower feerate bound in sat/kvB, or -1 when unset
+    pub start: f64,
+    pub end: f64,
+    pub within_target: f64,
+    pub total_confirmed: f64,
+    pub in_mempool: f64,
+    pub left_mempool: f64,
+}
+
+impl Default for EstimatorBucket {
+    fn default() -> Self {
+        Self { start: -1.0, end: -1.0, within_target: 0.0, total_confirmed: 0.0, in_mempool: 0.0, left_mempool: 0.0 }
+    }
+}
+
+/// The passing and first failing bucket ranges behind an estimate
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EstimationResult {
+    pub pass: EstimatorBucket,
+    pub fail: EstimatorBucket,
+    pub decay: f64,
+    pub scale: u32,
+}
+
+/// Details of a smart fee estimate (Bitcoin Core: FeeCalculation)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FeeCalculation {
+    pub est: EstimationResult,
+    pub desired_target: u32,
+    /// Target actually estimated, after clamping to the history available
+    pub returned_target: u32,
+}
+
+/// Decaying confirmation counts per feerate bucket (Bitcoin Core: TxConfirmStats)
 #[derive(Debug, Clone)]
-struct HistoricalTx {
-    fee_rate: FeeRate,
-    time: SystemTime,
-    confirmed_block: Option<u32>,
+struct TxConfirmStats {
+    /// Upper feerate bound of each bucket, in sat/kvB
+    buckets: Vec<f64>,
+    /// Sum of the feerates of the transactions counted in each bucket
+    feerate_avg: Vec<f64>,
+    /// Transactions confirmed per bucket
+    tx_ct_avg: Vec<f64>,
+    /// conf_avg[p][b]: transactions in bucket b confirmed within p + 1 periods
+    conf_avg: Vec<Vec<f64>>,
+    /// fail_avg[p][b]: transactions in bucket b that left the mempool unconfirmed after p + 1 periods
+    fail_avg: Vec<Vec<f64>>,
+    /// Unconfirmed transactions by entry height (modulo the buffer size) and bucket
+    unconf_txs: Vec<Vec<u32>>,
+    /// Unconfirmed transactions older than the circular buffer
+    old_unconf_txs: Vec<u32>,
+    decay: f64,
+    /// Blocks per period
+    scale: u32,
 }
 
-/// Simple fee estimator based on recent confirmations
-#[derive(Debug)]
-pub struct FeeEstimator {
-    /// Recent confirmed transactions
-    history: VecDeque<HistoricalTx>,
+impl TxConfirmStats {
+    fn new(buckets: &[f64], periods: usize, decay: f64, scale: u32) -> Self {
+        let mut stats = Self {
+            buckets: buckets.to_vec(),
+            feerate_avg: vec![0.0; buckets.len()],
+            tx_ct_avg: vec![0.0; buckets.len()],
+            conf_avg: vec![vec![0.0; buckets.len()]; periods],
+            fail_avg: vec![vec![0.0; buckets.len()]; periods],
+            unconf_txs: Vec::new(),
+            old_unconf_txs: Vec::new(),
+            decay,
+            scale,
+        };
+        stats.resize_in_memory_counters();
+        stats
+    }
+
+    fn resize_in_memory_counters(&mut self) {
+        self.unconf_txs = vec![vec![0; self.buckets.len()]; self.max_confirms() as usize];
+        self.old_unconf_txs = vec![0; self.buckets.len()];
+    }
+
+    fn max_confirms(&self) -> u32 {
+        self.scale * self.conf_avg.len() as u32
+    }
+
+    /// Index of the first bucket whose bound is at least `feerate`
+    fn bucket_index(&self, feerate: f64) -> usize {
+        self.buckets.partition_point(|&bound| bound < feerate).min(self.buckets.len() - 1)
+    }
+
+    /// Move the slot `height` reuses into the old counts
+    fn clear_current(&mut self, height: u32) {
+        let slot = height as usize % self.unconf_txs.len();
+        for (old, current) in self.old_unconf_txs.iter_mut().zip(self.unconf_txs[slot].iter_mut()) {
+            *old += *current;
+            *current = 0;
+        }
+    }
+
+    /// A transaction confirmed after `blocks_to_confirm` blocks (1-based)
+    fn record(&mut self, blocks_to_confirm: u32, feerate: f64) {
+        if blocks_to_confirm < 1 {
+            return;
+        }
+        let periods_to_confirm = blocks_to_confirm.div_ceil(self.scale) as usize;
+        let bucket = self.bucket_index(feerate);
+        for period in periods_to_confirm.saturating_sub(1)..self.conf_avg.len() {
+            self.conf_avg[period][bucket] += 1.0;
+        }
+        self.tx_ct_avg[bucket] += 1.0;
+        self.feerate_avg[bucket] += feerate;
+    }
+
+    fn update_moving_averages(&mut self) {
+        let decay = self.decay;
+        for value in self.feerate_avg.iter_mut().chain(self.tx_ct_avg.iter_mut()) {
+            *value *= decay;
+        }
+        for row in self.conf_avg.iter_mut().chain(self.fail_avg.iter_mut()) {
+            for value in row.iter_mut() {
+                *value *= decay;
+            }
+        }
+    }
+
+    fn new_tx(&mut self, height: u32, feerate: f64) -> usize {
+        let bucket = self.bucket_index(feerate);
+        let slot = height as usize % self.unconf_txs.len();
+        self.unconf_txs[slot][bucket] += 1;
+        bucket
+    }
+
+    /// Stop counting a transaction as unconfirmed; one that leaves without
+    /// confirming counts as a failure for every period it waited
+    fn remove_tx(&mut self, entry_height: u32, best_seen_height: u32, bucket: usize, in_block: bool) {
+        let blocks_ago = if best_seen_height == 0 { 0 } else { best_seen_height as i64 - entry_height as i64 };
+        if blocks_ago < 0 {
+            return;
+        }
+        let blocks_ago = blocks_ago as usize;
+        if blocks_ago >= self.unconf_txs.len() {
+            self.old_unconf_txs[bucket] = self.old_unconf_txs[bucket].saturating_sub(1);
+        } else {
+            let slot = entry_height as usize % self.unconf_txs.len();
+            self.unconf_txs[slot][bucket] = self.unconf_txs[slot][bucket].saturating_sub(1);
+        }
+        if !in_block && blocks_ago >= self.scale as usize {
+            let periods_ago = blocks_ago / self.scale as usize;
+            for row in self.fail_avg.iter_mut().take(periods_ago) {
+                row[bucket] += 1.0;
+            }
+        }
+    }
+
+    /// Median feerate of the cheapest bucket range that confirmed within
+    /// `conf_target` at least `success_break_point` of the time, grouping
+    /// buckets from the top until each range holds `sufficient_tx_val` per
+    /// block of data (Bitcoin Core: EstimateMedianVal). -1 if none did.
+    fn estimate_median_val(
+        &self,
+        conf_target: u32,
+        sufficient_tx_val: f64,
+        success_break_point: f64,
+        block_height: u32,
+        result: &mut EstimationResult,
+    ) -> f64 {
+        let mut n_conf = 0.0;
+        let mut total_num = 0.0;
+        let mut extra_num = 0u32;
+        let mut fail_num = 0.0;
+        let period_target = conf_target.div_ceil(self.scale) as usize;
+        let max_bucket = self.buckets.len() - 1;
+
+        let mut cur_near = max_bucket;
+        let mut best_near = max_bucket;
+        let mut cur_far = max_bucket;
+        let mut best_far = max_bucket;
+
+        let mut partial_num = 0.0;
+        let mut found_answer = false;
+        let bins = self.unconf_txs.len();
+        let mut new_bucket_range = true;
+        let mut passing = true;
+        let mut pass_bucket = EstimatorBucket::default();
+        let mut fail_bucket = EstimatorBucket::default();
+
+        // Start counting from the highest feerates
+        for bucket in (0..=max_bucket).rev() {
+            if new_bucket_range {
+                cur_near = bucket;
+                new_bucket_range = false;
+            }
+            cur_far = bucket;
+            n_conf += self.conf_avg[period_target - 1][bucket];
+            partial_num += self.tx_ct_avg[bucket];
+            total_num += self.tx_ct_avg[bucket];
+            fail_num += self.fail_avg[period_target - 1][bucket];
+            for confct in conf_target as usize..self.max_confirms() as usize {
+                extra_num += self.unconf_txs[(block_height as usize + bins - confct % bins) % bins][bucket];
+            }
+            extra_num += self.old_unconf_txs[bucket];
 
-    /// Maximum history size
-    max_history: usize,
+            // Only test ranges with enough confirmed data points, so every
+            // target looks at the same bucket groupings
+            if partial_num < sufficient_tx_val / (1.0 - self.decay) {
+                continue;
+            }
+            partial_num = 0.0;
+
+            let cur_pct = n_conf / (total_num + fail_num + extra_num as f64);
+            if cur_pct < success_break_point {
+                if passing {
+                    // Record the first failing range
+                    let (min, max) = (cur_near.min(cur_far), cur_near.max(cur_far));
+                    fail_bucket = EstimatorBucket {
+                        start: if min > 0 { self.buckets[min - 1] } else { 0.0 },
+                        end: self.buckets[max],
+                        within_target: n_conf,
+                        total_confirmed: total_num,
+                        in_mempool: extra_num as f64,
+                        left_mempool: fail_num,
+                    };
+                    passing = false;
+                }
+                continue;
+            }
 
-    /// Fee rate buckets (sat/vB)
-    buckets: Vec<u64>,
+            fail_bucket = EstimatorBucket::default();
+            found_answer = true;
+            passing = true;
+            pass_bucket = EstimatorBucket {
+                within_target: n_conf,
+                total_confirmed: total_num,
+                in_mempool: extra_num as f64,
+                left_mempool: fail_num,
+                ..EstimatorBucket::default()
+            };
+            n_conf = 0.0;
+            total_num = 0.0;
+            fail_num = 0.0;
+            extra_num = 0;
+            best_near = cur_near;
+            best_far = cur_far;
+            new_bucket_range = true;
+        }
+
+        // Report the average feerate of the bucket holding the median
+        // transaction of the best range
+        let mut median = -1.0;
+        let (min, max) = (best_near.min(best_far), best_near.max(best_far));
+        let mut tx_sum: f64 = self.tx_ct_avg[min..=max].iter().sum();
+        if found_answer && tx_sum != 0.0 {
+            tx_sum /= 2.0;
+            for j in min..=max {
+                if self.tx_ct_avg[j] < tx_sum {
+                    tx_sum -= self.tx_ct_avg[j];
+                } else {
+                    median = self.feerate_avg[j] / self.tx_ct_avg[j];
+                    break;
+                }
+            }
+            pass_bucket.start = if min > 0 { self.buckets[min - 1] } else { 0.0 };
+            pass_bucket.end = self.buckets[max];
+        }
 
-    /// Confirmation counts per bucket per target
-    confirmations: Vec<Vec<usize>>,
+        // Passing until the last few buckets ran out of data: report those as failed
+        if passing && !new_bucket_range {
+            let (min, max) = (cur_near.min(cur_far), cur_near.max(cur_far));
+            fail_bucket = EstimatorBucket {
+                start: if min > 0 { self.buckets[min - 1] } else { 0.0 },
+                end: self.buckets[max],
+                within_target: n_conf,
+                total_confirmed: total_num,
+                in_mempool: extra_num as f64,
+                left_mempool: fail_num,
+            };
+        }
 
-    /// Current block height
-    current_height: u32,
+        *result = EstimationResult { pass: pass_bucket, fail: fail_bucket, decay: self.decay, scale: self.scale };
+        median
+    }
 
-    /// Minimum tracked fee rate
-    min_tracked_fee: FeeRate,
+    fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        write_f64(w, self.decay)?;
+        self.scale.consensus_encode(w)?;
+        write_f64s(w, &self.feerate_avg)?;
+        write_f64s(w, &self.tx_ct_avg)?;
+        for table in [&self.conf_avg, &self.fail_avg] {
+            VarInt(table.len() as u64).consensus_encode(w)?;
+            for row in table {
+                write_f64s(w, row)?;
+            }
+        }
+        Ok(())
+    }
 
-    /// Fallback fee rate
-    fallback_fee: FeeRate,
+    fn read(r: &mut impl Read, buckets: &[f64]) -> Result<Self> {
+        let decay = read_f64(r)?;
+        if decay <= 0.0 || decay >= 1.0 {
+            bail!("Corrupt estimates file. Decay must be between 0 and 1 (non-inclusive)");
+        }
+        let scale = u32::consensus_decode(r)?;
+        if scale == 0 {
+            bail!("Corrupt estimates file. Scale must be non-zero");
+        }
+        let feerate_avg = read_f64s(r)?;
+        let tx_ct_avg = read_f64s(r)?;
+        if feerate_avg.len() != buckets.len() || tx_ct_avg.len() != buckets.len() {
+            bail!("Corrupt estimates file. Mismatch in feerate average bucket count");
+        }
+        let mut tables = Vec::with_capacity(2);
+        for _ in 0..2 {
+            let periods = VarInt::consensus_decode(r)?.0 as usize;
+            let max_confirms = scale as usize * periods;
+            if max_confirms == 0 || max_confirms > 6 * 24 * 7 {
+                bail!("Corrupt estimates file. Must maintain estimates for between 1 and 1008 (one week) confirms");
+            }
+            let mut table = Vec::with_capacity(periods);
+            for _ in 0..periods {
+                let row = read_f64s(r)?;
+                if row.len() != buckets.len() {
+                    bail!("Corrupt estimates file. Mismatch in confirmation average bucket count");
+                }
+                table.push(row);
+            }
+            tables.push(table);
+        }
+        let fail_avg = tables.pop().expect("two tables");
+        let conf_avg = tables.pop().expect("two tables");
+        if fail_avg.len() != conf_avg.len() {
+            bail!("Corrupt estimates file. Mismatch in confirms tracked for failures");
+        }
+
+        let mut stats = Self {
+            buckets: buckets.to_vec(),
+            feerate_avg,
+            tx_ct_avg,
+            conf_avg,
+            fail_avg,
+            unconf_txs: Vec::new(),
+            old_unconf_txs: Vec::new(),
+            decay,
+            scale,
+        };
+        stats.resize_in_memory_counters();
+        Ok(stats)
+    }
+}
+
+/// Doubles are stored as their IEEE 754 bits (Bitcoin Core: EncodedDoubleFormatter)
+fn write_f64(w: &mut impl Write, value: f64) -> io::Result<()> {
+    value.to_bits().consensus_encode(w).map(|_| ())
+}
+
+fn read_f64(r: &mut impl Read) -> Result<f64> {
+    Ok(f64::from_bits(u64::consensus_decode(r)?))
+}
+
+fn write_f64s(w: &mut impl Write, values: &[f64]) -> io::Result<()> {
+    VarInt(values.len() as u64).consensus_encode(w)?;
+    values.iter().try_for_each(|v| write_f64(w, *v))
+}
+
+fn read_f64s(r: &mut impl Read) -> Result<Vec<f64>> {
+    let len = VarInt::consensus_decode(r)?.0 as usize;
+    if len > 1000 {
+        bail!("Corrupt estimates file. Too many values");
+    }
+    (0..len).map(|_| read_f64(r)).collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TrackedTx {
+    height: u32,
+    bucket: usize,
+}
+
+/// Fee estimator over short, medium and long horizons (Bitcoin Core: CBlockPolicyEstimator)
+#[derive(Debug)]
+pub struct FeeEstimator {
+    buckets: Vec<f64>,
+    short_stats: TxConfirmStats,
+    fee_stats: TxConfirmStats,
+    long_stats: TxConfirmStats,
+
+    /// Mempool transactions being tracked, by txid
+    mempool_txs: HashMap<Txid, TrackedTx>,
+
+    best_seen_height: u32,
+    first_recorded_height: u32,
+    /// Block range covered by the estimates loaded from disk
+    historical_first: u32,
+    historical_best: u32,
+
+    /// Transactions seen since the last block
+    tracked_txs: u32,
+    untracked_txs: u32,
 }
 
 impl FeeEstimator {
     pub fn new() -> Self {
-        // Fee buckets: 1, 2, 3, 5, 10, 20, 30, 50, 100, 200, 300, 500, 1000 sat/vB
-        let buckets = vec![1, 2, 3, 5, 10, 20, 30, 50, 100, 200, 300, 500, 1000];
-        let confirmations = vec![vec![0; 25]; buckets.len()]; // 25 block targets
+        let mut buckets = Vec::new();
+        let mut bound = MIN_BUCKET_FEERATE;
+        while bound <= MAX_BUCKET_FEERATE {
+            buckets.push(bound);
+            bound *= FEE_SPACING;
+        }
+        buckets.push(INF_FEERATE);
 
         Self {
-            history: VecDeque::with_capacity(10000),
-            max_history: 10000,
+            short_stats: TxConfirmStats::new(&buckets, SHORT_BLOCK_PERIODS, SHORT_DECAY, SHORT_SCALE),
+            fee_stats: TxConfirmStats::new(&buckets, MED_BLOCK_PERIODS, MED_DECAY, MED_SCALE),
+            long_stats: TxConfirmStats::new(&buckets, LONG_BLOCK_PERIODS, LONG_DECAY, LONG_SCALE),
             buckets,
-            confirmations,
-            current_height: 0,
-            min_tracked_fee: FeeRate::from_sat_per_vb(1),
-            fallback_fee: FeeRate::from_sat_per_vb(20),
+            mempool_txs: HashMap::new(),
+            best_seen_height: 0,
+            first_recorded_height: 0,
+            historical_first: 0,
+            historical_best: 0,
+            tracked_txs: 0,
+            untracked_txs: 0,
         }
     }
 
-    /// Record a new transaction entering mempool
-    pub fn add_tx(&mut self, fee_rate: FeeRate) {
-        if fee_rate < self.min_tracked_fee {
+    fn stats(&self, horizon: FeeEstimateHorizon) -> &TxConfirmStats {
+        match horizon {
+            FeeEstimateHorizon::Short => &self.short_stats,
+            FeeEstimateHorizon::Medium => &self.fee_stats,
+            FeeEstimateHorizon::Long => &self.long_stats,
+        }
+    }
+
+    fn all_stats(&mut self) -> [&mut TxConfirmStats; 3] {
+        [&mut self.fee_stats, &mut self.short_stats, &mut self.long_stats]
+    }
+
+    /// Start tracking a transaction that just entered the mempool.
+    /// `valid_fee_estimate` is false for transactions whose feerate says
+    /// nothing about what miners accept: replacements, package members and
+    /// children of unconfirmed parents.
+    pub fn process_transaction(&mut self, entry: &MempoolEntry, valid_fee_estimate: bool) {
+        if self.mempool_txs.contains_key(&entry.txid) {
             return;
         }
+        // Ignore side chains and re-orgs
+        if entry.height != self.best_seen_height {
+            return;
+        }
+        if !valid_fee_estimate {
+            self.untracked_txs += 1;
+            return;
+        }
+        self.tracked_txs += 1;
 
-        let tx = HistoricalTx {
-            fee_rate,
-            time: SystemTime::now(),
-            confirmed_block: None,
-        };
+        let feerate = FeeRate::from_fee_and_vsize(entry.fee, entry.vsize).as_sat_per_kvb() as f64;
+        let mut bucket = 0;
+        for stats in self.all_stats() {
+            bucket = stats.new_tx(entry.height, feerate);
+        }
+        self.mempool_txs.insert(entry.txid, TrackedTx { height: entry.height, bucket });
+    }
 
-        self.history.push_back(tx);
+    /// Stop tracking a transaction that left the mempool without confirming
+    pub fn remove_tx(&mut self, txid: &Txid) -> bool {
+        self.remove_tracked(txid, false)
+    }
 
-        // Trim history if too large
-        while self.history.len() > self.max_history {
-            self.history.pop_front();
+    fn remove_tracked(&mut self, txid: &Txid, in_block: bool) -> bool {
+        let Some(tracked) = self.mempool_txs.remove(txid) else {
+            return false;
+        };
+        let best_seen = self.best_seen_height;
+        for stats in self.all_stats() {
+            stats.remove_tx(tracked.height, best_seen, tracked.bucket, in_block);
         }
+        true
     }
 
-    /// Record a transaction confirmation
-    pub fn confirm_tx(&mut self, fee_rate: FeeRate, block_height: u32) {
-        // Find bucket
-        let bucket_idx = self.find_bucket(fee_rate);
+    /// Record the confirmation of `entries`, the mempool transactions
+    /// included in the block at `height`
+    pub fn process_block(&mut self, height: u32, entries: &[MempoolEntry]) {
+        // Ignore side chains and re-orgs
+        if height <= self.best_seen_height {
+            return;
+        }
+        self.best_seen_height = height;
 
-        // Calculate blocks to confirm
-        let blocks_to_confirm = block_height.saturating_sub(self.current_height) as usize;
-        if blocks_to_confirm > 0 && blocks_to_confirm < self.confirmations[0].len() {
-            self.confirmations[bucket_idx][blocks_to_confirm] += 1;
+        for stats in self.all_stats() {
+            stats.clear_current(height);
+            stats.update_moving_averages();
         }
 
-        // Update history
-        for tx in self.history.iter_mut().rev() {
-            if tx.fee_rate == fee_rate && tx.confirmed_block.is_none() {
-                tx.confirmed_block = Some(block_height);
-                break;
+        let mut counted = 0;
+        for entry in entries {
+            if !self.remove_tracked(&entry.txid, true) {
+                continue;
+            }
+            let Some(blocks_to_confirm) = height.checked_sub(entry.height).filter(|b| *b > 0) else {
+                continue;
+            };
+            let feerate = FeeRate::from_fee_and_vsize(entry.fee, entry.vsize).as_sat_per_kvb() as f64;
+            for stats in self.all_stats() {
+                stats.record(blocks_to_confirm, feerate);
             }
+            counted += 1;
         }
-    }
 
-    /// Update current block height
-    pub fn update_height(&mut self, height: u32) {
-        self.current_height = height;
+        if self.first_recorded_height == 0 && counted > 0 {
+            self.first_recorded_height = height;
+            eprintln!("[fees] blockpolicy first recorded height {}", height);
+        }
+        if counted > 0 {
+            eprintln!(
+                "[fees] blockpolicy estimates updated by {} of {} block txs, since last block {} of {} tracked, mempool map size {}, max target {}",
+                counted,
+                entries.len(),
+                self.tracked_txs,
+                self.tracked_txs + self.untracked_txs,
+                self.mempool_txs.len(),
+                self.max_usable_estimate()
+            );
+        }
+        self.tracked_txs = 0;
+        self.untracked_txs = 0;
+    }
 
-        // Clean old history (older than 1 day)
-        let cutoff = SystemTime::now() - Duration::from_secs(86400);
-        while let Some(tx) = self.history.front() {
-            if tx.time < cutoff {
-                self.history.pop_front();
-            } else {
-                break;
-            }
+    /// Estimate for one horizon at an explicit success threshold
+    /// (Bitcoin Core: estimateRawFee)
+    pub fn estimate_raw_fee(
+        &self,
+        conf_target: u32,
+        success_threshold: f64,
+        horizon: FeeEstimateHorizon,
+    ) -> (Option<FeeRate>, EstimationResult) {
+        let mut result = EstimationResult::default();
+        let stats = self.stats(horizon);
+        let sufficient_txs = if horizon == FeeEstimateHorizon::Short { SUFFICIENT_TXS_SHORT } else { SUFFICIENT_FEETXS };
+        if conf_target == 0 || conf_target > stats.max_confirms() || success_threshold > 1.0 {
+            return (None, result);
         }
+        let median =
+            stats.estimate_median_val(conf_target, sufficient_txs, success_threshold, self.best_seen_height, &mut result);
+        (to_fee_rate(median), result)
     }
 
-    /// Estimate fee for a given priority
-    pub fn estimate_fee(&self, priority: FeePriority) -> FeeRate {
-        let target = priority.target_blocks();
-        self.estimate_fee_for_target(target)
+    /// Highest confirmation target a horizon can answer
+    pub fn highest_target_tracked(&self, horizon: FeeEstimateHorizon) -> u32 {
+        self.stats(horizon).max_confirms()
     }
 
-    /// Estimate fee for a specific block target
-    pub fn estimate_fee_for_target(&self, target_blocks: usize) -> FeeRate {
-        if target_blocks == 0 || target_blocks >= self.confirmations[0].len() {
-            return self.fallback_fee;
+    fn block_span(&self) -> u32 {
+        if self.first_recorded_height == 0 {
+            return 0;
         }
+        self.best_seen_height - self.first_recorded_height
+    }
 
-        // Find minimum fee rate that has sufficient confirmation rate
-        for (bucket_idx, &fee_sat_vb) in self.buckets.iter().enumerate().rev() {
-            let confirmations = self.confirmations[bucket_idx][target_blocks];
+    fn historical_block_span(&self) -> u32 {
+        if self.historical_first == 0
+            || self.historical_best < self.historical_first
+            || self.best_seen_height - self.historical_best > OLDEST_ESTIMATE_HISTORY
+        {
+            return 0;
+        }
+        self.historical_best - self.historical_first
+    }
 
-            // If we have at least 5 confirmations at this rate, use it
-            if confirmations >= 5 {
-                return FeeRate::from_sat_per_vb(fee_sat_vb);
+    /// Highest target with enough history behind it
+    pub fn max_usable_estimate(&self) -> u32 {
+        self.long_stats.max_confirms().min(self.block_span().max(self.historical_block_span()) / 2)
+    }
+
+    /// Estimate from the shortest horizon tracking `conf_target`; with
+    /// `check_shorter_horizon`, a lower answer from a shorter horizon's
+    /// highest target wins, which keeps estimates monotonic
+    fn estimate_combined_fee(
+        &self,
+        conf_target: u32,
+        success_threshold: f64,
+        check_shorter_horizon: bool,
+        result: &mut EstimationResult,
+    ) -> f64 {
+        let height = self.best_seen_height;
+        let mut estimate = -1.0;
+        if conf_target < 1 || conf_target > self.long_stats.max_confirms() {
+            return estimate;
+        }
+        estimate = if conf_target <= self.short_stats.max_confirms() {
+            self.short_stats.estimate_median_val(conf_target, SUFFICIENT_TXS_SHORT, success_threshold, height, result)
+        } else if conf_target <= self.fee_stats.max_confirms() {
+            self.fee_stats.estimate_median_val(conf_target, SUFFICIENT_FEETXS, success_threshold, height, result)
+        } else {
+            self.long_stats.estimate_median_val(conf_target, SUFFICIENT_FEETXS, success_threshold, height, result)
+        };
+
+        if check_shorter_horizon {
+            for (stats, sufficient) in [(&self.fee_stats, SUFFICIENT_FEETXS), (&self.short_stats, SUFFICIENT_TXS_SHORT)] {
+                if conf_target <= stats.max_confirms() {
+                    continue;
+                }
+                let mut temp = EstimationResult::default();
+                let shorter =
+                    stats.estimate_median_val(stats.max_confirms(), sufficient, success_threshold, height, &mut temp);
+                if shorter > 0.0 && (estimate == -1.0 || shorter < estimate) {
+                    estimate = shorter;
+                    *result = temp;
+                }
             }
         }
+        estimate
+    }
 
-        // No sufficient data, use fallback
-        self.fallback_fee
+    /// Highest of the medium and long horizon estimates at 95% success
+    fn estimate_conservative_fee(&self, double_target: u32, result: &mut EstimationResult) -> f64 {
+        let height = self.best_seen_height;
+        let mut estimate = 0.0;
+        if double_target <= self.short_stats.max_confirms() {
+            estimate =
+                self.fee_stats.estimate_median_val(double_target, SUFFICIENT_FEETXS, DOUBLE_SUCCESS_PCT, height, result);
+        }
+        if double_target <= self.fee_stats.max_confirms() {
+            let mut temp = EstimationResult::default();
+            let long =
+                self.long_stats.estimate_median_val(double_target, SUFFICIENT_FEETXS, DOUBLE_SUCCESS_PCT, height, &mut temp);
+            if long > estimate {
+                estimate = long;
+                *result = temp;
+            }
+        }
+        estimate
     }
 
-    /// Get fee rate for economy transactions
-    pub fn estimate_economy_fee(&self) -> FeeRate {
-        self.estimate_fee(FeePriority::Economy)
+    /// Feerate likely to confirm within `conf_target` blocks
+    /// (Bitcoin Core: estimateSmartFee). The highest of the estimates for
+    /// half the target at 60%, the target at 85% and twice the target at
+    /// 95% success; conservative mode also considers the longer horizons at
+    /// twice the target, so short-term dips move it less.
+    pub fn estimate_smart_fee(&self, conf_target: u32, conservative: bool) -> (Option<FeeRate>, FeeCalculation) {
+        let mut calc = FeeCalculation { desired_target: conf_target, returned_target: conf_target, ..Default::default() };
+        if conf_target == 0 || conf_target > self.long_stats.max_confirms() {
+            return (None, calc);
+        }
+        // Reasonable estimates for the next block are not possible
+        let conf_target = conf_target.max(2).min(self.max_usable_estimate());
+        calc.returned_target = conf_target;
+        if conf_target <= 1 {
+            return (None, calc);
+        }
+
+        let mut temp = EstimationResult::default();
+        let mut median = self.estimate_combined_fee(conf_target / 2, HALF_SUCCESS_PCT, true, &mut temp);
+        calc.est = temp;
+        let actual = self.estimate_combined_fee(conf_target, SUCCESS_PCT, true, &mut temp);
+        if actual > median {
+            median = actual;
+            calc.est = temp;
+        }
+        let double = self.estimate_combined_fee(2 * conf_target, DOUBLE_SUCCESS_PCT, !conservative, &mut temp);
+        if double > median {
+            median = double;
+            calc.est = temp;
+        }
+        if conservative || median == -1.0 {
+            let cons = self.estimate_conservative_fee(2 * conf_target, &mut temp);
+            if cons > median {
+                median = cons;
+                calc.est = temp;
+            }
+        }
+        (to_fee_rate(median), calc)
     }
 
-    /// Get fee rate for high priority transactions
-    pub fn estimate_high_priority_fee(&self) -> FeeRate {
-        self.estimate_fee(FeePriority::High)
+    /// Estimate for a priority level, None without enough history
+    pub fn estimate_fee(&self, priority: FeePriority) -> Option<FeeRate> {
+        self.estimate_smart_fee(priority.target_blocks() as u32, false).0
     }
 
-    /// Get current statistics
-    pub fn get_stats(&self) -> FeeEstimatorStats {
-        FeeEstimatorStats {
-            tracked_txs: self.history.len(),
-            min_tracked_fee: self.min_tracked_fee,
-            fallback_fee: self.fallback_fee,
-            current_height: self.current_height,
-            economy_fee: self.estimate_economy_fee(),
-            standard_fee: self.estimate_fee(FeePriority::Medium),
-            high_priority_fee: self.estimate_high_priority_fee(),
-        }
+    /// Transactions currently tracked
+    pub fn tracked_count(&self) -> usize {
+        self.mempool_txs.len()
     }
 
-    /// Find bucket index for a fee rate
-    fn find_bucket(&self, fee_rate: FeeRate) -> usize {
-        let sat_vb = fee_rate.as_sat_per_vb();
+    pub fn best_seen_height(&self) -> u32 {
+        self.best_seen_height
+    }
 
-        for (i, &bucket_fee) in self.buckets.iter().enumerate() {
-            if sat_vb <= bucket_fee {
-                return i;
-            }
+    /// Count every still-unconfirmed tracked transaction as a failure, before
+    /// the estimates are written at shutdown (Bitcoin Core: FlushUnconfirmed)
+    pub fn flush_unconfirmed(&mut self) -> usize {
+        let txids: Vec<Txid> = self.mempool_txs.keys().copied().collect();
+        for txid in &txids {
+            self.remove_tracked(txid, false);
         }
+        txids.len()
+    }
 
-        self.buckets.len() - 1
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        CURRENT_FEES_FILE_VERSION.consensus_encode(w)?;
+        // Unused field, kept for compatibility
+        0i32.consensus_encode(w)?;
+        self.best_seen_height.consensus_encode(w)?;
+        // Save whichever block range the estimates mostly come from
+        if self.block_span() > self.historical_block_span() / 2 {
+            self.first_recorded_height.consensus_encode(w)?;
+            self.best_seen_height.consensus_encode(w)?;
+        } else {
+            self.historical_first.consensus_encode(w)?;
+            self.historical_best.consensus_encode(w)?;
+        }
+        write_f64s(w, &self.buckets)?;
+        self.fee_stats.write(w)?;
+        self.short_stats.write(w)?;
+        self.long_stats.write(w)
     }
 
-    /// Set fallback fee
-    pub fn set_fallback_fee(&mut self, fee_rate: FeeRate) {
-        self.fallback_fee = fee_rate;
+    /// Replace the statistics with those read from `r`
+    pub fn read(&mut self, r: &mut impl Read) -> Result<()> {
+        let version_required = i32::consensus_decode(r)?;
+        if version_required < CURRENT_FEES_FILE_VERSION {
+            bail!("up-version ({}) fee estimate file", version_required);
+        }
+        let _unused = i32::consensus_decode(r)?;
+        let best_seen = u32::consensus_decode(r)?;
+        let historical_first = u32::consensus_decode(r)?;
+        let historical_best = u32::consensus_decode(r)?;
+        if historical_first > historical_best || historical_best > best_seen {
+            bail!("Corrupt estimates file. Historical block range for estimates is invalid");
+        }
+        let buckets = read_f64s(r)?;
+        if buckets.len() <= 1 || buckets.len() > 1000 {
+            bail!("Corrupt estimates file. Must have between 2 and 1000 feerate buckets");
+        }
+        let fee_stats = TxConfirmStats::read(r, &buckets)?;
+        let short_stats = TxConfirmStats::read(r, &buckets)?;
+        let long_stats = TxConfirmStats::read(r, &buckets)?;
+
+        self.buckets = buckets;
+        self.fee_stats = fee_stats;
+        self.short_stats = short_stats;
+        self.long_stats = long_stats;
+        self.mempool_txs.clear();
+        self.best_seen_height = best_seen;
+        self.first_recorded_height = 0;
+        self.historical_first = historical_first;
+        self.historical_best = historical_best;
+        Ok(())
     }
 
-    /// Get minimum tracked fee
-    pub fn min_tracked_fee(&self) -> FeeRate {
-        self.min_tracked_fee
+    /// Write the estimates to `path` through a temporary file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut buf = Vec::new();
+        self.write(&mut buf).expect("vec write");
+        let tmp = path.with_extension("dat.new");
+        std::fs::write(&tmp, buf).with_context(|| format!("writing {}", tmp.display()))?;
+        std::fs::rename(&tmp, path).with_context(|| format!("renaming {} to {}", tmp.display(), path.display()))
     }
 
-    /// Clear all history
-    pub fn clear(&mut self) {
-        self.history.clear();
-        for bucket in &mut self.confirmations {
-            for count in bucket.iter_mut() {
-                *count = 0;
-            }
+    /// Load estimates saved by `save`, unless the file is older than `MAX_FILE_AGE`
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        let age = std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .with_context(|| format!("reading {}", path.display()))?
+            .elapsed()
+            .unwrap_or_default();
+        if age > MAX_FILE_AGE {
+            bail!(
+                "fee estimation file {} too old (age={} hours > {} hours) and will not be used to avoid serving stale estimates",
+                path.display(),
+                age.as_secs() / 3600,
+                MAX_FILE_AGE.as_secs() / 3600
+            );
         }
+        let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        self.read(&mut &data[..]).with_context(|| format!("parsing {}", path.display()))
     }
 }
 
@@ -220,34 +892,74 @@ impl Default for FeeEstimator {
     }
 }
 
-/// Fee estimator statistics
-#[derive(Debug, Clone)]
-pub struct FeeEstimatorStats {
-    pub tracked_txs: usize,
-    pub min_tracked_fee: FeeRate,
-    pub fallback_fee: FeeRate,
-    pub current_height: u32,
-    pub economy_fee: FeeRate,
-    pub standard_fee: FeeRate,
-    pub high_priority_fee: FeeRate,
+/// Rounded feerate for a median in sat/kvB; None for the -1 "no answer"
+fn to_fee_rate(median: f64) -> Option<FeeRate> {
+    (median >= 0.0).then(|| FeeRate::from_sat_per_kvb(median.round() as u64)).filter(|rate| *rate > FeeRate::ZERO)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{absolute, transaction, Amount, OutPoint, ScriptBuf, Transaction, TxIn, TxOut};
+
+    fn entry(n: u32, sat_per_vb: u64, height: u32) -> MempoolEntry {
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([(n % 251) as u8; 32]), n),
+                ..Default::default()
+            }],
+            output: vec![TxOut { value: Amount::from_sat(10_000), script_pubkey: ScriptBuf::new() }],
+        };
+        let vsize = tx.vsize() as u64;
+        MempoolEntry::new(tx, sat_per_vb * vsize, height)
+    }
+
+    /// Every block confirms the previous block's 20 txs at 10 sat/vB, and
+    /// 20 txs at 2 sat/vB stay in the mempool until they expire
+    fn feed_blocks(estimator: &mut FeeEstimator, blocks: u32) {
+        let mut n = 0;
+        let mut pending: Vec<MempoolEntry> = Vec::new();
+        let mut cheap: Vec<MempoolEntry> = Vec::new();
+        for height in 1..=blocks {
+            estimator.process_block(height, &pending);
+            for stale in cheap.drain(..) {
+                estimator.remove_tx(&stale.txid);
+            }
+            pending.clear();
+            for _ in 0..20 {
+                n += 1;
+                let fast = entry(n, 10, height);
+                estimator.process_transaction(&fast, true);
+                pending.push(fast);
+                n += 1;
+                let slow = entry(n, 2, height);
+                estimator.process_transaction(&slow, true);
+                cheap.push(slow);
+            }
+        }
+    }
 
     #[test]
     fn test_fee_estimator_creation() {
         let estimator = FeeEstimator::new();
-        assert_eq!(estimator.history.len(), 0);
-        assert_eq!(estimator.fallback_fee, FeeRate::from_sat_per_vb(20));
+        assert_eq!(estimator.tracked_count(), 0);
+        assert_eq!(estimator.highest_target_tracked(FeeEstimateHorizon::Long), 1008);
     }
 
     #[test]
     fn test_add_transaction() {
         let mut estimator = FeeEstimator::new();
-        estimator.add_tx(FeeRate::from_sat_per_vb(10));
-        assert_eq!(estimator.history.len(), 1);
+        estimator.process_transaction(&entry(1, 10, 0), true);
+        assert_eq!(estimator.tracked_count(), 1);
+        // Side-chain heights and invalid estimates are not tracked
+        estimator.process_transaction(&entry(2, 10, 5), true);
+        estimator.process_transaction(&entry(3, 10, 0), false);
+        assert_eq!(estimator.tracked_count(), 1);
+        assert!(estimator.remove_tx(&entry(1, 10, 0).txid));
+        assert_eq!(estimator.tracked_count(), 0);
     }
 
     #[test]
@@ -260,14 +972,61 @@ mod tests {
     #[test]
     fn test_estimate_with_no_data() {
         let estimator = FeeEstimator::new();
-        let fee = estimator.estimate_fee(FeePriority::Medium);
-        assert_eq!(fee, estimator.fallback_fee);
+        assert_eq!(estimator.estimate_fee(FeePriority::Medium), None);
+        let (fee, calc) = estimator.estimate_smart_fee(6, false);
+        assert_eq!(fee, None);
+        assert_eq!(calc.returned_target, 0);
     }
 
     #[test]
     fn test_bucket_finding() {
         let estimator = FeeEstimator::new();
-        let idx = estimator.find_bucket(FeeRate::from_sat_per_vb(5));
-        assert!(idx < estimator.buckets.len());
+        let stats = &estimator.fee_stats;
+        assert_eq!(stats.bucket_index(1.0), 0);
+        assert_eq!(stats.bucket_index(1000.0), 0);
+        assert_eq!(stats.bucket_index(1001.0), 1);
+        assert_eq!(stats.bucket_index(1e20), estimator.buckets.len() - 1);
+    }
+
+    #[test]
+    fn test_estimates_track_confirmed_feerate() {
+        let mut estimator = FeeEstimator::new();
+        feed_blocks(&mut estimator, 60);
+        assert_eq!(estimator.max_usable_estimate(), 29);
+
+        // Only the 10 sat/vB transactions ever confirm
+        let (fee, calc) = estimator.estimate_smart_fee(1, false);
+        assert_eq!(calc.returned_target, 2);
+        let fee = fee.unwrap();
+        assert!((9_000..=10_500).contains(&fee.as_sat_per_kvb()), "{}", fee);
+
+        let (conservative, _) = estimator.estimate_smart_fee(6, true);
+        assert!(conservative.unwrap() >= estimator.estimate_smart_fee(6, false).0.unwrap());
+
+        let (raw, result) = estimator.estimate_raw_fee(1, 0.95, FeeEstimateHorizon::Short);
+        assert!(raw.is_some());
+        assert!(result.pass.within_target > 0.0);
+        assert_eq!(result.scale, 1);
+        assert_eq!(estimator.estimate_raw_fee(13, 0.95, FeeEstimateHorizon::Short).0, None);
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let mut estimator = FeeEstimator::new();
+        feed_blocks(&mut estimator, 60);
+        assert!(estimator.flush_unconfirmed() > 0);
+        let mut buf = Vec::new();
+        estimator.write(&mut buf).unwrap();
+        assert_eq!(&buf[..4], &149900i32.to_le_bytes());
+
+        let mut loaded = FeeEstimator::new();
+        loaded.read(&mut &buf[..]).unwrap();
+        assert_eq!(loaded.best_seen_height(), 60);
+        // The history the file covers keeps estimates usable after a restart
+        assert_eq!(loaded.max_usable_estimate(), estimator.max_usable_estimate());
+        assert_eq!(loaded.estimate_smart_fee(6, false).0, estimator.estimate_smart_fee(6, false).0);
+
+        buf[0] = 0;
+        assert!(FeeEstimator::new().read(&mut &buf[..]).is_err());
     }
 }
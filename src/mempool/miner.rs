@@ -0,0 +1,483 @@
+//! Block template assembly (Bitcoin Core: node/miner.cpp BlockAssembler).
+//!
+//! Transactions are selected as ancestor packages by modified ancestor
+//! feerate: prioritisation deltas count, and once a package is in the block
+//! its descendants are rescored without it, so a child is judged only by the
+//! ancestors it still needs. The coinbase's weight and sigops are reserved up
+//! front, and non-final transactions are skipped.
+
+use bitcoin::{Sequence, Transaction, Txid, Weight};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::sync::Arc;
+
+use super::entry::{FeeRate, MempoolEntry};
+
+/// Consensus limit on the sigop cost of a block
+pub const MAX_BLOCK_SIGOPS_COST: u64 = 80_000;
+
+/// Weight and sigop cost kept free for the coinbase transaction
+pub const COINBASE_RESERVED_WEIGHT: u64 = 4_000;
+pub const COINBASE_RESERVED_SIGOPS: u64 = 400;
+
+/// Block weight available to transactions by default, leaving room for the
+/// coinbase (Bitcoin Core: DEFAULT_BLOCK_MAX_WEIGHT)
+pub const DEFAULT_BLOCK_MAX_WEIGHT: u64 = Weight::MAX_BLOCK.to_wu() - COINBASE_RESERVED_WEIGHT;
+
+/// Lowest package feerate worth including (Bitcoin Core: DEFAULT_BLOCK_MIN_TX_FEE)
+pub const DEFAULT_BLOCK_MIN_TX_FEE: FeeRate = FeeRate::from_sat_per_kvb(1_000);
+
+/// Packages that fail to fit in a row before a nearly full block is closed
+const MAX_CONSECUTIVE_FAILURES: usize = 1_000;
+
+/// Lock times below this are heights, at or above it timestamps
+const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BlockAssemblerOptions {
+    /// Block weight limit, coinbase included
+    pub max_weight: u64,
+    /// Packages paying less than this are left out
+    pub min_fee_rate: FeeRate,
+}
+
+impl Default for BlockAssemblerOptions {
+    fn default() -> Self {
+        Self { max_weight: DEFAULT_BLOCK_MAX_WEIGHT, min_fee_rate: DEFAULT_BLOCK_MIN_TX_FEE }
+    }
+}
+
+/// Transaction selected for a block template
+#[derive(Debug, Clone)]
+pub struct TemplateTx {
+    pub tx: Arc<Transaction>,
+    pub txid: Txid,
+    /// Fee actually paid, without prioritisation
+    pub fee: u64,
+    pub sigop_cost: u64,
+    pub weight: u64,
+    pub vsize: u64,
+    /// Ancestor feerate of the package it was selected with, prioritisation included
+    pub fee_rate: FeeRate,
+}
+
+/// Transactions for the next block, parents before children
+#[derive(Debug, Clone, Default)]
+pub struct BlockTemplate {
+    /// Height of the block being built
+    pub height: u32,
+    /// Time lock times are compared against (median time past of the tip)
+    pub lock_time_cutoff: u32,
+    pub txs: Vec<TemplateTx>,
+    /// Sum of the transaction fees, without prioritisation
+    pub fees: u64,
+    /// Block weight and sigop cost, coinbase reservation included
+    pub weight: u64,
+    pub sigop_cost: u64,
+}
+
+/// Whether `tx` may be included at `height` (Bitcoin Core: IsFinalTx)
+pub fn is_final_tx(tx: &Transaction, height: u32, lock_time_cutoff: u32) -> bool {
+    let lock_time = tx.lock_time.to_consensus_u32();
+    if lock_time == 0 {
+        return true;
+    }
+    let limit = if lock_time < LOCKTIME_THRESHOLD { height } else { lock_time_cutoff };
+    lock_time < limit || tx.input.iter().all(|input| input.sequence == Sequence::MAX)
+}
+
+struct Node {
+    entry: MempoolEntry,
+    /// Fee including the prioritisation delta
+    modified_fee: u64,
+    weight: u64,
+    /// All in-mempool ancestors, excluding itself
+    ancestors: Vec<usize>,
+    children: Vec<usize>,
+}
+
+/// Package totals of a transaction and its ancestors not yet in the block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PackageScore {
+    fee: u64,
+    vsize: u64,
+    sigop_cost: u64,
+}
+
+/// Heap item ordered by ancestor feerate, best first, then by position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Candidate {
+    score: PackageScore,
+    idx: usize,
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.score.fee as u128 * other.score.vsize as u128)
+            .cmp(&(other.score.fee as u128 * self.score.vsize as u128))
+            .then_with(|| other.idx.cmp(&self.idx))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+pub struct BlockAssembler {
+    options: BlockAssemblerOptions,
+    nodes: Vec<Node>,
+    /// Ancestor scores as of the snapshot
+    ancestor_scores: Vec<PackageScore>,
+    in_block: Vec<bool>,
+    failed: HashSet<usize>,
+    /// Scores of transactions with ancestors in the block (Bitcoin Core: mapModifiedTx)
+    modified: HashMap<usize, PackageScore>,
+    modified_heap: BinaryHeap<Candidate>,
+    block: BlockTemplate,
+}
+
+impl BlockAssembler {
+    /// Assembler over a snapshot of mempool entries, as returned by
+    /// `Mempool::entries_by_depth`
+    pub fn new(entries: Vec<MempoolEntry>, options: BlockAssemblerOptions) -> Self {
+        let max_weight = options
+            .max_weight
+            .clamp(COINBASE_RESERVED_WEIGHT, Weight::MAX_BLOCK.to_wu() - COINBASE_RESERVED_WEIGHT);
+        let options = BlockAssemblerOptions { max_weight, ..options };
+
+        let index: HashMap<Txid, usize> = entries.iter().enumerate().map(|(i, e)| (e.txid, i)).collect();
+        let mut nodes: Vec<Node> = entries
+            .into_iter()
+            .map(|entry| Node {
+                modified_fee: entry.modified_fee(),
+                weight: entry.tx.weight().to_wu(),
+                entry,
+                ancestors: Vec::new(),
+                children: Vec::new(),
+            })
+            .collect();
+
+        // Entries come parents first, so each parent's ancestors are known
+        for i in 0..nodes.len() {
+            let parents: Vec<usize> = nodes[i].entry.parents.iter().filter_map(|p| index.get(p).copied()).collect();
+            let mut ancestors: HashSet<usize> = HashSet::new();
+            for &p in &parents {
+                ancestors.insert(p);
+                ancestors.extend(nodes[p].ancestors.iter().copied());
+                nodes[p].children.push(i);
+            }
+            let mut ancestors: Vec<usize> = ancestors.into_iter().collect();
+            ancestors.sort_unstable();
+            nodes[i].ancestors = ancestors;
+        }
+
+        let ancestor_scores = (0..nodes.len())
+            .map(|i| {
+                nodes[i].ancestors.iter().chain(std::iter::once(&i)).fold(
+                    PackageScore { fee: 0, vsize: 0, sigop_cost: 0 },
+                    |acc, &a| PackageScore {
+                        fee: acc.fee + nodes[a].modified_fee,
+                        vsize: acc.vsize + nodes[a].entry.vsize,
+                        sigop_cost: acc.sigop_cost + nodes[a].entry.sigop_cost,
+                    },
+                )
+            })
+            .collect();
+
+        let len = nodes.len();
+        Self {
+            options,
+            nodes,
+            ancestor_scores,
+            in_block: vec![false; len],
+            failed: HashSet::new(),
+            modified: HashMap::new(),
+            modified_heap: BinaryHeap::new(),
+            block: BlockTemplate::default(),
+        }
+    }
+
+    /// Fill a block at `height`, with time-based lock times compared against
+    /// `lock_time_cutoff` (Bitcoin Core: CreateNewBlock)
+    pub fn create_new_block(mut self, height: u32, lock_time_cutoff: u32) -> BlockTemplate {
+        self.block = BlockTemplate {
+            height,
+            lock_time_cutoff,
+            weight: COINBASE_RESERVED_WEIGHT,
+            sigop_cost: COINBASE_RESERVED_SIGOPS,
+            ..Default::default()
+        };
+        self.add_package_txs();
+        self.block
+    }
+
+    fn skip_mapped(&self, idx: usize) -> bool {
+        self.in_block[idx] || self.modified.contains_key(&idx) || self.failed.contains(&idx)
+    }
+
+    /// Best entry still in the modified set, dropping stale heap items
+    fn best_modified(&mut self) -> Option<Candidate> {
+        while let Some(top) = self.modified_heap.peek() {
+            if self.modified.get(&top.idx) == Some(&top.score) {
+                return Some(*top);
+            }
+            self.modified_heap.pop();
+        }
+        None
+    }
+
+    fn remove_modified(&mut self, idx: usize) {
+        self.modified.remove(&idx);
+    }
+
+    /// Whether the package fits the remaining weight and sigops
+    /// (Bitcoin Core: TestPackage)
+    fn test_package(&self, score: &PackageScore) -> bool {
+        self.block.weight + 4 * score.vsize < self.options.max_weight
+            && self.block.sigop_cost + score.sigop_cost < MAX_BLOCK_SIGOPS_COST
+    }
+
+    /// Ancestors of `idx` not yet in the block, and `idx` itself
+    fn package(&self, idx: usize) -> Vec<usize> {
+        let mut package: Vec<usize> =
+            self.nodes[idx].ancestors.iter().copied().filter(|&a| !self.in_block[a]).collect();
+        package.push(idx);
+        package
+    }
+
+    fn add_to_block(&mut self, idx: usize, fee_rate: FeeRate) {
+        let node = &self.nodes[idx];
+        self.block.txs.push(TemplateTx {
+            tx: node.entry.tx.clone(),
+            txid: node.entry.txid,
+            fee: node.entry.fee,
+            sigop_cost: node.entry.sigop_cost,
+            weight: node.weight,
+            vsize: node.entry.vsize,
+            fee_rate,
+        });
+        self.block.fees += node.entry.fee;
+        self.block.weight += node.weight;
+        self.block.sigop_cost += node.entry.sigop_cost;
+        self.in_block[idx] = true;
+    }
+
+    /// Take the newly included transactions out of their descendants' scores
+    /// (Bitcoin Core: UpdatePackagesForAdded)
+    fn update_packages_for_added(&mut self, added: &[usize]) {
+        for &a in added {
+            let mut descendants: Vec<usize> = Vec::new();
+            let mut stack = vec![a];
+            while let Some(i) = stack.pop() {
+                for &c in &self.nodes[i].children {
+                    if !descendants.contains(&c) {
+                        descendants.push(c);
+                        stack.push(c);
+                    }
+                }
+            }
+            for d in descendants {
+                if self.in_block[d] {
+                    continue;
+                }
+                let node = &self.nodes[a];
+                let score = self.modified.entry(d).or_insert(self.ancestor_scores[d]);
+                score.fee -= node.modified_fee;
+                score.vsize -= node.entry.vsize;
+                score.sigop_cost -= node.entry.sigop_cost;
+                let candidate = Candidate { score: *score, idx: d };
+                self.modified_heap.push(candidate);
+            }
+        }
+    }
+
+    /// Package selection by modified ancestor feerate (Bitcoin Core: addPackageTxs)
+    fn add_package_txs(&mut self) {
+        let mut by_ancestor_score: Vec<usize> = (0..self.nodes.len()).collect();
+        by_ancestor_score.sort_by(|&a, &b| {
+            Candidate { score: self.ancestor_scores[b], idx: b }
+                .cmp(&Candidate { score: self.ancestor_scores[a], idx: a })
+        });
+        let mut next = 0;
+        let mut consecutive_failures = 0;
+
+        loop {
+            while next < by_ancestor_score.len() && self.skip_mapped(by_ancestor_score[next]) {
+                next += 1;
+            }
+            let from_mempool = by_ancestor_score
+                .get(next)
+                .map(|&idx| Candidate { score: self.ancestor_scores[idx], idx });
+            let modified = self.best_modified();
+
+            // Take whichever of the two queues offers the better package
+            let (candidate, using_modified) = match (from_mempool, modified) {
+                (None, None) => break,
+                (Some(c), None) => (c, false),
+                (None, Some(m)) => (m, true),
+                (Some(c), Some(m)) => {
+                    if m > c {
+                        (m, true)
+                    } else {
+                        (c, false)
+                    }
+                }
+            };
+            if !using_modified {
+                next += 1;
+            }
+            let idx = candidate.idx;
+            let score = candidate.score;
+
+            // Everything left pays less
+            if score.fee < self.options.min_fee_rate.fee_for_vsize(score.vsize) {
+                break;
+            }
+
+            if !self.test_package(&score) {
+                if using_modified {
+                    self.remove_modified(idx);
+                    self.failed.insert(idx);
+                }
+                consecutive_failures += 1;
+                if consecutive_failures > MAX_CONSECUTIVE_FAILURES
+                    && self.block.weight + COINBASE_RESERVED_WEIGHT > self.options.max_weight
+                {
+                    break;
+                }
+                continue;
+            }
+
+            let package = self.package(idx);
+            let height = self.block.height;
+            let cutoff = self.block.lock_time_cutoff;
+            if !package.iter().all(|&i| is_final_tx(&self.nodes[i].entry.tx, height, cutoff)) {
+                if using_modified {
+                    self.remove_modified(idx);
+                }
+                self.failed.insert(idx);
+                continue;
+            }
+            consecutive_failures = 0;
+
+            // Ancestors are numbered before descendants
+            let fee_rate = FeeRate::from_fee_and_vsize(score.fee, score.vsize);
+            for &i in &package {
+                self.add_to_block(i, fee_rate);
+                self.remove_modified(i);
+            }
+            self.update_packages_for_added(&package);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mempool::test_util::{funded, spend};
+    use crate::mempool::{Mempool, MempoolPolicy};
+    use bitcoin::{absolute, OutPoint};
+
+    fn assemble(mempool: &Mempool, options: BlockAssemblerOptions) -> BlockTemplate {
+        BlockAssembler::new(mempool.entries_by_depth(), options).create_new_block(101, 1_700_000_000)
+    }
+
+    fn txids(template: &BlockTemplate) -> Vec<Txid> {
+        template.txs.iter().map(|t| t.txid).collect()
+    }
+
+    #[test]
+    fn test_rescores_descendants_of_included_packages() {
+        let mempool = Mempool::new(MempoolPolicy::regtest());
+        let vsize = funded(1).vsize() as u64;
+        let weight = funded(1).weight().to_wu();
+
+        // A 30 sat/vB parent with a 40 sat/vB and a 25 sat/vB child. The
+        // second child's ancestor feerate (over 27.5) beats the 26 sat/vB loner,
+        // but once the parent is in, the child stands at 25 and loses
+        let mut parent = funded(1);
+        parent.output.push(parent.output[0].clone());
+        let (parent_vsize, parent_weight) = (parent.vsize() as u64, parent.weight().to_wu());
+        let parent = mempool.add_tx(parent, 30 * parent_vsize, 100).unwrap();
+        let second = spend(OutPoint::new(parent, 1));
+        let first = mempool.add_tx(spend(OutPoint::new(parent, 0)), 40 * vsize, 100).unwrap();
+        let second = mempool.add_tx(second, 25 * vsize, 100).unwrap();
+        let loner = mempool.add_tx(funded(2), 26 * vsize, 100).unwrap();
+        let cheap = mempool.add_tx(funded(3), 2 * vsize, 100).unwrap();
+
+        // Room for three transactions next to the coinbase
+        let options = BlockAssemblerOptions {
+            max_weight: COINBASE_RESERVED_WEIGHT + parent_weight + 2 * weight + 1,
+            ..Default::default()
+        };
+        let template = assemble(&mempool, options);
+        assert_eq!(txids(&template), vec![parent, first, loner]);
+        assert_eq!(template.fees, 30 * parent_vsize + 66 * vsize);
+        assert_eq!(template.weight, COINBASE_RESERVED_WEIGHT + parent_weight + 2 * weight);
+        assert_eq!(template.sigop_cost, COINBASE_RESERVED_SIGOPS);
+
+        // With room to spare, everything above the minimum feerate goes in
+        let template = assemble(&mempool, BlockAssemblerOptions::default());
+        assert_eq!(txids(&template), vec![parent, first, loner, second, cheap]);
+        let template = assemble(
+            &mempool,
+            BlockAssemblerOptions { min_fee_rate: FeeRate::from_sat_per_vb(5), ..Default::default() },
+        );
+        assert_eq!(txids(&template), vec![parent, first, loner, second]);
+    }
+
+    #[test]
+    fn test_prioritisation_and_finality() {
+        let mempool = Mempool::new(MempoolPolicy::regtest());
+        let vsize = funded(1).vsize() as u64;
+
+        let low = mempool.add_tx(funded(1), 2 * vsize, 100).unwrap();
+        let high = mempool.add_tx(funded(2), 10 * vsize, 100).unwrap();
+        mempool.prioritise_transaction(low, 20 * vsize as i64);
+        let template = assemble(&mempool, BlockAssemblerOptions::default());
+        assert_eq!(txids(&template), vec![low, high]);
+        // Templates report the fee actually paid
+        assert_eq!(template.txs[0].fee, 2 * vsize);
+        assert_eq!(template.fees, 12 * vsize);
+
+        // Locked until height 102, so not final in block 101; its child waits too
+        let mut locked = funded(3);
+        locked.lock_time = absolute::LockTime::from_consensus(102);
+        locked.input[0].sequence = Sequence::ENABLE_LOCKTIME_NO_RBF;
+        let locked = mempool.add_tx(locked, 50 * vsize, 100).unwrap();
+        mempool.add_tx(spend(OutPoint::new(locked, 0)), 50 * vsize, 100).unwrap();
+        let template = assemble(&mempool, BlockAssemblerOptions::default());
+        assert_eq!(txids(&template), vec![low, high]);
+
+        let mut tx = funded(4);
+        tx.lock_time = absolute::LockTime::from_consensus(101);
+        tx.input[0].sequence = Sequence::ENABLE_LOCKTIME_NO_RBF;
+        assert!(!is_final_tx(&tx, 101, 0));
+        assert!(is_final_tx(&tx, 102, 0));
+        tx.input[0].sequence = Sequence::MAX;
+        assert!(is_final_tx(&tx, 101, 0));
+        tx.lock_time = absolute::LockTime::from_consensus(1_700_000_000);
+        tx.input[0].sequence = Sequence::ENABLE_LOCKTIME_NO_RBF;
+        assert!(!is_final_tx(&tx, 101, 1_700_000_000));
+        assert!(is_final_tx(&tx, 101, 1_700_000_001));
+    }
+
+    #[test]
+    fn test_sigop_limit() {
+        let mempool = Mempool::new(MempoolPolicy::regtest());
+        let vsize = funded(1).vsize() as u64;
+        let heavy = mempool.add_tx(funded(1), 20 * vsize, 100).unwrap();
+        let light = mempool.add_tx(funded(2), 10 * vsize, 100).unwrap();
+
+        let mut entries = mempool.entries_by_depth();
+        for entry in &mut entries {
+            if entry.txid == heavy {
+                entry.sigop_cost = MAX_BLOCK_SIGOPS_COST - COINBASE_RESERVED_SIGOPS;
+            }
+        }
+        let template = BlockAssembler::new(entries, BlockAssemblerOptions::default()).create_new_block(101, 0);
+        assert_eq!(txids(&template), vec![light]);
+    }
+}
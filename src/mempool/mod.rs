@@ -1,9 +1,22 @@
 pub mod entry;
+pub mod ephemeral;
 pub mod fees;
+pub mod miner;
+pub mod persist;
 pub mod policy;
+pub mod projection;
+pub mod standard;
+#[cfg(test)]
+mod test_util;
+pub mod truc;
 pub mod txmempool;
+pub mod validation;
 
 pub use entry::{FeeRate, MempoolEntry};
 pub use fees::FeeEstimator;
 pub use policy::MempoolPolicy;
-pub use txmempool::{Mempool, MempoolStats};
+pub use txmempool::{Mempool, MempoolEvent, MempoolStats, RemovalReason};
+pub use validation::{
+    check_package_consistency, is_child_with_parents_tree, AcceptedTx, PackageTxResult, TxValidationError,
+    TxValidationResult, MAX_PACKAGE_COUNT,
+};
@@ -0,0 +1,276 @@
+//! mempool.dat persistence (Bitcoin Core: node/mempool_persist.cpp).
+//!
+//! File layout, all integers little-endian:
+//! - u64 version (1, or 2 with an obfuscation key)
+//! - version 2 only: 8-byte XOR key, length-prefixed; every later byte is
+//!   XORed with `key[file offset % 8]`
+//! - u64 count, then per transaction: tx with witness, i64 entry time, i64 fee delta
+//! - map of txid -> fee delta for prioritised transactions not in the mempool
+//! - set of txids still waiting for their initial broadcast
+
+use anyhow::{anyhow, bail, Context, Result};
+use bitcoin::consensus::{Decodable, Encodable};
+use bitcoin::hashes::Hash;
+use bitcoin::{Transaction, Txid, VarInt};
+use rand::RngCore;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::txmempool::Mempool;
+
+pub const MEMPOOL_DUMP_VERSION_NO_XOR_KEY: u64 = 1;
+pub const MEMPOOL_DUMP_VERSION: u64 = 2;
+
+/// File name under the data directory
+pub const MEMPOOL_FILENAME: &str = "mempool.dat";
+
+/// Options for `load_mempool` (Bitcoin Core: ImportMempoolOptions)
+#[derive(Debug, Clone, Copy)]
+pub struct LoadOptions {
+    /// Give every transaction the current time instead of its saved entry time
+    pub use_current_time: bool,
+    /// Apply saved prioritisation deltas
+    pub apply_fee_delta: bool,
+    /// Restore the set of transactions waiting for initial broadcast
+    pub apply_unbroadcast: bool,
+}
+
+impl Default for LoadOptions {
+    /// Startup load: keep entry times and restore everything
+    fn default() -> Self {
+        Self { use_current_time: false, apply_fee_delta: true, apply_unbroadcast: true }
+    }
+}
+
+/// Outcome of `load_mempool`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadStats {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub expired: usize,
+    pub already_there: usize,
+    pub unbroadcast: usize,
+}
+
+/// Contents of a mempool.dat file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MempoolDump {
+    /// (transaction, entry time in unix seconds, fee delta), parents first
+    pub txs: Vec<(Transaction, i64, i64)>,
+    pub deltas: Vec<(Txid, i64)>,
+    pub unbroadcast: Vec<Txid>,
+}
+
+impl MempoolDump {
+    /// Snapshot the mempool
+    pub fn from_mempool(mempool: &Mempool) -> Self {
+        let mut deltas: HashMap<Txid, i64> = mempool.fee_deltas().into_iter().collect();
+        let txs = mempool
+            .entries_by_depth()
+            .into_iter()
+            .map(|entry| {
+                // In-mempool deltas travel with their transaction
+                deltas.remove(&entry.txid);
+                let time = entry.time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+                ((*entry.tx).clone(), time, entry.fee_delta)
+            })
+            .collect();
+
+        let mut deltas: Vec<(Txid, i64)> = deltas.into_iter().collect();
+        deltas.sort_by_key(|(txid, _)| txid.to_byte_array());
+        let mut unbroadcast = mempool.unbroadcast_txids();
+        unbroadcast.sort_by_key(|txid| txid.to_byte_array());
+        Self { txs, deltas, unbroadcast }
+    }
+
+    /// Serialize as a version 2 file obfuscated with `key`
+    pub fn serialize(&self, key: [u8; 8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        MEMPOOL_DUMP_VERSION.consensus_encode(&mut buf).expect("vec write");
+        key.to_vec().consensus_encode(&mut buf).expect("vec write");
+        let header_len = buf.len();
+
+        (self.txs.len() as u64).consensus_encode(&mut buf).expect("vec write");
+        for (tx, time, fee_delta) in &self.txs {
+            tx.consensus_encode(&mut buf).expect("vec write");
+            time.consensus_encode(&mut buf).expect("vec write");
+            fee_delta.consensus_encode(&mut buf).expect("vec write");
+        }
+        VarInt(self.deltas.len() as u64).consensus_encode(&mut buf).expect("vec write");
+        for (txid, delta) in &self.deltas {
+            txid.consensus_encode(&mut buf).expect("vec write");
+            delta.consensus_encode(&mut buf).expect("vec write");
+        }
+        VarInt(self.unbroadcast.len() as u64).consensus_encode(&mut buf).expect("vec write");
+        for txid in &self.unbroadcast {
+            txid.consensus_encode(&mut buf).expect("vec write");
+        }
+
+        xor(&mut buf[header_len..], &key, header_len);
+        buf
+    }
+
+    /// Parse a version 1 or 2 file
+    pub fn deserialize(data: &[u8]) -> Result<Self> {
+        let mut reader = data;
+        let version = u64::consensus_decode(&mut reader)?;
+        let key = match version {
+            MEMPOOL_DUMP_VERSION_NO_XOR_KEY => [0; 8],
+            MEMPOOL_DUMP_VERSION => Vec::<u8>::consensus_decode(&mut reader)?
+                .try_into()
+                .map_err(|_| anyhow!("bad obfuscation key size"))?,
+            other => bail!("unknown mempool.dat version {}", other),
+        };
+        let header_len = data.len() - reader.len();
+        let mut body = reader.to_vec();
+        xor(&mut body, &key, header_len);
+        let mut reader = &body[..];
+
+        let mut dump = MempoolDump::default();
+        let count = u64::consensus_decode(&mut reader)?;
+        for _ in 0..count {
+            let tx = Transaction::consensus_decode(&mut reader)?;
+            let time = i64::consensus_decode(&mut reader)?;
+            let fee_delta = i64::consensus_decode(&mut reader)?;
+            dump.txs.push((tx, time, fee_delta));
+        }
+        let count = VarInt::consensus_decode(&mut reader)?.0;
+        for _ in 0..count {
+            let txid = Txid::consensus_decode(&mut reader)?;
+            let delta = i64::consensus_decode(&mut reader)?;
+            dump.deltas.push((txid, delta));
+        }
+        let count = VarInt::consensus_decode(&mut reader)?.0;
+        for _ in 0..count {
+            dump.unbroadcast.push(Txid::consensus_decode(&mut reader)?);
+        }
+        Ok(dump)
+    }
+}
+
+/// XOR `data`, which starts at `offset` in the file, with the rolling key
+fn xor(data: &mut [u8], key: &[u8; 8], offset: usize) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= key[(offset + i) % key.len()];
+    }
+}
+
+/// Write the mempool to `path` through a temporary file, so a crash never
+/// leaves a truncated dump (Bitcoin Core: DumpMempool)
+pub fn dump_mempool(mempool: &Mempool, path: &Path) -> Result<usize> {
+    let dump = MempoolDump::from_mempool(mempool);
+    let mut key = [0u8; 8];
+    rand::thread_rng().fill_bytes(&mut key);
+
+    let tmp = path.with_extension("dat.new");
+    std::fs::write(&tmp, dump.serialize(key)).with_context(|| format!("writing {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("renaming {} to {}", tmp.display(), path.display()))?;
+    Ok(dump.txs.len())
+}
+
+/// Re-validate every transaction in `path` against the current tip and add
+/// those still valid (Bitcoin Core: LoadMempool)
+pub fn load_mempool(mempool: &Mempool, path: &Path, opts: LoadOptions) -> Result<LoadStats> {
+    let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    let dump = MempoolDump::deserialize(&data).with_context(|| format!("parsing {}", path.display()))?;
+
+    let now = SystemTime::now();
+    let expiry_cutoff = now
+        .checked_sub(mempool.policy().expiry)
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs() as i64);
+
+    let mut stats = LoadStats::default();
+    for (tx, time, fee_delta) in dump.txs {
+        let txid = tx.compute_txid();
+        if opts.apply_fee_delta && fee_delta != 0 {
+            mempool.prioritise_transaction(txid, fee_delta);
+        }
+        if !opts.use_current_time && time <= expiry_cutoff {
+            stats.expired += 1;
+            continue;
+        }
+        let time = if opts.use_current_time { now } else { UNIX_EPOCH + Duration::from_secs(time.max(0) as u64) };
+        match mempool.accept_tx_at(tx, time) {
+            Ok(_) => stats.succeeded += 1,
+            Err(_) if mempool.contains(&txid) => stats.already_there += 1,
+            Err(_) => stats.failed += 1,
+        }
+    }
+
+    if opts.apply_fee_delta {
+        for (txid, delta) in dump.deltas {
+            mempool.prioritise_transaction(txid, delta);
+        }
+    }
+    if opts.apply_unbroadcast {
+        let unbroadcast: HashSet<Txid> = dump.unbroadcast.into_iter().collect();
+        for txid in unbroadcast {
+            if mempool.contains(&txid) {
+                mempool.add_unbroadcast(txid);
+                stats.unbroadcast += 1;
+            }
+        }
+    }
+
+    eprintln!(
+        "[mempool] imported mempool transactions from disk: {} succeeded, {} failed, {} expired, {} already there, {} waiting for initial broadcast",
+        stats.succeeded, stats.failed, stats.expired, stats.already_there, stats.unbroadcast
+    );
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mempool::MempoolPolicy;
+    use bitcoin::{absolute, transaction, Amount, OutPoint, ScriptBuf, TxIn, TxOut, WPubkeyHash};
+
+    fn tx(n: u8) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn { previous_output: OutPoint::new(Txid::from_byte_array([n; 32]), 0), ..Default::default() }],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros()),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_dump_round_trip() {
+        let dump = MempoolDump {
+            txs: vec![(tx(1), 1_700_000_000, 0), (tx(2), 1_700_000_100, -500)],
+            deltas: vec![(Txid::from_byte_array([9; 32]), 1_000)],
+            unbroadcast: vec![tx(2).compute_txid()],
+        };
+        let data = dump.serialize([1, 2, 3, 4, 5, 6, 7, 8]);
+        // Version and key stay in the clear
+        assert_eq!(&data[..8], &MEMPOOL_DUMP_VERSION.to_le_bytes());
+        assert_eq!(&data[8..17], &[8, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(MempoolDump::deserialize(&data).unwrap(), dump);
+
+        // An all-zero key leaves the body readable as version 1
+        let mut plain = dump.serialize([0; 8]);
+        plain.splice(..17, MEMPOOL_DUMP_VERSION_NO_XOR_KEY.to_le_bytes());
+        assert_eq!(MempoolDump::deserialize(&plain).unwrap(), dump);
+    }
+
+    #[test]
+    fn test_snapshot_keeps_deltas_and_unbroadcast() {
+        let mempool = Mempool::new(MempoolPolicy::regtest());
+        let txid = mempool.add_tx(tx(1), 1_000, 100).unwrap();
+        let absent = Txid::from_byte_array([7; 32]);
+        mempool.prioritise_transaction(txid, 2_000);
+        mempool.prioritise_transaction(absent, 300);
+        mempool.add_unbroadcast(txid);
+
+        let dump = MempoolDump::from_mempool(&mempool);
+        assert_eq!(dump.txs.len(), 1);
+        assert_eq!(dump.txs[0].2, 2_000);
+        assert_eq!(dump.deltas, vec![(absent, 300)]);
+        assert_eq!(dump.unbroadcast, vec![txid]);
+    }
+}
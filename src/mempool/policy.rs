@@ -1,6 +1,13 @@
 use super::entry::FeeRate;
+use bitcoin::TxOut;
 use std::time::Duration;
 
+/// Scripts longer than this can never be spent (Bitcoin Core: MAX_SCRIPT_SIZE)
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+
+/// Largest transaction the CPFP carve-out admits, in virtual bytes
+pub const EXTRA_DESCENDANT_TX_SIZE_LIMIT: u64 = 10_000;
+
 /// Mempool policy configuration
 #[derive(Debug, Clone)]
 pub struct MempoolPolicy {
@@ -31,9 +38,15 @@ pub struct MempoolPolicy {
     /// Require standard transactions
     pub require_standard: bool,
 
-    /// Accept non-standard transactions
+    /// Relay bare multisig outputs
     pub permit_bare_multisig: bool,
 
+    /// Relay OP_RETURN data outputs
+    pub datacarrier: bool,
+
+    /// Largest OP_RETURN output script, in bytes
+    pub max_datacarrier_bytes: usize,
+
     /// Maximum standard transaction size
     pub max_tx_size: usize,
 
@@ -48,6 +61,12 @@ pub struct MempoolPolicy {
 
     /// Enable RBF
     pub enable_rbf: bool,
+
+    /// Allow replacing transactions that do not signal BIP125 replaceability
+    pub full_rbf: bool,
+
+    /// Most transactions one replacement may evict, descendants included (BIP125 rule 5)
+    pub max_replacement_candidates: usize,
 }
 
 impl Default for MempoolPolicy {
@@ -63,11 +82,15 @@ impl Default for MempoolPolicy {
             max_descendant_size_kb: 101,
             require_standard: true,
             permit_bare_multisig: true,
+            datacarrier: true,
+            max_datacarrier_bytes: 83,
             max_tx_size: 100_000, // 100 KB
             max_tx_sigops: 4000,
             dust_relay_fee: FeeRate::from_sat_per_vb(3),
             incremental_relay_fee: FeeRate::from_sat_per_vb(1),
             enable_rbf: true,
+            full_rbf: false,
+            max_replacement_candidates: 100,
         }
     }
 }
@@ -146,6 +169,19 @@ impl MempoolPolicy {
         Ok(())
     }
 
+    /// Limits for the CPFP carve-out: a small transaction with a single
+    /// unconfirmed parent may exceed that parent's descendant limits by one.
+    /// Lets either side of a two-party contract attach a fee bump even when
+    /// the other side has filled the descendant limit.
+    pub fn cpfp_carve_out(&self) -> Self {
+        Self {
+            max_ancestors: 2,
+            max_descendants: self.max_descendants + 1,
+            max_descendant_size_kb: self.max_descendant_size_kb + (EXTRA_DESCENDANT_TX_SIZE_LIMIT / 1000) as usize,
+            ..self.clone()
+        }
+    }
+
     /// Check if RBF is allowed and properly signaled
     pub fn check_rbf(&self, signals_rbf: bool, fee_delta: u64, size_delta: i64) -> Result<(), String> {
         if !self.enable_rbf {
@@ -169,11 +205,22 @@ impl MempoolPolicy {
         Ok(())
     }
 
-    /// Calculate dust threshold for an output
-    pub fn dust_threshold(&self, output_size: usize) -> u64 {
-        // Assume P2PKH output (34 bytes) + overhead
-        let total_size = output_size + 148; // 148 = typical input size
-        self.dust_relay_fee.fee_for_vsize(total_size as u64) * 3
+    /// Value below which spending `output` costs more than it is worth at the
+    /// dust relay fee (Bitcoin Core: GetDustThreshold). P2PKH comes to 546
+    /// sat, P2WPKH to 294 and P2TR to 330 at the default 3 sat/vB.
+    pub fn dust_threshold(&self, output: &TxOut) -> u64 {
+        if output.script_pubkey.is_op_return() || output.script_pubkey.len() > MAX_SCRIPT_SIZE {
+            return 0;
+        }
+        let script_len = output.script_pubkey.len();
+        let output_size = 8 + bitcoin::VarInt(script_len as u64).size() + script_len;
+        // Outpoint, script length, sequence plus a typical input script or witness
+        let spend_size = if output.script_pubkey.is_witness_program() { 32 + 4 + 1 + 107 / 4 + 4 } else { 32 + 4 + 1 + 107 + 4 };
+        self.dust_relay_fee.fee_for_vsize((output_size + spend_size) as u64)
+    }
+
+    pub fn is_dust(&self, output: &TxOut) -> bool {
+        output.value.to_sat() < self.dust_threshold(output)
     }
 }
 
@@ -203,6 +250,30 @@ mod tests {
         assert!(policy.check_ancestor_limits(10, 200_000).is_err());
     }
 
+    #[test]
+    fn test_cpfp_carve_out_limits() {
+        let carve_out = MempoolPolicy::default().cpfp_carve_out();
+        assert!(carve_out.check_descendant_limits(26, 50_000).is_ok());
+        assert!(carve_out.check_descendant_limits(27, 50_000).is_err());
+        assert!(carve_out.check_ancestor_limits(3, 1_000).is_err());
+    }
+
+    #[test]
+    fn test_dust_threshold() {
+        use bitcoin::hashes::Hash;
+        use bitcoin::{Amount, PubkeyHash, ScriptBuf, WPubkeyHash};
+
+        let policy = MempoolPolicy::default();
+        let output = |script_pubkey: ScriptBuf| TxOut { value: Amount::ZERO, script_pubkey };
+        // 3 sat/vB on 13 output bytes + 67 witness input bytes
+        let p2a = output(ScriptBuf::from(super::super::standard::P2A_SCRIPT.to_vec()));
+        assert_eq!(policy.dust_threshold(&p2a), 240);
+        assert!(policy.is_dust(&p2a));
+        assert_eq!(policy.dust_threshold(&output(ScriptBuf::new_p2pkh(&PubkeyHash::all_zeros()))), 546);
+        assert_eq!(policy.dust_threshold(&output(ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros()))), 294);
+        assert_eq!(policy.dust_threshold(&output(ScriptBuf::new_op_return([0u8; 4]))), 0);
+    }
+
     #[test]
     fn test_rbf_check() {
         let policy = MempoolPolicy::default();
@@ -0,0 +1,177 @@
+//! Instantaneous fee view from the current mempool contents.
+//!
+//! `project_blocks` fills the next blocks the way a miner would, running the
+//! block assembler once per block over what is still left, so the lowest
+//! feerate in the first projected block is the marginal feerate for
+//! next-block inclusion right now. `fee_histogram` buckets the
+//! mempool by feerate, highest first.
+
+use bitcoin::Txid;
+use std::collections::{HashMap, HashSet};
+
+use super::entry::FeeRate;
+use super::miner::{BlockAssembler, BlockAssemblerOptions, BlockTemplate};
+use super::txmempool::Mempool;
+
+/// Histogram bins start at this many vbytes and grow by 10% each
+/// (ElectrumX's mempool.get_fee_histogram compaction)
+pub const DEFAULT_HISTOGRAM_BIN_VSIZE: u64 = 100_000;
+
+/// One block the current mempool would fill
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectedBlock {
+    /// Weight and vsize of the transactions, without the coinbase
+    pub weight: u64,
+    pub vsize: u64,
+    pub fees: u64,
+    /// Transactions in inclusion order
+    pub txids: Vec<Txid>,
+    /// Package feerate each transaction was included at, lowest to highest
+    pub fee_rates: Vec<FeeRate>,
+}
+
+impl ProjectedBlock {
+    fn from_template(template: &BlockTemplate) -> Self {
+        let mut fee_rates: Vec<FeeRate> = template.txs.iter().map(|t| t.fee_rate).collect();
+        fee_rates.sort_unstable();
+        Self {
+            weight: template.txs.iter().map(|t| t.weight).sum(),
+            vsize: template.txs.iter().map(|t| t.vsize).sum(),
+            fees: template.fees,
+            txids: template.txs.iter().map(|t| t.txid).collect(),
+            fee_rates,
+        }
+    }
+
+    pub fn min_fee_rate(&self) -> FeeRate {
+        self.fee_rates.first().copied().unwrap_or(FeeRate::ZERO)
+    }
+
+    pub fn median_fee_rate(&self) -> FeeRate {
+        self.fee_rates.get(self.fee_rates.len() / 2).copied().unwrap_or(FeeRate::ZERO)
+    }
+
+    pub fn max_fee_rate(&self) -> FeeRate {
+        self.fee_rates.last().copied().unwrap_or(FeeRate::ZERO)
+    }
+}
+
+/// Feerate bin of the mempool histogram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistogramBin {
+    /// Lowest feerate in the bin
+    pub fee_rate: FeeRate,
+    pub vsize: u64,
+    pub count: usize,
+}
+
+/// The next `max_blocks` blocks of at most `max_weight` each, coinbase
+/// reservation included, that the current mempool would fill, in order.
+/// Each block is a `BlockAssembler` run over what the previous ones left.
+pub fn project_blocks(mempool: &Mempool, max_blocks: usize, max_weight: u64) -> Vec<ProjectedBlock> {
+    let options = BlockAssemblerOptions { max_weight, min_fee_rate: FeeRate::ZERO };
+    let mut entries = mempool.entries_by_depth();
+    let mut blocks = Vec::new();
+    while blocks.len() < max_blocks && !entries.is_empty() {
+        // Admission already required finality in the next block, and later
+        // blocks only loosen lock times
+        let template = BlockAssembler::new(entries.clone(), options).create_new_block(u32::MAX, u32::MAX);
+        if template.txs.is_empty() {
+            break;
+        }
+        let included: HashSet<Txid> = template.txs.iter().map(|t| t.txid).collect();
+        entries.retain(|e| !included.contains(&e.txid));
+        blocks.push(ProjectedBlock::from_template(&template));
+    }
+    blocks
+}
+
+/// Mempool vsize by feerate, highest first. Feerates are rounded down to
+/// 0.1 sat/vB, and adjacent feerates are merged until a bin holds about
+/// `bin_vsize` vbytes; each following bin is 10% larger.
+pub fn fee_histogram(mempool: &Mempool, bin_vsize: u64) -> Vec<HistogramBin> {
+    let mut by_rate: HashMap<u64, (u64, usize)> = HashMap::new();
+    for entry in mempool.entries_by_depth() {
+        let rate = entry.fee_rate.as_sat_per_kvb() / 100 * 100;
+        let bin = by_rate.entry(rate).or_default();
+        bin.0 += entry.vsize;
+        bin.1 += 1;
+    }
+    let mut rates: Vec<(u64, (u64, usize))> = by_rate.into_iter().collect();
+    rates.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+
+    let mut bins = Vec::new();
+    let mut bin_size = bin_vsize as f64;
+    let mut overshoot = 0.0;
+    let (mut vsize, mut count) = (0u64, 0usize);
+    let last = rates.len().saturating_sub(1);
+    for (i, (rate, (size, n))) in rates.into_iter().enumerate() {
+        vsize += size;
+        count += n;
+        if vsize as f64 + overshoot > bin_size || i == last {
+            bins.push(HistogramBin { fee_rate: FeeRate::from_sat_per_kvb(rate), vsize, count });
+            overshoot += vsize as f64 - bin_size;
+            vsize = 0;
+            count = 0;
+            bin_size *= 1.1;
+        }
+    }
+    bins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mempool::miner::{COINBASE_RESERVED_WEIGHT, DEFAULT_BLOCK_MAX_WEIGHT};
+    use crate::mempool::test_util::{funded, spend};
+    use crate::mempool::MempoolPolicy;
+    use bitcoin::OutPoint;
+
+    #[test]
+    fn test_projection_orders_by_package_feerate() {
+        let mempool = Mempool::new(MempoolPolicy::regtest());
+        let vsize = funded(1).vsize() as u64;
+        let weight = funded(1).weight().to_wu();
+
+        // A 1 sat/vB parent carried by a 20 sat/vB child beats a 5 sat/vB loner
+        let parent = mempool.add_tx(funded(1), vsize, 100).unwrap();
+        let child = mempool.add_tx(spend(OutPoint::new(parent, 0)), 20 * vsize, 100).unwrap();
+        let loner = mempool.add_tx(funded(2), 5 * vsize, 100).unwrap();
+        let cheap = mempool.add_tx(funded(3), vsize, 100).unwrap();
+
+        // Blocks hold two transactions each
+        let max_weight = COINBASE_RESERVED_WEIGHT + 2 * weight + 1;
+        let blocks = project_blocks(&mempool, 8, max_weight);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].txids, vec![parent, child]);
+        assert_eq!(blocks[0].min_fee_rate(), FeeRate::from_fee_and_vsize(21 * vsize, 2 * vsize));
+        assert_eq!(blocks[0].fees, 21 * vsize);
+        assert_eq!((blocks[0].weight, blocks[0].vsize), (2 * weight, 2 * vsize));
+        assert_eq!(blocks[1].txids, vec![loner, cheap]);
+        assert_eq!(blocks[1].min_fee_rate(), FeeRate::from_sat_per_vb(1));
+        assert_eq!(blocks[1].max_fee_rate(), FeeRate::from_sat_per_vb(5));
+
+        assert_eq!(project_blocks(&mempool, 1, max_weight).len(), 1);
+        assert_eq!(project_blocks(&mempool, 8, DEFAULT_BLOCK_MAX_WEIGHT)[0].txids.len(), 4);
+    }
+
+    #[test]
+    fn test_histogram_merges_feerates() {
+        let mempool = Mempool::new(MempoolPolicy::regtest());
+        let vsize = funded(1).vsize() as u64;
+        for (n, rate) in [(1u8, 10u64), (2, 10), (3, 5), (4, 2)] {
+            mempool.add_tx(funded(n), rate * vsize, 100).unwrap();
+        }
+
+        let bins = fee_histogram(&mempool, 2 * vsize);
+        assert_eq!(
+            bins,
+            vec![
+                HistogramBin { fee_rate: FeeRate::from_sat_per_vb(5), vsize: 3 * vsize, count: 3 },
+                HistogramBin { fee_rate: FeeRate::from_sat_per_vb(2), vsize, count: 1 },
+            ]
+        );
+        // One large bin takes everything
+        assert_eq!(fee_histogram(&mempool, 1_000_000).len(), 1);
+    }
+}
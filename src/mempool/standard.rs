@@ -0,0 +1,375 @@
+//! Standardness rules (Bitcoin Core: policy/policy.cpp).
+//!
+//! Transactions that are valid by consensus may still be refused relay when
+//! they use unusual scripts or sizes. These checks only apply while
+//! `MempoolPolicy::require_standard` is set.
+
+use super::policy::MempoolPolicy;
+use super::validation::{TxValidationError, TxValidationResult};
+use bitcoin::opcodes::all::OP_CHECKMULTISIG;
+use bitcoin::opcodes::Opcode;
+use bitcoin::script::Instruction;
+use bitcoin::{OutPoint, Script, Transaction, TxOut};
+use std::collections::HashMap;
+
+/// Pay-to-anchor output script: witness v1 with the 2-byte program 0x4e73
+pub const P2A_SCRIPT: [u8; 4] = [0x51, 0x02, 0x4e, 0x73];
+
+/// Transaction versions relayed by default
+pub const TX_MIN_STANDARD_VERSION: i32 = 1;
+pub const TX_MAX_STANDARD_VERSION: i32 = 3;
+
+/// Largest standard scriptSig, enough for a 15-of-15 P2SH multisig spend
+pub const MAX_STANDARD_SCRIPTSIG_SIZE: usize = 1650;
+
+/// Largest sigop count of a standard P2SH redeem script
+pub const MAX_P2SH_SIGOPS: usize = 15;
+
+/// P2WSH witness limits
+pub const MAX_STANDARD_P2WSH_SCRIPT_SIZE: usize = 3600;
+pub const MAX_STANDARD_P2WSH_STACK_ITEMS: usize = 100;
+pub const MAX_STANDARD_P2WSH_STACK_ITEM_SIZE: usize = 80;
+
+/// Largest standard tapscript stack item
+pub const MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE: usize = 80;
+
+/// Sigop cost units per legacy sigop
+const WITNESS_SCALE_FACTOR: usize = 4;
+
+/// Tapscript leaf version (BIP342)
+const TAPROOT_LEAF_TAPSCRIPT: u8 = 0xc0;
+
+/// First byte of a taproot annex (BIP341)
+const ANNEX_TAG: u8 = 0x50;
+
+/// Keyless anchor output anyone can spend to attach a fee-bumping child
+pub fn is_pay_to_anchor(script: &Script) -> bool {
+    script.as_bytes() == P2A_SCRIPT
+}
+
+/// Output script template (Bitcoin Core: TxoutType)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxoutType {
+    NonStandard,
+    Anchor,
+    PubKey,
+    PubKeyHash,
+    ScriptHash,
+    /// Bare multisig with `m`-of-`n` keys
+    Multisig { m: u8, n: u8 },
+    NullData,
+    WitnessV0KeyHash,
+    WitnessV0ScriptHash,
+    WitnessV1Taproot,
+    WitnessUnknown,
+}
+
+impl TxoutType {
+    /// Name as reported by Bitcoin Core RPCs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxoutType::NonStandard => "nonstandard",
+            TxoutType::Anchor => "anchor",
+            TxoutType::PubKey => "pubkey",
+            TxoutType::PubKeyHash => "pubkeyhash",
+            TxoutType::ScriptHash => "scripthash",
+            TxoutType::Multisig { .. } => "multisig",
+            TxoutType::NullData => "nulldata",
+            TxoutType::WitnessV0KeyHash => "witness_v0_keyhash",
+            TxoutType::WitnessV0ScriptHash => "witness_v0_scripthash",
+            TxoutType::WitnessV1Taproot => "witness_v1_taproot",
+            TxoutType::WitnessUnknown => "witness_unknown",
+        }
+    }
+}
+
+/// Classify an output script (Bitcoin Core: Solver)
+pub fn classify(script: &Script) -> TxoutType {
+    if script.is_p2sh() {
+        TxoutType::ScriptHash
+    } else if script.is_p2wpkh() {
+        TxoutType::WitnessV0KeyHash
+    } else if script.is_p2wsh() {
+        TxoutType::WitnessV0ScriptHash
+    } else if script.is_p2tr() {
+        TxoutType::WitnessV1Taproot
+    } else if is_pay_to_anchor(script) {
+        TxoutType::Anchor
+    } else if script.is_witness_program() {
+        TxoutType::WitnessUnknown
+    } else if is_null_data(script) {
+        TxoutType::NullData
+    } else if script.is_p2pk() {
+        TxoutType::PubKey
+    } else if script.is_p2pkh() {
+        TxoutType::PubKeyHash
+    } else if let Some((m, n)) = multisig_params(script) {
+        TxoutType::Multisig { m, n }
+    } else {
+        TxoutType::NonStandard
+    }
+}
+
+/// OP_RETURN followed only by pushes
+fn is_null_data(script: &Script) -> bool {
+    let bytes = script.as_bytes();
+    !bytes.is_empty() && script.is_op_return() && Script::from_bytes(&bytes[1..]).is_push_only()
+}
+
+/// `m` and `n` of an `OP_m <pubkey>... OP_n OP_CHECKMULTISIG` script
+fn multisig_params(script: &Script) -> Option<(u8, u8)> {
+    let small_int = |op: Opcode| match op.to_u8() {
+        n @ 0x51..=0x60 => Some(n - 0x50),
+        _ => None,
+    };
+    let instructions: Vec<Instruction> = script.instructions().collect::<Result<_, _>>().ok()?;
+    let (first, rest) = instructions.split_first()?;
+    let (last, rest) = rest.split_last()?;
+    let (n_op, keys) = rest.split_last()?;
+    if *last != Instruction::Op(OP_CHECKMULTISIG) {
+        return None;
+    }
+    let m = small_int(first.opcode()?)?;
+    let n = small_int(n_op.opcode()?)?;
+    let keys_ok = keys.iter().all(|k| matches!(k.push_bytes(), Some(b) if b.len() == 33 || b.len() == 65));
+    (keys_ok && keys.len() == n as usize && m <= n).then_some((m, n))
+}
+
+/// Whether an output script may be relayed (Bitcoin Core: IsStandard)
+pub fn is_standard_script(script: &Script, policy: &MempoolPolicy) -> bool {
+    match classify(script) {
+        TxoutType::NonStandard => false,
+        // Bare multisig beyond 1-of-3..3-of-3 is not relayed
+        TxoutType::Multisig { n, .. } => (1..=3).contains(&n),
+        TxoutType::NullData => policy.datacarrier && script.len() <= policy.max_datacarrier_bytes,
+        _ => true,
+    }
+}
+
+fn nonstandard(reason: &str) -> TxValidationError {
+    TxValidationError::new(TxValidationResult::NotStandard, reason, "")
+}
+
+/// Checks that need no spent outputs (Bitcoin Core: IsStandardTx)
+pub fn is_standard_tx(tx: &Transaction, policy: &MempoolPolicy) -> Result<(), TxValidationError> {
+    if !(TX_MIN_STANDARD_VERSION..=TX_MAX_STANDARD_VERSION).contains(&tx.version.0) {
+        return Err(nonstandard("version"));
+    }
+
+    for input in &tx.input {
+        if input.script_sig.len() > MAX_STANDARD_SCRIPTSIG_SIZE {
+            return Err(nonstandard("scriptsig-size"));
+        }
+        if !input.script_sig.is_push_only() {
+            return Err(nonstandard("scriptsig-not-pushonly"));
+        }
+    }
+
+    let mut data_outputs = 0;
+    for output in &tx.output {
+        if !is_standard_script(&output.script_pubkey, policy) {
+            return Err(nonstandard("scriptpubkey"));
+        }
+        match classify(&output.script_pubkey) {
+            TxoutType::NullData => data_outputs += 1,
+            TxoutType::Multisig { .. } if !policy.permit_bare_multisig => return Err(nonstandard("bare-multisig")),
+            _ => {}
+        }
+    }
+    // Only one OP_RETURN output per transaction
+    if data_outputs > 1 {
+        return Err(nonstandard("multi-op-return"));
+    }
+    Ok(())
+}
+
+/// Checks on the outputs being spent (Bitcoin Core: AreInputsStandard,
+/// IsWitnessStandard and the sigop limit). `prevouts` are in input order.
+pub fn check_inputs_standard(
+    tx: &Transaction,
+    prevouts: &[TxOut],
+    policy: &MempoolPolicy,
+) -> Result<(), TxValidationError> {
+    for (input, prevout) in tx.input.iter().zip(prevouts) {
+        match classify(&prevout.script_pubkey) {
+            TxoutType::NonStandard | TxoutType::WitnessUnknown => {
+                return Err(nonstandard("bad-txns-nonstandard-inputs"));
+            }
+            TxoutType::ScriptHash => {
+                let redeem = input.script_sig.redeem_script().ok_or_else(|| nonstandard("bad-txns-nonstandard-inputs"))?;
+                if redeem.count_sigops() > MAX_P2SH_SIGOPS {
+                    return Err(nonstandard("bad-txns-nonstandard-inputs"));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (input, prevout) in tx.input.iter().zip(prevouts) {
+        if !input.witness.is_empty() && !is_witness_standard(input, prevout) {
+            return Err(nonstandard("bad-witness-nonstandard"));
+        }
+    }
+
+    let sigop_cost = sigop_cost(tx, prevouts);
+    let max_cost = policy.max_tx_sigops * WITNESS_SCALE_FACTOR;
+    if sigop_cost > max_cost {
+        return Err(TxValidationError::new(
+            TxValidationResult::NotStandard,
+            "bad-txns-too-many-sigops",
+            format!("{} > {}", sigop_cost, max_cost),
+        ));
+    }
+    Ok(())
+}
+
+/// Sigop cost of `tx` spending `prevouts`, in input order
+/// (Bitcoin Core: GetTransactionSigOpCost)
+pub fn sigop_cost(tx: &Transaction, prevouts: &[TxOut]) -> usize {
+    let spent: HashMap<OutPoint, &TxOut> =
+        tx.input.iter().map(|i| i.previous_output).zip(prevouts).collect();
+    tx.total_sigop_cost(|outpoint| spent.get(outpoint).map(|o| (*o).clone()))
+}
+
+/// Witness limits for one input with a non-empty witness
+fn is_witness_standard(input: &bitcoin::TxIn, prevout: &TxOut) -> bool {
+    let mut program = prevout.script_pubkey.as_script();
+    let mut p2sh = false;
+    if program.is_p2sh() {
+        match input.script_sig.redeem_script() {
+            Some(redeem) => program = redeem,
+            None => return false,
+        }
+        p2sh = true;
+    }
+
+    // Witness data on a non-witness spend, or on an anchor
+    if !program.is_witness_program() || is_pay_to_anchor(program) {
+        return false;
+    }
+
+    let items: Vec<&[u8]> = input.witness.iter().collect();
+    if program.is_p2wsh() {
+        let Some((script, stack)) = items.split_last() else {
+            return false;
+        };
+        return script.len() <= MAX_STANDARD_P2WSH_SCRIPT_SIZE
+            && stack.len() <= MAX_STANDARD_P2WSH_STACK_ITEMS
+            && stack.iter().all(|item| item.len() <= MAX_STANDARD_P2WSH_STACK_ITEM_SIZE);
+    }
+
+    if program.is_p2tr() && !p2sh {
+        let mut stack = &items[..];
+        // Annexes are reserved for future upgrades
+        if stack.len() >= 2 && stack.last().is_some_and(|a| a.first() == Some(&ANNEX_TAG)) {
+            return false;
+        }
+        if stack.len() >= 2 {
+            // Script path: ..., script, control block
+            let control = stack[stack.len() - 1];
+            stack = &stack[..stack.len() - 2];
+            if control.first().is_some_and(|c| c & 0xfe == TAPROOT_LEAF_TAPSCRIPT) {
+                return stack.iter().all(|item| item.len() <= MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE);
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{absolute, transaction, Amount, ScriptBuf, TxIn, Txid, WPubkeyHash, WScriptHash, Witness};
+
+    fn tx_paying(script_pubkey: ScriptBuf) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn { previous_output: OutPoint::new(Txid::all_zeros(), 0), ..Default::default() }],
+            output: vec![TxOut { value: Amount::from_sat(10_000), script_pubkey }],
+        }
+    }
+
+    fn multisig(m: u8, n: u8) -> ScriptBuf {
+        let mut bytes = vec![0x50 + m];
+        for _ in 0..n {
+            bytes.push(33);
+            bytes.extend([2u8; 33]);
+        }
+        bytes.extend([0x50 + n, OP_CHECKMULTISIG.to_u8()]);
+        ScriptBuf::from(bytes)
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(&ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros())), TxoutType::WitnessV0KeyHash);
+        assert_eq!(classify(&ScriptBuf::from(P2A_SCRIPT.to_vec())), TxoutType::Anchor);
+        assert_eq!(classify(&multisig(1, 2)), TxoutType::Multisig { m: 1, n: 2 });
+        assert_eq!(classify(&ScriptBuf::from(vec![0x6a, 0x01, 0xff])), TxoutType::NullData);
+        // OP_RETURN followed by a non-push opcode
+        assert_eq!(classify(&ScriptBuf::from(vec![0x6a, 0xac])), TxoutType::NonStandard);
+        assert_eq!(classify(&ScriptBuf::from(vec![0x51])), TxoutType::NonStandard);
+    }
+
+    #[test]
+    fn test_standard_outputs() {
+        let policy = MempoolPolicy::default();
+        assert!(is_standard_tx(&tx_paying(ScriptBuf::new_p2wsh(&WScriptHash::all_zeros())), &policy).is_ok());
+        assert_eq!(is_standard_tx(&tx_paying(ScriptBuf::from(vec![0x51])), &policy).unwrap_err().reason, "scriptpubkey");
+        assert!(is_standard_tx(&tx_paying(multisig(2, 3)), &policy).is_ok());
+        assert!(is_standard_tx(&tx_paying(multisig(2, 4)), &policy).is_err());
+
+        let no_bare_multisig = MempoolPolicy { permit_bare_multisig: false, ..MempoolPolicy::default() };
+        assert_eq!(is_standard_tx(&tx_paying(multisig(1, 1)), &no_bare_multisig).unwrap_err().reason, "bare-multisig");
+
+        // 83 bytes: OP_RETURN OP_PUSHDATA1 <80 bytes>
+        let mut data = vec![0x6a, 0x4c, 80];
+        data.extend([0u8; 80]);
+        assert!(is_standard_tx(&tx_paying(ScriptBuf::from(data.clone())), &policy).is_ok());
+        data[2] = 81;
+        data.push(0);
+        assert!(is_standard_tx(&tx_paying(ScriptBuf::from(data)), &policy).is_err());
+
+        let mut two_data = tx_paying(ScriptBuf::from(vec![0x6a]));
+        two_data.output.push(two_data.output[0].clone());
+        assert_eq!(is_standard_tx(&two_data, &policy).unwrap_err().reason, "multi-op-return");
+
+        let mut v4 = tx_paying(ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros()));
+        v4.version = transaction::Version(4);
+        assert_eq!(is_standard_tx(&v4, &policy).unwrap_err().reason, "version");
+    }
+
+    #[test]
+    fn test_scriptsig_rules() {
+        let policy = MempoolPolicy::default();
+        let mut tx = tx_paying(ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros()));
+        tx.input[0].script_sig = ScriptBuf::from(vec![0xac]);
+        assert_eq!(is_standard_tx(&tx, &policy).unwrap_err().reason, "scriptsig-not-pushonly");
+    }
+
+    #[test]
+    fn test_witness_standardness() {
+        let policy = MempoolPolicy::default();
+        let mut tx = tx_paying(ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros()));
+        let anchor = TxOut { value: Amount::ZERO, script_pubkey: ScriptBuf::from(P2A_SCRIPT.to_vec()) };
+        assert!(check_inputs_standard(&tx, &[anchor.clone()], &policy).is_ok());
+
+        tx.input[0].witness = Witness::from_slice(&[vec![1u8]]);
+        assert_eq!(check_inputs_standard(&tx, &[anchor], &policy).unwrap_err().reason, "bad-witness-nonstandard");
+
+        // P2WSH stack items are capped at 80 bytes
+        let p2wsh = TxOut { value: Amount::ZERO, script_pubkey: ScriptBuf::new_p2wsh(&WScriptHash::all_zeros()) };
+        tx.input[0].witness = Witness::from_slice(&[vec![0u8; 81], vec![0x51]]);
+        assert!(check_inputs_standard(&tx, &[p2wsh.clone()], &policy).is_err());
+        tx.input[0].witness = Witness::from_slice(&[vec![0u8; 80], vec![0x51]]);
+        assert!(check_inputs_standard(&tx, &[p2wsh], &policy).is_ok());
+
+        let unknown = TxOut { value: Amount::ZERO, script_pubkey: ScriptBuf::from(vec![0x51]) };
+        tx.input[0].witness = Witness::new();
+        assert_eq!(
+            check_inputs_standard(&tx, &[unknown], &policy).unwrap_err().reason,
+            "bad-txns-nonstandard-inputs"
+        );
+    }
+}
@@ -0,0 +1,22 @@
+//! Transactions shared by the mempool's unit tests.
+
+use bitcoin::hashes::Hash;
+use bitcoin::{absolute, transaction, Amount, OutPoint, ScriptBuf, Transaction, TxIn, TxOut, Txid, WPubkeyHash};
+
+/// One-input, one-output transaction spending `prevout`
+pub fn spend(prevout: OutPoint) -> Transaction {
+    Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn { previous_output: prevout, ..Default::default() }],
+        output: vec![TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros()),
+        }],
+    }
+}
+
+/// Transaction spending a confirmed output, distinct for each `n`
+pub fn funded(n: u8) -> Transaction {
+    spend(OutPoint::new(Txid::from_byte_array([n; 32]), 0))
+}
@@ -0,0 +1,224 @@
+//! Topologically Restricted Until Confirmation (version 3) transaction policy
+//! (Bitcoin Core: policy/truc_policy.cpp).
+//!
+//! An unconfirmed TRUC transaction may have at most one unconfirmed parent or
+//! one unconfirmed child, and a TRUC child is kept small. This bounds what a
+//! counterparty can attach to a shared transaction, so a fee bump of the
+//! parent through its child cannot be pinned.
+
+use super::entry::MempoolEntry;
+use bitcoin::{transaction, Transaction, Txid};
+
+/// Transaction version that opts into TRUC rules
+pub const TRUC_VERSION: transaction::Version = transaction::Version(3);
+
+/// Largest unconfirmed ancestor count of a TRUC transaction, itself included
+pub const TRUC_ANCESTOR_LIMIT: usize = 2;
+
+/// Largest unconfirmed descendant count of a TRUC transaction, itself included
+pub const TRUC_DESCENDANT_LIMIT: usize = 2;
+
+/// Largest TRUC transaction, in virtual bytes
+pub const TRUC_MAX_VSIZE: u64 = 10_000;
+
+/// Largest TRUC transaction with an unconfirmed parent, in virtual bytes
+pub const TRUC_CHILD_MAX_VSIZE: u64 = 1_000;
+
+pub fn is_truc(tx: &Transaction) -> bool {
+    tx.version == TRUC_VERSION
+}
+
+/// A broken TRUC rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrucViolation {
+    pub reason: String,
+    /// Existing child of the same parent; replacing it would resolve the
+    /// violation (sibling eviction)
+    pub sibling: Option<Txid>,
+}
+
+impl TrucViolation {
+    fn new(reason: String) -> Self {
+        Self { reason, sibling: None }
+    }
+}
+
+/// TRUC rules for a single transaction against its mempool parents
+/// (Bitcoin Core: SingleTRUCChecks). `conflicts` are the mempool transactions
+/// it replaces, which no longer count as children of its parent.
+pub fn single_truc_checks(
+    tx: &Transaction,
+    vsize: u64,
+    mempool_parents: &[MempoolEntry],
+    conflicts: &[Txid],
+) -> Result<(), TrucViolation> {
+    let txid = tx.compute_txid();
+    if !is_truc(tx) {
+        return match mempool_parents.iter().find(|p| is_truc(&p.tx)) {
+            Some(parent) => Err(TrucViolation::new(format!(
+                "non-version=3 tx {} cannot spend from version=3 tx {}",
+                txid, parent.txid
+            ))),
+            None => Ok(()),
+        };
+    }
+
+    if let Some(parent) = mempool_parents.iter().find(|p| !is_truc(&p.tx)) {
+        return Err(TrucViolation::new(format!(
+            "version=3 tx {} cannot spend from non-version=3 tx {}",
+            txid, parent.txid
+        )));
+    }
+    if vsize > TRUC_MAX_VSIZE {
+        return Err(TrucViolation::new(format!(
+            "version=3 tx {} is too big: {} > {} virtual bytes",
+            txid, vsize, TRUC_MAX_VSIZE
+        )));
+    }
+
+    let Some(parent) = mempool_parents.first() else {
+        return Ok(());
+    };
+    if mempool_parents.len() + parent.ancestor_count > TRUC_ANCESTOR_LIMIT {
+        return Err(TrucViolation::new(format!("tx {} would have too many ancestors", txid)));
+    }
+    if vsize > TRUC_CHILD_MAX_VSIZE {
+        return Err(TrucViolation::new(format!(
+            "version=3 child tx {} is too big: {} > {} virtual bytes",
+            txid, vsize, TRUC_CHILD_MAX_VSIZE
+        )));
+    }
+
+    let child_replaced = parent.children.iter().any(|c| conflicts.contains(c));
+    if parent.descendant_count + 1 > TRUC_DESCENDANT_LIMIT && !child_replaced {
+        // A lone existing child may be evicted in favour of this one
+        let sibling = match (parent.children.len(), parent.descendant_count) {
+            (1, 2) => parent.children.iter().next().copied(),
+            _ => None,
+        };
+        return Err(TrucViolation {
+            reason: format!("tx {} would exceed descendant count limit", parent.txid),
+            sibling,
+        });
+    }
+    Ok(())
+}
+
+/// TRUC rules for a package transaction against in-package parents that are
+/// not in the mempool yet (Bitcoin Core: PackageTRUCChecks)
+pub fn package_truc_checks(
+    tx: &Transaction,
+    vsize: u64,
+    package: &[&Transaction],
+    mempool_parents: &[MempoolEntry],
+) -> Result<(), String> {
+    let txid = tx.compute_txid();
+    let package_parents: Vec<&Transaction> = package
+        .iter()
+        .copied()
+        .filter(|p| {
+            let parent_txid = p.compute_txid();
+            tx.input.iter().any(|i| i.previous_output.txid == parent_txid)
+        })
+        .collect();
+    let Some(parent) = package_parents.first() else {
+        return Ok(());
+    };
+
+    if !is_truc(tx) {
+        return match package_parents.iter().find(|p| is_truc(p)) {
+            Some(p) => Err(format!("non-version=3 tx {} cannot spend from version=3 tx {}", txid, p.compute_txid())),
+            None => Ok(()),
+        };
+    }
+    if let Some(p) = package_parents.iter().find(|p| !is_truc(p)) {
+        return Err(format!("version=3 tx {} cannot spend from non-version=3 tx {}", txid, p.compute_txid()));
+    }
+    if vsize > TRUC_CHILD_MAX_VSIZE {
+        return Err(format!(
+            "version=3 child tx {} is too big: {} > {} virtual bytes",
+            txid, vsize, TRUC_CHILD_MAX_VSIZE
+        ));
+    }
+
+    // The in-package parent must itself be unconfirmed-parentless
+    let parent_txid = parent.compute_txid();
+    let parent_has_parents = parent.input.iter().any(|i| {
+        package.iter().any(|p| p.compute_txid() == i.previous_output.txid)
+    });
+    if package_parents.len() + mempool_parents.len() > 1 || parent_has_parents {
+        return Err(format!("tx {} would have too many ancestors", txid));
+    }
+
+    // ...and may have no other child in the package
+    let siblings = package
+        .iter()
+        .filter(|p| p.compute_txid() != txid && p.input.iter().any(|i| i.previous_output.txid == parent_txid))
+        .count();
+    if siblings > 0 {
+        return Err(format!("tx {} would exceed descendant count limit", parent_txid));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{absolute, Amount, OutPoint, ScriptBuf, TxIn, TxOut};
+
+    fn tx(version: transaction::Version, prevout: OutPoint) -> Transaction {
+        Transaction {
+            version,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn { previous_output: prevout, ..Default::default() }],
+            output: vec![TxOut { value: Amount::from_sat(1000), script_pubkey: ScriptBuf::from(vec![0x51]) }],
+        }
+    }
+
+    #[test]
+    fn test_version_mixing() {
+        let v3_parent = tx(TRUC_VERSION, OutPoint::new(Txid::all_zeros(), 0));
+        let v2_parent = tx(transaction::Version::TWO, OutPoint::new(Txid::all_zeros(), 1));
+        let v3_entry = MempoolEntry::new(v3_parent.clone(), 0, 100);
+        let v2_entry = MempoolEntry::new(v2_parent.clone(), 0, 100);
+
+        let v2_child = tx(transaction::Version::TWO, OutPoint::new(v3_parent.compute_txid(), 0));
+        assert!(single_truc_checks(&v2_child, 100, &[v3_entry.clone()], &[]).is_err());
+
+        let v3_child = tx(TRUC_VERSION, OutPoint::new(v2_parent.compute_txid(), 0));
+        assert!(single_truc_checks(&v3_child, 100, &[v2_entry], &[]).is_err());
+
+        let v3_child = tx(TRUC_VERSION, OutPoint::new(v3_parent.compute_txid(), 0));
+        assert!(single_truc_checks(&v3_child, 100, &[v3_entry.clone()], &[]).is_ok());
+        let err = single_truc_checks(&v3_child, TRUC_CHILD_MAX_VSIZE + 1, &[v3_entry], &[]).unwrap_err();
+        assert!(err.reason.contains("too big"));
+    }
+
+    #[test]
+    fn test_sibling_eviction_candidate() {
+        let parent = tx(TRUC_VERSION, OutPoint::new(Txid::all_zeros(), 0));
+        let sibling = Txid::from_byte_array([7; 32]);
+        let mut entry = MempoolEntry::new(parent.clone(), 0, 100);
+        entry.children.insert(sibling);
+        entry.descendant_count = 2;
+
+        let child = tx(TRUC_VERSION, OutPoint::new(parent.compute_txid(), 1));
+        let err = single_truc_checks(&child, 100, &[entry.clone()], &[]).unwrap_err();
+        assert_eq!(err.sibling, Some(sibling));
+
+        // Replacing the existing child directly is not a violation
+        assert!(single_truc_checks(&child, 100, &[entry], &[sibling]).is_ok());
+    }
+
+    #[test]
+    fn test_package_checks() {
+        let parent = tx(TRUC_VERSION, OutPoint::new(Txid::all_zeros(), 0));
+        let child = tx(TRUC_VERSION, OutPoint::new(parent.compute_txid(), 0));
+        assert!(package_truc_checks(&child, 100, &[&parent, &child], &[]).is_ok());
+
+        let v2_child = tx(transaction::Version::TWO, OutPoint::new(parent.compute_txid(), 0));
+        assert!(package_truc_checks(&v2_child, 100, &[&parent, &v2_child], &[]).is_err());
+        assert!(package_truc_checks(&child, TRUC_CHILD_MAX_VSIZE + 1, &[&parent, &child], &[]).is_err());
+    }
+}
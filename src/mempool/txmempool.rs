@@ -1,14 +1,76 @@
 use super::entry::MempoolEntry;
+use super::ephemeral;
 use super::fees::FeeEstimator;
-use super::policy::MempoolPolicy;
+use super::miner::{is_final_tx, BlockAssembler, BlockAssemblerOptions, BlockTemplate};
+use super::policy::{MempoolPolicy, EXTRA_DESCENDANT_TX_SIZE_LIMIT};
+use super::standard;
+use super::truc;
+use super::entry::FeeRate;
+use super::validation::{
+    check_package_consistency, is_child_with_parents, AcceptedTx, PackageAcceptResult, PackageTxResult,
+    ReplacementReport, TxValidationError, TxValidationResult, MAX_PACKAGE_COUNT, MAX_PACKAGE_WEIGHT,
+};
 use anyhow::{anyhow, Result};
-use bitcoin::{Transaction, Txid};
+use bitcoin::{relative, OutPoint, Transaction, TxOut, Txid, Wtxid};
 use dashmap::DashMap;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use parking_lot::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::broadcast;
+use crate::index::coins::{Coin, CoinsIndex, MEMPOOL_HEIGHT};
+use crate::index::{self, BlockIndex};
 use crate::kernel::Kernel;
 
+/// Blocks before a coinbase output can be spent
+const COINBASE_MATURITY: u32 = 100;
+
+/// How long acceptance waits for the coins index to apply a block the
+/// kernel just connected
+const COINS_SYNC_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Capacity of the mempool event channel; slower subscribers miss events
+const EVENT_CHANNEL_CAPACITY: usize = 10_000;
+
+/// Half-life of the rolling minimum fee once a block has been connected
+/// (Bitcoin Core: CTxMemPool::ROLLING_FEE_HALFLIFE)
+const ROLLING_FEE_HALFLIFE: u64 = 12 * 60 * 60;
+
+/// Minimum fee raised by size-limit evictions, decaying back to zero
+/// (Bitcoin Core: rollingMinimumFeeRate)
+#[derive(Debug, Default)]
+struct RollingMinFee {
+    /// Feerate in sat/kvB
+    rate: f64,
+    /// Unix time of the last decay step
+    last_update: u64,
+    /// Decay only starts after a block follows the last bump
+    block_since_bump: bool,
+}
+
+/// Why a transaction left the mempool (Bitcoin Core: MemPoolRemovalReason)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalReason {
+    /// Expired from the mempool
+    Expiry,
+    /// Evicted because the mempool is full
+    SizeLimit,
+    /// Included in a block
+    Block,
+    /// Conflicts with a transaction in a block
+    Conflict,
+    /// Replaced by a transaction paying more fees (BIP125)
+    Replaced,
+}
+
+/// Mempool change, tagged with the mempool sequence number it was assigned
+#[derive(Debug, Clone)]
+pub enum MempoolEvent {
+    Added { tx: Arc<Transaction>, sequence: u64 },
+    Removed { tx: Arc<Transaction>, reason: RemovalReason, sequence: u64 },
+}
+
 /// Main mempool structure
 pub struct Mempool {
     /// All transactions in the mempool
@@ -34,6 +96,32 @@ pub struct Mempool {
 
     /// Kernel for consensus validation
     kernel: Option<Arc<Kernel>>,
+
+    /// UTXO set for input lookups and fee calculation
+    coins: Option<Arc<CoinsIndex>>,
+
+    /// Incremented on every addition and removal (Bitcoin Core: m_sequence_number)
+    sequence: AtomicU64,
+
+    /// Addition/removal notifications (ZMQ, indexes)
+    events: broadcast::Sender<MempoolEvent>,
+
+    /// Minimum fee after evictions for size
+    rolling_min_fee: RwLock<RollingMinFee>,
+
+    /// Fee deltas from prioritisation, kept until the transaction confirms
+    /// (Bitcoin Core: mapDeltas)
+    deltas: DashMap<Txid, i64>,
+
+    /// Transactions submitted locally that no peer has requested yet
+    unbroadcast: RwLock<HashSet<Txid>>,
+
+    /// Set once mempool.dat has been loaded (or loading was skipped)
+    loaded: AtomicBool,
+
+    /// Held from the first acceptance check until the insert, so concurrent
+    /// submissions cannot both pass against the same mempool (Bitcoin Core: pool.cs)
+    validation: Mutex<()>,
 }
 
 impl Mempool {
@@ -47,6 +135,14 @@ impl Mempool {
             current_height: Arc::new(RwLock::new(0)),
             spends: DashMap::new(),
             kernel: None,
+            coins: None,
+            sequence: AtomicU64::new(1),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            rolling_min_fee: RwLock::new(RollingMinFee::default()),
+            deltas: DashMap::new(),
+            unbroadcast: RwLock::new(HashSet::new()),
+            loaded: AtomicBool::new(false),
+            validation: Mutex::new(()),
         }
     }
 
@@ -55,6 +151,11 @@ impl Mempool {
         self.kernel = Some(kernel);
     }
 
+    /// Set the UTXO set used to resolve inputs
+    pub fn set_coins(&mut self, coins: Arc<CoinsIndex>) {
+        self.coins = Some(coins);
+    }
+
     /// Create mempool with kernel
     pub fn with_kernel(policy: MempoolPolicy, kernel: Arc<Kernel>) -> Self {
         Self {
@@ -66,104 +167,651 @@ impl Mempool {
             current_height: Arc::new(RwLock::new(0)),
             spends: DashMap::new(),
             kernel: Some(kernel),
+            coins: None,
+            sequence: AtomicU64::new(1),
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            rolling_min_fee: RwLock::new(RollingMinFee::default()),
+            deltas: DashMap::new(),
+            unbroadcast: RwLock::new(HashSet::new()),
+            loaded: AtomicBool::new(false),
+            validation: Mutex::new(()),
         }
     }
 
     /// Add a transaction to the mempool
     pub fn add_tx(&self, tx: Transaction, fee: u64, height: u32) -> Result<Txid> {
         let txid = tx.compute_txid();
-
-        // Check if already in mempool
+        let _validation = self.validation.lock();
         if self.entries.contains_key(&txid) {
             return Err(anyhow!("transaction already in mempool"));
         }
+        self.check_consensus(&tx)?;
+
+        let (entry, replacement) = self.check_entry(MempoolEntry::new(tx, fee, height), false)?;
+        let valid_fee_estimate = replacement.is_none();
+        self.insert_entry(entry, replacement.as_ref(), valid_fee_estimate)?;
+        Ok(txid)
+    }
+
+    /// Validate a transaction against the UTXO set and mempool policy, then add it
+    /// (Bitcoin Core: AcceptToMemoryPool)
+    pub fn accept_tx(&self, tx: Transaction) -> Result<AcceptedTx, TxValidationError> {
+        self.accept_tx_at(tx, SystemTime::now())
+    }
+
+    /// `accept_tx` with the entry time set to `time`, for transactions
+    /// reloaded from mempool.dat
+    pub fn accept_tx_at(&self, tx: Transaction, time: SystemTime) -> Result<AcceptedTx, TxValidationError> {
+        let _validation = self.validation.lock();
+        let (mut entry, replacement) = self.pre_checks(&tx, &HashMap::new(), false)?;
+        entry.time = time;
+        let accepted = Self::accepted(&entry, replacement.clone());
+        let valid_fee_estimate = replacement.is_none();
+        self.insert_entry(entry, replacement.as_ref(), valid_fee_estimate)?;
+        Ok(accepted)
+    }
+
+    /// Run every acceptance check without changing the mempool. `package_outputs`
+    /// holds outputs of not-yet-accepted transactions the candidate may spend.
+    pub fn test_accept(
+        &self,
+        tx: &Transaction,
+        package_outputs: &HashMap<OutPoint, TxOut>,
+    ) -> Result<AcceptedTx, TxValidationError> {
+        let (entry, replacement) = self.pre_checks(tx, package_outputs, false)?;
+        Ok(Self::accepted(&entry, replacement))
+    }
+
+    fn accepted(entry: &MempoolEntry, replacement: Option<ReplacementReport>) -> AcceptedTx {
+        AcceptedTx {
+            txid: entry.txid,
+            wtxid: entry.tx.compute_wtxid(),
+            vsize: entry.vsize,
+            fee: entry.fee,
+            replacement,
+            effective_fee: entry.fee,
+            effective_vsize: entry.vsize,
+            effective_includes: vec![entry.tx.compute_wtxid()],
+        }
+    }
+
+    /// Validate and add a child-with-parents package (Bitcoin Core: AcceptPackage).
+    ///
+    /// Each transaction is first tried on its own. Those that fail only on
+    /// feerate, and any that spend them, are then evaluated together: their
+    /// combined feerate must meet the mempool minimum, and together they may
+    /// replace a conflicting mempool transaction. `max_fee_rate` rejects any
+    /// transaction paying more than it.
+    pub fn accept_package(&self, txs: &[Transaction], max_fee_rate: Option<FeeRate>) -> PackageAcceptResult {
+        if txs.len() > MAX_PACKAGE_COUNT {
+            return PackageAcceptResult::failed("package-too-many-transactions");
+        }
+        if txs.iter().map(|tx| tx.weight().to_wu()).sum::<u64>() > MAX_PACKAGE_WEIGHT {
+            return PackageAcceptResult::failed("package-too-large");
+        }
+        if let Err(e) = check_package_consistency(txs) {
+            return PackageAcceptResult::failed(e);
+        }
+        if txs.len() > 1 && !is_child_with_parents(txs) {
+            return PackageAcceptResult::failed("package-not-child-with-parents");
+        }
 
-        // Validate with Kernel if available
-        if let Some(ref kernel) = self.kernel {
-            match kernel.validate_transaction(&tx) {
-                Ok((false, Some(reason))) => {
-                    return Err(anyhow!("transaction invalid: {}", reason));
+        let max_fee_check = |entry: &MempoolEntry| match max_fee_rate {
+            Some(rate) if entry.fee > rate.fee_for_vsize(entry.vsize) => Err(TxValidationError::policy(
+                "max feerate exceeded",
+                format!("{} > {}", entry.fee, rate.fee_for_vsize(entry.vsize)),
+            )),
+            _ => Ok(()),
+        };
+
+        let _validation = self.validation.lock();
+        let mut result = PackageAcceptResult::default();
+        let mut deferred: Vec<&Transaction> = Vec::new();
+        let mut deferred_txids = HashSet::new();
+        for tx in txs {
+            let txid = tx.compute_txid();
+            if let Some(entry) = self.entries.get(&txid) {
+                result.results.insert(tx.compute_wtxid(), PackageTxResult::AlreadyInMempool(Self::accepted(&entry, None)));
+                continue;
+            }
+            if tx.input.iter().any(|i| deferred_txids.contains(&i.previous_output.txid)) {
+                deferred.push(tx);
+                deferred_txids.insert(txid);
+                continue;
+            }
+
+            let accepted = self.pre_checks(tx, &HashMap::new(), false).and_then(|(entry, replacement)| {
+                max_fee_check(&entry)?;
+                let accepted = Self::accepted(&entry, replacement.clone());
+                self.insert_entry(entry, replacement.as_ref(), false)?;
+                Ok(accepted)
+            });
+            match accepted {
+                Ok(accepted) => {
+                    result.results.insert(tx.compute_wtxid(), PackageTxResult::Accepted(accepted));
                 }
-                Ok((false, None)) => {
-                    return Err(anyhow!("transaction invalid"));
+                Err(e) if e.result == TxValidationResult::Reconsiderable && txs.len() > 1 => {
+                    deferred.push(tx);
+                    deferred_txids.insert(txid);
                 }
                 Err(e) => {
-                    return Err(anyhow!("validation error: {}", e));
+                    result.results.insert(tx.compute_wtxid(), PackageTxResult::Rejected(e));
+                    result.error = Some("transaction failed".to_string());
+                    return result;
                 }
-                Ok((true, _)) => {
-                    // Valid, continue
+            }
+        }
+
+        if !deferred.is_empty() {
+            if let Err(error) = self.accept_subpackage(&deferred, &max_fee_check, &mut result) {
+                result.error = Some(error);
+            }
+        }
+        result
+    }
+
+    /// Evaluate and insert transactions whose feerate is judged together
+    fn accept_subpackage(
+        &self,
+        txs: &[&Transaction],
+        max_fee_check: &dyn Fn(&MempoolEntry) -> Result<(), TxValidationError>,
+        result: &mut PackageAcceptResult,
+    ) -> Result<(), String> {
+        let mut package_outputs = HashMap::new();
+        let mut entries = Vec::with_capacity(txs.len());
+        for tx in txs {
+            let checked = self.pre_checks(tx, &package_outputs, true).and_then(|(entry, replacement)| {
+                max_fee_check(&entry)?;
+                Ok((entry, replacement))
+            });
+            match checked {
+                Ok(checked) => entries.push(checked),
+                Err(e) => {
+                    result.results.insert(tx.compute_wtxid(), PackageTxResult::Rejected(e));
+                    return Err("transaction failed".to_string());
                 }
             }
+            let txid = tx.compute_txid();
+            for (vout, output) in tx.output.iter().enumerate() {
+                package_outputs.insert(OutPoint::new(txid, vout as u32), output.clone());
+            }
+        }
+
+        // TRUC and ephemeral dust rules against parents in the package
+        for (entry, _) in &entries {
+            let mempool_parents: Vec<MempoolEntry> =
+                entry.parents.iter().filter_map(|txid| self.entries.get(txid).map(|e| e.clone())).collect();
+            let checked = truc::package_truc_checks(&entry.tx, entry.vsize, txs, &mempool_parents)
+                .map_err(|e| TxValidationError::policy("TRUC-violation", e))
+                .and_then(|()| {
+                    if !self.policy.require_standard {
+                        return Ok(());
+                    }
+                    ephemeral::check_ephemeral_spends(&entry.tx, txs, &self.policy)
+                        .map_err(|e| TxValidationError::policy("missing-ephemeral-spends", e))
+                });
+            if let Err(e) = checked {
+                result.results.insert(entry.tx.compute_wtxid(), PackageTxResult::Rejected(e));
+                return Err("transaction failed".to_string());
+            }
+        }
+
+        let package_fee: u64 = entries.iter().map(|(e, _)| e.fee).sum();
+        let package_vsize: u64 = entries.iter().map(|(e, _)| e.vsize).sum();
+        let min_fee = self.min_fee_rate().fee_for_vsize(package_vsize);
+        if package_fee < min_fee {
+            let reason = if self.min_fee_rate() > self.policy.min_relay_fee {
+                "mempool min fee not met"
+            } else {
+                "min relay fee not met"
+            };
+            for (entry, _) in &entries {
+                let error = TxValidationError::reconsiderable(reason, format!("{} < {}", package_fee, min_fee));
+                result.results.insert(entry.tx.compute_wtxid(), PackageTxResult::Rejected(error));
+            }
+            return Err("transaction failed".to_string());
+        }
+
+        // Ancestors in the mempool plus the package itself must fit the chain limits
+        let mut parents = HashSet::new();
+        for (entry, _) in &entries {
+            parents.extend(self.find_parents(&entry.tx));
+        }
+        let (ancestors, ancestor_size, _) = self.calculate_ancestors(&parents);
+        self.policy
+            .check_ancestor_limits(ancestors.len() + entries.len(), ancestor_size + package_vsize)
+            .map_err(|e| format!("package-mempool-limits, {}", e))?;
+        for txid in &ancestors {
+            if let Some(ancestor) = self.entries.get(txid) {
+                self.policy
+                    .check_descendant_limits(
+                        ancestor.descendant_count + entries.len(),
+                        ancestor.descendant_size + package_vsize,
+                    )
+                    .map_err(|e| format!("package-mempool-limits, {}", e))?;
+            }
+        }
+
+        if entries.iter().any(|(_, replacement)| replacement.is_some()) {
+            let package_modified_fee: u64 = entries.iter().map(|(e, _)| e.modified_fee()).sum();
+            self.check_package_replacement(&entries, package_modified_fee, package_vsize)?;
+        }
 
-            // Check UTXO availability
-            match kernel.check_tx_inputs(&tx) {
-                Ok((false, missing)) => {
-                    return Err(anyhow!("missing inputs: {} unavailable", missing));
+        let includes: Vec<Wtxid> = entries.iter().map(|(e, _)| e.tx.compute_wtxid()).collect();
+        for (entry, _) in entries {
+            let wtxid = entry.tx.compute_wtxid();
+            // Recompute ancestor state now that package parents are in the mempool
+            let inserted = self.check_entry(entry, true).map(|(entry, replacement)| {
+                let accepted = Self::accepted(&entry, replacement.clone());
+                self.add_entry(entry, replacement.as_ref(), false);
+                accepted
+            });
+            match inserted {
+                Ok(mut accepted) => {
+                    accepted.effective_fee = package_fee;
+                    accepted.effective_vsize = package_vsize;
+                    accepted.effective_includes = includes.clone();
+                    result.results.insert(wtxid, PackageTxResult::Accepted(accepted));
                 }
                 Err(e) => {
-                    return Err(anyhow!("input check error: {}", e));
+                    result.results.insert(wtxid, PackageTxResult::Rejected(e));
+                    self.trim_to_size();
+                    return Err("transaction failed".to_string());
+                }
+            }
+        }
+
+        // Trim once the whole package is in, so a parent is judged with its child
+        self.trim_to_size();
+        let mut evicted = false;
+        for (wtxid, tx_result) in result.results.iter_mut() {
+            if let PackageTxResult::Accepted(accepted) = tx_result {
+                if includes.contains(wtxid) && !self.entries.contains_key(&accepted.txid) {
+                    *tx_result = PackageTxResult::Rejected(TxValidationError::policy("mempool full", ""));
+                    evicted = true;
+                }
+            }
+        }
+        if evicted {
+            return Err("mempool full".to_string());
+        }
+        Ok(())
+    }
+
+    /// Package RBF: a parent and its only child may together replace mempool
+    /// transactions that neither could replace alone
+    fn check_package_replacement(
+        &self,
+        entries: &[(MempoolEntry, Option<ReplacementReport>)],
+        package_fee: u64,
+        package_vsize: u64,
+    ) -> Result<(), String> {
+        if entries.len() != 2 {
+            return Err("package RBF failed: package must be 1-parent-1-child".to_string());
+        }
+        let package_txids: HashSet<Txid> = entries.iter().map(|(e, _)| e.txid).collect();
+        if entries.iter().any(|(e, _)| e.tx.input.iter().any(|i| {
+            !package_txids.contains(&i.previous_output.txid) && self.entries.contains_key(&i.previous_output.txid)
+        })) {
+            return Err("package RBF failed: new transaction cannot have mempool ancestors".to_string());
+        }
+
+        // Merge what parent and child would evict
+        let mut combined = ReplacementReport::default();
+        for report in entries.iter().filter_map(|(_, r)| r.as_ref()) {
+            for txid in &report.conflicts {
+                if !combined.conflicts.contains(txid) {
+                    combined.conflicts.push(*txid);
                 }
-                Ok((true, _)) => {
-                    // Inputs available, continue
+            }
+            for &(txid, fee) in &report.replaced {
+                if !combined.replaced.iter().any(|(t, _)| *t == txid) {
+                    combined.replaced.push((txid, fee));
+                    combined.replaced_fees += fee;
                 }
             }
         }
+        if combined.replaced.len() > self.policy.max_replacement_candidates {
+            return Err(format!("package RBF failed: too many potential replacements ({})", combined.replaced.len()));
+        }
+        self.check_replacement_fees(package_fee, package_vsize, &combined)
+            .map_err(|e| format!("package RBF failed: {}", e))
+    }
+
+    fn pre_checks(
+        &self,
+        tx: &Transaction,
+        package_outputs: &HashMap<OutPoint, TxOut>,
+        package_fees: bool,
+    ) -> Result<(MempoolEntry, Option<ReplacementReport>), TxValidationError> {
+        if tx.is_coinbase() {
+            return Err(TxValidationError::consensus("coinbase"));
+        }
+        if self.entries.contains_key(&tx.compute_txid()) {
+            return Err(TxValidationError::new(TxValidationResult::Conflict, "txn-already-in-mempool", ""));
+        }
+        self.check_consensus(tx)?;
+
+        let height = self.coins_height()?;
+        let (fee, coins) = self.calculate_fee(tx, height, package_outputs)?;
+        self.check_final(tx, height, &coins)?;
+        let prevouts: Vec<TxOut> = coins.into_iter().map(|coin| coin.output).collect();
+        if self.policy.require_standard {
+            standard::check_inputs_standard(tx, &prevouts, &self.policy)?;
+            ephemeral::pre_check_ephemeral_tx(tx, fee, &self.policy)?;
+        }
+        let mut entry = MempoolEntry::new(tx.clone(), fee, height);
+        entry.sigop_cost = standard::sigop_cost(tx, &prevouts) as u64;
+        let checked = self.check_entry(entry, package_fees)?;
+        // Signatures last: they are the most expensive check
+        self.check_scripts(tx, &prevouts)?;
+        Ok(checked)
+    }
+
+    /// Context-free checks delegated to the kernel
+    fn check_consensus(&self, tx: &Transaction) -> Result<(), TxValidationError> {
+        let Some(ref kernel) = self.kernel else {
+            return Ok(());
+        };
+        match kernel.validate_transaction(tx) {
+            Ok((true, _)) => Ok(()),
+            Ok((false, reason)) => Err(TxValidationError::consensus(
+                reason.unwrap_or_else(|| "transaction invalid".to_string()),
+            )),
+            Err(e) => Err(TxValidationError::consensus(format!("validation error: {}", e))),
+        }
+    }
 
-        // Check basic policy
-        if !self.policy.is_size_acceptable(tx.vsize()) {
-            return Err(anyhow!("transaction too large"));
+    /// Input scripts and signatures against the outputs they spend, through the kernel
+    fn check_scripts(&self, tx: &Transaction, prevouts: &[TxOut]) -> Result<(), TxValidationError> {
+        let Some(ref kernel) = self.kernel else {
+            return Ok(());
+        };
+        match kernel.verify_scripts(tx, prevouts) {
+            Ok((true, _)) => Ok(()),
+            Ok((false, reason)) => Err(TxValidationError::consensus(
+                reason.unwrap_or_else(|| "mandatory-script-verify-flag-failed".to_string()),
+            )),
+            Err(e) => Err(TxValidationError::consensus(format!("script validation error: {}", e))),
+        }
+    }
+
+    /// Height of the UTXO set inputs are resolved against. The coins index
+    /// follows the kernel in the background: a block it has yet to apply would
+    /// let spent coins pass and hide new ones, so acceptance waits briefly for
+    /// it to catch up one block and is otherwise refused until it has synced.
+    fn coins_height(&self) -> Result<u32, TxValidationError> {
+        let (Some(coins), Some(kernel)) = (self.coins.as_deref(), self.kernel.as_deref()) else {
+            return Ok(self.tip_height());
+        };
+        let best = coins.best_block().map(|(height, _)| height);
+        let one_behind = matches!((best, kernel.get_height()), (Some(height), Ok(tip)) if height + 1 == tip);
+        let synced = index::is_synced(coins, kernel)
+            || (one_behind && index::wait_until_synced(coins, kernel, COINS_SYNC_TIMEOUT));
+        match coins.best_block() {
+            Some((height, _)) if synced && height >= 0 => Ok(height as u32),
+            _ => Err(TxValidationError::new(
+                TxValidationResult::NotReady,
+                "coins-index-syncing",
+                format!("UTXO set at height {} of {}", best.unwrap_or(-1), kernel.get_height().unwrap_or(-1)),
+            )),
+        }
+    }
+
+    /// Height of the chain tip the mempool builds on
+    fn tip_height(&self) -> u32 {
+        match self.kernel.as_ref().and_then(|k| k.get_height().ok()) {
+            Some(height) if height >= 0 => height as u32,
+            _ => *self.current_height.read(),
+        }
+    }
+
+    /// Look up a spent output in the package, the mempool or the UTXO set
+    fn find_prevout(
+        &self,
+        outpoint: &OutPoint,
+        package_outputs: &HashMap<OutPoint, TxOut>,
+    ) -> Result<Option<Coin>, TxValidationError> {
+        if let Some(output) = package_outputs.get(outpoint) {
+            return Ok(Some(Coin { output: output.clone(), height: MEMPOOL_HEIGHT, is_coinbase: false }));
+        }
+        if let Some(parent) = self.entries.get(&outpoint.txid) {
+            return Ok(parent.tx.output.get(outpoint.vout as usize).map(|output| Coin {
+                output: output.clone(),
+                height: MEMPOOL_HEIGHT,
+                is_coinbase: false,
+            }));
         }
+        match self.coins {
+            Some(ref coins) => coins
+                .get_coin(outpoint)
+                .map_err(|e| TxValidationError::consensus(format!("coins database error: {}", e))),
+            None => Ok(None),
+        }
+    }
 
-        let entry = MempoolEntry::new(tx.clone(), fee, height);
+    /// Sum of input values minus outputs (Bitcoin Core: Consensus::CheckTxInputs);
+    /// also returns the spent coins in input order
+    fn calculate_fee(
+        &self,
+        tx: &Transaction,
+        height: u32,
+        package_outputs: &HashMap<OutPoint, TxOut>,
+    ) -> Result<(u64, Vec<Coin>), TxValidationError> {
+        let mut value_in = 0u64;
+        let mut coins = Vec::with_capacity(tx.input.len());
+        for input in &tx.input {
+            let coin = self.find_prevout(&input.previous_output, package_outputs)?.ok_or_else(|| {
+                TxValidationError::new(TxValidationResult::MissingInputs, "missing-inputs", "")
+            })?;
+            // A coin above the spend height is not spendable yet either
+            let depth = (height + 1).saturating_sub(coin.height);
+            if coin.is_coinbase && depth < COINBASE_MATURITY {
+                return Err(TxValidationError::new(
+                    TxValidationResult::PrematureSpend,
+                    "bad-txns-premature-spend-of-coinbase",
+                    format!("tried to spend coinbase at depth {}", depth),
+                ));
+            }
+            value_in = value_in
+                .checked_add(coin.output.value.to_sat())
+                .ok_or_else(|| TxValidationError::consensus("bad-txns-inputvalues-outofrange"))?;
+            coins.push(coin);
+        }
 
-        if !self.policy.is_fee_acceptable(entry.fee_rate) {
-            return Err(anyhow!(
-                "fee rate too low: {} < {}",
-                entry.fee_rate.as_sat_per_vb(),
-                self.policy.min_relay_fee.as_sat_per_vb()
+        let value_out: u64 = tx.output.iter().map(|o| o.value.to_sat()).sum();
+        if value_in < value_out {
+            return Err(TxValidationError::new(
+                TxValidationResult::Consensus,
+                "bad-txns-in-belowout",
+                format!("value in ({}) < value out ({})", value_in, value_out),
             ));
         }
+        Ok((value_in - value_out, coins))
+    }
 
-        // Check for conflicts (double spends)
-        let conflicts = self.find_conflicts(&tx);
-        if !conflicts.is_empty() && !entry.signals_replacement {
-            return Err(anyhow!("transaction conflicts with existing mempool tx"));
+    /// Median time past of the active chain's block at `height`
+    fn median_time_past_at(&self, height: u32) -> Option<u32> {
+        let kernel = self.kernel.as_ref()?;
+        let hash = kernel.get_block_hash(height as i32).ok()?;
+        kernel.median_time_past(&hash).ok().flatten()
+    }
+
+    /// The lock time and BIP68 relative lock times of `tx`, spending `coins`,
+    /// must let it into the block after `height` (Bitcoin Core:
+    /// CheckFinalTxAtTip, CheckSequenceLocksAtTip)
+    fn check_final(&self, tx: &Transaction, height: u32, coins: &[Coin]) -> Result<(), TxValidationError> {
+        let next_height = height + 1;
+        let tip_time = self
+            .median_time_past_at(height)
+            .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as u32);
+        if !is_final_tx(tx, next_height, tip_time) {
+            return Err(TxValidationError::new(TxValidationResult::PrematureSpend, "non-final", ""));
+        }
+        if tx.version.0 < 2 {
+            return Ok(());
         }
 
-        // Handle RBF if there are conflicts
-        if !conflicts.is_empty() {
-            self.handle_replacement(&entry, &conflicts)?;
+        // Latest height and time at which some input is still locked
+        let mut min_height: i64 = -1;
+        let mut min_time: i64 = -1;
+        for (input, coin) in tx.input.iter().zip(coins) {
+            let Some(lock) = input.sequence.to_relative_lock_time() else {
+                continue;
+            };
+            // Unconfirmed parents count as mined in the next block
+            let coin_height = if coin.height == MEMPOOL_HEIGHT { next_height } else { coin.height };
+            match lock {
+                relative::LockTime::Blocks(blocks) => {
+                    min_height = min_height.max(coin_height as i64 + blocks.value() as i64 - 1);
+                }
+                relative::LockTime::Time(time) => {
+                    let coin_time = self.median_time_past_at(coin_height.saturating_sub(1)).unwrap_or(0);
+                    min_time = min_time.max(coin_time as i64 + ((time.value() as i64) << 9) - 1);
+                }
+            }
+        }
+        if min_height >= next_height as i64 || min_time >= tip_time as i64 {
+            return Err(TxValidationError::new(TxValidationResult::PrematureSpend, "non-BIP68-final", ""));
+        }
+        Ok(())
+    }
+
+    /// Policy checks for a new entry; returns it with ancestor state filled in,
+    /// plus the mempool transactions it would replace. With `package_fees` the
+    /// feerate and replacement fee rules are left to the package checks.
+    fn check_entry(
+        &self,
+        mut entry: MempoolEntry,
+        package_fees: bool,
+    ) -> Result<(MempoolEntry, Option<ReplacementReport>), TxValidationError> {
+        let tx = entry.tx.clone();
+        entry.fee_delta = self.deltas.get(&entry.txid).map_or(0, |d| *d);
+
+        // Check basic policy
+        if !self.policy.is_size_acceptable(tx.vsize()) {
+            return Err(TxValidationError::new(TxValidationResult::NotStandard, "tx-size", ""));
+        }
+        if self.policy.require_standard {
+            standard::is_standard_tx(&tx, &self.policy)?;
+        }
+
+        if !package_fees {
+            let min_fee = self.min_fee_rate().fee_for_vsize(entry.vsize);
+            if self.min_fee_rate() > self.policy.min_relay_fee && entry.fee < min_fee {
+                return Err(TxValidationError::reconsiderable(
+                    "mempool min fee not met",
+                    format!("{} < {}", entry.fee, min_fee),
+                ));
+            }
+            let min_relay_fee = self.policy.min_fee_for_size(entry.vsize);
+            if entry.fee < min_relay_fee {
+                return Err(TxValidationError::reconsiderable(
+                    "min relay fee not met",
+                    format!("{} < {}", entry.fee, min_relay_fee),
+                ));
+            }
         }
 
         // Find parents in mempool
         let parents = self.find_parents(&tx);
+        let parent_entries: Vec<MempoolEntry> =
+            parents.iter().filter_map(|txid| self.entries.get(txid).map(|e| e.clone())).collect();
 
-        // Check ancestor limits
-        let (ancestor_count, ancestor_size, ancestor_fees) =
-            self.calculate_ancestors(&parents)?;
+        // Check for conflicts (double spends)
+        let mut conflicts = self.find_conflicts(&tx);
+
+        if let Err(violation) = truc::single_truc_checks(&tx, entry.vsize, &parent_entries, &conflicts) {
+            match violation.sibling {
+                // Sibling eviction: replace the parent's existing child under the usual RBF rules
+                Some(sibling) => conflicts.push(sibling),
+                None => return Err(TxValidationError::policy("TRUC-violation", violation.reason)),
+            }
+        }
+        if self.policy.require_standard {
+            let parent_txs: Vec<&Transaction> = parent_entries.iter().map(|e| e.tx.as_ref()).collect();
+            ephemeral::check_ephemeral_spends(&tx, &parent_txs, &self.policy)
+                .map_err(|e| TxValidationError::policy("missing-ephemeral-spends", e))?;
+        }
 
-        self.policy.check_ancestor_limits(
-            ancestor_count + 1,
-            ancestor_size + entry.vsize,
-        ).map_err(|e| anyhow!(e))?;
+        let replacement = if conflicts.is_empty() {
+            None
+        } else {
+            let report = self.replacement_candidates(&tx, &conflicts)?;
+            if !package_fees {
+                self.check_replacement_fees(entry.modified_fee(), entry.vsize, &report)
+                    .map_err(|e| TxValidationError::reconsiderable("insufficient fee", e))?;
+            }
+            Some(report)
+        };
+
+        // Check ancestor and descendant limits
+        let (ancestors, ancestor_size, ancestor_fees) = self.calculate_ancestors(&parents);
+        if let Err(e) = self.check_chain_limits(&self.policy, &ancestors, ancestor_size, entry.vsize) {
+            // CPFP carve-out: one extra small child of a single unconfirmed parent
+            let carve_out = entry.vsize <= EXTRA_DESCENDANT_TX_SIZE_LIMIT
+                && !truc::is_truc(&tx)
+                && self
+                    .check_chain_limits(&self.policy.cpfp_carve_out(), &ancestors, ancestor_size, entry.vsize)
+                    .is_ok();
+            if !carve_out {
+                return Err(TxValidationError::policy("too-long-mempool-chain", e));
+            }
+        }
 
-        // Create and insert entry
         let mut entry = entry;
-        entry.parents = parents.clone();
-        entry.ancestor_count = ancestor_count + 1;
+        entry.parents = parents;
+        entry.ancestor_count = ancestors.len() + 1;
         entry.ancestor_size = ancestor_size + entry.vsize;
         entry.ancestor_fees = ancestor_fees + entry.fee;
+        Ok((entry, replacement))
+    }
+
+    /// Insert a checked entry, evicting the transactions it replaces, then
+    /// trim the mempool to its size limit
+    fn insert_entry(
+        &self,
+        entry: MempoolEntry,
+        replacement: Option<&ReplacementReport>,
+        valid_fee_estimate: bool,
+    ) -> Result<(), TxValidationError> {
+        let txid = entry.txid;
+        self.add_entry(entry, replacement, valid_fee_estimate);
+
+        self.trim_to_size();
+        if !self.entries.contains_key(&txid) {
+            return Err(TxValidationError::policy("mempool full", ""));
+        }
+        Ok(())
+    }
+
+    /// Insert a checked entry, evicting the transactions it replaces.
+    /// `valid_fee_estimate` lets the fee estimator track it; only
+    /// transactions without unconfirmed parents count.
+    fn add_entry(&self, mut entry: MempoolEntry, replacement: Option<&ReplacementReport>, valid_fee_estimate: bool) {
+        let txid = entry.txid;
+        entry.fee_delta = self.deltas.get(&txid).map_or(0, |d| *d);
+
+        // Remove conflicts and their descendants
+        for (replaced_txid, _) in replacement.map_or(&[][..], |r| &r.replaced[..]) {
+            let _ = self.remove_tx(replaced_txid, RemovalReason::Replaced);
+        }
 
         // Update spends map
-        for input in &tx.input {
+        for input in &entry.tx.input {
             self.spends.insert(input.previous_output, txid);
         }
 
-        // Update descendants of parents
-        for parent_txid in &parents {
+        // Link to parents and update descendant state of every ancestor
+        for parent_txid in &entry.parents {
             if let Some(mut parent) = self.entries.get_mut(parent_txid) {
                 parent.children.insert(txid);
-                parent.update_descendant_state(
+            }
+        }
+        let (ancestors, _, _) = self.calculate_ancestors(&entry.parents);
+        for ancestor_txid in &ancestors {
+            if let Some(mut ancestor) = self.entries.get_mut(ancestor_txid) {
+                ancestor.update_descendant_state(
                     entry.vsize as i64,
                     entry.fee as i64,
                     1,
@@ -175,20 +823,17 @@ impl Mempool {
         *self.total_size.write() += entry.vsize as usize;
         *self.total_fees.write() += entry.fee;
 
-        // Add to fee estimator
-        self.fee_estimator.write().add_tx(entry.fee_rate);
+        self.fee_estimator.write().process_transaction(&entry, valid_fee_estimate && entry.parents.is_empty());
 
         // Insert entry
+        let tx = entry.tx.clone();
         self.entries.insert(txid, entry);
-
-        // Check if we need to evict
-        self.maybe_evict()?;
-
-        Ok(txid)
+        self.notify(|sequence| MempoolEvent::Added { tx, sequence });
     }
 
     /// Remove a transaction from the mempool
-    pub fn remove_tx(&self, txid: &Txid) -> Result<MempoolEntry> {
+    pub fn remove_tx(&self, txid: &Txid, reason: RemovalReason) -> Result<MempoolEntry> {
+        let descendants = self.get_descendants(txid);
         let entry = self
             .entries
             .remove(txid)
@@ -199,12 +844,22 @@ impl Mempool {
         for input in &entry.tx.input {
             self.spends.remove(&input.previous_output);
         }
+        self.unbroadcast.write().remove(txid);
+        // Confirmed transactions were already handed to the estimator by remove_for_block
+        if reason != RemovalReason::Block {
+            self.fee_estimator.write().remove_tx(txid);
+        }
 
-        // Update parents
+        // Update parents and every ancestor
         for parent_txid in &entry.parents {
             if let Some(mut parent) = self.entries.get_mut(parent_txid) {
                 parent.children.remove(txid);
-                parent.update_descendant_state(
+            }
+        }
+        let (ancestors, _, _) = self.calculate_ancestors(&entry.parents);
+        for ancestor_txid in &ancestors {
+            if let Some(mut ancestor) = self.entries.get_mut(ancestor_txid) {
+                ancestor.update_descendant_state(
                     -(entry.vsize as i64),
                     -(entry.fee as i64),
                     -1,
@@ -212,11 +867,15 @@ impl Mempool {
             }
         }
 
-        // Update children
+        // Update children and every descendant
         for child_txid in &entry.children {
             if let Some(mut child) = self.entries.get_mut(child_txid) {
                 child.parents.remove(txid);
-                child.update_ancestor_state(
+            }
+        }
+        for descendant_txid in &descendants {
+            if let Some(mut descendant) = self.entries.get_mut(descendant_txid) {
+                descendant.update_ancestor_state(
                     -(entry.vsize as i64),
                     -(entry.fee as i64),
                     -1,
@@ -228,19 +887,104 @@ impl Mempool {
         *self.total_size.write() -= entry.vsize as usize;
         *self.total_fees.write() -= entry.fee;
 
+        let tx = entry.tx.clone();
+        self.notify(|sequence| MempoolEvent::Removed { tx, reason, sequence });
+
         Ok(entry)
     }
 
+    /// Remove a transaction and all of its descendants (Bitcoin Core:
+    /// removeRecursive). Returns how many left the mempool.
+    pub fn remove_recursive(&self, txid: &Txid, reason: RemovalReason) -> usize {
+        let descendants = self.get_descendants(txid);
+        std::iter::once(*txid)
+            .chain(descendants)
+            .filter(|txid| self.remove_tx(txid, reason).is_ok())
+            .count()
+    }
+
+    /// Remove transactions confirmed by `block`, connected at `height`, and
+    /// any that conflict with them
+    pub fn remove_for_block(&self, block: &bitcoin::Block, height: u32) {
+        let _validation = self.validation.lock();
+        let confirmed: Vec<MempoolEntry> =
+            block.txdata.iter().filter_map(|tx| self.get_entry(&tx.compute_txid())).collect();
+        self.fee_estimator.write().process_block(height, &confirmed);
+        *self.current_height.write() = height;
+
+        {
+            let mut rolling = self.rolling_min_fee.write();
+            rolling.last_update = unix_now();
+            rolling.block_since_bump = true;
+        }
+
+        for tx in &block.txdata {
+            let txid = tx.compute_txid();
+            self.deltas.remove(&txid);
+            if self.remove_tx(&txid, RemovalReason::Block).is_ok() {
+                continue;
+            }
+            if tx.is_coinbase() {
+                continue;
+            }
+            for input in &tx.input {
+                let conflict = self.spends.get(&input.previous_output).map(|e| *e.value());
+                if let Some(conflict) = conflict {
+                    self.remove_recursive(&conflict, RemovalReason::Conflict);
+                }
+            }
+        }
+    }
+
+    /// Current mempool sequence number (getrawmempool mempool_sequence)
+    pub fn sequence(&self) -> u64 {
+        self.sequence.load(Ordering::SeqCst)
+    }
+
+    /// Subscribe to mempool additions and removals
+    pub fn subscribe(&self) -> broadcast::Receiver<MempoolEvent> {
+        self.events.subscribe()
+    }
+
+    fn notify(&self, event: impl FnOnce(u64) -> MempoolEvent) {
+        let sequence = self.sequence.fetch_add(1, Ordering::SeqCst);
+        // No receivers is fine: nothing subscribed
+        let _ = self.events.send(event(sequence));
+    }
+
     /// Get a transaction from the mempool
     pub fn get_tx(&self, txid: &Txid) -> Option<Arc<Transaction>> {
         self.entries.get(txid).map(|entry| entry.tx.clone())
     }
 
+    /// Get a copy of a mempool entry
+    pub fn get_entry(&self, txid: &Txid) -> Option<MempoolEntry> {
+        self.entries.get(txid).map(|entry| entry.clone())
+    }
+
     /// Check if mempool contains transaction
     pub fn contains(&self, txid: &Txid) -> bool {
         self.entries.contains_key(txid)
     }
 
+    /// Parents of `tx` found neither in the mempool nor the UTXO set, for
+    /// fetching the parents of an orphan
+    pub fn missing_parents(&self, tx: &Transaction) -> Vec<Txid> {
+        let mut missing = Vec::new();
+        for input in &tx.input {
+            let txid = input.previous_output.txid;
+            if !missing.contains(&txid) && matches!(self.find_prevout(&input.previous_output, &HashMap::new()), Ok(None)) {
+                missing.push(txid);
+            }
+        }
+        missing
+    }
+
+    /// Check if a mempool transaction spends this outpoint
+    pub fn is_spent(&self, outpoint: &bitcoin::OutPoint) -> bool {
+        self.spends.contains_key(outpoint)
+    }
+
     /// Get mempool size
     pub fn size(&self) -> usize {
         self.entries.len()
@@ -259,78 +1003,21 @@ impl Mempool {
     /// Update current height
     pub fn update_height(&self, height: u32) {
         *self.current_height.write() = height;
-        self.fee_estimator.write().update_height(height);
 
         // Remove expired transactions
         let _ = self.remove_expired();
     }
 
-    /// Get transactions for mining (sorted by fee rate)
-    pub fn get_block_template(&self, max_weight: usize) -> Vec<Arc<Transaction>> {
-        let mut entries: Vec<_> = self
-            .entries
-            .iter()
-            .map(|entry| entry.value().clone())
-            .collect();
-
-        // Sort by ancestor fee rate (descending)
-        entries.sort_by(|a, b| {
-            b.ancestor_fee_rate().cmp(&a.ancestor_fee_rate())
-        });
-
-        let mut template = Vec::new();
-        let mut included = HashSet::new();
-        let mut current_weight = 0;
-
-        for entry in entries {
-            // Skip if already included or exceeds weight
-            if included.contains(&entry.txid) {
-                continue;
-            }
-
-            let tx_weight = entry.tx.weight().to_wu() as usize;
-            if current_weight + tx_weight > max_weight {
-                continue;
-            }
-
-            // Include all ancestors first
-            let ancestors = self.get_ancestors(&entry.txid);
-            let mut can_include = true;
-
-            for ancestor_txid in &ancestors {
-                if !included.contains(ancestor_txid) {
-                    if let Some(ancestor_entry) = self.entries.get(ancestor_txid) {
-                        let ancestor_weight = ancestor_entry.tx.weight().to_wu() as usize;
-                        if current_weight + ancestor_weight > max_weight {
-                            can_include = false;
-                            break;
-                        }
-                    }
-                }
-            }
-
-            if !can_include {
-                continue;
-            }
-
-            // Include ancestors
-            for ancestor_txid in ancestors {
-                if !included.contains(&ancestor_txid) {
-                    if let Some(ancestor_entry) = self.entries.get(&ancestor_txid) {
-                        template.push(ancestor_entry.tx.clone());
-                        included.insert(ancestor_txid);
-                        current_weight += ancestor_entry.tx.weight().to_wu() as usize;
-                    }
-                }
-            }
-
-            // Include the transaction itself
-            template.push(entry.tx.clone());
-            included.insert(entry.txid);
-            current_weight += tx_weight;
-        }
-
-        template
+    /// Transactions for a block on the current tip, selected by modified
+    /// ancestor feerate (Bitcoin Core: BlockAssembler::CreateNewBlock)
+    pub fn get_block_template(&self, options: BlockAssemblerOptions) -> BlockTemplate {
+        let height = self.tip_height() + 1;
+        let lock_time_cutoff = self
+            .kernel
+            .as_ref()
+            .and_then(|k| k.get_best_block_hash().and_then(|tip| k.median_time_past(&tip)).ok().flatten())
+            .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as u32);
+        BlockAssembler::new(self.entries_by_depth(), options).create_new_block(height, lock_time_cutoff)
     }
 
     /// Get statistics
@@ -340,8 +1027,8 @@ impl Mempool {
             bytes: self.total_size(),
             usage: *self.total_size.read(),
             max_mempool: self.policy.max_size,
-            mempool_min_fee: self.policy.min_relay_fee.as_sat_per_vb() as f64 / 1000.0,
-            min_relay_tx_fee: self.policy.min_relay_fee.as_sat_per_vb() as f64 / 1000.0,
+            mempool_min_fee: self.min_fee_rate(),
+            min_relay_tx_fee: self.policy.min_relay_fee,
             total_fee: *self.total_fees.read() as f64 / 100_000_000.0,
         }
     }
@@ -351,10 +1038,64 @@ impl Mempool {
         self.entries.iter().map(|entry| *entry.key()).collect()
     }
 
+    /// All entries, parents before children
+    pub fn entries_by_depth(&self) -> Vec<MempoolEntry> {
+        let mut entries: Vec<MempoolEntry> = self.entries.iter().map(|e| e.value().clone()).collect();
+        entries.sort_by_key(|e| e.ancestor_count);
+        entries
+    }
+
+    /// Add `fee_delta` to the fee a transaction is mined by, whether or not it
+    /// is in the mempool yet (Bitcoin Core: PrioritiseTransaction)
+    pub fn prioritise_transaction(&self, txid: Txid, fee_delta: i64) {
+        let total = {
+            let mut delta = self.deltas.entry(txid).or_insert(0);
+            *delta = delta.saturating_add(fee_delta);
+            *delta
+        };
+        if total == 0 {
+            self.deltas.remove(&txid);
+        }
+        if let Some(mut entry) = self.entries.get_mut(&txid) {
+            entry.fee_delta = total;
+        }
+    }
+
+    /// Prioritisation deltas by txid
+    pub fn fee_deltas(&self) -> Vec<(Txid, i64)> {
+        self.deltas.iter().map(|d| (*d.key(), *d.value())).collect()
+    }
+
+    /// Track a locally submitted transaction until a peer requests it
+    pub fn add_unbroadcast(&self, txid: Txid) {
+        if self.entries.contains_key(&txid) {
+            self.unbroadcast.write().insert(txid);
+        }
+    }
+
+    /// A peer requested the transaction, so initial broadcast succeeded
+    pub fn remove_unbroadcast(&self, txid: &Txid) {
+        self.unbroadcast.write().remove(txid);
+    }
+
+    pub fn unbroadcast_txids(&self) -> Vec<Txid> {
+        self.unbroadcast.read().iter().copied().collect()
+    }
+
+    /// Whether mempool.dat loading has finished
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.load(Ordering::SeqCst)
+    }
+
+    pub fn set_loaded(&self, loaded: bool) {
+        self.loaded.store(loaded, Ordering::SeqCst);
+    }
+
     /// Clear the mempool
     pub fn clear(&self) {
         self.entries.clear();
         self.spends.clear();
+        self.unbroadcast.write().clear();
         *self.total_size.write() = 0;
         *self.total_fees.write() = 0;
     }
@@ -366,7 +1107,9 @@ impl Mempool {
 
         for input in &tx.input {
             if let Some(entry) = self.spends.get(&input.previous_output) {
-                conflicts.push(*entry.value());
+                if !conflicts.contains(entry.value()) {
+                    conflicts.push(*entry.value());
+                }
             }
         }
 
@@ -398,7 +1141,7 @@ impl Mempool {
 
             if let Some(entry) = self.entries.get(&current) {
                 for parent in &entry.parents {
-                    if !visited.contains(parent) {
+                    if !visited.contains(parent) && !ancestors.contains(parent) {
                         to_visit.push(*parent);
                         ancestors.push(*parent);
                     }
@@ -409,86 +1152,195 @@ impl Mempool {
         ancestors
     }
 
-    fn calculate_ancestors(&self, parents: &HashSet<Txid>) -> Result<(usize, u64, u64)> {
-        let mut count = 0;
+    /// Every mempool ancestor reachable from `parents`, with their total vsize and fees.
+    /// Shared ancestors are counted once.
+    fn calculate_ancestors(&self, parents: &HashSet<Txid>) -> (HashSet<Txid>, u64, u64) {
+        let mut ancestors = HashSet::new();
+        for parent_txid in parents {
+            if self.entries.contains_key(parent_txid) {
+                ancestors.insert(*parent_txid);
+                ancestors.extend(self.get_ancestors(parent_txid));
+            }
+        }
+
         let mut size = 0u64;
         let mut fees = 0u64;
-
-        for parent_txid in parents {
-            if let Some(parent) = self.entries.get(parent_txid) {
-                count += parent.ancestor_count;
-                size += parent.ancestor_size;
-                fees += parent.ancestor_fees;
+        for ancestor_txid in &ancestors {
+            if let Some(ancestor) = self.entries.get(ancestor_txid) {
+                size += ancestor.vsize;
+                fees += ancestor.fee;
             }
         }
 
-        Ok((count, size, fees))
+        (ancestors, size, fees)
     }
 
-    fn handle_replacement(
+    /// Ancestor limits for a new transaction of `vsize`, and descendant limits
+    /// of each of its ancestors once it is added
+    fn check_chain_limits(
         &self,
-        new_entry: &MempoolEntry,
-        conflicts: &[Txid],
-    ) -> Result<()> {
-        // Calculate total fees of conflicts
-        let mut conflict_fees = 0u64;
-        let mut conflict_size = 0i64;
-
-        for conflict_txid in conflicts {
-            if let Some(conflict) = self.entries.get(conflict_txid) {
-                if !conflict.signals_replacement {
-                    return Err(anyhow!("conflicting tx does not signal RBF"));
-                }
-                conflict_fees += conflict.fee;
-                conflict_size += conflict.vsize as i64;
+        policy: &MempoolPolicy,
+        ancestors: &HashSet<Txid>,
+        ancestor_size: u64,
+        vsize: u64,
+    ) -> Result<(), String> {
+        policy.check_ancestor_limits(ancestors.len() + 1, ancestor_size + vsize)?;
+        for ancestor_txid in ancestors {
+            if let Some(ancestor) = self.entries.get(ancestor_txid) {
+                policy
+                    .check_descendant_limits(ancestor.descendant_count + 1, ancestor.descendant_size + vsize)
+                    .map_err(|e| format!("exceeds descendant limit for {}: {}", ancestor_txid, e))?;
             }
         }
+        Ok(())
+    }
 
-        // Check RBF rules
-        let fee_delta = new_entry.fee.saturating_sub(conflict_fees);
-        let size_delta = (new_entry.vsize as i64) - conflict_size;
-
-        self.policy.check_rbf(
-            new_entry.signals_replacement,
-            fee_delta,
-            size_delta,
-        ).map_err(|e| anyhow!(e))?;
+    fn get_descendants(&self, txid: &Txid) -> Vec<Txid> {
+        let mut descendants = Vec::new();
+        let mut to_visit = vec![*txid];
+        let mut visited = HashSet::new();
 
-        // Remove conflicts
-        for conflict_txid in conflicts {
-            let _ = self.remove_tx(conflict_txid);
+        while let Some(current) = to_visit.pop() {
+            if !visited.insert(current) {
+                continue;
+            }
+            if let Some(entry) = self.entries.get(&current) {
+                for child in &entry.children {
+                    if !visited.contains(child) && !descendants.contains(child) {
+                        to_visit.push(*child);
+                        descendants.push(*child);
+                    }
+                }
+            }
         }
 
-        Ok(())
+        descendants
     }
 
-    fn maybe_evict(&self) -> Result<()> {
-        let current_size = *self.total_size.read();
-        if current_size <= self.policy.max_size {
-            return Ok(());
+    /// Whether a transaction or one of its mempool ancestors signals BIP125
+    /// replaceability; always true with full RBF and for TRUC transactions
+    fn is_replaceable(&self, txid: &Txid) -> bool {
+        self.policy.full_rbf
+            || std::iter::once(*txid)
+                .chain(self.get_ancestors(txid))
+                .any(|t| self.entries.get(&t).is_some_and(|e| e.signals_replacement || truc::is_truc(&e.tx)))
+    }
+
+    /// BIP125 rules that do not depend on fees (1, 2 and 5). Returns the
+    /// conflicts and their descendants a replacement would evict.
+    fn replacement_candidates(
+        &self,
+        tx: &Transaction,
+        conflicts: &[Txid],
+    ) -> Result<ReplacementReport, TxValidationError> {
+        // Rule 1: every original must be replaceable
+        for txid in conflicts {
+            if !self.policy.enable_rbf || !self.is_replaceable(txid) {
+                return Err(TxValidationError::new(
+                    TxValidationResult::Conflict,
+                    "txn-mempool-conflict",
+                    format!("{} is not replaceable", txid),
+                ));
+            }
         }
 
-        // Evict lowest fee rate transactions until we're under the limit
-        let mut entries: Vec<_> = self
-            .entries
+        // Rule 5: bound the number of evicted transactions
+        let mut evicted: Vec<Txid> = conflicts.to_vec();
+        let mut seen: HashSet<Txid> = conflicts.iter().copied().collect();
+        for txid in conflicts {
+            evicted.extend(self.get_descendants(txid).into_iter().filter(|d| seen.insert(*d)));
+        }
+        if evicted.len() > self.policy.max_replacement_candidates {
+            return Err(TxValidationError::policy(
+                "too many potential replacements",
+                format!("{} > {}", evicted.len(), self.policy.max_replacement_candidates),
+            ));
+        }
+
+        // Rule 2: unconfirmed inputs only if an original already spent from the same transaction
+        let conflict_parents: HashSet<Txid> = conflicts
             .iter()
-            .map(|entry| entry.value().clone())
+            .filter_map(|txid| self.entries.get(txid))
+            .flat_map(|e| e.tx.input.iter().map(|i| i.previous_output.txid).collect::<Vec<_>>())
             .collect();
+        for input in &tx.input {
+            let parent = input.previous_output.txid;
+            if seen.contains(&parent) {
+                return Err(TxValidationError::consensus("bad-txns-spends-conflicting-tx"));
+            }
+            if self.entries.contains_key(&parent) && !conflict_parents.contains(&parent) {
+                return Err(TxValidationError::policy(
+                    "replacement-adds-unconfirmed",
+                    format!("input {} is unconfirmed", input.previous_output),
+                ));
+            }
+        }
 
-        entries.sort_by(|a, b| a.fee_rate.cmp(&b.fee_rate));
-
-        let mut evicted_size = 0;
-        for entry in entries {
-            if current_size - evicted_size <= self.policy.max_size {
-                break;
+        let mut report = ReplacementReport { conflicts: conflicts.to_vec(), ..Default::default() };
+        for txid in evicted {
+            if let Some(e) = self.entries.get(&txid) {
+                report.replaced.push((txid, e.modified_fee()));
+                report.replaced_fees += e.modified_fee();
             }
+        }
+        Ok(report)
+    }
 
-            if self.remove_tx(&entry.txid).is_ok() {
-                evicted_size += entry.vsize as usize;
+    /// BIP125 fee rules (3, 4 and 6) for a replacement paying modified `fee` for `vsize`
+    fn check_replacement_fees(&self, fee: u64, vsize: u64, report: &ReplacementReport) -> Result<(), String> {
+        // Rule 6: a higher feerate than every transaction it directly conflicts with
+        for txid in &report.conflicts {
+            if let Some(conflict) = self.entries.get(txid) {
+                let conflict_fee = conflict.modified_fee();
+                if fee as u128 * conflict.vsize as u128 <= conflict_fee as u128 * vsize as u128 {
+                    return Err(format!(
+                        "new feerate {}/{} sat/vB <= old feerate {}/{} sat/vB of {}",
+                        fee, vsize, conflict_fee, conflict.vsize, txid
+                    ));
+                }
             }
         }
 
-        Ok(())
+        // Rule 3: at least the absolute fees of everything evicted
+        if fee < report.replaced_fees {
+            return Err(format!("less fees than conflicting txs; {} < {}", fee, report.replaced_fees));
+        }
+
+        // Rule 4: pay for its own relay bandwidth
+        self.policy.check_rbf(true, fee - report.replaced_fees, vsize as i64)
+    }
+
+    /// Evict packages with the lowest descendant score until the mempool fits
+    /// its size limit, raising the rolling minimum fee above each evicted
+    /// package (Bitcoin Core: TrimToSize)
+    fn trim_to_size(&self) {
+        while *self.total_size.read() > self.policy.max_size {
+            // A transaction leaves together with its descendants, scored by the
+            // better of its own feerate and that of the whole package
+            let worst = self
+                .entries
+                .iter()
+                .map(|e| (e.txid, e.fee_rate.max(e.descendant_fee_rate()), e.descendant_fee_rate()))
+                .min_by_key(|(_, score, _)| *score);
+            let Some((txid, _, package_rate)) = worst else {
+                break;
+            };
+
+            let removed_rate = package_rate.saturating_add(self.policy.incremental_relay_fee);
+            self.track_package_removed(removed_rate);
+
+            let evicted = self.remove_recursive(&txid, RemovalReason::SizeLimit);
+            eprintln!("[mempool] evicted {} tx(s) for size, rolling min fee {}", evicted, removed_rate);
+        }
+    }
+
+    fn track_package_removed(&self, rate: FeeRate) {
+        let rate = rate.as_sat_per_kvb() as f64;
+        let mut rolling = self.rolling_min_fee.write();
+        if rate > rolling.rate {
+            rolling.rate = rate;
+            rolling.block_since_bump = false;
+        }
     }
 
     fn remove_expired(&self) -> Result<usize> {
@@ -503,14 +1355,48 @@ impl Mempool {
             .collect();
 
         for txid in expired {
-            if self.remove_tx(&txid).is_ok() {
-                removed += 1;
-            }
+            // An earlier expired ancestor may already have taken it out
+            removed += self.remove_recursive(&txid, RemovalReason::Expiry);
         }
 
         Ok(removed)
     }
 
+    /// Lowest feerate a new transaction must pay: the relay minimum or the
+    /// rolling minimum fee left by recent evictions, whichever is higher
+    pub fn min_fee_rate(&self) -> FeeRate {
+        self.policy.min_relay_fee.max(self.rolling_min_fee_at(unix_now()))
+    }
+
+    /// Rolling minimum fee at unix time `now`. After a block it halves every
+    /// 12 hours, faster while the mempool is well below its limit, and drops
+    /// to zero once under half the incremental relay fee (Bitcoin Core: GetMinFee).
+    fn rolling_min_fee_at(&self, now: u64) -> FeeRate {
+        let mut rolling = self.rolling_min_fee.write();
+        if !rolling.block_since_bump || rolling.rate == 0.0 {
+            return FeeRate::from_sat_per_kvb(rolling.rate.round() as u64);
+        }
+
+        if now > rolling.last_update + 10 {
+            let usage = *self.total_size.read();
+            let mut halflife = ROLLING_FEE_HALFLIFE as f64;
+            if usage < self.policy.max_size / 4 {
+                halflife /= 4.0;
+            } else if usage < self.policy.max_size / 2 {
+                halflife /= 2.0;
+            }
+            rolling.rate /= 2f64.powf((now - rolling.last_update) as f64 / halflife);
+            rolling.last_update = now;
+
+            let incremental = self.policy.incremental_relay_fee.as_sat_per_kvb() as f64;
+            if rolling.rate < incremental / 2.0 {
+                rolling.rate = 0.0;
+                return FeeRate::ZERO;
+            }
+        }
+        FeeRate::from_sat_per_kvb(rolling.rate.round() as u64).max(self.policy.incremental_relay_fee)
+    }
+
     /// Get fee estimator
     pub fn fee_estimator(&self) -> Arc<RwLock<FeeEstimator>> {
         self.fee_estimator.clone()
@@ -522,6 +1408,10 @@ impl Mempool {
     }
 }
 
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
 /// Mempool statistics
 #[derive(Debug, Clone)]
 pub struct MempoolStats {
@@ -529,8 +1419,8 @@ pub struct MempoolStats {
     pub bytes: usize,
     pub usage: usize,
     pub max_mempool: usize,
-    pub mempool_min_fee: f64,
-    pub min_relay_tx_fee: f64,
+    pub mempool_min_fee: FeeRate,
+    pub min_relay_tx_fee: FeeRate,
     pub total_fee: f64,
 }
 
@@ -538,6 +1428,7 @@ pub struct MempoolStats {
 mod tests {
     use super::*;
     use bitcoin::consensus::deserialize;
+    use bitcoin::hashes::Hash;
 
     fn create_dummy_tx(n: u8) -> Transaction {
         let hex = format!(
@@ -579,6 +1470,362 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_events_and_sequence() {
+        let mempool = Mempool::new(MempoolPolicy::regtest());
+        let mut events = mempool.subscribe();
+        assert_eq!(mempool.sequence(), 1);
+
+        let tx = spend(bitcoin::OutPoint::new(Txid::from_byte_array([1; 32]), 0), 10_000);
+        let txid = mempool.add_tx(tx, 1000, 100).unwrap();
+        mempool.remove_tx(&txid, RemovalReason::Expiry).unwrap();
+        assert_eq!(mempool.sequence(), 3);
+
+        match events.try_recv().unwrap() {
+            MempoolEvent::Added { tx, sequence } => {
+                assert_eq!(tx.compute_txid(), txid);
+                assert_eq!(sequence, 1);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        match events.try_recv().unwrap() {
+            MempoolEvent::Removed { reason, sequence, .. } => {
+                assert_eq!(reason, RemovalReason::Expiry);
+                assert_eq!(sequence, 2);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    fn spend(prevout: bitcoin::OutPoint, value: u64) -> Transaction {
+        Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn { previous_output: prevout, ..Default::default() }],
+            output: vec![bitcoin::TxOut {
+                value: bitcoin::Amount::from_sat(value),
+                script_pubkey: bitcoin::ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::all_zeros()),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_accept_computes_fee_from_mempool_parent() {
+        let mempool = Mempool::new(MempoolPolicy::mainnet());
+        let parent = spend(bitcoin::OutPoint::new(Txid::from_byte_array([1; 32]), 0), 100_000);
+        let parent_txid = mempool.add_tx(parent, 10_000, 100).unwrap();
+
+        let child = spend(bitcoin::OutPoint::new(parent_txid, 0), 90_000);
+        let checked = mempool.test_accept(&child, &HashMap::new()).unwrap();
+        assert_eq!(checked.fee, 10_000);
+        assert_eq!(mempool.size(), 1);
+
+        let accepted = mempool.accept_tx(child.clone()).unwrap();
+        assert_eq!(accepted.txid, child.compute_txid());
+        assert_eq!(mempool.get_entry(&parent_txid).unwrap().descendant_count, 2);
+
+        let err = mempool.accept_tx(child).unwrap_err();
+        assert_eq!(err.reason, "txn-already-in-mempool");
+    }
+
+    #[test]
+    fn test_package_pays_for_zero_fee_parent() {
+        let mempool = Mempool::new(MempoolPolicy::mainnet());
+        let funding = spend(bitcoin::OutPoint::new(Txid::from_byte_array([1; 32]), 0), 100_000);
+        let funding_txid = mempool.add_tx(funding, 10_000, 100).unwrap();
+
+        let parent = spend(bitcoin::OutPoint::new(funding_txid, 0), 100_000);
+        let child = spend(bitcoin::OutPoint::new(parent.compute_txid(), 0), 80_000);
+        let err = mempool.accept_tx(parent.clone()).unwrap_err();
+        assert_eq!(err.result, TxValidationResult::Reconsiderable);
+
+        // Reversed order is not child-with-parents
+        let result = mempool.accept_package(&[child.clone(), parent.clone()], None);
+        assert_eq!(result.error.as_deref(), Some("package-not-sorted"));
+
+        let result = mempool.accept_package(&[parent.clone(), child.clone()], None);
+        assert_eq!(result.error, None);
+        match &result.results[&parent.compute_wtxid()] {
+            PackageTxResult::Accepted(accepted) => {
+                assert_eq!(accepted.fee, 0);
+                assert_eq!(accepted.effective_fee, 20_000);
+                assert_eq!(accepted.effective_includes.len(), 2);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(mempool.contains(&child.compute_txid()));
+        assert_eq!(mempool.get_entry(&parent.compute_txid()).unwrap().descendant_count, 2);
+        assert_eq!(mempool.get_entry(&funding_txid).unwrap().descendant_count, 3);
+
+        let result = mempool.accept_package(&[parent.clone(), child], None);
+        assert!(matches!(result.results[&parent.compute_wtxid()], PackageTxResult::AlreadyInMempool(_)));
+    }
+
+    #[test]
+    fn test_aggregates_span_whole_chain() {
+        let mempool = Mempool::new(MempoolPolicy::mainnet());
+        let a = mempool.add_tx(spend(bitcoin::OutPoint::new(Txid::from_byte_array([1; 32]), 0), 100_000), 1_000, 100).unwrap();
+        let b = mempool.accept_tx(spend(bitcoin::OutPoint::new(a, 0), 99_000)).unwrap().txid;
+        let c = mempool.accept_tx(spend(bitcoin::OutPoint::new(b, 0), 98_000)).unwrap().txid;
+
+        let entry = mempool.get_entry(&a).unwrap();
+        assert_eq!(entry.descendant_count, 3);
+        assert_eq!(entry.descendant_fees, 3_000);
+        let entry = mempool.get_entry(&c).unwrap();
+        assert_eq!(entry.ancestor_count, 3);
+        assert_eq!(entry.ancestor_fees, 3_000);
+
+        mempool.remove_tx(&a, RemovalReason::Block).unwrap();
+        assert_eq!(mempool.get_entry(&c).unwrap().ancestor_count, 2);
+        assert_eq!(mempool.get_entry(&b).unwrap().descendant_count, 2);
+    }
+
+    #[test]
+    fn test_descendant_limit_and_cpfp_carve_out() {
+        let policy = MempoolPolicy { max_descendants: 2, ..MempoolPolicy::mainnet() };
+        let mempool = Mempool::new(policy);
+        let mut parent = spend(bitcoin::OutPoint::new(Txid::from_byte_array([1; 32]), 0), 10_000);
+        parent.output = vec![parent.output[0].clone(); 4];
+        let parent_txid = mempool.add_tx(parent, 10_000, 200).unwrap();
+
+        let first = mempool.accept_tx(spend(bitcoin::OutPoint::new(parent_txid, 0), 9_000)).unwrap().txid;
+        // A grandchild has two ancestors, so the carve-out does not apply
+        let err = mempool.accept_tx(spend(bitcoin::OutPoint::new(first, 0), 8_000)).unwrap_err();
+        assert_eq!(err.reason, "too-long-mempool-chain");
+
+        // One more direct child of the parent is carved out...
+        mempool.accept_tx(spend(bitcoin::OutPoint::new(parent_txid, 1), 9_000)).unwrap();
+        assert_eq!(mempool.get_entry(&parent_txid).unwrap().descendant_count, 3);
+        // ...but only one
+        let err = mempool.accept_tx(spend(bitcoin::OutPoint::new(parent_txid, 2), 9_000)).unwrap_err();
+        assert_eq!(err.reason, "too-long-mempool-chain");
+    }
+
+    #[test]
+    fn test_fee_estimator_follows_mempool() {
+        let tx = |n: u8| spend(bitcoin::OutPoint::new(Txid::from_byte_array([n; 32]), 0), 10_000);
+        let mempool = Mempool::new(MempoolPolicy::mainnet());
+        let estimator = mempool.fee_estimator();
+
+        // Children of unconfirmed parents say nothing about miners' feerates
+        let parent = mempool.add_tx(tx(1), 1_000, 0).unwrap();
+        mempool.add_tx(spend(bitcoin::OutPoint::new(parent, 0), 9_000), 1_000, 0).unwrap();
+        assert_eq!(estimator.read().tracked_count(), 1);
+
+        let block = bitcoin::Block {
+            header: bitcoin::constants::genesis_block(bitcoin::Network::Regtest).header,
+            txdata: vec![(*mempool.get_tx(&parent).unwrap()).clone()],
+        };
+        mempool.remove_for_block(&block, 1);
+        assert_eq!(estimator.read().tracked_count(), 0);
+        assert_eq!(estimator.read().best_seen_height(), 1);
+
+        let loner = mempool.add_tx(tx(2), 1_000, 1).unwrap();
+        assert_eq!(estimator.read().tracked_count(), 1);
+        mempool.remove_tx(&loner, RemovalReason::Conflict).unwrap();
+        assert_eq!(estimator.read().tracked_count(), 0);
+    }
+
+    #[test]
+    fn test_block_conflict_removes_descendants() {
+        let outpoint = bitcoin::OutPoint::new(Txid::from_byte_array([1; 32]), 0);
+        let mempool = Mempool::new(MempoolPolicy::mainnet());
+        let parent = mempool.add_tx(spend(outpoint, 10_000), 1_000, 0).unwrap();
+        let child = mempool.add_tx(spend(bitcoin::OutPoint::new(parent, 0), 9_000), 1_000, 0).unwrap();
+        let grandchild = mempool.add_tx(spend(bitcoin::OutPoint::new(child, 0), 8_000), 1_000, 0).unwrap();
+
+        // The block confirms a different spend of the parent's input
+        let block = bitcoin::Block {
+            header: bitcoin::constants::genesis_block(bitcoin::Network::Regtest).header,
+            txdata: vec![spend(outpoint, 9_500)],
+        };
+        mempool.remove_for_block(&block, 1);
+        for txid in [parent, child, grandchild] {
+            assert!(!mempool.contains(&txid));
+        }
+        assert_eq!(mempool.size(), 0);
+    }
+
+    #[test]
+    fn test_trim_evicts_by_descendant_score_and_decays() {
+        let tx = |n: u8| spend(bitcoin::OutPoint::new(Txid::from_byte_array([n; 32]), 0), 10_000);
+        let vsize = tx(1).vsize() as u64;
+        let policy = MempoolPolicy { max_size: 3 * vsize as usize, ..MempoolPolicy::mainnet() };
+        let mempool = Mempool::new(policy);
+
+        // A cheap parent carried by a high-fee child outscores a medium-fee loner
+        let parent = mempool.add_tx(tx(1), vsize, 100).unwrap();
+        let child = mempool.add_tx(spend(bitcoin::OutPoint::new(parent, 0), 9_000), 10 * vsize, 100).unwrap();
+        let loner = mempool.add_tx(tx(2), 3 * vsize, 100).unwrap();
+        let newcomer = mempool.add_tx(tx(3), 4 * vsize, 100).unwrap();
+
+        assert!(!mempool.contains(&loner));
+        for txid in [parent, child, newcomer] {
+            assert!(mempool.contains(&txid));
+        }
+        // Evicted package feerate plus the incremental relay fee
+        assert_eq!(mempool.min_fee_rate(), FeeRate::from_sat_per_vb(4));
+        let err = mempool.add_tx(tx(4), 3 * vsize, 100).unwrap_err();
+        assert!(err.to_string().contains("mempool min fee not met"));
+
+        // No decay until a block arrives, then one half-life halves it
+        let now = unix_now();
+        assert_eq!(mempool.rolling_min_fee_at(now + ROLLING_FEE_HALFLIFE), FeeRate::from_sat_per_vb(4));
+        {
+            let mut rolling = mempool.rolling_min_fee.write();
+            rolling.block_since_bump = true;
+            rolling.last_update = now;
+        }
+        assert_eq!(mempool.rolling_min_fee_at(now + ROLLING_FEE_HALFLIFE), FeeRate::from_sat_per_vb(2));
+        assert_eq!(mempool.rolling_min_fee_at(now + 10 * ROLLING_FEE_HALFLIFE), FeeRate::from_sat_per_vb(0));
+        assert_eq!(mempool.min_fee_rate(), MempoolPolicy::mainnet().min_relay_fee);
+    }
+
+    fn signal_rbf(mut tx: Transaction) -> Transaction {
+        for input in &mut tx.input {
+            input.sequence = bitcoin::Sequence::ENABLE_RBF_NO_LOCKTIME;
+        }
+        tx
+    }
+
+    #[test]
+    fn test_replacement_pays_for_evicted_descendants() {
+        let mempool = Mempool::new(MempoolPolicy::mainnet());
+        let funding = spend(bitcoin::OutPoint::new(Txid::from_byte_array([1; 32]), 0), 100_000);
+        let funding_txid = mempool.add_tx(funding, 10_000, 100).unwrap();
+        let original = signal_rbf(spend(bitcoin::OutPoint::new(funding_txid, 0), 90_000));
+        let original_txid = mempool.add_tx(original, 10_000, 100).unwrap();
+        let child = spend(bitcoin::OutPoint::new(original_txid, 0), 85_000);
+        let child_txid = mempool.add_tx(child, 5_000, 100).unwrap();
+
+        // Beats the original alone but not original + child (rule 3)
+        let cheap = spend(bitcoin::OutPoint::new(funding_txid, 0), 86_000);
+        let err = mempool.accept_tx(cheap).unwrap_err();
+        assert_eq!(err.reason, "insufficient fee");
+        assert_eq!(err.result, TxValidationResult::Reconsiderable);
+
+        // New unconfirmed inputs are not allowed (rule 2)
+        let other = spend(bitcoin::OutPoint::new(Txid::from_byte_array([3; 32]), 0), 50_000);
+        let other_txid = mempool.add_tx(other, 1_000, 100).unwrap();
+        let mut adds_unconfirmed = spend(bitcoin::OutPoint::new(funding_txid, 0), 80_000);
+        adds_unconfirmed.input.push(bitcoin::TxIn {
+            previous_output: bitcoin::OutPoint::new(other_txid, 0),
+            ..Default::default()
+        });
+        assert_eq!(mempool.accept_tx(adds_unconfirmed).unwrap_err().reason, "replacement-adds-unconfirmed");
+
+        let replacement = spend(bitcoin::OutPoint::new(funding_txid, 0), 80_000);
+        let accepted = mempool.accept_tx(replacement).unwrap();
+        let report = accepted.replacement.unwrap();
+        assert_eq!(report.conflicts, vec![original_txid]);
+        assert_eq!(report.replaced, vec![(original_txid, 10_000), (child_txid, 5_000)]);
+        assert_eq!(report.replaced_fees, 15_000);
+        assert!(!mempool.contains(&original_txid));
+        assert!(!mempool.contains(&child_txid));
+    }
+
+    #[test]
+    fn test_replacement_uses_modified_fees() {
+        let mempool = Mempool::new(MempoolPolicy::mainnet());
+        let funding = spend(bitcoin::OutPoint::new(Txid::from_byte_array([1; 32]), 0), 100_000);
+        let funding_txid = mempool.add_tx(funding, 10_000, 100).unwrap();
+        let original = signal_rbf(spend(bitcoin::OutPoint::new(funding_txid, 0), 90_000));
+        let original_txid = mempool.add_tx(original, 10_000, 100).unwrap();
+        mempool.prioritise_transaction(original_txid, 20_000);
+
+        // 20k outbids the original's base fee but not its modified fee
+        let replacement = spend(bitcoin::OutPoint::new(funding_txid, 0), 80_000);
+        assert_eq!(mempool.accept_tx(replacement.clone()).unwrap_err().reason, "insufficient fee");
+        assert!(mempool.contains(&original_txid));
+
+        mempool.prioritise_transaction(replacement.compute_txid(), 15_000);
+        let report = mempool.accept_tx(replacement).unwrap().replacement.unwrap();
+        assert_eq!(report.replaced, vec![(original_txid, 30_000)]);
+        assert_eq!(report.replaced_fees, 30_000);
+        assert!(!mempool.contains(&original_txid));
+    }
+
+    #[test]
+    fn test_non_final_rejected() {
+        let mempool = Mempool::new(MempoolPolicy::mainnet());
+        let parent = spend(bitcoin::OutPoint::new(Txid::from_byte_array([1; 32]), 0), 100_000);
+        let parent_txid = mempool.add_tx(parent, 10_000, 0).unwrap();
+        let child = |sequence: bitcoin::Sequence, lock_time: u32| {
+            let mut tx = spend(bitcoin::OutPoint::new(parent_txid, 0), 90_000);
+            tx.input[0].sequence = sequence;
+            tx.lock_time = bitcoin::absolute::LockTime::from_consensus(lock_time);
+            tx
+        };
+
+        // Lock time at the next block's height
+        let err = mempool.test_accept(&child(bitcoin::Sequence::ENABLE_LOCKTIME_NO_RBF, 1), &HashMap::new());
+        assert_eq!(err.unwrap_err().reason, "non-final");
+        assert!(mempool.test_accept(&child(bitcoin::Sequence::ENABLE_LOCKTIME_NO_RBF, 0), &HashMap::new()).is_ok());
+
+        // An unconfirmed parent counts as mined in the next block, so even a 1-block lock holds
+        let err = mempool.test_accept(&child(bitcoin::Sequence::from_height(1), 0), &HashMap::new());
+        assert_eq!(err.unwrap_err().reason, "non-BIP68-final");
+        assert!(mempool.test_accept(&child(bitcoin::Sequence::from_height(0), 0), &HashMap::new()).is_ok());
+    }
+
+    #[test]
+    fn test_full_rbf_replaces_non_signaling() {
+        for full_rbf in [false, true] {
+            let mempool = Mempool::new(MempoolPolicy { full_rbf, ..MempoolPolicy::mainnet() });
+            let funding = spend(bitcoin::OutPoint::new(Txid::from_byte_array([1; 32]), 0), 100_000);
+            let funding_txid = mempool.add_tx(funding, 10_000, 100).unwrap();
+            let original = spend(bitcoin::OutPoint::new(funding_txid, 0), 90_000);
+            mempool.add_tx(original, 10_000, 100).unwrap();
+
+            let replacement = spend(bitcoin::OutPoint::new(funding_txid, 0), 80_000);
+            match mempool.accept_tx(replacement) {
+                Ok(_) => assert!(full_rbf),
+                Err(e) => {
+                    assert!(!full_rbf);
+                    assert_eq!(e.reason, "txn-mempool-conflict");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_truc_sibling_eviction() {
+        let mempool = Mempool::new(MempoolPolicy::mainnet());
+        let mut parent = spend(bitcoin::OutPoint::new(Txid::from_byte_array([1; 32]), 0), 50_000);
+        parent.version = truc::TRUC_VERSION;
+        parent.output.push(parent.output[0].clone());
+        let parent_txid = mempool.add_tx(parent, 1_000, 100).unwrap();
+
+        let mut first = spend(bitcoin::OutPoint::new(parent_txid, 0), 49_000);
+        first.version = truc::TRUC_VERSION;
+        let first_txid = mempool.accept_tx(first).unwrap().txid;
+
+        // A non-TRUC child may not spend a TRUC parent
+        let v2_child = spend(bitcoin::OutPoint::new(parent_txid, 1), 45_000);
+        assert_eq!(mempool.accept_tx(v2_child).unwrap_err().reason, "TRUC-violation");
+
+        // A second TRUC child replaces the first if it pays more
+        let mut second = spend(bitcoin::OutPoint::new(parent_txid, 1), 45_000);
+        second.version = truc::TRUC_VERSION;
+        let accepted = mempool.accept_tx(second).unwrap();
+        assert_eq!(accepted.replacement.unwrap().conflicts, vec![first_txid]);
+        assert!(!mempool.contains(&first_txid));
+        assert_eq!(mempool.get_entry(&parent_txid).unwrap().descendant_count, 2);
+    }
+
+    #[test]
+    fn test_accept_rejects_missing_and_overspending_inputs() {
+        let mempool = Mempool::new(MempoolPolicy::mainnet());
+        let orphan = spend(bitcoin::OutPoint::new(Txid::from_byte_array([2; 32]), 0), 1000);
+        assert_eq!(mempool.missing_parents(&orphan), vec![Txid::from_byte_array([2; 32])]);
+        assert_eq!(mempool.accept_tx(orphan).unwrap_err().result, TxValidationResult::MissingInputs);
+
+        let parent = spend(bitcoin::OutPoint::new(Txid::from_byte_array([1; 32]), 0), 1000);
+        let parent_txid = mempool.add_tx(parent, 1000, 100).unwrap();
+        let overspend = spend(bitcoin::OutPoint::new(parent_txid, 0), 2000);
+        assert_eq!(mempool.accept_tx(overspend).unwrap_err().reason, "bad-txns-in-belowout");
+    }
+
     #[test]
     fn test_get_stats() {
         let policy = MempoolPolicy::default();
@@ -0,0 +1,229 @@
+//! Mempool acceptance results (Bitcoin Core: TxValidationState).
+
+use bitcoin::{Transaction, Txid, Wtxid};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Most transactions in a package (Bitcoin Core: MAX_PACKAGE_COUNT)
+pub const MAX_PACKAGE_COUNT: usize = 25;
+
+/// Largest total package weight (Bitcoin Core: MAX_PACKAGE_WEIGHT)
+pub const MAX_PACKAGE_WEIGHT: u64 = 404_000;
+
+/// Class of a rejection; decides how RPC callers report it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxValidationResult {
+    /// Invalid by consensus rules
+    Consensus,
+    /// Violates standardness policy
+    NotStandard,
+    /// An input is neither in the UTXO set nor in the mempool
+    MissingInputs,
+    /// Spends an immature coinbase output
+    PrematureSpend,
+    /// Already in the mempool, or conflicts with it
+    Conflict,
+    /// Rejected by mempool policy (fees, chain limits, size)
+    MempoolPolicy,
+    /// Fails only on feerate; may still be accepted together with a fee-paying child
+    Reconsiderable,
+    /// The UTXO set has not caught up with the chain tip yet; try again later
+    NotReady,
+}
+
+/// Why a transaction was not accepted to the mempool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxValidationError {
+    pub result: TxValidationResult,
+    /// Short reason string, as in Bitcoin Core's reject reasons
+    pub reason: String,
+    /// Optional details
+    pub debug: String,
+}
+
+impl TxValidationError {
+    pub fn new(result: TxValidationResult, reason: impl Into<String>, debug: impl Into<String>) -> Self {
+        Self { result, reason: reason.into(), debug: debug.into() }
+    }
+
+    pub fn consensus(reason: impl Into<String>) -> Self {
+        Self::new(TxValidationResult::Consensus, reason, "")
+    }
+
+    pub fn policy(reason: impl Into<String>, debug: impl Into<String>) -> Self {
+        Self::new(TxValidationResult::MempoolPolicy, reason, debug)
+    }
+
+    pub fn reconsiderable(reason: impl Into<String>, debug: impl Into<String>) -> Self {
+        Self::new(TxValidationResult::Reconsiderable, reason, debug)
+    }
+}
+
+impl fmt::Display for TxValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.debug.is_empty() {
+            write!(f, "{}", self.reason)
+        } else {
+            write!(f, "{}, {}", self.reason, self.debug)
+        }
+    }
+}
+
+impl std::error::Error for TxValidationError {}
+
+/// A transaction that passed (or, with test acceptance, would pass) mempool checks
+#[derive(Debug, Clone)]
+pub struct AcceptedTx {
+    pub txid: Txid,
+    pub wtxid: Wtxid,
+    pub vsize: u64,
+    pub fee: u64,
+    /// Set when the transaction replaced mempool transactions (BIP125)
+    pub replacement: Option<ReplacementReport>,
+    /// Fee and vsize the feerate was judged on; the whole package for package-accepted transactions
+    pub effective_fee: u64,
+    pub effective_vsize: u64,
+    /// Transactions whose fees counted towards the effective feerate
+    pub effective_includes: Vec<Wtxid>,
+}
+
+/// Mempool transactions evicted by a replacement
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplacementReport {
+    /// Transactions spending the same outputs as the replacement
+    pub conflicts: Vec<Txid>,
+    /// Every evicted transaction with its modified fee: the conflicts and their descendants
+    pub replaced: Vec<(Txid, u64)>,
+    /// Total modified fees of `replaced`
+    pub replaced_fees: u64,
+}
+
+/// Outcome for one transaction of a package
+#[derive(Debug, Clone)]
+pub enum PackageTxResult {
+    Accepted(AcceptedTx),
+    /// Already in the mempool before the package was submitted
+    AlreadyInMempool(AcceptedTx),
+    Rejected(TxValidationError),
+}
+
+/// Result of package acceptance (Bitcoin Core: PackageMempoolAcceptResult)
+#[derive(Debug, Clone, Default)]
+pub struct PackageAcceptResult {
+    /// Package-wide failure; `None` when every transaction is in the mempool
+    pub error: Option<String>,
+    /// Per-transaction results; transactions never evaluated are missing
+    pub results: HashMap<Wtxid, PackageTxResult>,
+}
+
+impl PackageAcceptResult {
+    pub(crate) fn failed(error: impl Into<String>) -> Self {
+        Self { error: Some(error.into()), results: HashMap::new() }
+    }
+}
+
+/// Package-wide sanity checks (Bitcoin Core: IsConsistentPackage): no duplicates,
+/// parents before children, and no two transactions spending the same output
+pub fn check_package_consistency(txs: &[Transaction]) -> Result<(), String> {
+    let txids: Vec<Txid> = txs.iter().map(|tx| tx.compute_txid()).collect();
+    let unique: HashSet<&Txid> = txids.iter().collect();
+    if unique.len() != txids.len() {
+        return Err("package-contains-duplicates".to_string());
+    }
+
+    let mut later: HashSet<Txid> = txids.iter().copied().collect();
+    let mut spent = HashSet::new();
+    for (tx, txid) in txs.iter().zip(&txids) {
+        later.remove(txid);
+        for input in &tx.input {
+            if later.contains(&input.previous_output.txid) {
+                return Err("package-not-sorted".to_string());
+            }
+            if !spent.insert(input.previous_output) {
+                return Err("conflict-in-package".to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The last transaction spends at least one output of each of the others
+/// (Bitcoin Core: IsChildWithParents)
+pub fn is_child_with_parents(txs: &[Transaction]) -> bool {
+    let Some((child, parents)) = txs.split_last() else {
+        return false;
+    };
+    if parents.is_empty() {
+        return false;
+    }
+    let spent: HashSet<Txid> = child.input.iter().map(|i| i.previous_output.txid).collect();
+    parents.iter().all(|p| spent.contains(&p.compute_txid()))
+}
+
+/// Child-with-parents where no parent spends another parent
+/// (Bitcoin Core: IsChildWithParentsTree)
+pub fn is_child_with_parents_tree(txs: &[Transaction]) -> bool {
+    if !is_child_with_parents(txs) {
+        return false;
+    }
+    let parents = &txs[..txs.len() - 1];
+    let parent_txids: HashSet<Txid> = parents.iter().map(|tx| tx.compute_txid()).collect();
+    parents
+        .iter()
+        .all(|tx| tx.input.iter().all(|i| !parent_txids.contains(&i.previous_output.txid)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{absolute, transaction, Amount, OutPoint, ScriptBuf, TxIn, TxOut};
+
+    fn spend(prevouts: &[OutPoint]) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: prevouts.iter().map(|p| TxIn { previous_output: *p, ..Default::default() }).collect(),
+            output: vec![TxOut { value: Amount::from_sat(1000), script_pubkey: ScriptBuf::new() }],
+        }
+    }
+
+    #[test]
+    fn test_package_consistency() {
+        let parent = spend(&[OutPoint::new(Txid::all_zeros(), 0)]);
+        let child = spend(&[OutPoint::new(parent.compute_txid(), 0)]);
+        assert!(check_package_consistency(&[parent.clone(), child.clone()]).is_ok());
+        assert_eq!(check_package_consistency(&[child.clone(), parent.clone()]).unwrap_err(), "package-not-sorted");
+        assert_eq!(
+            check_package_consistency(&[parent.clone(), parent.clone()]).unwrap_err(),
+            "package-contains-duplicates"
+        );
+
+        let rival = spend(&[OutPoint::new(Txid::all_zeros(), 0), OutPoint::new(Txid::all_zeros(), 1)]);
+        assert_eq!(check_package_consistency(&[parent, rival]).unwrap_err(), "conflict-in-package");
+    }
+
+    #[test]
+    fn test_child_with_parents() {
+        let a = spend(&[OutPoint::new(Txid::all_zeros(), 0)]);
+        let b = spend(&[OutPoint::new(Txid::all_zeros(), 1)]);
+        let child = spend(&[OutPoint::new(a.compute_txid(), 0), OutPoint::new(b.compute_txid(), 0)]);
+        assert!(is_child_with_parents_tree(&[a.clone(), b.clone(), child.clone()]));
+        assert!(!is_child_with_parents(&[a.clone()]));
+        assert!(!is_child_with_parents(&[a.clone(), b.clone()]));
+
+        // A grandparent chain is child-with-parents but not a tree
+        let mid = spend(&[OutPoint::new(a.compute_txid(), 0)]);
+        let grandchild = spend(&[OutPoint::new(a.compute_txid(), 1), OutPoint::new(mid.compute_txid(), 0)]);
+        let chain = [a, mid, grandchild];
+        assert!(is_child_with_parents(&chain));
+        assert!(!is_child_with_parents_tree(&chain));
+    }
+
+    #[test]
+    fn test_error_display() {
+        let e = TxValidationError::policy("min relay fee not met", "100 < 141");
+        assert_eq!(e.to_string(), "min relay fee not met, 100 < 141");
+        assert_eq!(TxValidationError::consensus("bad-txns-in-belowout").to_string(), "bad-txns-in-belowout");
+    }
+}
@@ -0,0 +1,397 @@
+//! Block creation around the mempool's block assembler: coinbase and
+//! witness commitment, required difficulty, and the block checks that can be
+//! done before handing a block to the kernel (Bitcoin Core: node/miner.cpp,
+//! pow.cpp and the CheckBlock/ContextualCheckBlock parts of validation.cpp).
+//!
+//! The kernel only reports whether a block was accepted, so these checks are
+//! what turns the common failures into BIP22 reject reasons.
+
+use anyhow::{Context, Result};
+use bitcoin::block::{Header, Version};
+use bitcoin::blockdata::opcodes::all::OP_RETURN;
+use bitcoin::consensus::Params;
+use bitcoin::hashes::Hash;
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::{
+    absolute, merkle_tree, transaction, Amount, Block, BlockHash, CompactTarget, Network, OutPoint,
+    ScriptBuf, Sequence, Target, Transaction, TxIn, TxOut, Weight, Witness, WitnessMerkleNode, Wtxid,
+};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::kernel::Kernel;
+use crate::mempool::miner::MAX_BLOCK_SIGOPS_COST;
+use crate::mempool::Mempool;
+use crate::p2p::control::{P2PCommand, P2PHandle};
+
+/// Version of new blocks: BIP9 top bits, no deployment signalled
+/// (Bitcoin Core: VERSIONBITS_TOP_BITS)
+pub const BLOCK_VERSION: i32 = 0x2000_0000;
+
+/// How far ahead of our clock a block timestamp may be (Bitcoin Core: MAX_FUTURE_BLOCK_TIME)
+pub const MAX_FUTURE_BLOCK_TIME: u32 = 2 * 60 * 60;
+
+/// Witness reserved value the coinbase commits to (all zeros)
+pub const WITNESS_RESERVED_VALUE: [u8; 32] = [0; 32];
+
+/// Prefix of the witness commitment output: OP_RETURN, push 36, 0xaa21a9ed
+const WITNESS_COMMITMENT_HEADER: [u8; 4] = [0xaa, 0x21, 0xa9, 0xed];
+
+pub fn unix_time() -> u32 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as u32
+}
+
+/// Blocks between subsidy halvings
+pub fn halving_interval(network: Network) -> u32 {
+    match network {
+        Network::Regtest => 150,
+        _ => 210_000,
+    }
+}
+
+/// New coins a block at `height` may create (Bitcoin Core: GetBlockSubsidy)
+pub fn block_subsidy(height: u32, network: Network) -> u64 {
+    let halvings = height / halving_interval(network);
+    if halvings >= 64 {
+        return 0;
+    }
+    (50 * Amount::ONE_BTC.to_sat()) >> halvings
+}
+
+/// Header of the active-chain block at `height`
+fn header_at(kernel: &Kernel, height: i32) -> Result<Header> {
+    let hash = kernel.get_block_hash(height)?;
+    kernel
+        .get_block_header(&hash)?
+        .with_context(|| format!("block {} at height {} is not stored", hash, height))
+}
+
+/// `bits` a block on top of the active-chain block `prev` must carry if it is
+/// timestamped `block_time` (Bitcoin Core: GetNextWorkRequired)
+pub fn next_work_required(kernel: &Kernel, prev: &BlockHash, block_time: u32) -> Result<CompactTarget> {
+    let params = Params::new(kernel.network());
+    let pow_limit = params.max_attainable_target.to_compact_lossy();
+    let Some(info) = kernel.get_block_info(prev)? else {
+        return Ok(pow_limit);
+    };
+    let last = kernel.get_block_header(prev)?.context("previous block is not stored")?;
+    let interval = params.difficulty_adjustment_interval() as i32;
+
+    if (info.height + 1) % interval != 0 {
+        if params.allow_min_difficulty_blocks {
+            // A block more than 20 minutes late may use the minimum difficulty
+            if block_time as u64 > last.time as u64 + params.pow_target_spacing * 2 {
+                return Ok(pow_limit);
+            }
+            // Otherwise the last bits that were not a minimum-difficulty exception
+            let (mut height, mut header) = (info.height, last);
+            while height > 0 && height % interval != 0 && header.bits == pow_limit {
+                height -= 1;
+                header = header_at(kernel, height)?;
+            }
+            return Ok(header.bits);
+        }
+        return Ok(last.bits);
+    }
+
+    // Retarget over the 2015 blocks since the last adjustment
+    let first = header_at(kernel, info.height - (interval - 1))?;
+    let timespan = last.time.saturating_sub(first.time) as u64;
+    Ok(CompactTarget::from_next_work_required(last.bits, timespan, &params))
+}
+
+/// Commitment to the witnesses of `txs`, which come after the coinbase, as
+/// a coinbase output script (Bitcoin Core: GenerateCoinbaseCommitment)
+pub fn witness_commitment_script<'a>(txs: impl IntoIterator<Item = &'a Transaction>) -> ScriptBuf {
+    // The coinbase's wtxid counts as zero
+    let wtxids = std::iter::once(Wtxid::all_zeros())
+        .chain(txs.into_iter().map(|tx| tx.compute_wtxid()))
+        .map(|wtxid| wtxid.to_raw_hash());
+    let root: WitnessMerkleNode = merkle_tree::calculate_root(wtxids).expect("at least the coinbase").into();
+    let commitment = Block::compute_witness_commitment(&root, &WITNESS_RESERVED_VALUE);
+
+    let mut data = PushBytesBuf::from(WITNESS_COMMITMENT_HEADER);
+    data.extend_from_slice(commitment.as_byte_array()).expect("36 bytes fit a push");
+    Builder::new().push_opcode(OP_RETURN).push_slice(data).into_script()
+}
+
+/// Coinbase for a block at `height` paying `value` to `script_pubkey`, with
+/// the witness commitment as a second output when given
+pub fn create_coinbase(
+    height: u32,
+    value: u64,
+    script_pubkey: ScriptBuf,
+    witness_commitment: Option<ScriptBuf>,
+) -> Transaction {
+    // BIP34 height, padded with OP_0 so the scriptSig is at least two bytes
+    let script_sig = Builder::new()
+        .push_int(height as i64)
+        .push_opcode(bitcoin::opcodes::OP_0)
+        .into_script();
+    let mut output = vec![TxOut { value: Amount::from_sat(value), script_pubkey }];
+    let mut witness = Witness::new();
+    if let Some(commitment) = witness_commitment {
+        output.push(TxOut { value: Amount::ZERO, script_pubkey: commitment });
+        witness.push(WITNESS_RESERVED_VALUE);
+    }
+    Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::null(), script_sig, sequence: Sequence::MAX, witness }],
+        output,
+    }
+}
+
+/// Header for `txdata` on top of `prev`, with the merkle root filled in and
+/// the nonce left at zero
+pub fn block_header(prev: BlockHash, txdata: &[Transaction], time: u32, bits: CompactTarget) -> Header {
+    let merkle_root = merkle_tree::calculate_root(txdata.iter().map(|tx| tx.compute_txid().to_raw_hash()))
+        .map(Into::into)
+        .unwrap_or_else(bitcoin::TxMerkleNode::all_zeros);
+    Header {
+        version: Version::from_consensus(BLOCK_VERSION),
+        prev_blockhash: prev,
+        merkle_root,
+        time,
+        bits,
+        nonce: 0,
+    }
+}
+
+/// Add the witness reserved value to a coinbase that commits to witnesses
+/// but has none (Bitcoin Core: UpdateUncommittedBlockStructures)
+pub fn update_uncommitted_block_structures(block: &mut Block) {
+    let Some(coinbase) = block.txdata.first_mut() else {
+        return;
+    };
+    let commits = coinbase.output.iter().any(|o| {
+        let bytes = o.script_pubkey.as_bytes();
+        bytes.len() >= 38 && bytes[0] == OP_RETURN.to_u8() && bytes[1] == 0x24 && bytes[2..6] == WITNESS_COMMITMENT_HEADER
+    });
+    if commits && coinbase.input.len() == 1 && coinbase.input[0].witness.is_empty() {
+        coinbase.input[0].witness.push(WITNESS_RESERVED_VALUE);
+    }
+}
+
+/// Checks that need no chain state (Bitcoin Core: CheckBlock, plus the
+/// witness commitment from ContextualCheckBlock). `check_pow` is off for
+/// block proposals, which are not mined yet.
+pub fn check_block(block: &Block, check_pow: bool) -> Result<(), &'static str> {
+    if check_pow && block.header.validate_pow(block.header.target()).is_err() {
+        return Err("high-hash");
+    }
+    if !block.check_merkle_root() {
+        return Err("bad-txnmrklroot");
+    }
+    // A repeated transaction leaves the merkle root unchanged (CVE-2012-2459)
+    let mut txids = HashSet::with_capacity(block.txdata.len());
+    if !block.txdata.iter().all(|tx| txids.insert(tx.compute_txid())) {
+        return Err("bad-txns-duplicate");
+    }
+    if block.txdata.is_empty() || block.weight() > Weight::MAX_BLOCK {
+        return Err("bad-blk-length");
+    }
+    if !block.txdata[0].is_coinbase() {
+        return Err("bad-cb-missing");
+    }
+    if block.txdata[1..].iter().any(|tx| tx.is_coinbase()) {
+        return Err("bad-cb-multiple");
+    }
+    let legacy_sigops: usize = block.txdata.iter().map(|tx| tx.total_sigop_cost(|_| None)).sum();
+    if legacy_sigops as u64 > MAX_BLOCK_SIGOPS_COST {
+        return Err("bad-blk-sigops");
+    }
+    if !block.check_witness_commitment() {
+        return Err("bad-witness-merkle-match");
+    }
+    Ok(())
+}
+
+/// Checks against the block's parent: difficulty, timestamp and BIP34
+/// height (Bitcoin Core: ContextualCheckBlockHeader, ContextualCheckBlock).
+/// Returns the height the block would have.
+pub fn check_block_header_contextual(kernel: &Kernel, header: &Header, now: u32) -> Result<u32, &'static str> {
+    let prev = match kernel.get_block_info(&header.prev_blockhash) {
+        Ok(Some(prev)) => prev,
+        _ => return Err("prev-blk-not-found"),
+    };
+    match next_work_required(kernel, &header.prev_blockhash, header.time) {
+        Ok(bits) if bits == header.bits => {}
+        Ok(_) => return Err("bad-diffbits"),
+        Err(_) => return Err("prev-blk-not-found"),
+    }
+    if let Ok(Some(mtp)) = kernel.median_time_past(&header.prev_blockhash) {
+        if header.time <= mtp {
+            return Err("time-too-old");
+        }
+    }
+    if header.time > now.saturating_add(MAX_FUTURE_BLOCK_TIME) {
+        return Err("time-too-new");
+    }
+    if header.version.to_consensus() < 4 {
+        return Err("bad-version");
+    }
+    Ok((prev.height + 1) as u32)
+}
+
+/// Full pre-kernel check of a block: context-free checks, then the parent
+/// and the coinbase height
+pub fn check_block_contextual(kernel: &Kernel, block: &Block, check_pow: bool, now: u32) -> Result<u32, &'static str> {
+    check_block(block, check_pow)?;
+    let height = check_block_header_contextual(kernel, &block.header, now)?;
+    // Compared as a script prefix like Core, so heights 1-16 may be OP_1-OP_16
+    let expected = Builder::new().push_int(height as i64).into_script();
+    let script_sig = block.coinbase().map(|cb| cb.input[0].script_sig.as_bytes()).unwrap_or_default();
+    if !script_sig.starts_with(expected.as_bytes()) {
+        return Err("bad-cb-height");
+    }
+    Ok(height)
+}
+
+/// Hand a block to the kernel. Once it is the new tip its transactions
+/// leave the mempool and it is announced to peers, and None is returned;
+/// otherwise the BIP22 reason it was not accepted.
+pub fn submit_block(
+    kernel: &Kernel,
+    mempool: &Mempool,
+    p2p: Option<&P2PHandle>,
+    block: Block,
+) -> Result<Option<&'static str>> {
+    let hash = block.block_hash();
+    if let Some(info) = kernel.get_block_info(&hash)? {
+        return Ok(Some(if info.in_active_chain { "duplicate" } else { "duplicate-inconclusive" }));
+    }
+
+    let height = match check_block_contextual(kernel, &block, true, unix_time()) {
+        Ok(height) => height,
+        Err(reason) => {
+            eprintln!("[mining] block {} rejected: {}", hash, reason);
+            return Ok(Some(reason));
+        }
+    };
+    if let Err(e) = kernel.process_block(&bitcoin::consensus::serialize(&block)) {
+        eprintln!("[mining] block {} rejected by the kernel: {:#}", hash, e);
+        return Ok(Some("rejected"));
+    }
+    if kernel.get_best_block_hash().ok() != Some(hash) {
+        return Ok(Some("inconclusive"));
+    }
+
+    eprintln!("[mining] new block {} at height {}", hash, height);
+    mempool.remove_for_block(&block, height);
+    if let Some(p2p) = p2p {
+        p2p.set_block_height(height as i32);
+        p2p.send(P2PCommand::RelayBlock(Arc::new(block)));
+    }
+    Ok(None)
+}
+
+/// Increment the nonce (and the timestamp when the nonces run out) until
+/// the header meets its own target, spending at most `max_tries` hashes
+/// from the caller's budget. Only practical at regtest difficulty.
+pub fn grind_header(header: &mut Header, max_tries: &mut u64) -> bool {
+    let target = Target::from_compact(header.bits);
+    while !target.is_met_by(header.block_hash()) {
+        if *max_tries == 0 {
+            return false;
+        }
+        *max_tries -= 1;
+        if header.nonce == u32::MAX {
+            header.time += 1;
+        }
+        header.nonce = header.nonce.wrapping_add(1);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::WPubkeyHash;
+
+    fn payout() -> ScriptBuf {
+        ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros())
+    }
+
+    fn spend_with_witness(prevout: OutPoint) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: prevout,
+                witness: Witness::from_slice(&[vec![1u8; 72], vec![2u8; 33]]),
+                ..Default::default()
+            }],
+            output: vec![TxOut { value: Amount::from_sat(10_000), script_pubkey: payout() }],
+        }
+    }
+
+    fn regtest_block(txs: Vec<Transaction>) -> Block {
+        let commitment = witness_commitment_script(&txs);
+        let coinbase = create_coinbase(1, block_subsidy(1, Network::Regtest), payout(), Some(commitment));
+        let txdata: Vec<Transaction> = std::iter::once(coinbase).chain(txs).collect();
+        let genesis = bitcoin::constants::genesis_block(Network::Regtest);
+        let header = block_header(genesis.block_hash(), &txdata, genesis.header.time + 1, genesis.header.bits);
+        let mut block = Block { header, txdata };
+        let mut tries = u64::MAX;
+        assert!(grind_header(&mut block.header, &mut tries));
+        block
+    }
+
+    #[test]
+    fn test_subsidy_halvings() {
+        assert_eq!(block_subsidy(0, Network::Bitcoin), 5_000_000_000);
+        assert_eq!(block_subsidy(210_000, Network::Bitcoin), 2_500_000_000);
+        assert_eq!(block_subsidy(840_000, Network::Bitcoin), 312_500_000);
+        assert_eq!(block_subsidy(150, Network::Regtest), 2_500_000_000);
+        assert_eq!(block_subsidy(64 * 150, Network::Regtest), 0);
+    }
+
+    #[test]
+    fn test_built_block_passes_checks() {
+        let tx = spend_with_witness(OutPoint::new(bitcoin::Txid::from_byte_array([7; 32]), 0));
+        let block = regtest_block(vec![tx]);
+        assert_eq!(check_block(&block, true), Ok(()));
+        assert_eq!(block.txdata[0].input[0].script_sig.as_bytes(), &[0x51, 0x00]);
+        assert!(block.check_witness_commitment());
+
+        // The commitment output is what getblocktemplate reports
+        let commitment = witness_commitment_script(&block.txdata[1..]);
+        assert_eq!(block.txdata[0].output[1].script_pubkey, commitment);
+        assert_eq!(commitment.len(), 38);
+    }
+
+    #[test]
+    fn test_reject_reasons() {
+        let tx = spend_with_witness(OutPoint::new(bitcoin::Txid::from_byte_array([7; 32]), 0));
+        let block = regtest_block(vec![tx.clone()]);
+
+        let mut bad = block.clone();
+        bad.txdata[1].output[0].value = Amount::from_sat(9_000);
+        assert_eq!(check_block(&bad, false), Err("bad-txnmrklroot"));
+
+        let mut bad = block.clone();
+        bad.txdata.push(tx);
+        bad.header.merkle_root = bad.compute_merkle_root().unwrap();
+        assert_eq!(check_block(&bad, false), Err("bad-txns-duplicate"));
+
+        let mut bad = block.clone();
+        bad.txdata.swap(0, 1);
+        bad.header.merkle_root = bad.compute_merkle_root().unwrap();
+        assert_eq!(check_block(&bad, false), Err("bad-cb-missing"));
+
+        // Dropping the witness reserved value breaks the commitment, and
+        // update_uncommitted_block_structures puts it back
+        let mut bad = block.clone();
+        bad.txdata[0].input[0].witness = Witness::new();
+        assert_eq!(check_block(&bad, false), Err("bad-witness-merkle-match"));
+        update_uncommitted_block_structures(&mut bad);
+        assert_eq!(check_block(&bad, false), Ok(()));
+
+        let mut bad = block;
+        bad.header.bits = CompactTarget::from_consensus(0x1d00ffff);
+        assert_eq!(check_block(&bad, true), Err("high-hash"));
+        assert_eq!(check_block(&bad, false), Ok(()));
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default ban duration (24 hours)
+pub const DEFAULT_BAN_TIME: i64 = 24 * 60 * 60;
+
+/// IP network (single address or CIDR range)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Subnet {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Subnet {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = mask_u32(self.prefix);
+                u32::from(net) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = mask_u128(self.prefix);
+                u128::from(net) & mask == u128::from(*ip) & mask
+            }
+            (IpAddr::V4(_), IpAddr::V6(ip)) => match ip.to_ipv4_mapped() {
+                Some(v4) => self.contains(&IpAddr::V4(v4)),
+                None => false,
+            },
+            (IpAddr::V6(_), IpAddr::V4(_)) => false,
+        }
+    }
+}
+
+fn mask_u32(prefix: u8) -> u32 {
+    if prefix == 0 { 0 } else { u32::MAX << (32 - prefix as u32) }
+}
+
+fn mask_u128(prefix: u8) -> u128 {
+    if prefix == 0 { 0 } else { u128::MAX << (128 - prefix as u32) }
+}
+
+impl FromStr for Subnet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ip_str, prefix_str) = match s.split_once('/') {
+            Some((ip, prefix)) => (ip, Some(prefix)),
+            None => (s, None),
+        };
+        let ip: IpAddr = ip_str.parse().map_err(|_| format!("invalid IP: {}", ip_str))?;
+        let max = if ip.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix_str {
+            None => max,
+            Some(p) => match p.parse::<u8>() {
+                Ok(p) if p <= max => p,
+                _ => {
+                    // Netmask notation, e.g. 1.2.3.0/255.255.255.0
+                    let mask: IpAddr = p.parse().map_err(|_| format!("invalid prefix: {}", p))?;
+                    let (ones, bits) = match mask {
+                        IpAddr::V4(m) => (u32::from(m).leading_ones(), u32::from(m).count_ones()),
+                        IpAddr::V6(m) => (u128::from(m).leading_ones(), u128::from(m).count_ones()),
+                    };
+                    if ones != bits || mask.is_ipv4() != ip.is_ipv4() {
+                        return Err(format!("invalid netmask: {}", p));
+                    }
+                    ones as u8
+                }
+            },
+        };
+
+        // Normalize the network address
+        let network = match ip {
+            IpAddr::V4(v4) => IpAddr::V4((u32::from(v4) & mask_u32(prefix)).into()),
+            IpAddr::V6(v6) => IpAddr::V6((u128::from(v6) & mask_u128(prefix)).into()),
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// A manual ban
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BanEntry {
+    pub create_time: i64,
+    pub ban_until: i64,
+}
+
+/// Manually banned subnets (Bitcoin Core: BanMan)
+#[derive(Debug, Default)]
+pub struct BanMan {
+    banned: BTreeMap<Subnet, BanEntry>,
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64
+}
+
+impl BanMan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ban `subnet` for `bantime` seconds, or until the `bantime` timestamp if `absolute`.
+    /// Returns false if it was already banned.
+    pub fn ban(&mut self, subnet: Subnet, bantime: i64, absolute: bool) -> bool {
+        self.sweep();
+        if self.banned.contains_key(&subnet) {
+            return false;
+        }
+        let now = now_secs();
+        let bantime = if bantime <= 0 { DEFAULT_BAN_TIME } else { bantime };
+        let ban_until = if absolute { bantime } else { now + bantime };
+        self.banned.insert(subnet, BanEntry { create_time: now, ban_until });
+        true
+    }
+
+    pub fn unban(&mut self, subnet: &Subnet) -> bool {
+        self.banned.remove(subnet).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.banned.clear();
+    }
+
+    pub fn is_banned(&self, ip: &IpAddr) -> bool {
+        let now = now_secs();
+        self.banned
+            .iter()
+            .any(|(subnet, entry)| entry.ban_until > now && subnet.contains(ip))
+    }
+
+    /// Active bans
+    pub fn list(&mut self) -> Vec<(Subnet, BanEntry)> {
+        self.sweep();
+        self.banned.iter().map(|(s, e)| (*s, e.clone())).collect()
+    }
+
+    /// Drop expired bans
+    fn sweep(&mut self) {
+        let now = now_secs();
+        self.banned.retain(|_, entry| entry.ban_until > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subnet_parsing() {
+        let s: Subnet = "1.2.3.4".parse().unwrap();
+        assert_eq!(s.to_string(), "1.2.3.4/32");
+        let s: Subnet = "1.2.3.99/24".parse().unwrap();
+        assert_eq!(s.to_string(), "1.2.3.0/24");
+        let s: Subnet = "10.0.0.0/255.255.0.0".parse().unwrap();
+        assert_eq!(s.to_string(), "10.0.0.0/16");
+        assert!("1.2.3.4/33".parse::<Subnet>().is_err());
+        assert!("not-an-ip".parse::<Subnet>().is_err());
+    }
+
+    #[test]
+    fn test_subnet_contains() {
+        let s: Subnet = "192.168.1.0/24".parse().unwrap();
+        assert!(s.contains(&"192.168.1.77".parse().unwrap()));
+        assert!(!s.contains(&"192.168.2.1".parse().unwrap()));
+        assert!(s.contains(&"::ffff:192.168.1.5".parse().unwrap()));
+        let s: Subnet = "2001:db8::/32".parse().unwrap();
+        assert!(s.contains(&"2001:db8::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_ban_and_unban() {
+        let mut banman = BanMan::new();
+        let s: Subnet = "1.2.3.0/24".parse().unwrap();
+        assert!(banman.ban(s, 3600, false));
+        assert!(!banman.ban(s, 3600, false));
+        assert!(banman.is_banned(&"1.2.3.4".parse().unwrap()));
+        assert_eq!(banman.list().len(), 1);
+        assert!(banman.unban(&s));
+        assert!(!banman.is_banned(&"1.2.3.4".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_expired_ban() {
+        let mut banman = BanMan::new();
+        let s: Subnet = "1.2.3.4".parse().unwrap();
+        banman.ban(s, now_secs() - 10, true);
+        assert!(!banman.is_banned(&"1.2.3.4".parse().unwrap()));
+        assert!(banman.list().is_empty());
+    }
+}
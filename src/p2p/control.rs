@@ -0,0 +1,301 @@
+//! Control handle into the running `PeerManager`.
+//!
+//! The `PeerManager` event loop owns the peer connections, so the RPC layer
+//! never touches them directly. Instead the manager publishes per-peer
+//! statistics into shared state and accepts commands (connect, disconnect,
+//! ping, ...) over a channel that it drains on every loop iteration.
+
+use bitcoin::p2p::ServiceFlags;
+use parking_lot::{Mutex, RwLock};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+use super::banman::BanMan;
+use super::legacy::Peer;
+
+/// How a connection was established (Bitcoin Core: ConnectionType)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionType {
+    /// Automatic outbound connection (DNS seeds, addr relay)
+    OutboundFullRelay,
+    /// Requested by the user (-peer, addnode)
+    Manual,
+}
+
+impl ConnectionType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionType::OutboundFullRelay => "outbound-full-relay",
+            ConnectionType::Manual => "manual",
+        }
+    }
+}
+
+/// Per-peer statistics, updated by the connection and read by `getpeerinfo`
+#[derive(Debug, Clone)]
+pub struct PeerStats {
+    pub id: u64,
+    pub addr: SocketAddr,
+    pub conn_type: ConnectionType,
+    pub conntime: i64,
+    pub services: u64,
+    pub version: u32,
+    pub subver: String,
+    pub relay_txes: bool,
+    /// Peer sent wtxidrelay (BIP 339): transactions are announced to it by wtxid
+    pub wtxid_relay: bool,
+    pub time_offset: i64,
+    pub starting_height: i32,
+    /// Best block height we know the peer has (start height or announced headers)
+    pub best_height: i32,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    pub last_send: i64,
+    pub last_recv: i64,
+    pub ping_time: Option<Duration>,
+    pub min_ping: Option<Duration>,
+    /// Outstanding ping: (nonce, sent at)
+    pub ping_pending: Option<(u64, Instant)>,
+    /// Feerate below which the peer asked not to be sent transactions, in sat/kvB
+    pub min_fee_filter: u64,
+    /// Last feefilter we sent: (sat/kvB, sent at)
+    pub fee_filter_sent: Option<(u64, Instant)>,
+}
+
+impl PeerStats {
+    pub fn new(id: u64, addr: SocketAddr, conn_type: ConnectionType) -> Self {
+        Self {
+            id,
+            addr,
+            conn_type,
+            conntime: unix_now(),
+            services: 0,
+            version: 0,
+            subver: String::new(),
+            relay_txes: false,
+            wtxid_relay: false,
+            time_offset: 0,
+            starting_height: -1,
+            best_height: -1,
+            bytes_sent: 0,
+            bytes_recv: 0,
+            last_send: 0,
+            last_recv: 0,
+            ping_time: None,
+            min_ping: None,
+            ping_pending: None,
+            min_fee_filter: 0,
+            fee_filter_sent: None,
+        }
+    }
+
+    /// Record a pong; returns true if it answered our outstanding ping
+    pub fn pong_received(&mut self, nonce: u64) -> bool {
+        match self.ping_pending {
+            Some((expected, sent)) if expected == nonce => {
+                let rtt = sent.elapsed();
+                self.ping_time = Some(rtt);
+                self.min_ping = Some(self.min_ping.map_or(rtt, |m| m.min(rtt)));
+                self.ping_pending = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Cumulative traffic counters across all connections
+#[derive(Debug, Default)]
+pub struct NetTotals {
+    pub bytes_sent: AtomicU64,
+    pub bytes_recv: AtomicU64,
+}
+
+/// Requests from the RPC layer, processed inside `PeerManager::event_loop`
+#[derive(Debug)]
+pub enum P2PCommand {
+    Connect { addr: SocketAddr, conn_type: ConnectionType },
+    /// A peer dialed and handshaken off the event loop, ready to be taken in
+    Connected { addr: SocketAddr, peer: Peer },
+    Disconnect(u64),
+    DisconnectBanned,
+    PingAll,
+    SetNetworkActive(bool),
+    /// Announce a transaction accepted through RPC to all tx-relaying peers
+    RelayTransaction(Arc<bitcoin::Transaction>),
+    /// Announce a block submitted through RPC to all peers
+    #[cfg(feature = "mining")]
+    RelayBlock(Arc<bitcoin::Block>),
+}
+
+/// State shared between the `PeerManager` and its handles
+#[derive(Debug)]
+pub struct P2PShared {
+    pub(crate) peers: RwLock<BTreeMap<u64, Arc<Mutex<PeerStats>>>>,
+    pub(crate) totals: Arc<NetTotals>,
+    pub(crate) banman: Mutex<BanMan>,
+    pub(crate) added_nodes: Mutex<Vec<String>>,
+    pub(crate) network_active: AtomicBool,
+    pub(crate) local_services: AtomicU64,
+    pub(crate) header_height: AtomicI32,
+    pub(crate) block_height: AtomicI32,
+}
+
+impl P2PShared {
+    pub fn new(start_height: i32, header_height: i32) -> Self {
+        Self {
+            peers: RwLock::new(BTreeMap::new()),
+            totals: Arc::new(NetTotals::default()),
+            banman: Mutex::new(BanMan::new()),
+            added_nodes: Mutex::new(Vec::new()),
+            network_active: AtomicBool::new(true),
+            local_services: AtomicU64::new(ServiceFlags::WITNESS.to_u64()),
+            header_height: AtomicI32::new(header_height),
+            block_height: AtomicI32::new(start_height),
+        }
+    }
+}
+
+/// Cloneable handle used by the RPC server
+#[derive(Clone)]
+pub struct P2PHandle {
+    shared: Arc<P2PShared>,
+    cmd_tx: mpsc::UnboundedSender<P2PCommand>,
+    user_agent: String,
+    default_port: u16,
+}
+
+impl P2PHandle {
+    pub(crate) fn new(
+        shared: Arc<P2PShared>,
+        cmd_tx: mpsc::UnboundedSender<P2PCommand>,
+        user_agent: String,
+        default_port: u16,
+    ) -> Self {
+        Self { shared, cmd_tx, user_agent, default_port }
+    }
+
+    /// Queue a command for the event loop; false if P2P has stopped
+    pub fn send(&self, cmd: P2PCommand) -> bool {
+        self.cmd_tx.send(cmd).is_ok()
+    }
+
+    /// Snapshot of all connected peers, ordered by id
+    pub fn peers(&self) -> Vec<PeerStats> {
+        self.shared.peers.read().values().map(|s| s.lock().clone()).collect()
+    }
+
+    pub fn peer_count(&self) -> usize {
+        self.shared.peers.read().len()
+    }
+
+    pub fn find_peer(&self, addr: &SocketAddr) -> Option<PeerStats> {
+        self.shared
+            .peers
+            .read()
+            .values()
+            .map(|s| s.lock().clone())
+            .find(|s| &s.addr == addr)
+    }
+
+    pub fn peer_by_id(&self, id: u64) -> Option<PeerStats> {
+        self.shared.peers.read().get(&id).map(|s| s.lock().clone())
+    }
+
+    /// (bytes received, bytes sent)
+    pub fn net_totals(&self) -> (u64, u64) {
+        (
+            self.shared.totals.bytes_recv.load(Ordering::Relaxed),
+            self.shared.totals.bytes_sent.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn banman(&self) -> parking_lot::MutexGuard<'_, BanMan> {
+        self.shared.banman.lock()
+    }
+
+    pub fn added_nodes(&self) -> parking_lot::MutexGuard<'_, Vec<String>> {
+        self.shared.added_nodes.lock()
+    }
+
+    pub fn network_active(&self) -> bool {
+        self.shared.network_active.load(Ordering::SeqCst)
+    }
+
+    pub fn set_network_active(&self, active: bool) {
+        self.shared.network_active.store(active, Ordering::SeqCst);
+        self.send(P2PCommand::SetNetworkActive(active));
+    }
+
+    pub fn local_services(&self) -> u64 {
+        self.shared.local_services.load(Ordering::Relaxed)
+    }
+
+    pub fn header_height(&self) -> i32 {
+        self.shared.header_height.load(Ordering::Relaxed)
+    }
+
+    pub fn block_height(&self) -> i32 {
+        self.shared.block_height.load(Ordering::Relaxed)
+    }
+
+    /// Called after a block has been connected by the kernel
+    pub fn set_block_height(&self, height: i32) {
+        self.shared.block_height.store(height, Ordering::Relaxed);
+    }
+
+    pub fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
+
+    pub fn default_port(&self) -> u16 {
+        self.default_port
+    }
+}
+
+/// Human-readable names of service bits (Bitcoin Core: GetServicesNames)
+pub fn service_names(services: u64) -> Vec<String> {
+    const NAMES: &[(u64, &str)] = &[
+        (1 << 0, "NETWORK"),
+        (1 << 2, "BLOOM"),
+        (1 << 3, "WITNESS"),
+        (1 << 6, "COMPACT_FILTERS"),
+        (1 << 10, "NETWORK_LIMITED"),
+        (1 << 11, "P2P_V2"),
+    ];
+    NAMES
+        .iter()
+        .filter(|(bit, _)| services & bit != 0)
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+pub(crate) fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_names() {
+        assert_eq!(service_names(0x409), vec!["NETWORK", "WITNESS", "NETWORK_LIMITED"]);
+        assert!(service_names(0).is_empty());
+    }
+
+    #[test]
+    fn test_pong_tracking() {
+        let mut stats = PeerStats::new(0, "127.0.0.1:8333".parse().unwrap(), ConnectionType::Manual);
+        stats.ping_pending = Some((42, Instant::now()));
+        assert!(!stats.pong_received(7));
+        assert!(stats.pong_received(42));
+        assert!(stats.ping_time.is_some());
+        assert_eq!(stats.ping_time, stats.min_ping);
+        assert!(stats.ping_pending.is_none());
+    }
+}
@@ -11,7 +11,8 @@ use bitcoin::p2p::{
     message_compact_blocks as msg_cmpct,
     message_network as msg_net,
 };
-use bitcoin::{BlockHash, Network};
+use bitcoin::{BlockHash, Network, Txid, Wtxid};
+use parking_lot::Mutex;
 use rand::Rng;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::{File, OpenOptions};
@@ -19,7 +20,8 @@ use std::io::{BufReader, BufWriter, Read as StdRead, Write as StdWrite};
 use std::net::{Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{lookup_host, TcpStream};
 use tokio::time::{sleep, timeout};
@@ -27,7 +29,9 @@ use tokio::task::spawn_blocking;
 use tokio::sync::mpsc;
 
 use crate::chainparams::ChainParams;
-use crate::seeds;
+
+use super::orphanage::{TxOrphanage, TxOutcome};
+use super::control::{unix_now, ConnectionType, NetTotals, P2PCommand, P2PHandle, P2PShared, PeerStats};
 
 /// 광고할 프로토콜 번호(현대 피어 경로를 열기 위해 70016 사용)
 const ADVERTISED_PROTO: u32 = 70016;
@@ -49,38 +53,99 @@ const PER_PEER_INFLIGHT: usize = 4; // Max blocks per peer to reduce out-of-orde
 
 const MAX_OUTBOUND_FROM_ADDR: usize = 8;
 
+// Keepalive / latency measurement (Bitcoin Core: PING_INTERVAL)
+const PING_INTERVAL: Duration = Duration::from_secs(2 * 60);
+// Retry interval for addnode'd peers that are not connected
+const ADDED_NODE_RETRY: Duration = Duration::from_secs(60);
+// How long announced transactions stay available for getdata
+const RELAY_TX_CACHE_TIME: Duration = Duration::from_secs(15 * 60);
+// Locally submitted blocks kept for peers that fetch them after our announcement
+const MAX_RELAY_BLOCKS: usize = 8;
+// Resend our feefilter at this interval, or sooner after a large change
+// (Bitcoin Core: AVG_FEEFILTER_BROADCAST_INTERVAL, MAX_FEEFILTER_CHANGE_DELAY)
+const FEEFILTER_BROADCAST_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const MAX_FEEFILTER_CHANGE_DELAY: Duration = Duration::from_secs(5 * 60);
+// First protocol version that understands feefilter (BIP 133)
+const FEEFILTER_VERSION: u32 = 70013;
+
+/// Default P2P port for a network
+pub fn default_port(net: Network) -> u16 {
+    match net {
+        Network::Bitcoin  => 8333,
+        Network::Testnet  => 18333,
+        Network::Testnet4 => 48333,
+        Network::Signet   => 38333,
+        Network::Regtest  => 18444,
+    }
+}
+
+/// What we announce in the version message of a new connection
+#[derive(Debug, Clone)]
+pub struct HandshakeParams {
+    pub magic: p2p::Magic,
+    pub totals: Arc<NetTotals>,
+    pub user_agent: String,
+    pub start_height: i32,
+    pub services: p2p::ServiceFlags,
+}
+
 /// 단순 피어 연결
+#[derive(Debug)]
 pub struct Peer {
-    net: Network,
     magic: p2p::Magic,
     stream: TcpStream,
     pub their_services: p2p::ServiceFlags,
     pub their_start_height: i32,  // 피어의 블록 높이
+    /// Services we announced in our version message
+    pub our_services: p2p::ServiceFlags,
     negotiated: bool,
     sendheaders_sent: bool,
     wtxidrelay_sent: bool,
     verack_seen: bool,
+    stats: Arc<Mutex<PeerStats>>,
+    totals: Arc<NetTotals>,
 }
 
 impl Peer {
-    pub async fn connect(addr: SocketAddr, net: Network) -> Result<Self> {
+    /// Open the TCP connection to `addr`, without the version handshake
+    pub async fn dial(addr: SocketAddr) -> Result<TcpStream> {
         eprintln!("[p2p] connecting to {addr}");
-        let stream = match tokio::time::timeout(Duration::from_secs(5), TcpStream::connect(addr)).await {
-            Ok(Ok(s)) => s,
-            Ok(Err(e)) => return Err(anyhow!("connect failed: {e}")),
-            Err(_) => return Err(anyhow!("connect timeout after 5s")),
-        };
-        Ok(Self {
-            net,
-            magic: net.magic(),
+        match tokio::time::timeout(Duration::from_secs(5), TcpStream::connect(addr)).await {
+            Ok(Ok(s)) => Ok(s),
+            Ok(Err(e)) => Err(anyhow!("connect failed: {e}")),
+            Err(_) => Err(anyhow!("connect timeout after 5s")),
+        }
+    }
+
+    /// Dial `addr` and complete the version handshake. Runs off the event
+    /// loop; the peer id is assigned once the `PeerManager` takes the peer in.
+    pub async fn open(addr: SocketAddr, conn_type: ConnectionType, params: HandshakeParams) -> Result<Self> {
+        let stream = Self::dial(addr).await?;
+        let stats = Arc::new(Mutex::new(PeerStats::new(0, addr, conn_type)));
+        let mut peer = Self::new(stream, params.magic, stats, params.totals);
+        peer.handshake(&params.user_agent, params.start_height, params.services).await?;
+        Ok(peer)
+    }
+
+    pub fn new(
+        stream: TcpStream,
+        magic: p2p::Magic,
+        stats: Arc<Mutex<PeerStats>>,
+        totals: Arc<NetTotals>,
+    ) -> Self {
+        Self {
+            magic,
             stream,
             their_services: p2p::ServiceFlags::NONE,
             their_start_height: 0,
+            our_services: p2p::ServiceFlags::NONE,
             negotiated: false,
             sendheaders_sent: false,
             wtxidrelay_sent: false,
             verack_seen: false,
-        })
+            stats,
+            totals,
+        }
     }
 
     pub async fn send(&mut self, msg: message::NetworkMessage) -> Result<()> {
@@ -88,6 +153,7 @@ impl Peer {
         let bytes = encode::serialize(&raw);
         self.stream.write_all(&bytes).await?;
         self.stream.flush().await?;  // CRITICAL: Ensure data is sent to peer!
+        self.record_traffic(bytes.len(), 0);
         Ok(())
     }
 
@@ -99,12 +165,33 @@ impl Peer {
         let mut payload = vec![0u8; len];
         self.stream.read_exact(&mut payload).await?;
 
+        self.record_traffic(0, header.len() + len);
+
         let raw: message::RawNetworkMessage =
             bitcoin::consensus::deserialize(&[&header[..], &payload[..]].concat())?;
 
         Ok(raw.into_payload())
     }
 
+    fn record_traffic(&self, sent: usize, recv: usize) {
+        let now = unix_now();
+        let mut stats = self.stats.lock();
+        if sent > 0 {
+            stats.bytes_sent += sent as u64;
+            stats.last_send = now;
+            self.totals.bytes_sent.fetch_add(sent as u64, Ordering::Relaxed);
+        }
+        if recv > 0 {
+            stats.bytes_recv += recv as u64;
+            stats.last_recv = now;
+            self.totals.bytes_recv.fetch_add(recv as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> &Arc<Mutex<PeerStats>> {
+        &self.stats
+    }
+
     pub async fn handshake(&mut self, user_agent: &str, start_height: i32, our_services: p2p::ServiceFlags) -> Result<()> {
         let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
         let mut vm = msg_net::VersionMessage::new(
@@ -117,6 +204,7 @@ impl Peer {
             start_height,
         );
         vm.version = ADVERTISED_PROTO;
+        self.our_services = our_services;
 
         self.send(message::NetworkMessage::Version(vm)).await?;
         eprintln!("[p2p] sent Version (ua={user_agent}, proto={}, services={:?})", ADVERTISED_PROTO, our_services);
@@ -134,6 +222,16 @@ impl Peer {
                     );
                     self.their_services = peer_vm.services;
                     self.their_start_height = peer_vm.start_height;  // 피어 높이 저장
+                    {
+                        let mut stats = self.stats.lock();
+                        stats.services = peer_vm.services.to_u64();
+                        stats.version = peer_vm.version;
+                        stats.subver = peer_vm.user_agent.clone();
+                        stats.relay_txes = peer_vm.relay;
+                        stats.time_offset = peer_vm.timestamp - now;
+                        stats.starting_height = peer_vm.start_height;
+                        stats.best_height = peer_vm.start_height;
+                    }
 
                     // CRITICAL: BIP 339 - WtxidRelay MUST be sent BEFORE Verack!
                     // Protocol version >= 70016 requires this order
@@ -148,6 +246,10 @@ impl Peer {
                     eprintln!("[p2p] sent Verack");
                     got_version = true;
                 }
+                // Only valid between version and verack (BIP 339)
+                message::NetworkMessage::WtxidRelay if got_version && !self.verack_seen => {
+                    self.stats.lock().wtxid_relay = true;
+                }
                 message::NetworkMessage::Verack => {
                     eprintln!("[p2p] recv Verack");
                     self.verack_seen = true;
@@ -291,10 +393,32 @@ pub struct PeerManager {
     chain_params: ChainParams,                  // Checkpoints, AssumeValid, MinimumChainWork
 
     on_block: Option<Arc<dyn Fn(&[u8]) -> anyhow::Result<()> + Send + Sync>>,
-    on_tx: Option<Arc<dyn Fn(&bitcoin::Transaction) -> anyhow::Result<()> + Send + Sync>>,
+    on_tx: Option<Arc<dyn Fn(&bitcoin::Transaction) -> TxOutcome + Send + Sync>>,
+    // Current mempool minimum fee in sat/kvB, announced with feefilter
+    min_fee: Option<Arc<dyn Fn() -> u64 + Send + Sync>>,
+    // Mempool feerate of a transaction in sat/kvB, checked against each peer's feefilter
+    tx_fee_rate: Option<Arc<dyn Fn(&Txid) -> Option<u64> + Send + Sync>>,
+    // Called when a peer fetches a transaction we announced
+    on_tx_served: Option<Arc<dyn Fn(&Txid) + Send + Sync>>,
 
     // Sequential block processing channel
     block_tx: Option<mpsc::UnboundedSender<(BlockHash, Vec<u8>)>>,
+
+    // RPC control (getpeerinfo, addnode, setban, ...)
+    shared: Arc<P2PShared>,
+    cmd_tx: mpsc::UnboundedSender<P2PCommand>,
+    cmd_rx: mpsc::UnboundedReceiver<P2PCommand>,
+    next_peer_id: u64,
+
+    // Transactions we announced, served on getdata (Bitcoin Core: mapRelay)
+    relay_txs: HashMap<Txid, (Arc<bitcoin::Transaction>, Instant)>,
+    relay_wtxids: HashMap<Wtxid, Txid>,
+    relay_blocks: VecDeque<Arc<bitcoin::Block>>,
+
+    // Transactions waiting for their parents, and mempool verdicts from the tx callback
+    orphanage: TxOrphanage,
+    tx_results_tx: mpsc::UnboundedSender<(SocketAddr, Arc<bitcoin::Transaction>, TxOutcome)>,
+    tx_results_rx: mpsc::UnboundedReceiver<(SocketAddr, Arc<bitcoin::Transaction>, TxOutcome)>,
 }
 
 impl PeerManager {
@@ -461,6 +585,10 @@ impl PeerManager {
             }
         }
 
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        let (tx_results_tx, tx_results_rx) = mpsc::unbounded_channel();
+        let shared = Arc::new(P2PShared::new(start_height, header_chain_height));
+
         Self {
             net,
             user_agent: user_agent.into(),
@@ -481,10 +609,34 @@ impl PeerManager {
             chain_params,
             on_block: None,
             on_tx: None,
+            min_fee: None,
+            tx_fee_rate: None,
+            on_tx_served: None,
             block_tx: None,
+            shared,
+            cmd_tx,
+            cmd_rx,
+            next_peer_id: 0,
+            relay_txs: HashMap::new(),
+            relay_wtxids: HashMap::new(),
+            relay_blocks: VecDeque::new(),
+            orphanage: TxOrphanage::default(),
+            tx_results_tx,
+            tx_results_rx,
         }
     }
 
+    /// Use `chain_params` for magic, seeds and checkpoints, e.g. signet with `-signetseednode`
+    pub fn with_chain_params(mut self, chain_params: ChainParams) -> Self {
+        self.chain_params = chain_params;
+        self
+    }
+
+    /// Control handle for the RPC layer
+    pub fn handle(&self) -> P2PHandle {
+        P2PHandle::new(self.shared.clone(), self.cmd_tx.clone(), self.user_agent.clone(), default_port(self.net))
+    }
+
     pub fn with_block_processor<F>(mut self, f: F) -> Self
     where
         F: Fn(&[u8]) -> anyhow::Result<()> + Send + Sync + 'static,
@@ -523,24 +675,73 @@ impl PeerManager {
         self
     }
 
+    /// Mempool acceptance for relayed transactions; `MissingInputs` makes the
+    /// transaction an orphan and fetches its parents from the sending peer
     pub fn with_tx_processor<F>(mut self, f: F) -> Self
     where
-        F: Fn(&bitcoin::Transaction) -> anyhow::Result<()> + Send + Sync + 'static,
+        F: Fn(&bitcoin::Transaction) -> TxOutcome + Send + Sync + 'static,
     {
         self.on_tx = Some(Arc::new(f));
         self
     }
+
+    /// Source of the minimum feerate announced to peers (BIP 133 feefilter)
+    pub fn with_fee_filter<F>(mut self, f: F) -> Self
+    where
+        F: Fn() -> u64 + Send + Sync + 'static,
+    {
+        self.min_fee = Some(Arc::new(f));
+        self
+    }
+
+    /// Feerate of a mempool transaction, so announcements skip peers whose
+    /// feefilter it does not meet
+    pub fn with_tx_fee_rate<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Txid) -> Option<u64> + Send + Sync + 'static,
+    {
+        self.tx_fee_rate = Some(Arc::new(f));
+        self
+    }
+
+    /// Notified when a peer requests one of our announced transactions, which
+    /// ends its initial broadcast
+    pub fn with_tx_served<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Txid) + Send + Sync + 'static,
+    {
+        self.on_tx_served = Some(Arc::new(f));
+        self
+    }
     pub fn peers_len(&self) -> usize { self.peers.len() }
 
     pub async fn add_outbound(&mut self, addr: SocketAddr) -> Result<()> {
-        if self.peers.contains_key(&addr) { return Ok(()); }
-        let mut p = Peer::connect(addr, self.net).await?;
+        self.add_connection(addr, ConnectionType::OutboundFullRelay).await
+    }
+
+    /// Connect to a user-requested peer (-peer, addnode)
+    pub async fn add_manual(&mut self, addr: SocketAddr) -> Result<()> {
+        self.add_connection(addr, ConnectionType::Manual).await
+    }
+
+    /// Whether a new connection to `addr` is allowed
+    fn check_connect(&self, addr: &SocketAddr) -> Result<()> {
+        if !self.shared.network_active.load(Ordering::SeqCst) {
+            return Err(anyhow!("network activity disabled"));
+        }
+        if self.shared.banman.lock().is_banned(&addr.ip()) {
+            return Err(anyhow!("{addr} is banned"));
+        }
+        Ok(())
+    }
 
+    /// Version message fields for a new connection
+    fn handshake_params(&self) -> HandshakeParams {
         // CRITICAL: Don't advertise NETWORK during IBD!
         // If we advertise NETWORK, peers expect us to have headers
         // When we only have genesis, they think we're broken and disconnect
         // Only advertise WITNESS during IBD
-        let our_services = if self.headers_synced {
+        let services = if self.headers_synced {
             p2p::ServiceFlags::NETWORK | p2p::ServiceFlags::WITNESS
         } else {
             p2p::ServiceFlags::WITNESS  // IBD: Only WITNESS, no NETWORK
@@ -549,7 +750,55 @@ impl PeerManager {
         // CRITICAL: Use self.start_height, not a parameter
         // start_height represents OUR current blockchain height (blocks we have)
         // During IBD this should be 0 (or actual verified block count)
-        p.handshake(&self.user_agent, self.start_height, our_services).await?;
+        HandshakeParams {
+            magic: self.chain_params.magic,
+            totals: self.shared.totals.clone(),
+            user_agent: self.user_agent.clone(),
+            start_height: self.start_height,
+            services,
+        }
+    }
+
+    /// Dial and handshake with `addr` in place, blocking the caller until done
+    async fn add_connection(&mut self, addr: SocketAddr, conn_type: ConnectionType) -> Result<()> {
+        if self.peers.contains_key(&addr) { return Ok(()); }
+        self.check_connect(&addr)?;
+        let p = Peer::open(addr, conn_type, self.handshake_params()).await?;
+        self.register_peer(addr, p).await
+    }
+
+    /// Dial and handshake with `addr` in a background task, so a slow or
+    /// silent peer does not stall the event loop. The ready peer comes back
+    /// as `P2PCommand::Connected`.
+    fn spawn_connection(&self, addr: SocketAddr, conn_type: ConnectionType) {
+        if self.peers.contains_key(&addr) { return; }
+        if let Err(e) = self.check_connect(&addr) {
+            eprintln!("[p2p] connect to {addr} failed: {e:#}");
+            return;
+        }
+        let params = self.handshake_params();
+        let cmd_tx = self.cmd_tx.clone();
+        tokio::spawn(async move {
+            match Peer::open(addr, conn_type, params).await {
+                Ok(peer) => { let _ = cmd_tx.send(P2PCommand::Connected { addr, peer }); }
+                Err(e) => eprintln!("[p2p] connect to {addr} failed: {e:#}"),
+            }
+        });
+    }
+
+    /// Take in a peer that completed its handshake
+    async fn register_peer(&mut self, addr: SocketAddr, p: Peer) -> Result<()> {
+        // The handshake may have raced another connection, a ban or setnetworkactive
+        if self.peers.contains_key(&addr) {
+            return Err(anyhow!("already connected to {addr}"));
+        }
+        self.check_connect(&addr)?;
+
+        let id = self.next_peer_id;
+        self.next_peer_id += 1;
+        p.stats().lock().id = id;
+        self.shared.local_services.store(p.our_services.to_u64(), Ordering::Relaxed);
+        self.shared.peers.write().insert(id, p.stats().clone());
 
         // 피어의 높이를 추적
         let peer_height = p.their_start_height;
@@ -615,20 +864,17 @@ impl PeerManager {
 
     /// DNS 부트스트랩 (최대 연결/시도 제한)
     pub async fn bootstrap(&mut self) -> Result<usize> {
+        if !self.shared.network_active.load(Ordering::SeqCst) {
+            return Ok(0);
+        }
         let max_boot = 6usize;
         let mut attempts = 0usize;
         let mut connected = 0usize;
 
-        for &seed in seeds::dns_seeds(self.net) {
+        for seed in self.chain_params.seeds.clone() {
             eprintln!("[bootstrap] seed={seed}");
-            let default_port = match self.net {
-                Network::Bitcoin  => 8333,
-                Network::Testnet  => 18333,
-                Network::Testnet4 => 48333,
-                Network::Signet   => 38333,
-                Network::Regtest  => 18444,
-            };
-            let target = if seed.contains(':') { seed.to_string() } else { format!("{}:{}", seed, default_port) };
+            let default_port = default_port(self.net);
+            let target = if seed.contains(':') { seed.clone() } else { format!("{}:{}", seed, default_port) };
             match lookup_host(target).await {
                 Ok(addrs) => {
                     for addr in addrs {
@@ -884,10 +1130,323 @@ impl PeerManager {
         Ok(())
     }
 
+    /// Drop a connection and unpublish its stats
+    fn remove_peer(&mut self, addr: &SocketAddr) {
+        if let Some(p) = self.peers.remove(addr) {
+            let id = p.stats().lock().id;
+            self.shared.peers.write().remove(&id);
+        }
+        self.peer_heights.remove(addr);
+        let orphans = self.orphanage.erase_for_peer(addr);
+        if orphans > 0 {
+            eprintln!("[p2p] dropped {orphans} orphan txs from {addr}");
+        }
+        if self.sync_peer == Some(*addr) {
+            self.sync_peer = None;
+        }
+    }
+
+    /// Apply queued RPC commands
+    async fn process_commands(&mut self) {
+        while let Ok(cmd) = self.cmd_rx.try_recv() {
+            match cmd {
+                P2PCommand::Connect { addr, conn_type } => self.spawn_connection(addr, conn_type),
+                P2PCommand::Connected { addr, peer } => {
+                    let conn_type = peer.stats().lock().conn_type;
+                    match self.register_peer(addr, peer).await {
+                        Ok(()) => eprintln!("[p2p] connected to {addr} ({})", conn_type.as_str()),
+                        Err(e) => eprintln!("[p2p] connect to {addr} failed: {e:#}"),
+                    }
+                }
+                P2PCommand::Disconnect(id) => {
+                    let addr = self.peers.iter()
+                        .find(|(_, p)| p.stats().lock().id == id)
+                        .map(|(a, _)| *a);
+                    if let Some(addr) = addr {
+                        eprintln!("[p2p] disconnecting peer={id} ({addr})");
+                        self.remove_peer(&addr);
+                    }
+                }
+                P2PCommand::DisconnectBanned => {
+                    let banned: Vec<SocketAddr> = {
+                        let banman = self.shared.banman.lock();
+                        self.peers.keys().filter(|a| banman.is_banned(&a.ip())).copied().collect()
+                    };
+                    for addr in banned {
+                        eprintln!("[p2p] disconnecting banned peer {addr}");
+                        self.remove_peer(&addr);
+                    }
+                }
+                P2PCommand::PingAll => self.send_pings().await,
+                P2PCommand::RelayTransaction(tx) => self.relay_transaction(tx).await,
+                #[cfg(feature = "mining")]
+                P2PCommand::RelayBlock(block) => self.relay_block(block).await,
+                P2PCommand::SetNetworkActive(active) => {
+                    eprintln!("[p2p] network activity {}", if active { "enabled" } else { "disabled" });
+                    if !active {
+                        let addrs: Vec<SocketAddr> = self.peers.keys().copied().collect();
+                        for addr in addrs {
+                            self.remove_peer(&addr);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Announce a transaction to every peer that accepts tx relay
+    async fn relay_transaction(&mut self, tx: Arc<bitcoin::Transaction>) {
+        let txid = tx.compute_txid();
+        let wtxid = tx.compute_wtxid();
+        let now = Instant::now();
+        self.relay_txs.retain(|_, (_, added)| now.duration_since(*added) < RELAY_TX_CACHE_TIME);
+        self.relay_wtxids.retain(|_, txid| self.relay_txs.contains_key(txid));
+        self.relay_txs.insert(txid, (tx, now));
+        self.relay_wtxids.insert(wtxid, txid);
+
+        let fee_rate = self.tx_fee_rate.as_ref().and_then(|f| f(&txid));
+        let mut announced = 0usize;
+        for p in self.peers.values_mut() {
+            let inv = {
+                let stats = p.stats().lock();
+                if !stats.relay_txes || fee_rate.is_some_and(|rate| rate < stats.min_fee_filter) {
+                    continue;
+                }
+                // wtxidrelay peers ignore MSG_TX announcements
+                if stats.wtxid_relay { msg_blk::Inventory::WTx(wtxid) } else { msg_blk::Inventory::Transaction(txid) }
+            };
+            if p.send(message::NetworkMessage::Inv(vec![inv])).await.is_ok() {
+                announced += 1;
+            }
+        }
+        eprintln!("[p2p] announced tx {txid} to {announced} peers");
+    }
+
+    /// Announce a locally submitted block to every peer
+    #[cfg(feature = "mining")]
+    async fn relay_block(&mut self, block: Arc<bitcoin::Block>) {
+        let hash = block.block_hash();
+        if self.relay_blocks.len() == MAX_RELAY_BLOCKS {
+            self.relay_blocks.pop_front();
+        }
+        self.relay_blocks.push_back(block);
+
+        let mut announced = 0usize;
+        for p in self.peers.values_mut() {
+            if p.send(message::NetworkMessage::Inv(vec![msg_blk::Inventory::Block(hash)])).await.is_ok() {
+                announced += 1;
+            }
+        }
+        eprintln!("[p2p] announced block {hash} to {announced} peers");
+    }
+
+    /// Announced transaction a getdata entry asks for, by txid or wtxid
+    fn relayed_tx(&self, inv: &msg_blk::Inventory) -> Option<Arc<bitcoin::Transaction>> {
+        let txid = match inv {
+            msg_blk::Inventory::Transaction(txid) | msg_blk::Inventory::WitnessTransaction(txid) => *txid,
+            msg_blk::Inventory::WTx(wtxid) => *self.relay_wtxids.get(wtxid)?,
+            _ => return None,
+        };
+        self.relay_txs.get(&txid).map(|(tx, _)| tx.clone())
+    }
+
+    /// Answer getdata for transactions and blocks we announced
+    async fn respond_getdata(&mut self, addr: SocketAddr, invs: &[msg_blk::Inventory]) {
+        let mut not_found = Vec::new();
+        let mut txs = Vec::new();
+        let mut blocks = Vec::new();
+        for inv in invs {
+            match inv {
+                msg_blk::Inventory::Transaction(_)
+                | msg_blk::Inventory::WitnessTransaction(_)
+                | msg_blk::Inventory::WTx(_) => {
+                    match self.relayed_tx(inv) {
+                        Some(tx) => {
+                            if let Some(cb) = &self.on_tx_served {
+                                cb(&tx.compute_txid());
+                            }
+                            txs.push(tx)
+                        }
+                        None => not_found.push(*inv),
+                    }
+                }
+                msg_blk::Inventory::WitnessBlock(hash) => {
+                    match self.relay_blocks.iter().find(|b| b.block_hash() == *hash) {
+                        Some(block) => blocks.push(block.clone()),
+                        None => not_found.push(*inv),
+                    }
+                }
+                _ => not_found.push(*inv),
+            }
+        }
+
+        if let Some(p) = self.peers.get_mut(&addr) {
+            for tx in txs {
+                let _ = p.send(message::NetworkMessage::Tx((*tx).clone())).await;
+            }
+            for block in blocks {
+                let _ = p.send(message::NetworkMessage::Block((*block).clone())).await;
+            }
+            if !not_found.is_empty() {
+                let _ = p.send(message::NetworkMessage::NotFound(not_found)).await;
+            }
+        }
+    }
+
+    /// Validate a relayed transaction off the network loop; the verdict comes
+    /// back through `process_tx_results`
+    fn submit_tx(&self, addr: SocketAddr, tx: Arc<bitcoin::Transaction>) {
+        let Some(cb) = self.on_tx.clone() else { return; };
+        let results = self.tx_results_tx.clone();
+        tokio::spawn(async move {
+            let outcome = match spawn_blocking({
+                let tx = tx.clone();
+                move || (cb)(&tx)
+            }).await {
+                Ok(outcome) => outcome,
+                Err(e) => TxOutcome::Rejected(format!("spawn error: {e}")),
+            };
+            let _ = results.send((addr, tx, outcome));
+        });
+    }
+
+    /// Act on mempool verdicts (Bitcoin Core: ProcessMessage "tx" and
+    /// ProcessOrphanTx): accepted parents release their orphans for another
+    /// try, and transactions with missing inputs are kept while their parents
+    /// are requested from the peer that sent them
+    async fn process_tx_results(&mut self) {
+        while let Ok((addr, tx, outcome)) = self.tx_results_rx.try_recv() {
+            let wtxid = tx.compute_wtxid();
+            match outcome {
+                TxOutcome::Accepted => {
+                    self.orphanage.erase_tx(&wtxid);
+                    self.orphanage.add_children_to_work_set(&tx);
+                }
+                TxOutcome::MissingInputs(parents) => {
+                    let parents = self.orphanage.parents_to_request(&parents);
+                    if !self.peers.contains_key(&addr) || !self.orphanage.add_tx(tx.clone(), addr) {
+                        continue;
+                    }
+                    eprintln!(
+                        "[p2p] stored orphan tx {} from {addr}, requesting {} parents ({} orphans)",
+                        tx.compute_txid(),
+                        parents.len(),
+                        self.orphanage.len()
+                    );
+                    if let Some(p) = self.peers.get_mut(&addr) {
+                        if !parents.is_empty() {
+                            let invs = parents.into_iter().map(msg_blk::Inventory::WitnessTransaction).collect();
+                            let _ = p.send(message::NetworkMessage::GetData(invs)).await;
+                        }
+                    }
+                    let evicted = self.orphanage.limit_orphans();
+                    if evicted > 0 {
+                        eprintln!("[p2p] orphanage overflow, removed {evicted} txs");
+                    }
+                }
+                TxOutcome::Rejected(reason) => {
+                    if self.orphanage.erase_tx(&wtxid) {
+                        eprintln!("[p2p] removed orphan tx {}: {reason}", tx.compute_txid());
+                    }
+                }
+            }
+        }
+
+        for (peer, orphan) in self.orphanage.take_work() {
+            self.submit_tx(peer, orphan);
+        }
+    }
+
+    /// Tell tx-relaying peers our mempool minimum fee: on connect, every
+    /// broadcast interval if it changed, and early when it moved by a third
+    async fn send_fee_filters(&mut self) {
+        let Some(min_fee) = self.min_fee.as_ref().map(|f| f()) else { return; };
+        for p in self.peers.values_mut() {
+            let due = {
+                let stats = p.stats().lock();
+                if !stats.relay_txes || stats.version < FEEFILTER_VERSION {
+                    continue;
+                }
+                match stats.fee_filter_sent {
+                    None => true,
+                    Some((sent, at)) => {
+                        let elapsed = at.elapsed();
+                        let big_change = min_fee * 4 < sent * 3 || min_fee * 3 > sent * 4;
+                        (elapsed >= FEEFILTER_BROADCAST_INTERVAL && min_fee != sent)
+                            || (elapsed >= MAX_FEEFILTER_CHANGE_DELAY && big_change)
+                    }
+                }
+            };
+            if due && p.send(message::NetworkMessage::FeeFilter(min_fee as i64)).await.is_ok() {
+                p.stats().lock().fee_filter_sent = Some((min_fee, Instant::now()));
+            }
+        }
+    }
+
+    /// Ping every peer that has no outstanding ping, to measure latency
+    async fn send_pings(&mut self) {
+        for p in self.peers.values_mut() {
+            if p.stats().lock().ping_pending.is_some() { continue; }
+            let nonce = rand::thread_rng().gen::<u64>();
+            if p.send(message::NetworkMessage::Ping(nonce)).await.is_ok() {
+                p.stats().lock().ping_pending = Some((nonce, Instant::now()));
+            }
+        }
+    }
+
+    /// Resolve, dial and handshake with added nodes in the background; each
+    /// ready peer comes back as `P2PCommand::Connected`
+    fn connect_added_nodes(&self) {
+        let nodes = self.shared.added_nodes.lock().clone();
+        let connected: Arc<HashSet<SocketAddr>> = Arc::new(self.peers.keys().copied().collect());
+        let params = self.handshake_params();
+        for node in nodes {
+            let target = if node.contains(':') { node.clone() } else { format!("{}:{}", node, default_port(self.net)) };
+            let connected = connected.clone();
+            let cmd_tx = self.cmd_tx.clone();
+            let params = params.clone();
+            tokio::spawn(async move {
+                let Ok(mut addrs) = lookup_host(target).await else { return; };
+                let Some(addr) = addrs.next() else { return; };
+                if connected.contains(&addr) {
+                    return;
+                }
+                match Peer::open(addr, ConnectionType::Manual, params).await {
+                    Ok(peer) => {
+                        let _ = cmd_tx.send(P2PCommand::Connected { addr, peer });
+                    }
+                    Err(e) => eprintln!("[p2p] addnode {node} connect failed: {e:#}"),
+                }
+            });
+        }
+    }
+
     pub async fn event_loop(&mut self) -> Result<()> {
         let mut last_headers_ts = tokio::time::Instant::now();
+        let mut last_ping_round = tokio::time::Instant::now();
+        let mut last_added_check: Option<tokio::time::Instant> = None;
 
         loop {
+            self.process_commands().await;
+
+            if !self.shared.network_active.load(Ordering::SeqCst) {
+                sleep(Duration::from_millis(200)).await;
+                continue;
+            }
+
+            if last_added_check.is_none_or(|t| t.elapsed() >= ADDED_NODE_RETRY) {
+                self.connect_added_nodes();
+                last_added_check = Some(tokio::time::Instant::now());
+            }
+
+            if last_ping_round.elapsed() >= PING_INTERVAL {
+                self.send_pings().await;
+                last_ping_round = tokio::time::Instant::now();
+            }
+
+            self.send_fee_filters().await;
+            self.process_tx_results().await;
+
             // 피어 없으면 재부트스트랩
             if self.peers.is_empty() {
                 self.sync_peer = None;  // Reset sync peer
@@ -924,7 +1483,7 @@ impl PeerManager {
                         } else {
                             eprintln!("[p2p] ⚠️  recv error from {addr}: {} - dropping peer", err_str);
                         }
-                        self.peers.remove(&addr);
+                        self.remove_peer(&addr);
                         continue;
                     }
                     Err(_) => None,
@@ -970,6 +1529,13 @@ impl PeerManager {
 
                                 // Bitcoin Core 방식: 헤더만 처리
                                 let added = self.extend_headers(&h);
+                                self.shared.header_height.store(self.header_chain_height, Ordering::Relaxed);
+                                if added > 0 {
+                                    if let Some(p) = self.peers.get(&addr) {
+                                        let mut stats = p.stats().lock();
+                                        stats.best_height = stats.best_height.max(self.header_chain_height);
+                                    }
+                                }
 
                                 // 진행률 표시
                                 let progress = if self.best_known_height > 0 {
@@ -1098,6 +1664,11 @@ impl PeerManager {
                                 }
                             }
 
+                            let orphans = self.orphanage.erase_for_block(&b);
+                            if orphans > 0 {
+                                eprintln!("[p2p] erased {orphans} orphan txs included or conflicted by block {h}");
+                            }
+
                             // 3) Send block to sequential processor
                             // Bitcoin Core processes blocks sequentially to ensure parent blocks
                             // are processed before children. We use a channel to maintain order.
@@ -1114,10 +1685,19 @@ impl PeerManager {
                                 let _ = p.send(message::NetworkMessage::Pong(nonce)).await;
                             }
                         }
-                        message::NetworkMessage::Pong(_) => { /* ignore */ }
+                        message::NetworkMessage::Pong(nonce) => {
+                            if let Some(p) = self.peers.get(&addr) {
+                                p.stats().lock().pong_received(nonce);
+                            }
+                        }
                         message::NetworkMessage::NotFound(v) => {
                             eprintln!("[p2p] notfound: {} entries", v.len());
                         }
+                        message::NetworkMessage::FeeFilter(rate) => {
+                            if let Some(p) = self.peers.get(&addr) {
+                                p.stats().lock().min_fee_filter = rate.clamp(0, bitcoin::Amount::MAX_MONEY.to_sat() as i64) as u64;
+                            }
+                        }
                         message::NetworkMessage::Addr(addrs) => {
                             let mut added = 0usize;
                             for (_time, a) in addrs {
@@ -1143,20 +1723,17 @@ impl PeerManager {
                         message::NetworkMessage::GetHeaders(gh) => {
                             let _ = self.respond_getheaders(addr, &gh).await;
                         }
+                        message::NetworkMessage::GetData(invs) => {
+                            self.respond_getdata(addr, &invs).await;
+                        }
                         message::NetworkMessage::Tx(tx) => {
                             let txid = tx.compute_txid();
                             eprintln!("[p2p] received tx: {}", txid);
 
-                            // Process transaction via callback
-                            if let Some(ref cb) = self.on_tx {
-                                let tx_clone = tx.clone();
-                                let cb = cb.clone();
-                                tokio::spawn(async move {
-                                    if let Err(e) = (cb)(&tx_clone) {
-                                        eprintln!("[p2p] tx processing error {}: {:#}", tx_clone.compute_txid(), e);
-                                    }
-                                });
+                            if self.orphanage.have_tx(&tx.compute_wtxid()) {
+                                continue;
                             }
+                            self.submit_tx(addr, Arc::new(tx));
                         }
                         other => {
                             eprintln!("[p2p] other: {:?}", other.command());
@@ -1206,7 +1783,7 @@ impl PeerManager {
             if !self.headers_synced && tokio::time::Instant::now().duration_since(last_headers_ts) > STALL_LIMIT {
                 if let Some(sync_addr) = self.sync_peer {
                     eprintln!("[p2p] headers stall; replacing sync peer {}", sync_addr);
-                    self.peers.remove(&sync_addr);
+                    self.remove_peer(&sync_addr);
                     self.sync_peer = None;
                 }
                 let _ = self.bootstrap().await;
@@ -3,6 +3,9 @@ pub mod peer;
 pub mod manager;
 pub mod inventory;
 pub mod legacy;
+pub mod banman;
+pub mod control;
+pub mod orphanage;
 
 pub use messages::{P2PMessage, InventoryType};
 pub use peer::{Peer, PeerState};
@@ -11,3 +14,5 @@ pub use inventory::InventoryManager;
 
 // Re-export legacy for compatibility
 pub use legacy::PeerManager;
+pub use control::P2PHandle;
+pub use orphanage::TxOutcome;
@@ -0,0 +1,286 @@
+//! Orphan transaction pool (Bitcoin Core: TxOrphanage).
+//!
+//! Transactions that spend outputs we do not know about yet are held here
+//! while their parents are fetched from the peer that announced them. When a
+//! parent is accepted, the orphans spending it are queued for that peer to
+//! be validated again.
+
+use bitcoin::{Block, OutPoint, Transaction, Txid, Wtxid};
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Default orphan pool size (Bitcoin Core: DEFAULT_MAX_ORPHAN_TRANSACTIONS)
+pub const DEFAULT_MAX_ORPHAN_TRANSACTIONS: usize = 100;
+
+/// How long an orphan waits for its parents (Bitcoin Core: ORPHAN_TX_EXPIRE_TIME)
+pub const ORPHAN_TX_EXPIRE_TIME: Duration = Duration::from_secs(20 * 60);
+
+/// Minimum time between sweeps for expired orphans
+pub const ORPHAN_TX_EXPIRE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Larger transactions are not kept as orphans (Bitcoin Core: MAX_STANDARD_TX_WEIGHT)
+pub const MAX_ORPHAN_TX_WEIGHT: u64 = 400_000;
+
+/// Result of handing a relayed transaction to the mempool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxOutcome {
+    Accepted,
+    /// Spends outputs of these unknown transactions
+    MissingInputs(Vec<Txid>),
+    Rejected(String),
+}
+
+#[derive(Debug, Clone)]
+struct OrphanTx {
+    tx: Arc<Transaction>,
+    from_peer: SocketAddr,
+    expire: Instant,
+}
+
+#[derive(Debug)]
+pub struct TxOrphanage {
+    orphans: HashMap<Wtxid, OrphanTx>,
+    /// Orphans by the outpoints they spend, to find them when a parent arrives
+    by_prevout: HashMap<OutPoint, HashSet<Wtxid>>,
+    /// Orphans announced by each peer
+    by_peer: HashMap<SocketAddr, HashSet<Wtxid>>,
+    /// Orphans whose parents arrived, to validate again on behalf of each peer
+    work_sets: HashMap<SocketAddr, HashSet<Wtxid>>,
+    max_orphans: usize,
+    next_sweep: Instant,
+}
+
+impl Default for TxOrphanage {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ORPHAN_TRANSACTIONS)
+    }
+}
+
+impl TxOrphanage {
+    pub fn new(max_orphans: usize) -> Self {
+        Self {
+            orphans: HashMap::new(),
+            by_prevout: HashMap::new(),
+            by_peer: HashMap::new(),
+            work_sets: HashMap::new(),
+            max_orphans,
+            next_sweep: Instant::now() + ORPHAN_TX_EXPIRE_INTERVAL,
+        }
+    }
+
+    /// Store an orphan announced by `peer`; false if it is already held or too large
+    pub fn add_tx(&mut self, tx: Arc<Transaction>, peer: SocketAddr) -> bool {
+        let wtxid = tx.compute_wtxid();
+        if self.orphans.contains_key(&wtxid) {
+            return false;
+        }
+        if tx.weight().to_wu() > MAX_ORPHAN_TX_WEIGHT {
+            eprintln!("[p2p] ignoring large orphan tx {} (weight {})", tx.compute_txid(), tx.weight().to_wu());
+            return false;
+        }
+
+        for input in &tx.input {
+            self.by_prevout.entry(input.previous_output).or_default().insert(wtxid);
+        }
+        self.by_peer.entry(peer).or_default().insert(wtxid);
+        self.orphans.insert(wtxid, OrphanTx { tx, from_peer: peer, expire: Instant::now() + ORPHAN_TX_EXPIRE_TIME });
+        true
+    }
+
+    pub fn have_tx(&self, wtxid: &Wtxid) -> bool {
+        self.orphans.contains_key(wtxid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty()
+    }
+
+    /// Number of orphans announced by `peer`
+    pub fn count_from(&self, peer: &SocketAddr) -> usize {
+        self.by_peer.get(peer).map_or(0, |set| set.len())
+    }
+
+    /// Drop an orphan; true if it was held
+    pub fn erase_tx(&mut self, wtxid: &Wtxid) -> bool {
+        let Some(orphan) = self.orphans.remove(wtxid) else {
+            return false;
+        };
+        for input in &orphan.tx.input {
+            if let Some(set) = self.by_prevout.get_mut(&input.previous_output) {
+                set.remove(wtxid);
+                if set.is_empty() {
+                    self.by_prevout.remove(&input.previous_output);
+                }
+            }
+        }
+        if let Some(set) = self.by_peer.get_mut(&orphan.from_peer) {
+            set.remove(wtxid);
+            if set.is_empty() {
+                self.by_peer.remove(&orphan.from_peer);
+            }
+        }
+        if let Some(set) = self.work_sets.get_mut(&orphan.from_peer) {
+            set.remove(wtxid);
+        }
+        true
+    }
+
+    /// Drop everything a disconnected peer sent us
+    pub fn erase_for_peer(&mut self, peer: &SocketAddr) -> usize {
+        self.work_sets.remove(peer);
+        let wtxids: Vec<Wtxid> = self.by_peer.get(peer).map(|set| set.iter().copied().collect()).unwrap_or_default();
+        wtxids.iter().filter(|wtxid| self.erase_tx(wtxid)).count()
+    }
+
+    /// Drop orphans included in or conflicting with `block`
+    pub fn erase_for_block(&mut self, block: &Block) -> usize {
+        let mut erase = HashSet::new();
+        for tx in &block.txdata {
+            erase.insert(tx.compute_wtxid());
+            for input in &tx.input {
+                if let Some(set) = self.by_prevout.get(&input.previous_output) {
+                    erase.extend(set.iter().copied());
+                }
+            }
+        }
+        erase.iter().filter(|wtxid| self.erase_tx(wtxid)).count()
+    }
+
+    /// Expire old orphans (at most every sweep interval), then evict random
+    /// ones until the pool fits its limit. Returns how many were removed.
+    pub fn limit_orphans(&mut self) -> usize {
+        let mut removed = 0;
+        let now = Instant::now();
+        if now >= self.next_sweep {
+            let expired: Vec<Wtxid> =
+                self.orphans.iter().filter(|(_, o)| o.expire <= now).map(|(wtxid, _)| *wtxid).collect();
+            removed += expired.iter().filter(|wtxid| self.erase_tx(wtxid)).count();
+            self.next_sweep = now + ORPHAN_TX_EXPIRE_INTERVAL;
+        }
+
+        let mut rng = rand::thread_rng();
+        while self.orphans.len() > self.max_orphans {
+            let victim = *self.orphans.keys().nth(rng.gen_range(0..self.orphans.len())).expect("non-empty");
+            self.erase_tx(&victim);
+            removed += 1;
+        }
+        removed
+    }
+
+    /// `tx` was accepted: queue the orphans spending its outputs for another
+    /// try, each on behalf of the peer that sent it
+    pub fn add_children_to_work_set(&mut self, tx: &Transaction) {
+        let txid = tx.compute_txid();
+        for vout in 0..tx.output.len() as u32 {
+            let Some(children) = self.by_prevout.get(&OutPoint::new(txid, vout)) else {
+                continue;
+            };
+            for wtxid in children {
+                if let Some(orphan) = self.orphans.get(wtxid) {
+                    self.work_sets.entry(orphan.from_peer).or_default().insert(*wtxid);
+                }
+            }
+        }
+    }
+
+    /// Take every queued orphan, with the peer it came from
+    pub fn take_work(&mut self) -> Vec<(SocketAddr, Arc<Transaction>)> {
+        let mut work = Vec::new();
+        for (peer, set) in self.work_sets.drain() {
+            for wtxid in set {
+                if let Some(orphan) = self.orphans.get(&wtxid) {
+                    work.push((peer, orphan.tx.clone()));
+                }
+            }
+        }
+        work
+    }
+
+    /// Missing parents worth requesting: duplicates and parents that are
+    /// themselves waiting here are dropped
+    pub fn parents_to_request(&self, parents: &[Txid]) -> Vec<Txid> {
+        let held: HashSet<Txid> = self.orphans.values().map(|o| o.tx.compute_txid()).collect();
+        let mut seen = HashSet::new();
+        parents.iter().copied().filter(|txid| !held.contains(txid) && seen.insert(*txid)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{absolute, transaction, Amount, ScriptBuf, TxIn, TxOut};
+
+    fn tx(prevout: OutPoint) -> Arc<Transaction> {
+        Arc::new(Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn { previous_output: prevout, ..Default::default() }],
+            output: vec![TxOut { value: Amount::from_sat(1_000), script_pubkey: ScriptBuf::new() }],
+        })
+    }
+
+    fn peer(n: u8) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, n], 8333))
+    }
+
+    #[test]
+    fn test_parent_arrival_queues_orphan() {
+        let mut orphanage = TxOrphanage::default();
+        let parent = tx(OutPoint::new(Txid::all_zeros(), 0));
+        let child = tx(OutPoint::new(parent.compute_txid(), 0));
+
+        assert!(orphanage.add_tx(child.clone(), peer(1)));
+        assert!(!orphanage.add_tx(child.clone(), peer(2)));
+        assert_eq!(orphanage.count_from(&peer(1)), 1);
+        assert!(orphanage.take_work().is_empty());
+
+        orphanage.add_children_to_work_set(&parent);
+        let work = orphanage.take_work();
+        assert_eq!(work.len(), 1);
+        assert_eq!(work[0].0, peer(1));
+        assert_eq!(work[0].1.compute_wtxid(), child.compute_wtxid());
+
+        assert!(orphanage.erase_tx(&child.compute_wtxid()));
+        assert!(orphanage.is_empty());
+        assert_eq!(orphanage.count_from(&peer(1)), 0);
+    }
+
+    #[test]
+    fn test_limits_and_peer_disconnect() {
+        let mut orphanage = TxOrphanage::new(3);
+        for n in 0..5u8 {
+            orphanage.add_tx(tx(OutPoint::new(Txid::from_byte_array([n; 32]), 0)), peer(n % 2));
+        }
+        assert_eq!(orphanage.limit_orphans(), 2);
+        assert_eq!(orphanage.len(), 3);
+
+        let from_first = orphanage.count_from(&peer(0));
+        assert_eq!(orphanage.erase_for_peer(&peer(0)), from_first);
+        assert_eq!(orphanage.len(), 3 - from_first);
+    }
+
+    #[test]
+    fn test_erase_for_block_removes_conflicts() {
+        let mut orphanage = TxOrphanage::default();
+        let prevout = OutPoint::new(Txid::all_zeros(), 0);
+        let orphan = tx(prevout);
+        orphanage.add_tx(orphan, peer(1));
+
+        let mut conflict = (*tx(prevout)).clone();
+        conflict.output[0].value = Amount::from_sat(2_000);
+        let block = Block {
+            header: bitcoin::constants::genesis_block(bitcoin::Network::Regtest).header,
+            txdata: vec![conflict],
+        };
+        assert_eq!(orphanage.erase_for_block(&block), 1);
+        assert!(orphanage.is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+// src/rpc/address.rs
+//! Balance, UTXO and history queries for a single address or descriptor,
+//! answered from the address index (-addrindex). Bitcoin Core leaves this
+//! to wallets and external indexers.
+
+use bitcoin::{Network, ScriptBuf};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::descriptor;
+use crate::index::addrindex::{script_hash, AddrIndex, ScriptActivity};
+use crate::index::coins::MEMPOOL_HEIGHT;
+use crate::index::{self, BlockIndex};
+
+use super::protocol::{RpcError, RpcResult};
+use super::server::{blocking, Params, RpcTable};
+use super::util::btc;
+use super::AppState;
+
+/// How long a query waits for the index to apply a block the kernel just connected
+const SYNC_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn internal(e: anyhow::Error) -> RpcError {
+    RpcError::internal(e.to_string())
+}
+
+fn output_script(output: &str, network: Network) -> RpcResult<ScriptBuf> {
+    descriptor::output_script(output, network)
+        .ok_or_else(|| RpcError::invalid_address_or_key("Invalid address or descriptor"))
+}
+
+fn addrindex(state: &AppState) -> RpcResult<Arc<AddrIndex>> {
+    state
+        .addrindex
+        .clone()
+        .ok_or_else(|| RpcError::misc("Address index is not enabled. Start the node with -addrindex"))
+}
+
+/// `count` and `skip` of a paginated query (Bitcoin Core: listtransactions)
+fn page(params: &Params, idx: usize, default_count: i64) -> RpcResult<(usize, usize)> {
+    let count: i64 = params.optional_or(idx, "count", default_count)?;
+    let skip: i64 = params.optional_or(idx + 1, "skip", 0)?;
+    if count < 0 {
+        return Err(RpcError::invalid_parameter("Negative count"));
+    }
+    if skip < 0 {
+        return Err(RpcError::invalid_parameter("Negative skip"));
+    }
+    Ok((count as usize, skip as usize))
+}
+
+/// Confirmed activity of `script`, plus the mempool's when `include_mempool`
+fn script_activity(
+    state: &AppState,
+    addrindex: &AddrIndex,
+    script: &ScriptBuf,
+    include_mempool: bool,
+) -> RpcResult<ScriptActivity> {
+    if !index::wait_until_synced(addrindex, &state.kernel, SYNC_TIMEOUT) {
+        let height = addrindex.best_block().map_or(0, |(height, _)| height);
+        return Err(RpcError::misc(format!(
+            "Unable to get data because addrindex is still syncing. Current height: {}",
+            height
+        )));
+    }
+
+    let scripthash = script_hash(script);
+    let mut activity = addrindex.activity(&scripthash).map_err(internal)?;
+    if include_mempool {
+        let scripthashes = HashSet::from([scripthash]);
+        let mut unconfirmed = addrindex.mempool_activity(&state.mempool, &scripthashes).map_err(internal)?;
+        if let Some(unconfirmed) = unconfirmed.remove(&scripthash) {
+            activity.extend(unconfirmed);
+        }
+    }
+    Ok(activity)
+}
+
+/// Confirmations of a block at `height` with the chain at `tip`
+fn confirmations(tip: i32, height: u32) -> i64 {
+    (tip as i64 - height as i64 + 1).max(0)
+}
+
+/// getaddressbalance "address"
+///
+/// Confirmed balance of an address or descriptor, and the net change that
+/// its unconfirmed mempool transactions would make.
+pub async fn getaddressbalance(state: AppState, params: Params) -> RpcResult {
+    let address: String = params.required(0, "address")?;
+    let addrindex = addrindex(&state)?;
+
+    blocking(move || {
+        let script = output_script(&address, state.kernel.network())?;
+        let activity = script_activity(&state, &addrindex, &script, true)?;
+        let (confirmed, unconfirmed) = activity.balance();
+        let received: u64 = activity.fundings.iter().filter(|f| f.height != MEMPOOL_HEIGHT).map(|f| f.value).sum();
+        Ok(json!({
+            "confirmed": btc(confirmed),
+            // Negative when unconfirmed transactions spend more than they pay in
+            "unconfirmed": unconfirmed as f64 / 100_000_000.0,
+            "received": btc(received),
+            "tx_count": activity.history().len(),
+            "utxo_count": activity.unspent().len(),
+        }))
+    })
+    .await
+}
+
+/// getaddressutxos "address" ( count skip include_mempool )
+///
+/// Unspent outputs of an address or descriptor, oldest first. Outputs spent
+/// in the mempool are left out and unconfirmed ones are listed last, unless
+/// `include_mempool` is false.
+pub async fn getaddressutxos(state: AppState, params: Params) -> RpcResult {
+    let address: String = params.required(0, "address")?;
+    let (count, skip) = page(&params, 1, 1000)?;
+    let include_mempool: bool = params.optional_or(3, "include_mempool", true)?;
+    let addrindex = addrindex(&state)?;
+
+    blocking(move || {
+        let script = output_script(&address, state.kernel.network())?;
+        let activity = script_activity(&state, &addrindex, &script, include_mempool)?;
+        let tip = state.kernel.get_height().map_err(internal)?;
+        let script_hex = hex::encode(script.as_bytes());
+
+        let utxos: Vec<Value> = activity
+            .unspent()
+            .into_iter()
+            .skip(skip)
+            .take(count)
+            .map(|f| {
+                let mut utxo = json!({
+                    "txid": f.outpoint.txid.to_string(),
+                    "vout": f.outpoint.vout,
+                    "value": btc(f.value),
+                    "scriptPubKey": script_hex,
+                    "coinbase": f.is_coinbase,
+                    "confirmations": 0,
+                });
+                if f.height != MEMPOOL_HEIGHT {
+                    utxo["height"] = json!(f.height);
+                    utxo["confirmations"] = json!(confirmations(tip, f.height));
+                }
+                utxo
+            })
+            .collect();
+        Ok(Value::Array(utxos))
+    })
+    .await
+}
+
+/// getaddresshistory "address" ( count skip include_mempool )
+///
+/// Transactions that paid or spent from an address or descriptor, with the
+/// amounts received and sent. Like listtransactions, returns the `count`
+/// most recent after skipping the `skip` most recent, oldest first; mempool
+/// transactions are the most recent.
+pub async fn getaddresshistory(state: AppState, params: Params) -> RpcResult {
+    let address: String = params.required(0, "address")?;
+    let (count, skip) = page(&params, 1, 100)?;
+    let include_mempool: bool = params.optional_or(3, "include_mempool", true)?;
+    let addrindex = addrindex(&state)?;
+
+    blocking(move || {
+        let script = output_script(&address, state.kernel.network())?;
+        let activity = script_activity(&state, &addrindex, &script, include_mempool)?;
+        let tip = state.kernel.get_height().map_err(internal)?;
+
+        let history = activity.history();
+        let end = history.len().saturating_sub(skip);
+        let start = end.saturating_sub(count);
+        let mut result = Vec::with_capacity(end - start);
+        for entry in &history[start..end] {
+            let mut tx = json!({
+                "txid": entry.txid.to_string(),
+                "received": btc(entry.received),
+                "sent": btc(entry.sent),
+                "confirmations": 0,
+            });
+            if entry.is_confirmed() {
+                let blockhash = state.kernel.get_block_hash(entry.height as i32).map_err(internal)?;
+                tx["height"] = json!(entry.height);
+                tx["blockhash"] = json!(blockhash.to_string());
+                tx["confirmations"] = json!(confirmations(tip, entry.height));
+            }
+            result.push(tx);
+        }
+        Ok(Value::Array(result))
+    })
+    .await
+}
+
+pub fn register(table: &mut RpcTable) {
+    table.register("addressindex", "getaddressbalance", &["address"], |s, p| Box::pin(getaddressbalance(s, p)));
+    table.register("addressindex", "getaddresshistory", &["address", "count", "skip", "include_mempool"], |s, p| {
+        Box::pin(getaddresshistory(s, p))
+    });
+    table.register("addressindex", "getaddressutxos", &["address", "count", "skip", "include_mempool"], |s, p| {
+        Box::pin(getaddressutxos(s, p))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_params() {
+        assert_eq!(page(&Params::positional(vec![json!("addr")]), 1, 100).unwrap(), (100, 0));
+        assert_eq!(page(&Params::positional(vec![json!("addr"), json!(5), json!(10)]), 1, 100).unwrap(), (5, 10));
+        assert!(page(&Params::positional(vec![json!("addr"), json!(-1)]), 1, 100).is_err());
+        assert!(page(&Params::positional(vec![json!("addr"), json!(1), json!(-1)]), 1, 100).is_err());
+    }
+
+    #[test]
+    fn test_confirmations() {
+        assert_eq!(confirmations(100, 100), 1);
+        assert_eq!(confirmations(100, 90), 11);
+        // A block the index has and the kernel's tip does not yet
+        assert_eq!(confirmations(100, 101), 0);
+    }
+}
@@ -0,0 +1,349 @@
+// src/rpc/auth.rs
+//! HTTP Basic authentication for the RPC server, following Bitcoin Core's httprpc.cpp:
+//! - `.cookie` file in the datadir, regenerated on every start and removed on shutdown
+//! - `-rpcuser`/`-rpcpassword`
+//! - any number of `-rpcauth=<user>:<salt>$<hmac-sha256(salt, password)>` entries
+//! - per-user method whitelists (`-rpcwhitelist=<user>:<method>,<method>,...`)
+
+use anyhow::{anyhow, Context, Result};
+use axum::{
+    extract::State,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::Engine as _;
+use bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::AppState;
+
+/// Username used for cookie authentication
+pub const COOKIE_USER: &str = "__cookie__";
+
+/// Default cookie file name inside the datadir
+const COOKIE_FILE: &str = ".cookie";
+
+/// Authentication-related command line options
+#[derive(Debug, Clone, Default)]
+pub struct RpcAuthConfig {
+    pub datadir: PathBuf,
+    pub cookie_file: Option<PathBuf>,
+    pub rpcuser: Option<String>,
+    pub rpcpassword: Option<String>,
+    pub rpcauth: Vec<String>,
+    pub rpcwhitelist: Vec<String>,
+    pub rpcwhitelistdefault: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+enum Credential {
+    /// `-rpcuser`/`-rpcpassword` or the cookie
+    Plain { user: String, password: String },
+    /// `-rpcauth` entry: HMAC-SHA256 keyed by `salt` over the password
+    Salted { user: String, salt: String, hash: String },
+}
+
+/// Username of an authenticated request, stored in the request extensions
+#[derive(Debug, Clone)]
+pub struct RpcUser(pub String);
+
+pub struct RpcAuth {
+    credentials: Vec<Credential>,
+    whitelists: HashMap<String, HashSet<String>>,
+    whitelist_default: bool,
+    cookie_path: Option<PathBuf>,
+}
+
+impl RpcAuth {
+    /// Set up credentials. Writes a fresh cookie file unless `-rpcpassword` is set.
+    pub fn new(config: &RpcAuthConfig) -> Result<Self> {
+        let mut credentials = Vec::new();
+        let mut cookie_path = None;
+
+        match (&config.rpcuser, &config.rpcpassword) {
+            (Some(user), Some(password)) if !password.is_empty() => {
+                if user.contains(':') {
+                    anyhow::bail!("-rpcuser must not contain ':'");
+                }
+                credentials.push(Credential::Plain {
+                    user: user.clone(),
+                    password: password.clone(),
+                });
+            }
+            _ => {
+                let path = config
+                    .cookie_file
+                    .clone()
+                    .unwrap_or_else(|| config.datadir.join(COOKIE_FILE));
+                let password = generate_cookie(&path)?;
+                eprintln!("[rpc] Generated RPC authentication cookie {}", path.display());
+                credentials.push(Credential::Plain {
+                    user: COOKIE_USER.to_string(),
+                    password,
+                });
+                cookie_path = Some(path);
+            }
+        }
+
+        for entry in &config.rpcauth {
+            credentials.push(parse_rpcauth(entry)?);
+        }
+
+        let mut whitelists: HashMap<String, HashSet<String>> = HashMap::new();
+        for entry in &config.rpcwhitelist {
+            let (user, methods) = parse_whitelist(entry)?;
+            // Multiple entries for the same user intersect, as in Bitcoin Core
+            match whitelists.get_mut(&user) {
+                Some(existing) => existing.retain(|m| methods.contains(m)),
+                None => {
+                    whitelists.insert(user, methods);
+                }
+            }
+        }
+        let whitelist_default = config
+            .rpcwhitelistdefault
+            .unwrap_or(!config.rpcwhitelist.is_empty());
+
+        Ok(Self {
+            credentials,
+            whitelists,
+            whitelist_default,
+            cookie_path,
+        })
+    }
+
+    /// Validate an `Authorization` header value, returning the username
+    pub fn check(&self, authorization: &str) -> Option<String> {
+        let encoded = authorization.strip_prefix("Basic ")?.trim();
+        let decoded = base64::engine::general_purpose::STANDARD.decode(encoded).ok()?;
+        let decoded = String::from_utf8(decoded).ok()?;
+        let (user, password) = decoded.split_once(':')?;
+
+        let matched = self.credentials.iter().any(|cred| match cred {
+            Credential::Plain { user: u, password: p } => {
+                constant_time_eq(u.as_bytes(), user.as_bytes())
+                    & constant_time_eq(p.as_bytes(), password.as_bytes())
+            }
+            Credential::Salted { user: u, salt, hash } => {
+                constant_time_eq(u.as_bytes(), user.as_bytes())
+                    & constant_time_eq(hash.as_bytes(), hmac_password(salt, password).as_bytes())
+            }
+        });
+
+        matched.then(|| user.to_string())
+    }
+
+    /// Whether `user` may call `method` under the configured whitelists
+    pub fn is_method_allowed(&self, user: &str, method: &str) -> bool {
+        match self.whitelists.get(user) {
+            Some(methods) => methods.contains(method),
+            None => !self.whitelist_default,
+        }
+    }
+
+    /// Delete the cookie file (on shutdown)
+    pub fn remove_cookie(&self) {
+        if let Some(path) = &self.cookie_path {
+            if std::fs::remove_file(path).is_ok() {
+                eprintln!("[rpc] Removed RPC authentication cookie {}", path.display());
+            }
+        }
+    }
+}
+
+/// Write `__cookie__:<random>` to `path` (via a temp file) and return the password
+fn generate_cookie(path: &PathBuf) -> Result<String> {
+    let password = hex::encode(rand::thread_rng().gen::<[u8; 32]>());
+    let tmp = path.with_extension("tmp");
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).ok();
+    }
+    {
+        use std::io::Write;
+        let mut opts = std::fs::OpenOptions::new();
+        opts.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            opts.mode(0o600);
+        }
+        let mut file = opts
+            .open(&tmp)
+            .with_context(|| format!("unable to open cookie file {}", tmp.display()))?;
+        write!(file, "{}:{}", COOKIE_USER, password)?;
+    }
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("unable to rename cookie file to {}", path.display()))?;
+
+    Ok(password)
+}
+
+/// Parse `<user>:<salt>$<hash>`
+fn parse_rpcauth(entry: &str) -> Result<Credential> {
+    let (user, rest) = entry
+        .split_once(':')
+        .ok_or_else(|| anyhow!("invalid -rpcauth argument '{}'", entry))?;
+    let (salt, hash) = rest
+        .split_once('$')
+        .ok_or_else(|| anyhow!("invalid -rpcauth argument '{}'", entry))?;
+    if user.is_empty() || salt.is_empty() || hash.len() != 64 {
+        anyhow::bail!("invalid -rpcauth argument '{}'", entry);
+    }
+    Ok(Credential::Salted {
+        user: user.to_string(),
+        salt: salt.to_string(),
+        hash: hash.to_lowercase(),
+    })
+}
+
+/// Parse `<user>:<method>,<method>,...` (the method list may be empty)
+fn parse_whitelist(entry: &str) -> Result<(String, HashSet<String>)> {
+    let (user, methods) = entry
+        .split_once(':')
+        .ok_or_else(|| anyhow!("invalid -rpcwhitelist argument '{}'", entry))?;
+    let methods = methods
+        .split(',')
+        .map(|m| m.trim())
+        .filter(|m| !m.is_empty())
+        .map(|m| m.to_string())
+        .collect();
+    Ok((user.to_string(), methods))
+}
+
+/// Hex HMAC-SHA256 of `password` keyed by `salt` (share/rpcauth/rpcauth.py)
+fn hmac_password(salt: &str, password: &str) -> String {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(salt.as_bytes());
+    engine.input(password.as_bytes());
+    hex::encode(hmac::Hmac::<sha256::Hash>::from_engine(engine).to_byte_array())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Axum middleware rejecting requests without valid credentials
+pub async fn require_auth<B>(
+    State(state): State<AppState>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let user = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| state.auth.check(v));
+
+    match user {
+        Some(user) => {
+            req.extensions_mut().insert(RpcUser(user));
+            next.run(req).await
+        }
+        None => {
+            eprintln!("[rpc] ThreadRPCServer incorrect password attempt");
+            // Deter brute-forcing short passwords, as Bitcoin Core does
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Basic realm=\"jsonrpc\"")],
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn basic(user: &str, password: &str) -> String {
+        format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, password))
+        )
+    }
+
+    fn config(dir: &std::path::Path) -> RpcAuthConfig {
+        RpcAuthConfig {
+            datadir: dir.to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_hmac_password() {
+        assert_eq!(
+            hmac_password("cb77f0957de88ff388cf817ddbc7273", "secretpass"),
+            "3aa345de43f8cb2d3dbc0dba684be768525e483e2eb20a0998da77ea1c42e594"
+        );
+    }
+
+    #[test]
+    fn test_cookie_auth() {
+        let dir = tempfile::tempdir().unwrap();
+        let auth = RpcAuth::new(&config(dir.path())).unwrap();
+
+        let contents = std::fs::read_to_string(dir.path().join(".cookie")).unwrap();
+        let (user, password) = contents.split_once(':').unwrap();
+        assert_eq!(user, COOKIE_USER);
+        assert_eq!(auth.check(&basic(user, password)).as_deref(), Some(COOKIE_USER));
+        assert!(auth.check(&basic(user, "wrong")).is_none());
+
+        auth.remove_cookie();
+        assert!(!dir.path().join(".cookie").exists());
+    }
+
+    #[test]
+    fn test_rpcauth_and_plain() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config(dir.path());
+        cfg.rpcuser = Some("alice".into());
+        cfg.rpcpassword = Some("pw".into());
+        cfg.rpcauth = vec![
+            "bob:cb77f0957de88ff388cf817ddbc7273$3aa345de43f8cb2d3dbc0dba684be768525e483e2eb20a0998da77ea1c42e594".into(),
+        ];
+        let auth = RpcAuth::new(&cfg).unwrap();
+
+        // No cookie when -rpcpassword is set
+        assert!(!dir.path().join(".cookie").exists());
+        assert_eq!(auth.check(&basic("alice", "pw")).as_deref(), Some("alice"));
+        assert_eq!(auth.check(&basic("bob", "secretpass")).as_deref(), Some("bob"));
+        assert!(auth.check(&basic("bob", "pw")).is_none());
+        assert!(auth.check("Bearer abc").is_none());
+    }
+
+    #[test]
+    fn test_invalid_rpcauth() {
+        assert!(parse_rpcauth("bob").is_err());
+        assert!(parse_rpcauth("bob:salt").is_err());
+        assert!(parse_rpcauth("bob:salt$abc").is_err());
+    }
+
+    #[test]
+    fn test_whitelists() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config(dir.path());
+        cfg.rpcwhitelist = vec![
+            "alice:getblockcount,getbestblockhash".into(),
+            "alice:getblockcount,stop".into(),
+        ];
+        let auth = RpcAuth::new(&cfg).unwrap();
+
+        assert!(auth.is_method_allowed("alice", "getblockcount"));
+        assert!(!auth.is_method_allowed("alice", "getbestblockhash"));
+        assert!(!auth.is_method_allowed("alice", "stop"));
+        // Any whitelist makes unlisted users default to nothing
+        assert!(!auth.is_method_allowed("bob", "getblockcount"));
+
+        cfg.rpcwhitelistdefault = Some(false);
+        let auth = RpcAuth::new(&cfg).unwrap();
+        assert!(auth.is_method_allowed("bob", "stop"));
+    }
+}
@@ -1,16 +1,80 @@
 // src/rpc/blockchain.rs
-use anyhow::Result;
-use axum::{extract::State, http::StatusCode, Json};
-use bitcoin::BlockHash;
+use bitcoin::{BlockHash, OutPoint};
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
+use std::path::Path;
 use std::sync::Arc;
 
-use crate::kernel::Kernel;
+use crate::index::coins::{Coin, MEMPOOL_HEIGHT};
+use crate::index::{self, BlockIndex};
+use crate::kernel::{BlockInfo, Kernel};
+use crate::mempool::{persist, FeeRate};
 
 // Import AppState from mod.rs instead of defining it here
+use super::protocol::{RpcError, RpcResult, RPC_DATABASE_ERROR};
+use super::server::{blocking, parse_hash, Params, RpcTable};
+use super::util::{block_to_json, btc, header_to_json, mempool_entry_to_json, script_pubkey_to_json};
 use super::AppState;
 
+// ============================================================================
+// Shared accessors (also used by the REST interface)
+// ============================================================================
+
+fn internal(e: anyhow::Error) -> RpcError {
+    RpcError::internal(e.to_string())
+}
+
+/// Block index entry for `hash`, or RPC_INVALID_ADDRESS_OR_KEY
+pub fn lookup_block(k: &Kernel, hash: &BlockHash) -> RpcResult<BlockInfo> {
+    k.get_block_info(hash)
+        .map_err(internal)?
+        .ok_or_else(|| RpcError::invalid_address_or_key("Block not found"))
+}
+
+/// Successor of `info` on the active chain
+pub fn next_block_hash(k: &Kernel, info: &BlockInfo) -> Option<BlockHash> {
+    if !info.in_active_chain {
+        return None;
+    }
+    k.get_block_hash(info.height + 1).ok()
+}
+
+/// Raw block data for `hash`
+pub fn read_block(k: &Kernel, hash: &BlockHash) -> RpcResult<Vec<u8>> {
+    k.read_block(hash)
+        .map_err(internal)?
+        .ok_or_else(|| RpcError::misc("Block not available (pruned data)"))
+}
+
+/// Look up an unspent output in the coins index, optionally with the mempool applied on top
+pub fn lookup_coin(state: &AppState, outpoint: &OutPoint, include_mempool: bool) -> RpcResult<Option<Coin>> {
+    if include_mempool {
+        if state.mempool.is_spent(outpoint) {
+            return Ok(None);
+        }
+        if let Some(tx) = state.mempool.get_tx(&outpoint.txid) {
+            return Ok(tx.output.get(outpoint.vout as usize).map(|output| Coin {
+                output: output.clone(),
+                height: MEMPOOL_HEIGHT,
+                is_coinbase: false,
+            }));
+        }
+    }
+    state
+        .coins
+        .get_coin(outpoint)
+        .map_err(|e| RpcError::new(RPC_DATABASE_ERROR, e.to_string()))
+}
+
+/// Verbosity given as a number or a bool (true = 1)
+pub fn verbosity(params: &Params, idx: usize, name: &str, default: u8) -> RpcResult<u8> {
+    match params.get(idx) {
+        None | Some(Value::Null) => Ok(default),
+        Some(Value::Bool(b)) => Ok(*b as u8),
+        Some(_) => params.required(idx, name),
+    }
+}
+
 // ============================================================================
 // Blockchain RPC Methods
 // ============================================================================
@@ -27,15 +91,16 @@ pub struct BlockchainInfo {
     pub verificationprogress: f64,
     pub initialblockdownload: bool,
     pub size_on_disk: u64,
+    /// Block challenge, on signet only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signet_challenge: Option<String>,
 }
 
-pub async fn getblockchaininfo(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
+pub async fn getblockchaininfo(state: AppState, _params: Params) -> RpcResult {
     let k = state.kernel.clone();
-    
-    let result = tokio::task::spawn_blocking(move || {
-        let height = k.get_height().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let result = blocking(move || {
+        let height = k.get_height().map_err(|e| RpcError::internal(e.to_string()))?;
         let best_blockhash = if height >= 0 {
             k.get_best_block_hash()
                 .map(|h| h.to_string())
@@ -45,7 +110,7 @@ pub async fn getblockchaininfo(
         };
 
         let info = BlockchainInfo {
-            chain: "signet".to_string(),
+            chain: k.chain_name().to_string(),
             blocks: height,
             headers: height,
             bestblockhash: best_blockhash,
@@ -54,124 +119,108 @@ pub async fn getblockchaininfo(
             verificationprogress: 1.0,
             initialblockdownload: false,
             size_on_disk: 0,
+            signet_challenge: k.signet_challenge().map(|c| hex::encode(c.as_bytes())),
         };
 
-        Ok::<_, StatusCode>(info)
+        Ok(info)
     })
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+    .await?;
 
-    Ok(Json(json!(result)))
+    Ok(json!(result))
 }
 
 /// getbestblockhash
-pub async fn getbestblockhash(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
+pub async fn getbestblockhash(state: AppState, _params: Params) -> RpcResult {
     let k = state.kernel.clone();
-    
-    let hash = tokio::task::spawn_blocking(move || {
+
+    let hash = blocking(move || {
         k.get_best_block_hash()
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+            .map_err(|e| RpcError::internal(e.to_string()))
     })
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+    .await?;
 
-    Ok(Json(json!({ "result": hash.to_string() })))
+    Ok(json!(hash.to_string()))
 }
 
 /// getblockcount
-pub async fn getblockcount(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
+pub async fn getblockcount(state: AppState, _params: Params) -> RpcResult {
     let k = state.kernel.clone();
-    
-    let height = tokio::task::spawn_blocking(move || {
+
+    let height = blocking(move || {
         k.get_height()
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+            .map_err(|e| RpcError::internal(e.to_string()))
     })
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
-
-    Ok(Json(json!({ "result": height })))
-}
+    .await?;
 
-/// getblockhash
-#[derive(Deserialize)]
-pub struct GetBlockHashParams {
-    pub height: i32,
+    Ok(json!(height))
 }
 
-pub async fn getblockhash(
-    State(state): State<AppState>,
-    Json(params): Json<GetBlockHashParams>,
-) -> Result<Json<Value>, StatusCode> {
+/// getblockhash height
+pub async fn getblockhash(state: AppState, params: Params) -> RpcResult {
     let k = state.kernel.clone();
-    let height = params.height;
-    
-    let hash = tokio::task::spawn_blocking(move || {
-        k.get_block_hash(height)
-            .map_err(|_| StatusCode::NOT_FOUND)
+    let height: i64 = params.required(0, "height")?;
+
+    let hash = blocking(move || {
+        let tip = k.get_height().map_err(|e| RpcError::internal(e.to_string()))?;
+        if height < 0 || height > tip as i64 {
+            return Err(RpcError::invalid_parameter("Block height out of range"));
+        }
+        k.get_block_hash(height as i32)
+            .map_err(|_| RpcError::invalid_parameter("Block height out of range"))
     })
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+    .await?;
 
-    Ok(Json(json!({ "result": hash.to_string() })))
+    Ok(json!(hash.to_string()))
 }
 
-/// getblock
-#[derive(Deserialize)]
-pub struct GetBlockParams {
-    pub blockhash: String,
-    #[serde(default)]
-    pub verbosity: u8, // 0=hex, 1=json, 2=json+tx
-}
+/// getblock "blockhash" ( verbosity )
+/// verbosity: 0=hex, 1=json, 2=json+tx
+pub async fn getblock(state: AppState, params: Params) -> RpcResult {
+    let blockhash: String = params.required(0, "blockhash")?;
+    let hash: BlockHash = parse_hash(&blockhash, "blockhash")?;
+    let verbosity = verbosity(&params, 1, "verbosity", 1)?;
+    let k = state.kernel.clone();
 
-pub async fn getblock(
-    State(state): State<AppState>,
-    Json(params): Json<GetBlockParams>,
-) -> Result<Json<Value>, StatusCode> {
-    // Parse block hash
-    let blockhash = params.blockhash.parse::<BlockHash>()
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    blocking(move || {
+        let info = lookup_block(&k, &hash)?;
+        let raw = read_block(&k, &hash)?;
+        if verbosity == 0 {
+            return Ok(json!(hex::encode(raw)));
+        }
 
-    // TODO: Implement actual block retrieval via kernel
-    // For now, return placeholder
-    Ok(Json(json!({
-        "error": "getblock not yet implemented",
-        "blockhash": blockhash.to_string()
-    })))
+        let block: bitcoin::Block = bitcoin::consensus::deserialize(&raw)
+            .map_err(|e| RpcError::internal(format!("Block deserialization failed: {}", e)))?;
+        let tip = k.get_height().map_err(internal)?;
+        let next = next_block_hash(&k, &info);
+        Ok(block_to_json(&block, &info, tip, next, k.network(), verbosity >= 2))
+    })
+    .await
 }
 
-/// getblockheader
-#[derive(Deserialize)]
-pub struct GetBlockHeaderParams {
-    pub blockhash: String,
-    #[serde(default)]
-    pub verbose: bool,
-}
+/// getblockheader "blockhash" ( verbose )
+pub async fn getblockheader(state: AppState, params: Params) -> RpcResult {
+    let blockhash: String = params.required(0, "blockhash")?;
+    let hash: BlockHash = parse_hash(&blockhash, "blockhash")?;
+    let verbose: bool = params.optional_or(1, "verbose", true)?;
+    let k = state.kernel.clone();
 
-pub async fn getblockheader(
-    State(state): State<AppState>,
-    Json(params): Json<GetBlockHeaderParams>,
-) -> Result<Json<Value>, StatusCode> {
-    let blockhash = params.blockhash.parse::<BlockHash>()
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    blocking(move || {
+        let info = lookup_block(&k, &hash)?;
+        let raw = read_block(&k, &hash)?;
+        if !verbose {
+            return Ok(json!(hex::encode(&raw[..80.min(raw.len())])));
+        }
 
-    // TODO: Implement via kernel
-    Ok(Json(json!({
-        "error": "getblockheader not yet implemented",
-        "blockhash": blockhash.to_string()
-    })))
+        let block: bitcoin::Block = bitcoin::consensus::deserialize(&raw)
+            .map_err(|e| RpcError::internal(format!("Block deserialization failed: {}", e)))?;
+        let tip = k.get_height().map_err(internal)?;
+        let next = next_block_hash(&k, &info);
+        Ok(header_to_json(&block.header, &info, tip, next, Some(block.txdata.len())))
+    })
+    .await
 }
 
 /// getblockstats
-#[derive(Deserialize)]
-pub struct GetBlockStatsParams {
-    pub hash_or_height: serde_json::Value,
-    #[serde(default)]
-    pub stats: Option<Vec<String>>,
-}
 
 #[derive(Serialize)]
 pub struct BlockStats {
@@ -202,14 +251,12 @@ pub struct BlockStats {
     pub utxo_size_inc: i64,
 }
 
-pub async fn getblockstats(
-    State(state): State<AppState>,
-    Json(params): Json<GetBlockStatsParams>,
-) -> Result<Json<Value>, StatusCode> {
+pub async fn getblockstats(_state: AppState, params: Params) -> RpcResult {
+    let _hash_or_height: Value = params.required(0, "hash_or_height")?;
+    let _stats: Option<Vec<String>> = params.optional(1, "stats")?;
+
     // TODO: Implement via kernel
-    Ok(Json(json!({
-        "error": "getblockstats not yet implemented"
-    })))
+    Err(RpcError::misc("getblockstats not yet implemented"))
 }
 
 /// getchaintips
@@ -221,13 +268,11 @@ pub struct ChainTip {
     pub status: String, // "active", "valid-fork", "invalid", etc.
 }
 
-pub async fn getchaintips(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
+pub async fn getchaintips(state: AppState, _params: Params) -> RpcResult {
     let k = state.kernel.clone();
-    
-    let result = tokio::task::spawn_blocking(move || {
-        let height = k.get_height().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let result = blocking(move || {
+        let height = k.get_height().map_err(|e| RpcError::internal(e.to_string()))?;
         let hash = k.get_best_block_hash()
             .map(|h| h.to_string())
             .unwrap_or_default();
@@ -239,22 +284,14 @@ pub async fn getchaintips(
             status: "active".to_string(),
         }];
 
-        Ok::<_, StatusCode>(tips)
+        Ok(tips)
     })
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+    .await?;
 
-    Ok(Json(json!(result)))
+    Ok(json!(result))
 }
 
-/// getchaintxstats
-#[derive(Deserialize)]
-pub struct GetChainTxStatsParams {
-    #[serde(default)]
-    pub nblocks: Option<i32>,
-    #[serde(default)]
-    pub blockhash: Option<String>,
-}
+/// getchaintxstats ( nblocks "blockhash" )
 
 #[derive(Serialize)]
 pub struct ChainTxStats {
@@ -267,22 +304,18 @@ pub struct ChainTxStats {
     pub txrate: f64,
 }
 
-pub async fn getchaintxstats(
-    State(state): State<AppState>,
-    Json(params): Json<GetChainTxStatsParams>,
-) -> Result<Json<Value>, StatusCode> {
+pub async fn getchaintxstats(_state: AppState, params: Params) -> RpcResult {
+    let _nblocks: Option<i32> = params.optional(0, "nblocks")?;
+    let _blockhash: Option<String> = params.optional(1, "blockhash")?;
+
     // TODO: Implement via kernel
-    Ok(Json(json!({
-        "error": "getchaintxstats not yet implemented"
-    })))
+    Err(RpcError::misc("getchaintxstats not yet implemented"))
 }
 
 /// getdifficulty
-pub async fn getdifficulty(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
+pub async fn getdifficulty(_state: AppState, _params: Params) -> RpcResult {
     // TODO: Calculate from block header
-    Ok(Json(json!({ "result": 1.0 })))
+    Ok(json!(1.0))
 }
 
 /// getmempoolinfo
@@ -293,95 +326,123 @@ pub struct MempoolInfo {
     pub bytes: usize,
     pub usage: usize,
     pub maxmempool: usize,
-    pub mempoolminfee: f64,
-    pub minrelaytxfee: f64,
+    pub mempoolminfee: FeeRate,
+    pub minrelaytxfee: FeeRate,
+    pub fullrbf: bool,
+    pub unbroadcastcount: usize,
 }
 
-pub async fn getmempoolinfo(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
+pub async fn getmempoolinfo(state: AppState, _params: Params) -> RpcResult {
     let stats = state.mempool.get_stats();
 
     let info = MempoolInfo {
-        loaded: true,
+        loaded: state.mempool.is_loaded(),
         size: stats.size,
         bytes: stats.bytes,
         usage: stats.usage,
         maxmempool: stats.max_mempool,
         mempoolminfee: stats.mempool_min_fee,
         minrelaytxfee: stats.min_relay_tx_fee,
+        fullrbf: state.mempool.policy().full_rbf,
+        unbroadcastcount: state.mempool.unbroadcast_txids().len(),
     };
 
-    Ok(Json(json!(info)))
+    Ok(json!(info))
+}
+
+/// savemempool
+///
+/// Dumps the mempool to mempool.dat in the data directory.
+pub async fn savemempool(state: AppState, _params: Params) -> RpcResult {
+    if !state.mempool.is_loaded() {
+        return Err(RpcError::misc("The mempool was not loaded yet"));
+    }
+    let path = state.datadir.join(persist::MEMPOOL_FILENAME);
+    let p = path.clone();
+    blocking(move || {
+        persist::dump_mempool(&state.mempool, &p).map_err(|e| RpcError::misc(format!("Unable to dump mempool to disk: {e:#}")))
+    })
+    .await?;
+    Ok(json!({ "filename": path.display().to_string() }))
 }
 
-/// getrawmempool
-#[derive(Deserialize)]
-pub struct GetRawMempoolParams {
-    #[serde(default)]
-    pub verbose: bool,
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ImportMempoolOptions {
+    use_current_time: Option<bool>,
+    apply_fee_delta_priority: Option<bool>,
+    apply_unbroadcast_set: Option<bool>,
 }
 
-pub async fn getrawmempool(
-    State(state): State<AppState>,
-    Json(params): Json<GetRawMempoolParams>,
-) -> Result<Json<Value>, StatusCode> {
+/// importmempool "filepath" ( options )
+///
+/// Loads transactions from a mempool.dat file, re-validating each one.
+pub async fn importmempool(state: AppState, params: Params) -> RpcResult {
+    let filepath: String = params.required(0, "filepath")?;
+    let options: ImportMempoolOptions = params.optional_or(1, "options", ImportMempoolOptions::default())?;
+    let opts = persist::LoadOptions {
+        use_current_time: options.use_current_time.unwrap_or(true),
+        apply_fee_delta: options.apply_fee_delta_priority.unwrap_or(false),
+        apply_unbroadcast: options.apply_unbroadcast_set.unwrap_or(false),
+    };
+
+    blocking(move || {
+        persist::load_mempool(&state.mempool, Path::new(&filepath), opts)
+            .map_err(|e| RpcError::misc(format!("Unable to import mempool file, see debug.log for details. ({e:#})")))
+    })
+    .await?;
+    Ok(json!({}))
+}
+
+/// getrawmempool ( verbose )
+pub async fn getrawmempool(state: AppState, params: Params) -> RpcResult {
+    let verbose: bool = params.optional_or(0, "verbose", false)?;
+    let mempool_sequence: bool = params.optional_or(1, "mempool_sequence", false)?;
+    if verbose && mempool_sequence {
+        return Err(RpcError::invalid_parameter("Verbose results cannot contain mempool sequence values."));
+    }
+    let sequence = state.mempool.sequence();
     let txids = state.mempool.get_all_txids();
 
-    if params.verbose {
-        // TODO: Return detailed info for each transaction
+    if mempool_sequence {
+        let txid_strings: Vec<String> = txids.iter().map(|t| t.to_string()).collect();
+        Ok(json!({ "txids": txid_strings, "mempool_sequence": sequence }))
+    } else if verbose {
         let mut detailed = serde_json::Map::new();
         for txid in txids {
-            detailed.insert(
-                txid.to_string(),
-                json!({
-                    "vsize": 0,
-                    "fee": 0.0,
-                    "time": 0,
-                })
-            );
+            if let Some(entry) = state.mempool.get_entry(&txid) {
+                detailed.insert(txid.to_string(), mempool_entry_to_json(&entry, &state.mempool));
+            }
         }
-        Ok(Json(json!(detailed)))
+        Ok(json!(detailed))
     } else {
         let txid_strings: Vec<String> = txids.iter().map(|t| t.to_string()).collect();
-        Ok(Json(json!(txid_strings)))
+        Ok(json!(txid_strings))
     }
 }
 
-/// gettxout
-#[derive(Deserialize)]
-pub struct GetTxOutParams {
-    pub txid: String,
-    pub n: u32,
-    #[serde(default)]
-    pub include_mempool: bool,
-}
+/// gettxout "txid" n ( include_mempool )
+pub async fn gettxout(state: AppState, params: Params) -> RpcResult {
+    let txid: String = params.required(0, "txid")?;
+    let txid: bitcoin::Txid = parse_hash(&txid, "txid")?;
+    let n: u32 = params.required(1, "n")?;
+    let include_mempool: bool = params.optional_or(2, "include_mempool", true)?;
 
-#[derive(Serialize)]
-pub struct TxOut {
-    pub bestblock: String,
-    pub confirmations: i32,
-    pub value: f64,
-    pub scriptPubKey: ScriptPubKey,
-    pub coinbase: bool,
-}
-
-#[derive(Serialize)]
-pub struct ScriptPubKey {
-    pub asm: String,
-    pub hex: String,
-    #[serde(rename = "type")]
-    pub type_: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub address: Option<String>,
-}
+    let Some(coin) = lookup_coin(&state, &OutPoint::new(txid, n), include_mempool)? else {
+        return Ok(Value::Null);
+    };
+    let Some((best_height, best_hash)) = state.coins.best_block() else {
+        return Ok(Value::Null);
+    };
+    let confirmations = if coin.height == MEMPOOL_HEIGHT { 0 } else { best_height - coin.height as i32 + 1 };
 
-pub async fn gettxout(
-    State(state): State<AppState>,
-    Json(params): Json<GetTxOutParams>,
-) -> Result<Json<Value>, StatusCode> {
-    // TODO: Implement via kernel UTXO set
-    Ok(Json(json!(null)))
+    Ok(json!({
+        "bestblock": best_hash.to_string(),
+        "confirmations": confirmations,
+        "value": btc(coin.output.value.to_sat()),
+        "scriptPubKey": script_pubkey_to_json(&coin.output.script_pubkey, state.kernel.network()),
+        "coinbase": coin.is_coinbase,
+    }))
 }
 
 /// gettxoutsetinfo
@@ -397,13 +458,11 @@ pub struct TxOutSetInfo {
     pub total_amount: f64,
 }
 
-pub async fn gettxoutsetinfo(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
+pub async fn gettxoutsetinfo(state: AppState, _params: Params) -> RpcResult {
     let k = state.kernel.clone();
-    
-    let result = tokio::task::spawn_blocking(move || {
-        let height = k.get_height().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let result = blocking(move || {
+        let height = k.get_height().map_err(|e| RpcError::internal(e.to_string()))?;
         let bestblock = k.get_best_block_hash()
             .map(|h| h.to_string())
             .unwrap_or_default();
@@ -419,38 +478,60 @@ pub async fn gettxoutsetinfo(
             total_amount: 0.0,
         };
 
-        Ok::<_, StatusCode>(info)
+        Ok(info)
     })
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+    .await?;
 
-    Ok(Json(json!(result)))
+    Ok(json!(result))
 }
 
-/// verifychain
-#[derive(Deserialize)]
-pub struct VerifyChainParams {
-    #[serde(default = "default_checklevel")]
-    pub checklevel: u8,
-    #[serde(default = "default_nblocks")]
-    pub nblocks: i32,
+/// getindexinfo ( "index_name" )
+///
+/// Height and sync state of each optional index that is enabled, or only
+/// of `index_name`.
+pub async fn getindexinfo(state: AppState, params: Params) -> RpcResult {
+    let index_name: Option<String> = params.optional(0, "index_name")?;
+    let mut indexes: Vec<Arc<dyn BlockIndex>> = Vec::new();
+    if let Some(txindex) = &state.txindex {
+        indexes.push(txindex.clone());
+    }
+    if let Some(addrindex) = &state.addrindex {
+        indexes.push(addrindex.clone());
+    }
+
+    blocking(move || {
+        let mut result = Map::new();
+        for idx in indexes {
+            if index_name.as_deref().is_some_and(|name| name != idx.name()) {
+                continue;
+            }
+            result.insert(
+                idx.name().to_string(),
+                json!({
+                    "synced": index::is_synced(idx.as_ref(), &state.kernel),
+                    "best_block_height": idx.best_block().map_or(0, |(height, _)| height),
+                }),
+            );
+        }
+        Ok(Value::Object(result))
+    })
+    .await
 }
 
-fn default_checklevel() -> u8 { 3 }
-fn default_nblocks() -> i32 { 6 }
+/// verifychain ( checklevel nblocks )
+pub async fn verifychain(_state: AppState, params: Params) -> RpcResult {
+    let checklevel: u8 = params.optional_or(0, "checklevel", 3)?;
+    let _nblocks: i32 = params.optional_or(1, "nblocks", 6)?;
+    if checklevel > 4 {
+        return Err(RpcError::invalid_parameter("checklevel must be between 0 and 4"));
+    }
 
-pub async fn verifychain(
-    State(state): State<AppState>,
-    Json(params): Json<VerifyChainParams>,
-) -> Result<Json<Value>, StatusCode> {
     // TODO: Implement via kernel
-    Ok(Json(json!({ "result": true })))
+    Ok(json!(true))
 }
 
 /// stop - Gracefully shutdown the node
-pub async fn stop(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
+pub async fn stop(state: AppState, _params: Params) -> RpcResult {
     eprintln!("[rpc] 🛑 Received shutdown request via RPC");
 
     // Take the sender from the mutex to trigger shutdown
@@ -459,10 +540,10 @@ pub async fn stop(
         // Send shutdown signal
         let _ = tx.send(());
         eprintln!("[rpc] ✅ Shutdown signal sent - node will stop gracefully");
-        Ok(Json(json!({ "result": "Bitcoin server stopping" })))
+        Ok(json!("Bitcoin server stopping"))
     } else {
         eprintln!("[rpc] ⚠️  Shutdown already in progress");
-        Ok(Json(json!({ "result": "Shutdown already in progress" })))
+        Ok(json!("Shutdown already in progress"))
     }
 }
 
@@ -477,20 +558,14 @@ pub async fn stop(
 /// Bitcoin Core calls FlushStateToDisk() automatically every 50-70 minutes
 /// or when cache exceeds limits. Since we can't do that, call this manually
 /// every 100 blocks or so to ensure data persistence.
-pub async fn flushstate(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
+pub async fn flushstate(state: AppState, _params: Params) -> RpcResult {
     eprintln!("[rpc] 💾 Received flush request - forcing state to disk...");
     eprintln!("[rpc] ⚠️  WARNING: This will temporarily pause block processing!");
 
     let k = state.kernel.clone();
 
     // Get height before flush
-    let height_before = tokio::task::spawn_blocking(move || {
-        k.get_height().unwrap_or(-1)
-    })
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let height_before = blocking(move || Ok(k.get_height().unwrap_or(-1))).await?;
 
     eprintln!("[rpc] Current height: {}", height_before);
     eprintln!("[rpc] ℹ️  To flush, the Kernel will be dropped (triggers ForceFlushStateToDisk)");
@@ -503,10 +578,31 @@ pub async fn flushstate(
     eprintln!("[rpc] ✅ Flush complete - block index and chainstate written to disk");
     eprintln!("[rpc] ⚠️  NOTE: Kernel has been destroyed. Restart node to continue.");
 
-    Ok(Json(json!({
-        "result": {
-            "height": height_before,
-            "message": "State flushed to disk. Restart node to continue syncing."
-        }
-    })))
+    Ok(json!({
+        "height": height_before,
+        "message": "State flushed to disk. Restart node to continue syncing."
+    }))
+}
+
+pub fn register(table: &mut RpcTable) {
+    table.register("blockchain", "getblockchaininfo", &[], |s, p| Box::pin(getblockchaininfo(s, p)));
+    table.register("blockchain", "getbestblockhash", &[], |s, p| Box::pin(getbestblockhash(s, p)));
+    table.register("blockchain", "getblockcount", &[], |s, p| Box::pin(getblockcount(s, p)));
+    table.register("blockchain", "getblockhash", &["height"], |s, p| Box::pin(getblockhash(s, p)));
+    table.register("blockchain", "getblock", &["blockhash", "verbosity"], |s, p| Box::pin(getblock(s, p)));
+    table.register("blockchain", "getblockheader", &["blockhash", "verbose"], |s, p| Box::pin(getblockheader(s, p)));
+    table.register("blockchain", "getblockstats", &["hash_or_height", "stats"], |s, p| Box::pin(getblockstats(s, p)));
+    table.register("blockchain", "getchaintips", &[], |s, p| Box::pin(getchaintips(s, p)));
+    table.register("blockchain", "getchaintxstats", &["nblocks", "blockhash"], |s, p| Box::pin(getchaintxstats(s, p)));
+    table.register("blockchain", "getdifficulty", &[], |s, p| Box::pin(getdifficulty(s, p)));
+    table.register("blockchain", "getmempoolinfo", &[], |s, p| Box::pin(getmempoolinfo(s, p)));
+    table.register("blockchain", "getrawmempool", &["verbose", "mempool_sequence"], |s, p| Box::pin(getrawmempool(s, p)));
+    table.register("blockchain", "savemempool", &[], |s, p| Box::pin(savemempool(s, p)));
+    table.register("blockchain", "importmempool", &["filepath", "options"], |s, p| Box::pin(importmempool(s, p)));
+    table.register("blockchain", "gettxout", &["txid", "n", "include_mempool"], |s, p| Box::pin(gettxout(s, p)));
+    table.register("blockchain", "gettxoutsetinfo", &["hash_type", "hash_or_height", "use_index"], |s, p| Box::pin(gettxoutsetinfo(s, p)));
+    table.register("blockchain", "verifychain", &["checklevel", "nblocks"], |s, p| Box::pin(verifychain(s, p)));
+    table.register("util", "getindexinfo", &["index_name"], |s, p| Box::pin(getindexinfo(s, p)));
+    table.register("control", "stop", &[], |s, p| Box::pin(stop(s, p)));
+    table.register("hidden", "flushstate", &[], |s, p| Box::pin(flushstate(s, p)));
 }
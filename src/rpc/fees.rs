@@ -0,0 +1,179 @@
+// src/rpc/fees.rs
+use serde_json::{json, Map, Value};
+
+use crate::mempool::fees::{EstimatorBucket, FeeEstimateHorizon};
+use crate::mempool::miner::DEFAULT_BLOCK_MAX_WEIGHT;
+use crate::mempool::projection::{self, DEFAULT_HISTOGRAM_BIN_VSIZE};
+
+use super::protocol::{RpcError, RpcResult};
+use super::server::{blocking, Params, RpcTable};
+use super::util::btc;
+use super::AppState;
+
+/// Success threshold estimaterawfee uses by default
+const DEFAULT_RAW_FEE_THRESHOLD: f64 = 0.95;
+
+/// Blocks getprojectedblocks fills by default, and at most
+const DEFAULT_PROJECTED_BLOCKS: usize = 8;
+const MAX_PROJECTED_BLOCKS: usize = 100;
+
+/// Confirmation target between 1 and `max_target` (Bitcoin Core: ParseConfirmTarget)
+fn parse_conf_target(params: &Params, max_target: u32) -> RpcResult<u32> {
+    let target: i64 = params.required(0, "conf_target")?;
+    if target < 1 || target > max_target as i64 {
+        return Err(RpcError::invalid_parameter(format!(
+            "Invalid conf_target, must be between 1 and {}",
+            max_target
+        )));
+    }
+    Ok(target as u32)
+}
+
+/// estimatesmartfee conf_target ( "estimate_mode" )
+///
+/// Feerate likely to confirm within `conf_target` blocks, never below the
+/// mempool minimum fee.
+pub async fn estimatesmartfee(state: AppState, params: Params) -> RpcResult {
+    let estimator = state.mempool.fee_estimator();
+    let max_target = estimator.read().highest_target_tracked(FeeEstimateHorizon::Long);
+    let conf_target = parse_conf_target(&params, max_target)?;
+    let mode: Option<String> = params.optional(1, "estimate_mode")?;
+    let conservative = match mode.as_deref().map(str::to_ascii_lowercase).as_deref() {
+        None | Some("unset") | Some("economical") => false,
+        Some("conservative") => true,
+        Some(_) => {
+            return Err(RpcError::invalid_parameter(
+                r#"Invalid estimate_mode parameter, must be one of: "unset", "economical", "conservative""#,
+            ))
+        }
+    };
+
+    let (fee_rate, calc) = estimator.read().estimate_smart_fee(conf_target, conservative);
+    let mut result = Map::new();
+    match fee_rate {
+        Some(fee_rate) => {
+            let fee_rate = fee_rate.max(state.mempool.min_fee_rate()).max(state.mempool.policy().min_relay_fee);
+            result.insert("feerate".into(), json!(fee_rate));
+        }
+        None => {
+            result.insert("errors".into(), json!(["Insufficient data or no feerate found"]));
+        }
+    }
+    result.insert("blocks".into(), json!(calc.returned_target));
+    Ok(Value::Object(result))
+}
+
+fn bucket_to_json(bucket: &EstimatorBucket) -> Value {
+    let round2 = |v: f64| (v * 100.0).round() / 100.0;
+    json!({
+        "startrange": bucket.start.round(),
+        "endrange": bucket.end.round(),
+        "withintarget": round2(bucket.within_target),
+        "totalconfirmed": round2(bucket.total_confirmed),
+        "inmempool": round2(bucket.in_mempool),
+        "leftmempool": round2(bucket.left_mempool),
+    })
+}
+
+/// estimaterawfee conf_target ( threshold )
+///
+/// Raw estimator output for every horizon that tracks `conf_target`.
+pub async fn estimaterawfee(state: AppState, params: Params) -> RpcResult {
+    let estimator = state.mempool.fee_estimator();
+    let estimator = estimator.read();
+    let max_target = estimator.highest_target_tracked(FeeEstimateHorizon::Long);
+    let conf_target = parse_conf_target(&params, max_target)?;
+    let threshold: f64 = params.optional_or(1, "threshold", DEFAULT_RAW_FEE_THRESHOLD)?;
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(RpcError::invalid_parameter("Invalid threshold"));
+    }
+
+    let mut result = Map::new();
+    for horizon in FeeEstimateHorizon::ALL {
+        // Only horizons that track the target
+        if conf_target > estimator.highest_target_tracked(horizon) {
+            continue;
+        }
+        let (fee_rate, buckets) = estimator.estimate_raw_fee(conf_target, threshold, horizon);
+        let mut horizon_result = Map::new();
+        match fee_rate {
+            Some(fee_rate) => {
+                horizon_result.insert("feerate".into(), json!(fee_rate));
+                horizon_result.insert("decay".into(), json!(buckets.decay));
+                horizon_result.insert("scale".into(), json!(buckets.scale));
+                horizon_result.insert("pass".into(), bucket_to_json(&buckets.pass));
+                // A fail start of -1 means every bucket passed
+                if buckets.fail.start != -1.0 {
+                    horizon_result.insert("fail".into(), bucket_to_json(&buckets.fail));
+                }
+            }
+            None => {
+                horizon_result.insert("decay".into(), json!(buckets.decay));
+                horizon_result.insert("scale".into(), json!(buckets.scale));
+                horizon_result.insert("fail".into(), bucket_to_json(&buckets.fail));
+                horizon_result.insert(
+                    "errors".into(),
+                    json!(["Insufficient data or no feerate found which meets threshold"]),
+                );
+            }
+        }
+        result.insert(horizon.as_str().into(), Value::Object(horizon_result));
+    }
+    Ok(Value::Object(result))
+}
+
+/// getprojectedblocks ( nblocks )
+///
+/// The next blocks the current mempool would fill, best ancestor feerate
+/// first. The first block's `minfeerate` is what a transaction needs right
+/// now to be included next.
+pub async fn getprojectedblocks(state: AppState, params: Params) -> RpcResult {
+    let nblocks: usize = params.optional_or(0, "nblocks", DEFAULT_PROJECTED_BLOCKS)?;
+    if !(1..=MAX_PROJECTED_BLOCKS).contains(&nblocks) {
+        return Err(RpcError::invalid_parameter(format!(
+            "Invalid nblocks, must be between 1 and {}",
+            MAX_PROJECTED_BLOCKS
+        )));
+    }
+    let blocks = blocking(move || Ok(projection::project_blocks(&state.mempool, nblocks, DEFAULT_BLOCK_MAX_WEIGHT))).await?;
+    Ok(Value::Array(
+        blocks
+            .iter()
+            .map(|block| {
+                json!({
+                    "weight": block.weight,
+                    "vsize": block.vsize,
+                    "txs": block.txids.len(),
+                    "fees": btc(block.fees),
+                    "minfeerate": block.min_fee_rate(),
+                    "medianfeerate": block.median_fee_rate(),
+                    "maxfeerate": block.max_fee_rate(),
+                })
+            })
+            .collect(),
+    ))
+}
+
+/// getmempoolfeehistogram ( bin_vsize )
+///
+/// Mempool vsize by feerate, highest feerate first; each bin's `feerate` is
+/// the lowest in it.
+pub async fn getmempoolfeehistogram(state: AppState, params: Params) -> RpcResult {
+    let bin_vsize: u64 = params.optional_or(0, "bin_vsize", DEFAULT_HISTOGRAM_BIN_VSIZE)?;
+    if bin_vsize == 0 {
+        return Err(RpcError::invalid_parameter("Invalid bin_vsize, must be positive"));
+    }
+    let bins = blocking(move || Ok(projection::fee_histogram(&state.mempool, bin_vsize))).await?;
+    Ok(Value::Array(
+        bins.iter()
+            .map(|bin| json!({ "feerate": bin.fee_rate, "vsize": bin.vsize, "count": bin.count }))
+            .collect(),
+    ))
+}
+
+pub fn register(table: &mut RpcTable) {
+    table.register("util", "estimatesmartfee", &["conf_target", "estimate_mode"], |s, p| Box::pin(estimatesmartfee(s, p)));
+    table.register("hidden", "estimaterawfee", &["conf_target", "threshold"], |s, p| Box::pin(estimaterawfee(s, p)));
+    table.register("util", "getprojectedblocks", &["nblocks"], |s, p| Box::pin(getprojectedblocks(s, p)));
+    table.register("util", "getmempoolfeehistogram", &["bin_vsize"], |s, p| Box::pin(getmempoolfeehistogram(s, p)));
+}
@@ -0,0 +1,634 @@
+// src/rpc/mining.rs
+//! Mining RPCs (built with the `mining` feature): BIP 22/23 getblocktemplate
+//! and submitblock, submitheader, getmininginfo, transaction
+//! prioritisation, the generate* RPCs that mine regtest blocks locally, and
+//! the signet RPCs that add a BIP 325 solution from a key or a signed PSBT.
+
+use base64::Engine as _;
+use bitcoin::block::Header;
+use bitcoin::consensus::{deserialize, encode::serialize_hex};
+use bitcoin::psbt::Psbt;
+use bitcoin::{Address, Block, Network, PrivateKey, Script, ScriptBuf, Target, Transaction, Txid, Weight};
+use parking_lot::Mutex;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::descriptor::{self, descriptor_script};
+use crate::mempool::miner::{BlockAssemblerOptions, MAX_BLOCK_SIGOPS_COST};
+use crate::mining::{self, unix_time};
+use crate::signet;
+
+use super::protocol::{
+    RpcError, RpcResult, RPC_CLIENT_IN_INITIAL_DOWNLOAD, RPC_CLIENT_NOT_CONNECTED, RPC_DESERIALIZATION_ERROR,
+    RPC_VERIFY_ERROR,
+};
+use super::rawtransaction::decode_tx;
+use super::server::{blocking, parse_hash, Params, RpcTable};
+use super::AppState;
+
+/// A longpoll returns for mempool changes only after waiting this long
+/// (new blocks return it at once)
+const LONGPOLL_MEMPOOL_DELAY: Duration = Duration::from_secs(60);
+const LONGPOLL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Blocks getmininginfo averages the network hashrate over
+const NETWORK_HASHPS_BLOCKS: i32 = 120;
+
+/// Largest serialized block (Bitcoin Core: MAX_BLOCK_SERIALIZED_SIZE)
+const MAX_BLOCK_SERIALIZED_SIZE: u64 = 4_000_000;
+
+/// Hashes the generate RPCs try before giving up (Bitcoin Core: DEFAULT_MAX_TRIES)
+const DEFAULT_MAX_TRIES: u64 = 1_000_000;
+
+/// Hash budget for signed signet blocks; the minimum signet difficulty
+/// takes about five million hashes
+const SIGNET_MAX_TRIES: u64 = 100_000_000;
+
+/// Weight and transaction count of the last template handed out
+/// (Bitcoin Core: BlockAssembler::m_last_block_weight, m_last_block_num_txs)
+static LAST_TEMPLATE: Mutex<Option<(u64, usize)>> = parking_lot::const_mutex(None);
+
+fn internal(e: anyhow::Error) -> RpcError {
+    RpcError::internal(e.to_string())
+}
+
+fn target_hex(target: Target) -> String {
+    hex::encode(target.to_be_bytes())
+}
+
+fn decode_block(hex_str: &str) -> RpcResult<Block> {
+    hex::decode(hex_str)
+        .ok()
+        .and_then(|bytes| deserialize(&bytes).ok())
+        .ok_or_else(|| RpcError::new(RPC_DESERIALIZATION_ERROR, "Block decode failed"))
+}
+
+/// Hand a block to the kernel. Returns None once it is the new tip,
+/// otherwise the BIP22 reason it was not accepted.
+pub fn submit_block(state: &AppState, block: Block) -> RpcResult<Option<&'static str>> {
+    mining::submit_block(&state.kernel, &state.mempool, state.p2p.as_ref(), block).map_err(internal)
+}
+
+/// Wait until the tip moves away from the one in `longpollid`, or the
+/// mempool has changed and a minute has passed
+async fn wait_for_longpoll(state: &AppState, longpollid: &str) -> RpcResult<()> {
+    let (hash, sequence) = longpollid.split_at(longpollid.len().min(64));
+    let hash = parse_hash::<bitcoin::BlockHash>(hash, "longpollid")?;
+    let sequence: Option<u64> = sequence.parse().ok();
+
+    let start = Instant::now();
+    loop {
+        if state.kernel.get_best_block_hash().map_err(internal)? != hash {
+            return Ok(());
+        }
+        if start.elapsed() >= LONGPOLL_MEMPOOL_DELAY && sequence != Some(state.mempool.sequence()) {
+            return Ok(());
+        }
+        tokio::time::sleep(LONGPOLL_CHECK_INTERVAL).await;
+    }
+}
+
+/// Check a block proposal (BIP 23) without proof of work
+fn check_proposal(state: &AppState, block: &Block) -> RpcResult<Value> {
+    let k = &state.kernel;
+    if let Some(info) = k.get_block_info(&block.block_hash()).map_err(internal)? {
+        return Ok(json!(if info.in_active_chain { "duplicate" } else { "duplicate-inconclusive" }));
+    }
+    if k.get_best_block_hash().map_err(internal)? != block.header.prev_blockhash {
+        return Ok(json!("inconclusive-not-best-prevblk"));
+    }
+    match mining::check_block_contextual(k, block, false, unix_time()) {
+        Ok(_) => Ok(Value::Null),
+        Err(reason) => Ok(json!(reason)),
+    }
+}
+
+fn block_template(state: &AppState) -> RpcResult<Value> {
+    let k = &state.kernel;
+    let network = k.network();
+    let tip = k.get_best_block_hash().map_err(internal)?;
+    // Taken before selection, so a longpoll sees any change made meanwhile
+    let sequence = state.mempool.sequence();
+    let template = state.mempool.get_block_template(BlockAssemblerOptions::default());
+    let height = template.height;
+
+    let mintime = template.lock_time_cutoff + 1;
+    let curtime = unix_time().max(mintime);
+    let bits = mining::next_work_required(k, &tip, curtime).map_err(internal)?;
+
+    let positions: HashMap<Txid, usize> = template.txs.iter().enumerate().map(|(i, t)| (t.txid, i)).collect();
+    let transactions: Vec<Value> = template
+        .txs
+        .iter()
+        .map(|t| {
+            // 1-based positions of parents earlier in the template
+            let mut depends: Vec<usize> = t
+                .tx
+                .input
+                .iter()
+                .filter_map(|input| positions.get(&input.previous_output.txid).map(|p| p + 1))
+                .collect();
+            depends.sort_unstable();
+            depends.dedup();
+            json!({
+                "data": serialize_hex(&*t.tx),
+                "txid": t.txid.to_string(),
+                "hash": t.tx.compute_wtxid().to_string(),
+                "depends": depends,
+                "fee": t.fee,
+                "sigops": t.sigop_cost,
+                "weight": t.weight,
+            })
+        })
+        .collect();
+    *LAST_TEMPLATE.lock() = Some((template.weight, template.txs.len()));
+
+    let mut rules = vec!["csv", "!segwit", "taproot"];
+    if network == Network::Signet {
+        rules.push("!signet");
+    }
+    let commitment = mining::witness_commitment_script(template.txs.iter().map(|t| &*t.tx));
+
+    let mut result = json!({
+        "capabilities": ["proposal"],
+        "version": mining::BLOCK_VERSION,
+        "rules": rules,
+        "vbavailable": {},
+        "vbrequired": 0,
+        "previousblockhash": tip.to_string(),
+        "transactions": transactions,
+        "coinbaseaux": {},
+        "coinbasevalue": mining::block_subsidy(height, network) + template.fees,
+        "longpollid": format!("{}{}", tip, sequence),
+        "target": target_hex(Target::from_compact(bits)),
+        "mintime": mintime,
+        "mutable": ["time", "transactions", "prevblock"],
+        "noncerange": "00000000ffffffff",
+        "sigoplimit": MAX_BLOCK_SIGOPS_COST,
+        "sizelimit": MAX_BLOCK_SERIALIZED_SIZE,
+        "weightlimit": Weight::MAX_BLOCK.to_wu(),
+        "curtime": curtime,
+        "bits": format!("{:08x}", bits.to_consensus()),
+        "height": height,
+        "default_witness_commitment": hex::encode(commitment.as_bytes()),
+    });
+    if network == Network::Signet {
+        result["signet_challenge"] = json!(k.signet_challenge().map(|c| hex::encode(c.as_bytes())));
+    }
+    Ok(result)
+}
+
+/// getblocktemplate ( "template_request" )
+///
+/// Block template for external mining software (BIP 22, 23, 9, 145), or
+/// the check result of a block proposal.
+pub async fn getblocktemplate(state: AppState, params: Params) -> RpcResult {
+    let request: Value = params.optional(0, "template_request")?.unwrap_or_else(|| json!({}));
+    let request = request
+        .as_object()
+        .ok_or_else(|| RpcError::type_error("template_request must be an object"))?
+        .clone();
+
+    match request.get("mode") {
+        None | Some(Value::Null) => {}
+        Some(Value::String(mode)) if mode == "template" => {}
+        Some(Value::String(mode)) if mode == "proposal" => {
+            let data = request
+                .get("data")
+                .and_then(Value::as_str)
+                .ok_or_else(|| RpcError::type_error("Missing data String key for proposal"))?;
+            let block = decode_block(data)?;
+            return blocking(move || check_proposal(&state, &block)).await;
+        }
+        Some(_) => return Err(RpcError::invalid_parameter("Invalid mode")),
+    }
+
+    let network = state.kernel.network();
+    if network == Network::Bitcoin {
+        let Some(p2p) = state.p2p.as_ref().filter(|p2p| p2p.peer_count() > 0) else {
+            return Err(RpcError::new(RPC_CLIENT_NOT_CONNECTED, "Bitcoin is not connected!"));
+        };
+        if p2p.header_height() > p2p.block_height() {
+            return Err(RpcError::new(RPC_CLIENT_IN_INITIAL_DOWNLOAD, "Bitcoin is downloading blocks..."));
+        }
+    }
+
+    let rules: Vec<String> = match request.get("rules") {
+        Some(rules) => serde_json::from_value(rules.clone())
+            .map_err(|_| RpcError::type_error("rules must be an array of strings"))?,
+        None => Vec::new(),
+    };
+    if !rules.iter().any(|r| r == "segwit") {
+        return Err(RpcError::invalid_parameter(
+            r#"getblocktemplate must be called with the segwit rule set (call with {"rules": ["segwit"]})"#,
+        ));
+    }
+    if network == Network::Signet && !rules.iter().any(|r| r == "signet") {
+        return Err(RpcError::invalid_parameter(
+            r#"getblocktemplate must be called with the signet rule set (call with {"rules": ["segwit", "signet"]})"#,
+        ));
+    }
+
+    if let Some(longpollid) = request.get("longpollid").and_then(Value::as_str) {
+        wait_for_longpoll(&state, longpollid).await?;
+    }
+    blocking(move || block_template(&state)).await
+}
+
+/// submitblock "hexdata" ( "dummy" )
+///
+/// Submit a mined block. Returns null once it is the new tip, otherwise a
+/// BIP22 reason such as "duplicate", "high-hash" or "inconclusive".
+pub async fn submitblock(state: AppState, params: Params) -> RpcResult {
+    let hexdata: String = params.required(0, "hexdata")?;
+    let mut block = decode_block(&hexdata)?;
+    if !block.txdata.first().is_some_and(|tx| tx.is_coinbase()) {
+        return Err(RpcError::new(RPC_DESERIALIZATION_ERROR, "Block does not start with a coinbase"));
+    }
+    mining::update_uncommitted_block_structures(&mut block);
+
+    let result = blocking(move || submit_block(&state, block)).await?;
+    Ok(result.map_or(Value::Null, |reason| json!(reason)))
+}
+
+/// submitheader "hexdata"
+///
+/// Check a block header against its parent. libbitcoinkernel takes only
+/// whole blocks, so a valid header is not stored; submitblock adds the block.
+pub async fn submitheader(state: AppState, params: Params) -> RpcResult {
+    let hexdata: String = params.required(0, "hexdata")?;
+    let header: Header = hex::decode(&hexdata)
+        .ok()
+        .and_then(|bytes| deserialize(&bytes).ok())
+        .ok_or_else(|| RpcError::new(RPC_DESERIALIZATION_ERROR, "Block header decode failed"))?;
+
+    blocking(move || {
+        let k = &state.kernel;
+        if k.get_block_info(&header.prev_blockhash).map_err(internal)?.is_none() {
+            return Err(RpcError::new(
+                RPC_VERIFY_ERROR,
+                format!("Must submit previous header ({}) first", header.prev_blockhash),
+            ));
+        }
+        if header.validate_pow(header.target()).is_err() {
+            return Err(RpcError::new(RPC_VERIFY_ERROR, "high-hash"));
+        }
+        mining::check_block_header_contextual(k, &header, unix_time())
+            .map_err(|reason| RpcError::new(RPC_VERIFY_ERROR, reason))?;
+        Ok(Value::Null)
+    })
+    .await
+}
+
+/// Average hashes per second over the last `lookup` blocks up to `height`
+/// (Bitcoin Core: GetNetworkHashPS)
+fn network_hash_ps(state: &AppState, height: i32, lookup: i32) -> anyhow::Result<f64> {
+    let lookup = lookup.min(height);
+    if lookup <= 0 {
+        return Ok(0.0);
+    }
+    let k = &state.kernel;
+    let (mut min_time, mut max_time, mut work) = (u32::MAX, 0u32, 0.0f64);
+    for h in height - lookup..=height {
+        let hash = k.get_block_hash(h)?;
+        let Some(header) = k.get_block_header(&hash)? else {
+            continue;
+        };
+        min_time = min_time.min(header.time);
+        max_time = max_time.max(header.time);
+        // Work of the first block is already behind the window
+        if h > height - lookup {
+            work += header.difficulty_float() * 4_294_967_296.0;
+        }
+    }
+    if max_time <= min_time {
+        return Ok(0.0);
+    }
+    Ok(work / (max_time - min_time) as f64)
+}
+
+/// getmininginfo
+pub async fn getmininginfo(state: AppState, _params: Params) -> RpcResult {
+    blocking(move || {
+        let k = &state.kernel;
+        let height = k.get_height().map_err(internal)?;
+        let tip = k.get_best_block_hash().map_err(internal)?;
+        let header = k
+            .get_block_header(&tip)
+            .map_err(internal)?
+            .ok_or_else(|| RpcError::internal("tip block is not stored"))?;
+        let next_bits = mining::next_work_required(k, &tip, unix_time()).map_err(internal)?;
+        let next_target = Target::from_compact(next_bits);
+
+        let mut result = Map::new();
+        result.insert("blocks".into(), json!(height));
+        if let Some((weight, txs)) = *LAST_TEMPLATE.lock() {
+            result.insert("currentblockweight".into(), json!(weight));
+            result.insert("currentblocktx".into(), json!(txs));
+        }
+        result.insert("bits".into(), json!(format!("{:08x}", header.bits.to_consensus())));
+        result.insert("difficulty".into(), json!(header.difficulty_float()));
+        result.insert("target".into(), json!(target_hex(header.target())));
+        result.insert(
+            "networkhashps".into(),
+            json!(network_hash_ps(&state, height, NETWORK_HASHPS_BLOCKS).map_err(internal)?),
+        );
+        result.insert("pooledtx".into(), json!(state.mempool.size()));
+        result.insert("chain".into(), json!(k.chain_name()));
+        if let Some(challenge) = k.signet_challenge() {
+            result.insert("signet_challenge".into(), json!(hex::encode(challenge.as_bytes())));
+        }
+        result.insert(
+            "next".into(),
+            json!({
+                "height": height + 1,
+                "bits": format!("{:08x}", next_bits.to_consensus()),
+                "difficulty": next_target.difficulty_float(),
+                "target": target_hex(next_target),
+            }),
+        );
+        result.insert("warnings".into(), json!([]));
+        Ok(Value::Object(result))
+    })
+    .await
+}
+
+/// prioritisetransaction "txid" ( dummy ) fee_delta
+///
+/// Add `fee_delta` satoshis to the fee block templates see for a
+/// transaction, whether or not it is in the mempool yet.
+pub async fn prioritisetransaction(state: AppState, params: Params) -> RpcResult {
+    let txid: String = params.required(0, "txid")?;
+    let txid: Txid = parse_hash(&txid, "txid")?;
+    let dummy: Option<f64> = params.optional(1, "dummy")?;
+    if dummy.is_some_and(|d| d != 0.0) {
+        return Err(RpcError::invalid_parameter(
+            "Priority is no longer supported, dummy argument to prioritisetransaction must set to zero.",
+        ));
+    }
+    let fee_delta: i64 = params.required(2, "fee_delta")?;
+    state.mempool.prioritise_transaction(txid, fee_delta);
+    Ok(json!(true))
+}
+
+/// getprioritisedtransactions
+///
+/// Fee deltas set with prioritisetransaction, by txid.
+pub async fn getprioritisedtransactions(state: AppState, _params: Params) -> RpcResult {
+    let mut result = Map::new();
+    for (txid, delta) in state.mempool.fee_deltas() {
+        let mut obj = json!({ "fee_delta": delta, "in_mempool": false });
+        if let Some(entry) = state.mempool.get_entry(&txid) {
+            obj["in_mempool"] = json!(true);
+            obj["modified_fee"] = json!(entry.modified_fee());
+        }
+        result.insert(txid.to_string(), obj);
+    }
+    Ok(Value::Object(result))
+}
+
+/// Unmined block on the current tip paying `script_pubkey`, filled from the
+/// mempool, or with exactly `txs` when given. Like Bitcoin Core's
+/// generateblock, explicit transactions add no fees to the coinbase.
+fn create_block(state: &AppState, script_pubkey: ScriptBuf, txs: Option<Vec<Transaction>>) -> RpcResult<Block> {
+    let k = &state.kernel;
+    let tip = k.get_best_block_hash().map_err(internal)?;
+    let (height, median_time_past, txs, fees) = match txs {
+        None => {
+            let template = state.mempool.get_block_template(BlockAssemblerOptions::default());
+            let txs = template.txs.iter().map(|t| (*t.tx).clone()).collect();
+            (template.height, template.lock_time_cutoff, txs, template.fees)
+        }
+        Some(txs) => {
+            let height = k.get_height().map_err(internal)? + 1;
+            let median_time_past = k.median_time_past(&tip).map_err(internal)?.unwrap_or_default();
+            (height as u32, median_time_past, txs, 0)
+        }
+    };
+
+    let time = unix_time().max(median_time_past + 1);
+    let bits = mining::next_work_required(k, &tip, time).map_err(internal)?;
+    let commitment = mining::witness_commitment_script(&txs);
+    let value = mining::block_subsidy(height, k.network()) + fees;
+    let coinbase = mining::create_coinbase(height, value, script_pubkey, Some(commitment));
+    let txdata: Vec<Transaction> = std::iter::once(coinbase).chain(txs).collect();
+    let header = mining::block_header(tip, &txdata, time, bits);
+    Ok(Block { header, txdata })
+}
+
+/// Mine and submit `nblocks` blocks paying `script_pubkey`, stopping early
+/// when `max_tries` hashes are spent (Bitcoin Core: generateBlocks)
+fn generate_blocks(state: &AppState, script_pubkey: ScriptBuf, nblocks: i64, mut max_tries: u64) -> RpcResult<Value> {
+    let mut hashes = Vec::new();
+    while (hashes.len() as i64) < nblocks {
+        let mut block = create_block(state, script_pubkey.clone(), None)?;
+        if !mining::grind_header(&mut block.header, &mut max_tries) {
+            break;
+        }
+        let hash = block.block_hash();
+        if submit_block(state, block)?.is_some() {
+            return Err(RpcError::internal("ProcessNewBlock, block not accepted"));
+        }
+        hashes.push(hash.to_string());
+    }
+    Ok(json!(hashes))
+}
+
+fn address_script(address: &str, network: Network) -> Option<ScriptBuf> {
+    let address = Address::from_str(address).ok()?.require_network(network).ok()?;
+    Some(address.script_pubkey())
+}
+
+/// Script of a generateblock-style output: a descriptor or an address
+fn output_script(output: &str, network: Network) -> RpcResult<ScriptBuf> {
+    descriptor::output_script(output, network)
+        .ok_or_else(|| RpcError::invalid_address_or_key("Error: Invalid address or descriptor"))
+}
+
+/// Transactions named by mempool txid or given as raw hex, in order
+fn block_transactions(state: &AppState, transactions: &[String]) -> RpcResult<Vec<Transaction>> {
+    transactions
+        .iter()
+        .map(|s| match Txid::from_str(s) {
+            Ok(txid) => state
+                .mempool
+                .get_tx(&txid)
+                .map(|tx| (*tx).clone())
+                .ok_or_else(|| RpcError::invalid_address_or_key(format!("Transaction {} not in mempool.", txid))),
+            Err(_) => decode_tx(s).map_err(|_| {
+                RpcError::new(
+                    RPC_DESERIALIZATION_ERROR,
+                    format!("Transaction decode failed for {}. Make sure the tx has at least one input.", s),
+                )
+            }),
+        })
+        .collect()
+}
+
+/// Grind `block` within `max_tries` hashes, then submit it, or return its
+/// hex as well when `submit` is false
+fn finish_block(state: &AppState, mut block: Block, mut max_tries: u64, submit: bool) -> RpcResult<Value> {
+    if !mining::grind_header(&mut block.header, &mut max_tries) {
+        return Err(RpcError::misc("Failed to make block."));
+    }
+    let mut result = json!({ "hash": block.block_hash().to_string() });
+    if submit {
+        if submit_block(state, block)?.is_some() {
+            return Err(RpcError::internal("ProcessNewBlock, block not accepted"));
+        }
+    } else {
+        result["hex"] = json!(serialize_hex(&block));
+    }
+    Ok(result)
+}
+
+/// generatetoaddress nblocks "address" ( maxtries )
+///
+/// Mine blocks from the mempool to `address` right away. Returns the new
+/// block hashes; only practical on regtest.
+pub async fn generatetoaddress(state: AppState, params: Params) -> RpcResult {
+    let nblocks: i64 = params.required(0, "nblocks")?;
+    let address: String = params.required(1, "address")?;
+    let max_tries: u64 = params.optional_or(2, "maxtries", DEFAULT_MAX_TRIES)?;
+    let script_pubkey = address_script(&address, state.kernel.network())
+        .ok_or_else(|| RpcError::invalid_address_or_key("Error: Invalid address"))?;
+    blocking(move || generate_blocks(&state, script_pubkey, nblocks, max_tries)).await
+}
+
+/// generatetodescriptor num_blocks "descriptor" ( maxtries )
+///
+/// Mine blocks from the mempool to the script of a single-script
+/// descriptor. Returns the new block hashes.
+pub async fn generatetodescriptor(state: AppState, params: Params) -> RpcResult {
+    let num_blocks: i64 = params.required(0, "num_blocks")?;
+    let descriptor: String = params.required(1, "descriptor")?;
+    let max_tries: u64 = params.optional_or(2, "maxtries", DEFAULT_MAX_TRIES)?;
+    let script_pubkey =
+        descriptor_script(&descriptor, state.kernel.network()).map_err(RpcError::invalid_address_or_key)?;
+    blocking(move || generate_blocks(&state, script_pubkey, num_blocks, max_tries)).await
+}
+
+/// generateblock "output" ["rawtx/txid",...] ( submit )
+///
+/// Mine one block to an address or descriptor holding exactly the given
+/// transactions, in order: mempool txids or raw transactions. Returns the
+/// block hash, and the block hex when `submit` is false.
+pub async fn generateblock(state: AppState, params: Params) -> RpcResult {
+    let output: String = params.required(0, "output")?;
+    let transactions: Vec<String> = params.required(1, "transactions")?;
+    let submit: bool = params.optional_or(2, "submit", true)?;
+
+    let script_pubkey = output_script(&output, state.kernel.network())?;
+    let txs = block_transactions(&state, &transactions)?;
+
+    blocking(move || {
+        let block = create_block(&state, script_pubkey, Some(txs))?;
+        if let Err(reason) = mining::check_block_contextual(&state.kernel, &block, false, unix_time()) {
+            return Err(RpcError::new(RPC_VERIFY_ERROR, format!("TestBlockValidity failed: {}", reason)));
+        }
+        finish_block(&state, block, DEFAULT_MAX_TRIES, submit)
+    })
+    .await
+}
+
+fn signet_challenge(state: &AppState) -> RpcResult<ScriptBuf> {
+    state
+        .kernel
+        .signet_challenge()
+        .map(Script::to_owned)
+        .ok_or_else(|| RpcError::misc("This RPC is only available on signet"))
+}
+
+/// createsignetpsbt "output" ( ["rawtx/txid",...] )
+///
+/// Build a block on the tip paying `output`, filled from the mempool or
+/// with the given transactions, and return the base64 PSBT that solves it
+/// for the signet challenge once signed and finalized.
+pub async fn createsignetpsbt(state: AppState, params: Params) -> RpcResult {
+    let output: String = params.required(0, "output")?;
+    let transactions: Option<Vec<String>> = params.optional(1, "transactions")?;
+
+    let challenge = signet_challenge(&state)?;
+    let script_pubkey = output_script(&output, state.kernel.network())?;
+    let txs = transactions.map(|t| block_transactions(&state, &t)).transpose()?;
+
+    blocking(move || {
+        let block = create_block(&state, script_pubkey, txs)?;
+        let psbt = signet::signet_psbt(&block, &challenge).map_err(RpcError::internal)?;
+        Ok(json!(base64::engine::general_purpose::STANDARD.encode(psbt.serialize())))
+    })
+    .await
+}
+
+/// finalizesignetpsbt "psbt" ( submit )
+///
+/// Add the solution of a signed and finalized createsignetpsbt PSBT to its
+/// block, redo the proof of work and submit it. Returns the block hash,
+/// and the block hex when `submit` is false.
+pub async fn finalizesignetpsbt(state: AppState, params: Params) -> RpcResult {
+    let psbt: String = params.required(0, "psbt")?;
+    let submit: bool = params.optional_or(1, "submit", true)?;
+
+    let challenge = signet_challenge(&state)?;
+    let psbt = base64::engine::general_purpose::STANDARD
+        .decode(psbt)
+        .ok()
+        .and_then(|bytes| Psbt::deserialize(&bytes).ok())
+        .ok_or_else(|| RpcError::new(RPC_DESERIALIZATION_ERROR, "TX decode failed"))?;
+    let block = signet::block_from_psbt(&psbt, &challenge).map_err(RpcError::invalid_parameter)?;
+
+    blocking(move || finish_block(&state, block, SIGNET_MAX_TRIES, submit)).await
+}
+
+/// generatesignetblock "output" "privkey" ( ["rawtx/txid",...] submit )
+///
+/// Build a block paying `output`, sign it for the signet challenge with a
+/// WIF private key, and mine it. Returns the block hash, and the block
+/// hex when `submit` is false.
+pub async fn generatesignetblock(state: AppState, params: Params) -> RpcResult {
+    let output: String = params.required(0, "output")?;
+    let privkey: String = params.required(1, "privkey")?;
+    let transactions: Option<Vec<String>> = params.optional(2, "transactions")?;
+    let submit: bool = params.optional_or(3, "submit", true)?;
+
+    let challenge = signet_challenge(&state)?;
+    let key = PrivateKey::from_wif(&privkey).map_err(|_| RpcError::invalid_address_or_key("Invalid private key"))?;
+    let script_pubkey = output_script(&output, state.kernel.network())?;
+    let txs = transactions.map(|t| block_transactions(&state, &t)).transpose()?;
+
+    blocking(move || {
+        let mut block = create_block(&state, script_pubkey, txs)?;
+        signet::sign_block(&mut block, &challenge, &key).map_err(RpcError::invalid_parameter)?;
+        finish_block(&state, block, SIGNET_MAX_TRIES, submit)
+    })
+    .await
+}
+
+pub fn register(table: &mut RpcTable) {
+    table.register("mining", "getblocktemplate", &["template_request"], |s, p| Box::pin(getblocktemplate(s, p)));
+    table.register("mining", "submitblock", &["hexdata", "dummy"], |s, p| Box::pin(submitblock(s, p)));
+    table.register("mining", "submitheader", &["hexdata"], |s, p| Box::pin(submitheader(s, p)));
+    table.register("mining", "getmininginfo", &[], |s, p| Box::pin(getmininginfo(s, p)));
+    table.register("mining", "prioritisetransaction", &["txid", "dummy", "fee_delta"], |s, p| {
+        Box::pin(prioritisetransaction(s, p))
+    });
+    table.register("mining", "getprioritisedtransactions", &[], |s, p| Box::pin(getprioritisedtransactions(s, p)));
+    table.register("generating", "generatetoaddress", &["nblocks", "address", "maxtries"], |s, p| {
+        Box::pin(generatetoaddress(s, p))
+    });
+    table.register("generating", "generatetodescriptor", &["num_blocks", "descriptor", "maxtries"], |s, p| {
+        Box::pin(generatetodescriptor(s, p))
+    });
+    table.register("generating", "generateblock", &["output", "transactions", "submit"], |s, p| {
+        Box::pin(generateblock(s, p))
+    });
+    table.register("signet", "createsignetpsbt", &["output", "transactions"], |s, p| {
+        Box::pin(createsignetpsbt(s, p))
+    });
+    table.register("signet", "finalizesignetpsbt", &["psbt", "submit"], |s, p| Box::pin(finalizesignetpsbt(s, p)));
+    table.register("signet", "generatesignetblock", &["output", "privkey", "transactions", "submit"], |s, p| {
+        Box::pin(generatesignetblock(s, p))
+    });
+}
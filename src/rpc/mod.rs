@@ -1,55 +1,117 @@
 // src/rpc/mod.rs
+pub mod address;
+pub mod auth;
 pub mod blockchain;
-// pub mod network; // Temporarily disabled - requires ConnectionManager
+pub mod fees;
+#[cfg(feature = "mining")]
+pub mod mining;
+pub mod network;
+pub mod protocol;
+pub mod rawtransaction;
+pub mod rest;
+pub mod server;
+pub mod util;
+#[cfg(feature = "zmq")]
+pub mod zmq;
 
 use anyhow::Result;
-use axum::{routing::{get, post}, Router};
+use axum::{middleware, routing::{get, post}, Router};
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+use crate::index::addrindex::AddrIndex;
+use crate::index::coins::CoinsIndex;
+use crate::index::txindex::TxIndex;
 use crate::kernel::Kernel;
 use crate::mempool::Mempool;
+use crate::p2p::P2PHandle;
+use auth::RpcAuth;
+use server::RpcTable;
 
 #[derive(Clone)]
 pub struct AppState {
     pub kernel: Arc<Kernel>,
     pub mempool: Arc<Mempool>,
+    /// UTXO set of the active chain (gettxout, REST getutxos)
+    pub coins: Arc<CoinsIndex>,
+    /// txid -> block index, with -txindex (getrawtransaction)
+    pub txindex: Option<Arc<TxIndex>>,
+    /// scripthash -> outputs and spends, with -addrindex (getaddress*)
+    pub addrindex: Option<Arc<AddrIndex>>,
+    /// None when P2P is not running (e.g. regtest without -peer)
+    pub p2p: Option<P2PHandle>,
     pub shutdown_tx: Arc<Mutex<Option<tokio::sync::oneshot::Sender<()>>>>,
+    pub rpc_table: Arc<RpcTable>,
+    pub auth: Arc<RpcAuth>,
+    /// Node data directory (savemempool)
+    pub datadir: PathBuf,
+    /// ZMQ publishers, for getzmqnotifications
+    #[cfg(feature = "zmq")]
+    pub zmq: Option<Arc<crate::zmq::ZmqNotifier>>,
 }
 
-pub async fn start_rpc_server(
-    addr: SocketAddr,
-    kernel: Arc<Kernel>,
-    mempool: Arc<Mempool>,
-    shutdown_tx: tokio::sync::oneshot::Sender<()>,
-) -> Result<()> {
-    let state = AppState {
-        kernel,
-        mempool,
-        shutdown_tx: Arc::new(Mutex::new(Some(shutdown_tx))),
-    };
+/// Build the table of all RPC methods served by this node
+pub fn build_rpc_table() -> RpcTable {
+    let mut table = RpcTable::new();
+    server::register(&mut table);
+    address::register(&mut table);
+    blockchain::register(&mut table);
+    fees::register(&mut table);
+    #[cfg(feature = "mining")]
+    mining::register(&mut table);
+    network::register(&mut table);
+    rawtransaction::register(&mut table);
+    #[cfg(feature = "zmq")]
+    zmq::register(&mut table);
+    table
+}
+
+impl AppState {
+    pub fn new(
+        kernel: Arc<Kernel>,
+        mempool: Arc<Mempool>,
+        coins: Arc<CoinsIndex>,
+        p2p: Option<P2PHandle>,
+        shutdown_tx: tokio::sync::oneshot::Sender<()>,
+        auth: Arc<RpcAuth>,
+        datadir: PathBuf,
+    ) -> Self {
+        Self {
+            kernel,
+            mempool,
+            coins,
+            txindex: None,
+            addrindex: None,
+            p2p,
+            shutdown_tx: Arc::new(Mutex::new(Some(shutdown_tx))),
+            rpc_table: Arc::new(build_rpc_table()),
+            auth,
+            datadir,
+            #[cfg(feature = "zmq")]
+            zmq: None,
+        }
+    }
+}
 
+/// Serve JSON-RPC on `addr`, plus the unauthenticated REST interface when `rest` is set
+pub async fn start_rpc_server(addr: SocketAddr, state: AppState, rest: bool) -> Result<()> {
     let app = Router::new()
-        // Blockchain RPCs - GET support for simple queries, POST for queries with params
-        .route("/getblockchaininfo", get(blockchain::getblockchaininfo).post(blockchain::getblockchaininfo))
-        .route("/getbestblockhash", get(blockchain::getbestblockhash).post(blockchain::getbestblockhash))
-        .route("/getblockcount", get(blockchain::getblockcount).post(blockchain::getblockcount))
-        .route("/getblockhash", post(blockchain::getblockhash))
-        .route("/getblock", post(blockchain::getblock))
-        .route("/getblockheader", post(blockchain::getblockheader))
-        .route("/getchaintips", get(blockchain::getchaintips).post(blockchain::getchaintips))
-        .route("/getdifficulty", get(blockchain::getdifficulty).post(blockchain::getdifficulty))
-        .route("/getmempoolinfo", get(blockchain::getmempoolinfo).post(blockchain::getmempoolinfo))
-        .route("/getrawmempool", post(blockchain::getrawmempool))
-        .route("/gettxout", post(blockchain::gettxout))
-        .route("/gettxoutsetinfo", get(blockchain::gettxoutsetinfo).post(blockchain::gettxoutsetinfo))
-        .route("/verifychain", post(blockchain::verifychain))
-        .route("/stop", get(blockchain::stop).post(blockchain::stop))
-        .route("/flushstate", get(blockchain::flushstate).post(blockchain::flushstate))
-        .with_state(state);
+        // JSON-RPC 1.0/2.0 endpoint (bitcoin-cli, python-bitcoinrpc, batches)
+        .route("/", post(server::handle_jsonrpc))
+        // Per-method routes - GET support for simple queries, POST for queries with params
+        .route("/:method", get(server::handle_method_route).post(server::handle_method_route))
+        // Every route requires HTTP Basic auth (cookie, -rpcuser/-rpcpassword or -rpcauth)
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_auth));
+    // REST is merged after the auth layer so it stays public (Bitcoin Core: -rest)
+    let app = if rest { app.merge(rest::routes()) } else { app };
+    let app = app.with_state(state);
 
     eprintln!("[rpc] listening on http://{}", addr);
+    if rest {
+        eprintln!("[rest] REST interface enabled at http://{}/rest/", addr);
+    }
 
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
@@ -1,16 +1,45 @@
 // src/rpc/network.rs
-use anyhow::Result;
-use axum::{extract::State, http::StatusCode, Json};
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use serde_json::{json, Value};
 use std::net::SocketAddr;
-use std::sync::Arc;
+use tokio::net::lookup_host;
+
+use crate::mempool::FeeRate;
+use crate::p2p::banman::Subnet;
+use crate::p2p::control::{service_names, ConnectionType, P2PCommand, P2PHandle, PeerStats};
+
+use super::protocol::{
+    RpcError, RpcResult, RPC_CLIENT_INVALID_IP_OR_SUBNET, RPC_CLIENT_NODE_ALREADY_ADDED,
+    RPC_CLIENT_NODE_NOT_ADDED, RPC_CLIENT_NODE_NOT_CONNECTED, RPC_CLIENT_P2P_DISABLED,
+};
+use super::server::{Params, RpcTable};
+use super::AppState;
+
+/// Version reported by getnetworkinfo (Bitcoin Core format: MMmmpp)
+const CLIENT_VERSION: u32 = 270000;
+const PROTOCOL_VERSION: u32 = 70016;
+
+fn p2p(state: &AppState) -> RpcResult<&P2PHandle> {
+    state.p2p.as_ref().ok_or_else(|| {
+        RpcError::new(RPC_CLIENT_P2P_DISABLED, "Error: Peer-to-peer functionality missing or disabled")
+    })
+}
 
-use crate::network::ConnectionManager;
+fn network_name(addr: &SocketAddr) -> &'static str {
+    match addr {
+        SocketAddr::V4(_) => "ipv4",
+        SocketAddr::V6(v6) if v6.ip().to_ipv4_mapped().is_some() => "ipv4",
+        SocketAddr::V6(_) => "ipv6",
+    }
+}
 
-#[derive(Clone)]
-pub struct AppState {
-    pub connman: Arc<ConnectionManager>,
+/// Resolve "host[:port]" using the network's default port
+async fn resolve_node(node: &str, default_port: u16) -> Option<SocketAddr> {
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr);
+    }
+    let target = if node.contains(':') { node.to_string() } else { format!("{}:{}", node, default_port) };
+    lookup_host(target).await.ok()?.next()
 }
 
 // ============================================================================
@@ -29,9 +58,11 @@ pub struct NetworkInfo {
     pub timeoffset: i64,
     pub networkactive: bool,
     pub connections: usize,
+    pub connections_in: usize,
+    pub connections_out: usize,
     pub networks: Vec<NetworkDetails>,
-    pub relayfee: f64,
-    pub incrementalfee: f64,
+    pub relayfee: FeeRate,
+    pub incrementalfee: FeeRate,
     pub localaddresses: Vec<LocalAddress>,
     pub warnings: String,
 }
@@ -52,44 +83,51 @@ pub struct LocalAddress {
     pub score: i32,
 }
 
-pub async fn getnetworkinfo(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    let connections = state.connman.num_connections();
+pub async fn getnetworkinfo(state: AppState, _params: Params) -> RpcResult {
+    let policy = state.mempool.policy();
+
+    let (subversion, services, active, peers) = match &state.p2p {
+        Some(p2p) => (p2p.user_agent().to_string(), p2p.local_services(), p2p.network_active(), p2p.peers()),
+        None => ("/btck-mini-node:0.1/".to_string(), 0, false, Vec::new()),
+    };
+
+    // Median of the offsets reported by our peers
+    let mut offsets: Vec<i64> = peers.iter().map(|p| p.time_offset).collect();
+    offsets.sort_unstable();
+    let timeoffset = offsets.get(offsets.len() / 2).copied().unwrap_or(0);
+
+    let networks = ["ipv4", "ipv6"]
+        .iter()
+        .map(|name| NetworkDetails {
+            name: name.to_string(),
+            limited: false,
+            reachable: true,
+            proxy: String::new(),
+            proxy_randomize_credentials: false,
+        })
+        .collect();
 
     let info = NetworkInfo {
-        version: 270000, // Bitcoin Core 27.0
-        subversion: "/btck-rust-node:0.1.0/".to_string(),
-        protocolversion: 70016,
-        localservices: "0000000000000409".to_string(),
-        localservicesnames: vec!["NETWORK".to_string(), "WITNESS".to_string()],
+        version: CLIENT_VERSION,
+        subversion,
+        protocolversion: PROTOCOL_VERSION,
+        localservices: format!("{:016x}", services),
+        localservicesnames: service_names(services),
         localrelay: true,
-        timeoffset: 0,
-        networkactive: true,
-        connections,
-        networks: vec![
-            NetworkDetails {
-                name: "ipv4".to_string(),
-                limited: false,
-                reachable: true,
-                proxy: String::new(),
-                proxy_randomize_credentials: false,
-            },
-            NetworkDetails {
-                name: "ipv6".to_string(),
-                limited: false,
-                reachable: true,
-                proxy: String::new(),
-                proxy_randomize_credentials: false,
-            },
-        ],
-        relayfee: 0.00001,
-        incrementalfee: 0.00001,
+        timeoffset,
+        networkactive: active,
+        connections: peers.len(),
+        // Only outbound connections are made; we don't accept inbound peers yet
+        connections_in: 0,
+        connections_out: peers.len(),
+        networks,
+        relayfee: policy.min_relay_fee,
+        incrementalfee: policy.incremental_relay_fee,
         localaddresses: vec![],
         warnings: String::new(),
     };
 
-    Ok(Json(json!(info)))
+    Ok(json!(info))
 }
 
 /// getpeerinfo
@@ -97,7 +135,7 @@ pub async fn getnetworkinfo(
 pub struct PeerInfo {
     pub id: u64,
     pub addr: String,
-    pub addrbind: String,
+    pub network: String,
     pub services: String,
     pub servicesnames: Vec<String>,
     pub relaytxes: bool,
@@ -107,106 +145,141 @@ pub struct PeerInfo {
     pub bytesrecv: u64,
     pub conntime: i64,
     pub timeoffset: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub pingtime: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub minping: Option<f64>,
-    pub version: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pingwait: Option<f64>,
+    pub minfeefilter: FeeRate,
+    pub version: u32,
     pub subver: String,
     pub inbound: bool,
     pub startingheight: i32,
-    pub banscore: i32,
     pub synced_headers: i32,
     pub synced_blocks: i32,
+    pub connection_type: String,
+    pub transport_protocol_type: String,
 }
 
-pub async fn getpeerinfo(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    let peers = state.connman.get_peer_info().await;
-    Ok(Json(json!(peers)))
+impl PeerInfo {
+    fn new(stats: PeerStats, header_height: i32, block_height: i32) -> Self {
+        // We only know how far the peer got from its version message and the
+        // headers it sent us; cap that by what we have ourselves.
+        let (synced_headers, synced_blocks) = if stats.best_height < 0 {
+            (-1, -1)
+        } else {
+            (stats.best_height.min(header_height), stats.best_height.min(block_height))
+        };
+
+        Self {
+            id: stats.id,
+            addr: stats.addr.to_string(),
+            network: network_name(&stats.addr).to_string(),
+            services: format!("{:016x}", stats.services),
+            servicesnames: service_names(stats.services),
+            relaytxes: stats.relay_txes,
+            lastsend: stats.last_send,
+            lastrecv: stats.last_recv,
+            bytessent: stats.bytes_sent,
+            bytesrecv: stats.bytes_recv,
+            conntime: stats.conntime,
+            timeoffset: stats.time_offset,
+            pingtime: stats.ping_time.map(|d| d.as_secs_f64()),
+            minping: stats.min_ping.map(|d| d.as_secs_f64()),
+            pingwait: stats.ping_pending.map(|(_, sent)| sent.elapsed().as_secs_f64()),
+            minfeefilter: FeeRate::from_sat_per_kvb(stats.min_fee_filter),
+            version: stats.version,
+            subver: stats.subver,
+            inbound: false,
+            startingheight: stats.starting_height,
+            synced_headers,
+            synced_blocks,
+            connection_type: stats.conn_type.as_str().to_string(),
+            transport_protocol_type: "v1".to_string(),
+        }
+    }
 }
 
-/// getconnectioncount
-pub async fn getconnectioncount(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    let count = state.connman.num_connections();
-    Ok(Json(json!({ "result": count })))
+pub async fn getpeerinfo(state: AppState, _params: Params) -> RpcResult {
+    let p2p = p2p(&state)?;
+    let (header_height, block_height) = (p2p.header_height(), p2p.block_height());
+
+    let peers: Vec<PeerInfo> = p2p
+        .peers()
+        .into_iter()
+        .map(|stats| PeerInfo::new(stats, header_height, block_height))
+        .collect();
+
+    Ok(json!(peers))
 }
 
-/// addnode
-#[derive(Deserialize)]
-pub struct AddNodeParams {
-    pub node: String,
-    pub command: String, // "add", "remove", "onetry"
+/// getconnectioncount
+pub async fn getconnectioncount(state: AppState, _params: Params) -> RpcResult {
+    Ok(json!(p2p(&state)?.peer_count()))
 }
 
-pub async fn addnode(
-    State(state): State<AppState>,
-    Json(params): Json<AddNodeParams>,
-) -> Result<Json<Value>, StatusCode> {
-    match params.command.as_str() {
+/// addnode "node" "command"
+pub async fn addnode(state: AppState, params: Params) -> RpcResult {
+    let p2p = p2p(&state)?;
+    let node: String = params.required(0, "node")?;
+    let command: String = params.required(1, "command")?;
+
+    match command.as_str() {
+        "onetry" => {
+            let addr = resolve_node(&node, p2p.default_port())
+                .await
+                .ok_or_else(|| RpcError::new(RPC_CLIENT_NODE_NOT_CONNECTED, format!("Error: Unable to resolve {}", node)))?;
+            p2p.send(P2PCommand::Connect { addr, conn_type: ConnectionType::Manual });
+        }
         "add" => {
-            let addr: SocketAddr = params.node.parse()
-                .map_err(|_| StatusCode::BAD_REQUEST)?;
-            
-            state.connman.add_node(addr).await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            
-            Ok(Json(json!({ "result": null })))
+            let mut added = p2p.added_nodes();
+            if added.contains(&node) {
+                return Err(RpcError::new(RPC_CLIENT_NODE_ALREADY_ADDED, "Error: Node already added"));
+            }
+            added.push(node);
         }
         "remove" => {
-            let addr: SocketAddr = params.node.parse()
-                .map_err(|_| StatusCode::BAD_REQUEST)?;
-            
-            state.connman.remove_node(&addr).await;
-            Ok(Json(json!({ "result": null })))
-        }
-        "onetry" => {
-            let addr: SocketAddr = params.node.parse()
-                .map_err(|_| StatusCode::BAD_REQUEST)?;
-            
-            state.connman.connect_onetry(addr).await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            
-            Ok(Json(json!({ "result": null })))
+            let mut added = p2p.added_nodes();
+            let before = added.len();
+            added.retain(|n| n != &node);
+            if added.len() == before {
+                return Err(RpcError::new(
+                    RPC_CLIENT_NODE_NOT_ADDED,
+                    "Error: Node could not be removed. It has not been added previously.",
+                ));
+            }
         }
-        _ => Err(StatusCode::BAD_REQUEST)
+        _ => return Err(RpcError::invalid_parameter("command must be one of \"add\", \"remove\" or \"onetry\"")),
     }
-}
 
-/// disconnectnode
-#[derive(Deserialize)]
-pub struct DisconnectNodeParams {
-    #[serde(default)]
-    pub address: Option<String>,
-    #[serde(default)]
-    pub nodeid: Option<u64>,
+    Ok(Value::Null)
 }
 
-pub async fn disconnectnode(
-    State(state): State<AppState>,
-    Json(params): Json<DisconnectNodeParams>,
-) -> Result<Json<Value>, StatusCode> {
-    if let Some(nodeid) = params.nodeid {
-        state.connman.disconnect_node(nodeid).await;
-        Ok(Json(json!({ "result": null })))
-    } else if let Some(address) = params.address {
-        let addr: SocketAddr = address.parse()
-            .map_err(|_| StatusCode::BAD_REQUEST)?;
-        state.connman.disconnect_by_address(&addr).await;
-        Ok(Json(json!({ "result": null })))
-    } else {
-        Err(StatusCode::BAD_REQUEST)
-    }
-}
+/// disconnectnode ( "address" nodeid )
+pub async fn disconnectnode(state: AppState, params: Params) -> RpcResult {
+    let p2p = p2p(&state)?;
+    let address: Option<String> = params.optional(0, "address")?;
+    let nodeid: Option<u64> = params.optional(1, "nodeid")?;
+
+    let peer = match (address.filter(|a| !a.is_empty()), nodeid) {
+        (Some(address), None) => {
+            let addr: SocketAddr = address
+                .parse()
+                .map_err(|_| RpcError::new(RPC_CLIENT_NODE_NOT_CONNECTED, "Node not found in connected nodes"))?;
+            p2p.find_peer(&addr)
+        }
+        (None, Some(id)) => p2p.peer_by_id(id),
+        _ => return Err(RpcError::invalid_parameter("Only one of address and nodeid should be provided.")),
+    };
 
-/// getaddednodeinfo
-#[derive(Deserialize)]
-pub struct GetAddedNodeInfoParams {
-    #[serde(default)]
-    pub node: Option<String>,
+    let peer = peer.ok_or_else(|| RpcError::new(RPC_CLIENT_NODE_NOT_CONNECTED, "Node not found in connected nodes"))?;
+    p2p.send(P2PCommand::Disconnect(peer.id));
+
+    Ok(Value::Null)
 }
 
+/// getaddednodeinfo ( "node" )
 #[derive(Serialize)]
 pub struct AddedNodeInfo {
     pub addednode: String,
@@ -220,21 +293,34 @@ pub struct AddedNodeAddress {
     pub connected: String, // "inbound" or "outbound"
 }
 
-pub async fn getaddednodeinfo(
-    State(state): State<AppState>,
-    Json(params): Json<GetAddedNodeInfoParams>,
-) -> Result<Json<Value>, StatusCode> {
-    let added_nodes = state.connman.get_added_nodes().await;
-
-    let result: Vec<AddedNodeInfo> = if let Some(node) = params.node {
-        added_nodes.into_iter()
-            .filter(|n| n.addednode == node)
-            .collect()
-    } else {
-        added_nodes
-    };
+pub async fn getaddednodeinfo(state: AppState, params: Params) -> RpcResult {
+    let p2p = p2p(&state)?;
+    let filter: Option<String> = params.optional(0, "node")?;
+
+    let mut nodes = p2p.added_nodes().clone();
+    if let Some(node) = filter {
+        if !nodes.contains(&node) {
+            return Err(RpcError::new(RPC_CLIENT_NODE_NOT_ADDED, "Error: Node has not been added."));
+        }
+        nodes.retain(|n| n == &node);
+    }
+
+    let mut result = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let peer = match resolve_node(&node, p2p.default_port()).await {
+            Some(addr) => p2p.find_peer(&addr),
+            None => None,
+        };
+        result.push(AddedNodeInfo {
+            addednode: node,
+            connected: peer.is_some(),
+            addresses: peer
+                .map(|p| vec![AddedNodeAddress { address: p.addr.to_string(), connected: "outbound".to_string() }])
+                .unwrap_or_default(),
+        });
+    }
 
-    Ok(Json(json!(result)))
+    Ok(json!(result))
 }
 
 /// getnettotals
@@ -256,11 +342,9 @@ pub struct UploadTarget {
     pub time_left_in_cycle: u64,
 }
 
-pub async fn getnettotals(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    let (bytes_recv, bytes_sent) = state.connman.get_net_totals().await;
-    
+pub async fn getnettotals(state: AppState, _params: Params) -> RpcResult {
+    let (bytes_recv, bytes_sent) = p2p(&state)?.net_totals();
+
     let totals = NetTotals {
         totalbytesrecv: bytes_recv,
         totalbytessent: bytes_sent,
@@ -275,89 +359,101 @@ pub async fn getnettotals(
         },
     };
 
-    Ok(Json(json!(totals)))
-}
-
-/// getnetworkactive
-pub async fn getnetworkactive(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    let active = state.connman.is_network_active().await;
-    Ok(Json(json!({ "result": active })))
-}
-
-/// setnetworkactive
-#[derive(Deserialize)]
-pub struct SetNetworkActiveParams {
-    pub state: bool,
+    Ok(json!(totals))
 }
 
-pub async fn setnetworkactive(
-    State(state): State<AppState>,
-    Json(params): Json<SetNetworkActiveParams>,
-) -> Result<Json<Value>, StatusCode> {
-    state.connman.set_network_active(params.state).await;
-    Ok(Json(json!({ "result": params.state })))
+/// setnetworkactive state
+pub async fn setnetworkactive(state: AppState, params: Params) -> RpcResult {
+    let p2p = p2p(&state)?;
+    let active: bool = params.required(0, "state")?;
+    p2p.set_network_active(active);
+    Ok(json!(p2p.network_active()))
 }
 
 /// listbanned
 #[derive(Serialize)]
 pub struct BannedNode {
     pub address: String,
-    pub banned_until: i64,
     pub ban_created: i64,
-    pub ban_reason: String,
+    pub banned_until: i64,
+    pub ban_duration: i64,
+    pub time_remaining: i64,
 }
 
-pub async fn listbanned(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    let banned = state.connman.get_banned_list().await;
-    Ok(Json(json!(banned)))
+pub async fn listbanned(state: AppState, _params: Params) -> RpcResult {
+    let p2p = p2p(&state)?;
+    let now = chrono::Utc::now().timestamp();
+
+    let banned: Vec<BannedNode> = p2p
+        .banman()
+        .list()
+        .into_iter()
+        .map(|(subnet, entry)| BannedNode {
+            address: subnet.to_string(),
+            ban_created: entry.create_time,
+            banned_until: entry.ban_until,
+            ban_duration: entry.ban_until - entry.create_time,
+            time_remaining: (entry.ban_until - now).max(0),
+        })
+        .collect();
+
+    Ok(json!(banned))
 }
 
-/// setban
-#[derive(Deserialize)]
-pub struct SetBanParams {
-    pub subnet: String,
-    pub command: String, // "add" or "remove"
-    #[serde(default)]
-    pub bantime: Option<i64>,
-    #[serde(default)]
-    pub absolute: bool,
-}
+/// setban "subnet" "command" ( bantime absolute )
+pub async fn setban(state: AppState, params: Params) -> RpcResult {
+    let p2p = p2p(&state)?;
+    let subnet_str: String = params.required(0, "subnet")?;
+    let command: String = params.required(1, "command")?;
+    let bantime: i64 = params.optional_or(2, "bantime", 0)?;
+    let absolute: bool = params.optional_or(3, "absolute", false)?;
 
-pub async fn setban(
-    State(state): State<AppState>,
-    Json(params): Json<SetBanParams>,
-) -> Result<Json<Value>, StatusCode> {
-    match params.command.as_str() {
-        "add" => {
-            let bantime = params.bantime.unwrap_or(86400); // 24h default
-            state.connman.ban_node(&params.subnet, bantime, params.absolute).await
-                .map_err(|_| StatusCode::BAD_REQUEST)?;
-            Ok(Json(json!({ "result": null })))
-        }
-        "remove" => {
-            state.connman.unban_node(&params.subnet).await;
-            Ok(Json(json!({ "result": null })))
+    if command != "add" && command != "remove" {
+        return Err(RpcError::invalid_parameter("command must be \"add\" or \"remove\""));
+    }
+
+    let subnet: Subnet = subnet_str
+        .parse()
+        .map_err(|_| RpcError::new(RPC_CLIENT_INVALID_IP_OR_SUBNET, "Error: Invalid IP/Subnet"))?;
+
+    if command == "add" {
+        if !p2p.banman().ban(subnet, bantime, absolute) {
+            return Err(RpcError::new(RPC_CLIENT_NODE_ALREADY_ADDED, "Error: IP/Subnet already banned"));
         }
-        _ => Err(StatusCode::BAD_REQUEST)
+        p2p.send(P2PCommand::DisconnectBanned);
+    } else if !p2p.banman().unban(&subnet) {
+        return Err(RpcError::new(
+            RPC_CLIENT_INVALID_IP_OR_SUBNET,
+            "Error: Unban failed. Requested address/subnet was not previously manually banned.",
+        ));
     }
+
+    Ok(Value::Null)
 }
 
 /// clearbanned
-pub async fn clearbanned(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    state.connman.clear_banned().await;
-    Ok(Json(json!({ "result": null })))
+pub async fn clearbanned(state: AppState, _params: Params) -> RpcResult {
+    p2p(&state)?.banman().clear();
+    Ok(Value::Null)
 }
 
 /// ping
-pub async fn ping(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    state.connman.ping_all().await;
-    Ok(Json(json!({ "result": null })))
+pub async fn ping(state: AppState, _params: Params) -> RpcResult {
+    p2p(&state)?.send(P2PCommand::PingAll);
+    Ok(Value::Null)
+}
+
+pub fn register(table: &mut RpcTable) {
+    table.register("network", "getnetworkinfo", &[], |s, p| Box::pin(getnetworkinfo(s, p)));
+    table.register("network", "getpeerinfo", &[], |s, p| Box::pin(getpeerinfo(s, p)));
+    table.register("network", "getconnectioncount", &[], |s, p| Box::pin(getconnectioncount(s, p)));
+    table.register("network", "addnode", &["node", "command"], |s, p| Box::pin(addnode(s, p)));
+    table.register("network", "disconnectnode", &["address", "nodeid"], |s, p| Box::pin(disconnectnode(s, p)));
+    table.register("network", "getaddednodeinfo", &["node"], |s, p| Box::pin(getaddednodeinfo(s, p)));
+    table.register("network", "getnettotals", &[], |s, p| Box::pin(getnettotals(s, p)));
+    table.register("network", "setnetworkactive", &["state"], |s, p| Box::pin(setnetworkactive(s, p)));
+    table.register("network", "listbanned", &[], |s, p| Box::pin(listbanned(s, p)));
+    table.register("network", "setban", &["subnet", "command", "bantime", "absolute"], |s, p| Box::pin(setban(s, p)));
+    table.register("network", "clearbanned", &[], |s, p| Box::pin(clearbanned(s, p)));
+    table.register("network", "ping", &[], |s, p| Box::pin(ping(s, p)));
 }
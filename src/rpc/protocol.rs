@@ -0,0 +1,107 @@
+// src/rpc/protocol.rs
+//! JSON-RPC error codes and error type, mirroring Bitcoin Core's `rpc/protocol.h`
+//! so that `bitcoin-cli` and existing client libraries interpret failures the same way.
+
+use serde_json::{json, Value};
+use std::fmt;
+
+// Standard JSON-RPC 2.0 errors
+pub const RPC_INVALID_REQUEST: i32 = -32600;
+pub const RPC_METHOD_NOT_FOUND: i32 = -32601;
+pub const RPC_INVALID_PARAMS: i32 = -32602;
+pub const RPC_INTERNAL_ERROR: i32 = -32603;
+pub const RPC_PARSE_ERROR: i32 = -32700;
+
+// General application defined errors
+pub const RPC_MISC_ERROR: i32 = -1;
+pub const RPC_TYPE_ERROR: i32 = -3;
+pub const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+pub const RPC_OUT_OF_MEMORY: i32 = -7;
+pub const RPC_INVALID_PARAMETER: i32 = -8;
+pub const RPC_DATABASE_ERROR: i32 = -20;
+pub const RPC_DESERIALIZATION_ERROR: i32 = -22;
+pub const RPC_VERIFY_ERROR: i32 = -25;
+pub const RPC_VERIFY_REJECTED: i32 = -26;
+pub const RPC_VERIFY_ALREADY_IN_CHAIN: i32 = -27;
+pub const RPC_IN_WARMUP: i32 = -28;
+pub const RPC_METHOD_DEPRECATED: i32 = -32;
+
+// P2P client errors
+pub const RPC_CLIENT_NOT_CONNECTED: i32 = -9;
+pub const RPC_CLIENT_IN_INITIAL_DOWNLOAD: i32 = -10;
+pub const RPC_CLIENT_NODE_ALREADY_ADDED: i32 = -23;
+pub const RPC_CLIENT_NODE_NOT_ADDED: i32 = -24;
+pub const RPC_CLIENT_NODE_NOT_CONNECTED: i32 = -29;
+pub const RPC_CLIENT_INVALID_IP_OR_SUBNET: i32 = -30;
+pub const RPC_CLIENT_P2P_DISABLED: i32 = -31;
+
+/// Error returned by an RPC method, serialized as `{"code": .., "message": ..}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+pub type RpcResult<T = Value> = std::result::Result<T, RpcError>;
+
+impl RpcError {
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    pub fn misc(message: impl Into<String>) -> Self {
+        Self::new(RPC_MISC_ERROR, message)
+    }
+
+    pub fn invalid_parameter(message: impl Into<String>) -> Self {
+        Self::new(RPC_INVALID_PARAMETER, message)
+    }
+
+    pub fn invalid_address_or_key(message: impl Into<String>) -> Self {
+        Self::new(RPC_INVALID_ADDRESS_OR_KEY, message)
+    }
+
+    pub fn type_error(message: impl Into<String>) -> Self {
+        Self::new(RPC_TYPE_ERROR, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(RPC_INTERNAL_ERROR, message)
+    }
+
+    pub fn method_not_found() -> Self {
+        Self::new(RPC_METHOD_NOT_FOUND, "Method not found")
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({ "code": self.code, "message": self.message })
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (code {})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_json_shape() {
+        let err = RpcError::invalid_parameter("Block height out of range");
+        assert_eq!(
+            err.to_json(),
+            json!({ "code": -8, "message": "Block height out of range" })
+        );
+    }
+
+    #[test]
+    fn test_error_constructors() {
+        assert_eq!(RpcError::invalid_address_or_key("x").code, RPC_INVALID_ADDRESS_OR_KEY);
+        assert_eq!(RpcError::method_not_found().code, -32601);
+    }
+}
@@ -0,0 +1,502 @@
+// src/rpc/rawtransaction.rs
+use bitcoin::block::Header;
+use bitcoin::consensus::{deserialize, encode::serialize_hex};
+use bitcoin::{BlockHash, OutPoint, Transaction, TxOut, Txid};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::index::coins::MEMPOOL_HEIGHT;
+use crate::index::{self, txindex::TxIndex};
+use crate::kernel::BlockInfo;
+use crate::mempool::policy::MAX_SCRIPT_SIZE;
+use crate::mempool::{
+    check_package_consistency, is_child_with_parents_tree, AcceptedTx, FeeRate, PackageTxResult, TxValidationError,
+    TxValidationResult, MAX_PACKAGE_COUNT,
+};
+use crate::p2p::control::P2PCommand;
+
+use super::blockchain::{lookup_coin, verbosity};
+use super::protocol::{
+    RpcError, RpcResult, RPC_DESERIALIZATION_ERROR, RPC_VERIFY_ALREADY_IN_CHAIN, RPC_VERIFY_ERROR,
+    RPC_IN_WARMUP, RPC_VERIFY_REJECTED,
+};
+use super::server::{amount_from_value, blocking, parse_hash, Params, RpcTable};
+use super::util::{btc, script_pubkey_to_json, tx_to_json};
+use super::AppState;
+
+/// Default `maxfeerate` for sendrawtransaction/testmempoolaccept, in sat/kvB (0.10 BTC/kvB)
+const DEFAULT_MAX_RAW_TX_FEE_RATE: u64 = 10_000_000;
+
+pub(crate) fn decode_tx(hex_str: &str) -> RpcResult<Transaction> {
+    let bytes = hex::decode(hex_str).map_err(|_| {
+        RpcError::new(RPC_DESERIALIZATION_ERROR, "TX decode failed. Make sure the tx has at least one input.")
+    })?;
+    deserialize(&bytes).map_err(|_| {
+        RpcError::new(RPC_DESERIALIZATION_ERROR, "TX decode failed. Make sure the tx has at least one input.")
+    })
+}
+
+/// `maxfeerate` in sat/kvB
+fn max_fee_rate(params: &Params, idx: usize) -> RpcResult<u64> {
+    let Some(value) = params.get(idx) else {
+        return Ok(DEFAULT_MAX_RAW_TX_FEE_RATE);
+    };
+    let rate = amount_from_value(value)?;
+    if rate >= 100_000_000 {
+        return Err(RpcError::invalid_parameter("Fee rates larger than or equal to 1BTC/kvB are not accepted"));
+    }
+    Ok(rate)
+}
+
+/// Largest fee allowed for `vsize` at `max_fee_rate` sat/kvB; 0 disables the check
+fn max_fee_for(max_fee_rate: u64, vsize: u64) -> Option<u64> {
+    (max_fee_rate != 0).then(|| FeeRate::from_sat_per_kvb(max_fee_rate).fee_for_vsize(vsize))
+}
+
+fn is_unspendable(output: &TxOut) -> bool {
+    output.script_pubkey.is_op_return() || output.script_pubkey.len() > MAX_SCRIPT_SIZE
+}
+
+/// `maxburnamount` check shared by sendrawtransaction and submitpackage
+fn check_burn_amount(tx: &Transaction, max_burn: u64) -> RpcResult<()> {
+    if tx.output.iter().any(|o| is_unspendable(o) && o.value.to_sat() > max_burn) {
+        return Err(RpcError::new(
+            RPC_VERIFY_ERROR,
+            "Unspendable output exceeds maximum configured by user (maxburnamount)",
+        ));
+    }
+    Ok(())
+}
+
+fn max_burn_amount(params: &Params, idx: usize) -> RpcResult<u64> {
+    match params.get(idx) {
+        Some(value) => amount_from_value(value),
+        None => Ok(0),
+    }
+}
+
+fn check_package_size(len: usize) -> RpcResult<()> {
+    if len == 0 || len > MAX_PACKAGE_COUNT {
+        return Err(RpcError::invalid_parameter(format!(
+            "Array must contain between 1 and {} transactions.",
+            MAX_PACKAGE_COUNT
+        )));
+    }
+    Ok(())
+}
+
+/// Error for a mempool rejection in sendrawtransaction (Bitcoin Core: BroadcastTransaction)
+fn rejection_error(state: &AppState, tx: &Transaction, e: &TxValidationError) -> RpcError {
+    if e.result == TxValidationResult::MissingInputs {
+        // Missing inputs may just mean the transaction already confirmed
+        let txid = tx.compute_txid();
+        let confirmed = (0..tx.output.len()).any(|vout| {
+            matches!(lookup_coin(state, &OutPoint::new(txid, vout as u32), false), Ok(Some(coin)) if coin.height != MEMPOOL_HEIGHT)
+        });
+        if confirmed {
+            return RpcError::new(RPC_VERIFY_ALREADY_IN_CHAIN, "Transaction outputs already in utxo set");
+        }
+        return RpcError::new(RPC_VERIFY_ERROR, "Missing inputs");
+    }
+    if e.result == TxValidationResult::NotReady {
+        return RpcError::new(RPC_IN_WARMUP, e.to_string());
+    }
+    RpcError::new(RPC_VERIFY_REJECTED, e.to_string())
+}
+
+/// sendrawtransaction "hexstring" ( maxfeerate maxburnamount )
+pub async fn sendrawtransaction(state: AppState, params: Params) -> RpcResult {
+    let hex_str: String = params.required(0, "hexstring")?;
+    let tx = decode_tx(&hex_str)?;
+    let max_fee_rate = max_fee_rate(&params, 1)?;
+    check_burn_amount(&tx, max_burn_amount(&params, 2)?)?;
+
+    let txid = tx.compute_txid();
+    let tx = Arc::new(tx);
+    let s = state.clone();
+    let t = tx.clone();
+    blocking(move || {
+        // Already in the mempool: just relay it again
+        if s.mempool.contains(&txid) {
+            return Ok(());
+        }
+
+        let checked = s.mempool.test_accept(&t, &HashMap::new()).map_err(|e| rejection_error(&s, &t, &e))?;
+        if max_fee_for(max_fee_rate, checked.vsize).is_some_and(|max_fee| checked.fee > max_fee) {
+            return Err(RpcError::new(
+                RPC_VERIFY_ERROR,
+                "Fee exceeds maximum configured by user (e.g. -maxtxfee, maxfeerate)",
+            ));
+        }
+
+        let accepted = s.mempool.accept_tx((*t).clone()).map_err(|e| rejection_error(&s, &t, &e))?;
+        eprintln!("[rpc] sendrawtransaction accepted {} (fee {} sat)", accepted.txid, accepted.fee);
+        if let Some(replacement) = &accepted.replacement {
+            eprintln!(
+                "[rpc] {} replaced {} transaction(s) paying {} sat",
+                accepted.txid,
+                replacement.replaced.len(),
+                replacement.replaced_fees
+            );
+        }
+        Ok(())
+    })
+    .await?;
+
+    // Tracked until a peer asks for it; rebroadcast until then
+    state.mempool.add_unbroadcast(txid);
+    if let Some(p2p) = &state.p2p {
+        p2p.send(P2PCommand::RelayTransaction(tx));
+    }
+    Ok(json!(txid.to_string()))
+}
+
+fn fees_json(accepted: &AcceptedTx) -> Value {
+    json!({
+        "base": btc(accepted.fee),
+        "effective-feerate": FeeRate::from_fee_and_vsize(accepted.effective_fee, accepted.effective_vsize),
+        "effective-includes": accepted.effective_includes.iter().map(|w| w.to_string()).collect::<Vec<_>>(),
+    })
+}
+
+fn accept_result_json(accepted: &AcceptedTx) -> Value {
+    json!({
+        "txid": accepted.txid.to_string(),
+        "wtxid": accepted.wtxid.to_string(),
+        "allowed": true,
+        "vsize": accepted.vsize,
+        "fees": fees_json(accepted),
+    })
+}
+
+/// testmempoolaccept ["rawtx",...] ( maxfeerate )
+///
+/// Runs mempool acceptance for up to 25 transactions without adding them.
+/// Several transactions are evaluated as a package: later ones may spend
+/// outputs of earlier ones.
+pub async fn testmempoolaccept(state: AppState, params: Params) -> RpcResult {
+    let rawtxs: Vec<String> = params.required(0, "rawtxs")?;
+    check_package_size(rawtxs.len())?;
+    let max_fee_rate = max_fee_rate(&params, 1)?;
+    let txs = rawtxs.iter().map(|hex_str| decode_tx(hex_str)).collect::<RpcResult<Vec<_>>>()?;
+
+    let mempool = state.mempool.clone();
+    blocking(move || {
+        let ids = |tx: &Transaction| {
+            json!({ "txid": tx.compute_txid().to_string(), "wtxid": tx.compute_wtxid().to_string() })
+        };
+
+        if txs.len() > 1 {
+            if let Err(package_error) = check_package_consistency(&txs) {
+                let results: Vec<Value> = txs
+                    .iter()
+                    .map(|tx| {
+                        let mut obj = ids(tx);
+                        obj["package-error"] = json!(package_error);
+                        obj
+                    })
+                    .collect();
+                return Ok(json!(results));
+            }
+        }
+
+        let mut package_outputs: HashMap<OutPoint, TxOut> = HashMap::new();
+        let mut results = Vec::with_capacity(txs.len());
+        let mut failed = None;
+        for (i, tx) in txs.iter().enumerate() {
+            let result = match mempool.test_accept(tx, &package_outputs) {
+                Ok(accepted) if max_fee_for(max_fee_rate, accepted.vsize).is_some_and(|max| accepted.fee > max) => {
+                    Err("max-fee-exceeded".to_string())
+                }
+                Ok(accepted) => Ok(accepted),
+                Err(e) => Err(e.to_string()),
+            };
+            match result {
+                Ok(accepted) => {
+                    let txid = accepted.txid;
+                    for (vout, output) in tx.output.iter().enumerate() {
+                        package_outputs.insert(OutPoint::new(txid, vout as u32), output.clone());
+                    }
+                    results.push(accept_result_json(&accepted));
+                }
+                Err(reason) => {
+                    let mut obj = ids(tx);
+                    obj["allowed"] = json!(false);
+                    obj["reject-reason"] = json!(reason);
+                    results.push(obj);
+                    failed = Some(i);
+                    break;
+                }
+            }
+        }
+
+        // A package is accepted or rejected as a whole
+        if let Some(failed) = failed.filter(|_| txs.len() > 1) {
+            let failed_result = results.pop();
+            results = txs
+                .iter()
+                .enumerate()
+                .map(|(i, tx)| match (i == failed, &failed_result) {
+                    (true, Some(result)) => result.clone(),
+                    _ => {
+                        let mut obj = ids(tx);
+                        obj["package-error"] = json!("transaction failed");
+                        obj
+                    }
+                })
+                .collect();
+        }
+
+        Ok(json!(results))
+    })
+    .await
+}
+
+/// submitpackage ["rawtx",...] ( maxfeerate maxburnamount )
+///
+/// Submits a child with its unconfirmed parents, so the child's fees can pay
+/// for parents below the mempool minimum feerate.
+pub async fn submitpackage(state: AppState, params: Params) -> RpcResult {
+    let rawtxs: Vec<String> = params.required(0, "package")?;
+    check_package_size(rawtxs.len())?;
+    let max_fee_rate = max_fee_rate(&params, 1)?;
+    let max_burn = max_burn_amount(&params, 2)?;
+
+    let txs = rawtxs.iter().map(|hex_str| decode_tx(hex_str)).collect::<RpcResult<Vec<_>>>()?;
+    for tx in &txs {
+        check_burn_amount(tx, max_burn)?;
+    }
+    if txs.len() > 1 && !is_child_with_parents_tree(&txs) {
+        return Err(RpcError::invalid_parameter(
+            "package topology disallowed. not child-with-parents or parents depend on each other.",
+        ));
+    }
+
+    let mempool = state.mempool.clone();
+    let package = txs.clone();
+    let max_fee_rate = (max_fee_rate != 0).then(|| FeeRate::from_sat_per_kvb(max_fee_rate));
+    let result = blocking(move || Ok(mempool.accept_package(&package, max_fee_rate))).await?;
+
+    let mut tx_results = serde_json::Map::new();
+    let mut replaced = Vec::new();
+    for tx in &txs {
+        let wtxid = tx.compute_wtxid();
+        let mut obj = json!({ "txid": tx.compute_txid().to_string() });
+        match result.results.get(&wtxid) {
+            Some(PackageTxResult::Accepted(accepted)) | Some(PackageTxResult::AlreadyInMempool(accepted)) => {
+                obj["vsize"] = json!(accepted.vsize);
+                obj["fees"] = fees_json(accepted);
+                if let Some(replacement) = &accepted.replacement {
+                    replaced.extend(replacement.replaced.iter().map(|(txid, _)| txid.to_string()));
+                }
+                // Relay everything that ended up in the mempool, parents first
+                state.mempool.add_unbroadcast(accepted.txid);
+                if let Some(p2p) = &state.p2p {
+                    p2p.send(P2PCommand::RelayTransaction(Arc::new(tx.clone())));
+                }
+            }
+            Some(PackageTxResult::Rejected(e)) => obj["error"] = json!(e.to_string()),
+            None => obj["error"] = json!("unevaluated"),
+        }
+        tx_results.insert(wtxid.to_string(), obj);
+    }
+
+    if let Some(error) = &result.error {
+        eprintln!("[rpc] submitpackage rejected: {}", error);
+    }
+    Ok(json!({
+        "package_msg": result.error.as_deref().unwrap_or("success"),
+        "tx-results": tx_results,
+        "replaced-transactions": replaced,
+    }))
+}
+
+fn internal(e: anyhow::Error) -> RpcError {
+    RpcError::internal(e.to_string())
+}
+
+/// Find `txid` in `blockhash`, or else in the mempool and then the
+/// transaction index (Bitcoin Core: GetTransaction). Returns the block it
+/// was confirmed in, if any.
+fn find_transaction(
+    state: &AppState,
+    txid: &Txid,
+    blockhash: Option<&BlockHash>,
+) -> RpcResult<(Transaction, Option<(BlockInfo, Header)>)> {
+    let k = &state.kernel;
+    if let Some(hash) = blockhash {
+        let info = k
+            .get_block_info(hash)
+            .map_err(internal)?
+            .ok_or_else(|| RpcError::invalid_address_or_key("Block hash not found"))?;
+        let block = k.get_block(hash).map_err(internal)?.ok_or_else(|| RpcError::misc("Block not available"))?;
+        let tx = block.txdata.into_iter().find(|tx| tx.compute_txid() == *txid).ok_or_else(|| {
+            RpcError::invalid_address_or_key(
+                "No such transaction found in the provided block. Use gettransaction for wallet transactions.",
+            )
+        })?;
+        return Ok((tx, Some((info, block.header))));
+    }
+
+    if let Some(tx) = state.mempool.get_tx(txid) {
+        return Ok(((*tx).clone(), None));
+    }
+    let Some(txindex) = &state.txindex else {
+        return Err(RpcError::invalid_address_or_key(
+            "No such mempool transaction. Use -txindex or provide a block hash to enable blockchain transaction queries. Use gettransaction for wallet transactions.",
+        ));
+    };
+    match txindex.get_transaction(k, txid).map_err(internal)? {
+        Some((tx, location)) => {
+            let info = k.get_block_info(&location.block_hash).map_err(internal)?;
+            let header = k.get_block_header(&location.block_hash).map_err(internal)?;
+            Ok((tx, info.zip(header)))
+        }
+        None if index::is_synced(txindex.as_ref(), k) => Err(RpcError::invalid_address_or_key(
+            "No such mempool or blockchain transaction. Use gettransaction for wallet transactions.",
+        )),
+        None => Err(RpcError::invalid_address_or_key(
+            "No such mempool or blockchain transaction. Blockchain transactions are still in the process of being indexed. Use gettransaction for wallet transactions.",
+        )),
+    }
+}
+
+/// Add the spent outputs and the fee to a confirmed transaction's JSON
+/// (verbosity 2). Bitcoin Core reads them from undo data; here they come
+/// from the transaction index, and nothing is added without it.
+fn add_prevouts(txindex: &TxIndex, state: &AppState, tx: &Transaction, result: &mut Value) -> RpcResult<()> {
+    if tx.is_coinbase() {
+        return Ok(());
+    }
+    let network = state.kernel.network();
+    let mut prevouts = Vec::with_capacity(tx.input.len());
+    for input in &tx.input {
+        let outpoint = input.previous_output;
+        let Some((prev, location)) = txindex.get_transaction(&state.kernel, &outpoint.txid).map_err(internal)? else {
+            return Ok(());
+        };
+        let Some(output) = prev.output.get(outpoint.vout as usize) else {
+            return Ok(());
+        };
+        prevouts.push((output.clone(), location.height, prev.is_coinbase()));
+    }
+
+    let spent: u64 = prevouts.iter().map(|(output, ..)| output.value.to_sat()).sum();
+    let created: u64 = tx.output.iter().map(|output| output.value.to_sat()).sum();
+    for (vin, (output, height, generated)) in prevouts.into_iter().enumerate() {
+        result["vin"][vin]["prevout"] = json!({
+            "generated": generated,
+            "height": height,
+            "value": btc(output.value.to_sat()),
+            "scriptPubKey": script_pubkey_to_json(&output.script_pubkey, network),
+        });
+    }
+    result["fee"] = json!(btc(spent.saturating_sub(created)));
+    Ok(())
+}
+
+/// getrawtransaction "txid" ( verbosity "blockhash" )
+///
+/// A mempool transaction, or with -txindex any confirmed one; with
+/// `blockhash` only that block is searched. Verbosity 0 returns the hex,
+/// 1 the decoded transaction and its block, and 2 adds the fee and the
+/// spent outputs of confirmed transactions.
+pub async fn getrawtransaction(state: AppState, params: Params) -> RpcResult {
+    let txid: String = params.required(0, "txid")?;
+    let txid: Txid = parse_hash(&txid, "parameter 1")?;
+    let verbosity = verbosity(&params, 1, "verbosity", 0)?;
+    let blockhash: Option<String> = params.optional(2, "blockhash")?;
+    let blockhash = blockhash.map(|h| parse_hash::<BlockHash>(&h, "parameter 3")).transpose()?;
+
+    blocking(move || {
+        let network = state.kernel.network();
+        if txid == bitcoin::constants::genesis_block(network).txdata[0].compute_txid() {
+            return Err(RpcError::invalid_address_or_key(
+                "The genesis block coinbase is not considered an ordinary transaction and cannot be retrieved",
+            ));
+        }
+
+        let (tx, block) = find_transaction(&state, &txid, blockhash.as_ref())?;
+        if verbosity == 0 {
+            return Ok(json!(serialize_hex(&tx)));
+        }
+
+        let mut result = tx_to_json(&tx, network);
+        let Some((info, header)) = block else {
+            return Ok(result);
+        };
+        if blockhash.is_some() {
+            result["in_active_chain"] = json!(info.in_active_chain);
+        }
+        let tip = state.kernel.get_height().map_err(internal)?;
+        result["blockhash"] = json!(info.hash.to_string());
+        result["confirmations"] = json!(if info.in_active_chain { tip - info.height + 1 } else { 0 });
+        result["time"] = json!(header.time);
+        result["blocktime"] = json!(header.time);
+        if verbosity >= 2 {
+            if let Some(txindex) = &state.txindex {
+                add_prevouts(txindex, &state, &tx, &mut result)?;
+            }
+        }
+        Ok(result)
+    })
+    .await
+}
+
+pub fn register(table: &mut RpcTable) {
+    table.register("rawtransactions", "getrawtransaction", &["txid", "verbosity", "blockhash"], |s, p| Box::pin(getrawtransaction(s, p)));
+    table.register("rawtransactions", "sendrawtransaction", &["hexstring", "maxfeerate", "maxburnamount"], |s, p| Box::pin(sendrawtransaction(s, p)));
+    table.register("rawtransactions", "testmempoolaccept", &["rawtxs", "maxfeerate"], |s, p| Box::pin(testmempoolaccept(s, p)));
+    table.register("rawtransactions", "submitpackage", &["package", "maxfeerate", "maxburnamount"], |s, p| Box::pin(submitpackage(s, p)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{Amount, ScriptBuf};
+
+    #[test]
+    fn test_max_fee_for() {
+        // 0.10 BTC/kvB on a 200 vB transaction
+        assert_eq!(max_fee_for(DEFAULT_MAX_RAW_TX_FEE_RATE, 200), Some(2_000_000));
+        assert_eq!(max_fee_for(0, 200), None);
+    }
+
+    #[test]
+    fn test_max_fee_rate_param() {
+        assert_eq!(max_fee_rate(&Params::positional(vec![]), 1).unwrap(), DEFAULT_MAX_RAW_TX_FEE_RATE);
+        assert_eq!(max_fee_rate(&Params::positional(vec![json!("x"), json!(0)]), 1).unwrap(), 0);
+        assert!(max_fee_rate(&Params::positional(vec![json!("x"), json!(1)]), 1).is_err());
+    }
+
+    #[test]
+    fn test_unspendable_outputs() {
+        let burn = TxOut { value: Amount::from_sat(1), script_pubkey: ScriptBuf::from(vec![0x6a, 0x01, 0x00]) };
+        let normal = TxOut { value: Amount::from_sat(1), script_pubkey: ScriptBuf::from(vec![0x51]) };
+        assert!(is_unspendable(&burn));
+        assert!(!is_unspendable(&normal));
+    }
+
+    #[test]
+    fn test_package_size_and_burn_checks() {
+        assert!(check_package_size(0).is_err());
+        assert!(check_package_size(MAX_PACKAGE_COUNT).is_ok());
+        assert!(check_package_size(MAX_PACKAGE_COUNT + 1).is_err());
+
+        let tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut { value: Amount::from_sat(500), script_pubkey: ScriptBuf::from(vec![0x6a]) }],
+        };
+        assert_eq!(check_burn_amount(&tx, 0).unwrap_err().code, RPC_VERIFY_ERROR);
+        assert!(check_burn_amount(&tx, 500).is_ok());
+    }
+
+    #[test]
+    fn test_decode_tx_error() {
+        let err = decode_tx("zz").unwrap_err();
+        assert_eq!(err.code, RPC_DESERIALIZATION_ERROR);
+    }
+}
@@ -0,0 +1,527 @@
+// src/rpc/rest.rs
+//! Unauthenticated, read-only REST interface (Bitcoin Core: rest.cpp).
+//!
+//! Enabled with `--rest` and served on the RPC port. Every endpoint takes an
+//! output format suffix: `.bin` (raw bytes), `.hex` or `.json`.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use bitcoin::consensus::{deserialize, serialize, Decodable, Encodable};
+use bitcoin::hashes::Hash;
+use bitcoin::{BlockHash, OutPoint, Txid, VarInt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::index::coins::Coin;
+use crate::index::BlockIndex;
+
+use super::blockchain::{self, lookup_block, lookup_coin, next_block_hash, read_block};
+use super::protocol::{RpcError, RPC_INVALID_ADDRESS_OR_KEY};
+use super::server::{blocking, Params};
+use super::util::{block_to_json, btc, header_to_json, script_pubkey_to_json};
+use super::AppState;
+
+const MAX_REST_HEADERS_RESULTS: usize = 2000;
+const MAX_GETUTXOS_OUTPOINTS: usize = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RestFormat {
+    Bin,
+    Hex,
+    Json,
+}
+
+/// Split "<param>.<ext>" into the parameter and its output format
+fn parse_data_format(param: &str) -> (&str, Option<RestFormat>) {
+    let Some((value, ext)) = param.rsplit_once('.') else {
+        return (param, None);
+    };
+    let format = match ext {
+        "bin" => Some(RestFormat::Bin),
+        "hex" => Some(RestFormat::Hex),
+        "json" => Some(RestFormat::Json),
+        _ => None,
+    };
+    (value, format)
+}
+
+fn rest_err(status: StatusCode, message: impl Into<String>) -> Response {
+    let body = format!("{}\r\n", message.into());
+    (status, [(header::CONTENT_TYPE, "text/plain")], body).into_response()
+}
+
+fn format_not_found(json_only: bool) -> Response {
+    let available = if json_only { "json" } else { "bin, hex, json" };
+    rest_err(StatusCode::NOT_FOUND, format!("output format not found (available: {})", available))
+}
+
+fn internal_err(e: RpcError) -> Response {
+    rest_err(StatusCode::INTERNAL_SERVER_ERROR, e.message)
+}
+
+fn bin_response(data: Vec<u8>) -> Response {
+    ([(header::CONTENT_TYPE, "application/octet-stream")], data).into_response()
+}
+
+fn hex_response(data: &[u8]) -> Response {
+    ([(header::CONTENT_TYPE, "text/plain")], format!("{}\n", hex::encode(data))).into_response()
+}
+
+fn json_response(value: Value) -> Response {
+    ([(header::CONTENT_TYPE, "application/json")], format!("{}\n", value)).into_response()
+}
+
+fn parse_block_hash(s: &str) -> Result<BlockHash, Response> {
+    if s.len() != 64 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(rest_err(StatusCode::BAD_REQUEST, format!("Invalid hash: {}", s)));
+    }
+    BlockHash::from_str(s).map_err(|_| rest_err(StatusCode::BAD_REQUEST, format!("Invalid hash: {}", s)))
+}
+
+// ============================================================================
+// Blocks and headers
+// ============================================================================
+
+async fn rest_block(state: &AppState, param: &str, tx_details: bool) -> Response {
+    let (hash_str, format) = parse_data_format(param);
+    let Some(format) = format else { return format_not_found(false) };
+    let hash = match parse_block_hash(hash_str) {
+        Ok(h) => h,
+        Err(resp) => return resp,
+    };
+
+    let k = state.kernel.clone();
+    let result = blocking(move || {
+        let info = lookup_block(&k, &hash)?;
+        let raw = read_block(&k, &hash)?;
+        if format != RestFormat::Json {
+            return Ok((raw, Value::Null));
+        }
+        let block: bitcoin::Block = deserialize(&raw)
+            .map_err(|e| RpcError::internal(format!("Block deserialization failed: {}", e)))?;
+        let tip = k.get_height().map_err(|e| RpcError::internal(e.to_string()))?;
+        let next = next_block_hash(&k, &info);
+        Ok((Vec::new(), block_to_json(&block, &info, tip, next, k.network(), tx_details)))
+    })
+    .await;
+
+    match result {
+        Ok((raw, obj)) => match format {
+            RestFormat::Bin => bin_response(raw),
+            RestFormat::Hex => hex_response(&raw),
+            RestFormat::Json => json_response(obj),
+        },
+        Err(e) if e.code == RPC_INVALID_ADDRESS_OR_KEY => {
+            rest_err(StatusCode::NOT_FOUND, format!("{} not found", hash))
+        }
+        Err(e) => rest_err(StatusCode::NOT_FOUND, format!("{} not found ({})", hash, e.message)),
+    }
+}
+
+/// GET /rest/block/<hash>.<bin|hex|json>
+async fn block(State(state): State<AppState>, Path(param): Path<String>) -> Response {
+    rest_block(&state, &param, true).await
+}
+
+/// GET /rest/block/notxdetails/<hash>.<bin|hex|json>
+async fn block_notxdetails(State(state): State<AppState>, Path(param): Path<String>) -> Response {
+    rest_block(&state, &param, false).await
+}
+
+async fn rest_headers(state: &AppState, count_str: &str, param: &str) -> Response {
+    let (hash_str, format) = parse_data_format(param);
+    let Some(format) = format else { return format_not_found(false) };
+
+    let count = match count_str.parse::<usize>() {
+        Ok(c) if (1..=MAX_REST_HEADERS_RESULTS).contains(&c) => c,
+        _ => {
+            return rest_err(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Header count is invalid or out of acceptable range (1-{}): {}",
+                    MAX_REST_HEADERS_RESULTS, count_str
+                ),
+            )
+        }
+    };
+    let hash = match parse_block_hash(hash_str) {
+        Ok(h) => h,
+        Err(resp) => return resp,
+    };
+
+    let k = state.kernel.clone();
+    let result = blocking(move || {
+        let tip = k.get_height().map_err(|e| RpcError::internal(e.to_string()))?;
+        let mut headers = Vec::new();
+
+        // Walk the active chain forward from `hash`
+        let mut next = match k.get_block_info(&hash).map_err(|e| RpcError::internal(e.to_string()))? {
+            Some(info) if info.in_active_chain => Some(info),
+            _ => None,
+        };
+        while let Some(info) = next {
+            let raw = read_block(&k, &info.hash)?;
+            let block: bitcoin::Block = deserialize(&raw)
+                .map_err(|e| RpcError::internal(format!("Block deserialization failed: {}", e)))?;
+            let next_hash = next_block_hash(&k, &info);
+            headers.push((block.header, block.txdata.len(), info.clone(), next_hash));
+            if headers.len() == count {
+                break;
+            }
+            next = match next_hash {
+                Some(h) => k.get_block_info(&h).map_err(|e| RpcError::internal(e.to_string()))?,
+                None => None,
+            };
+        }
+        Ok((headers, tip))
+    })
+    .await;
+
+    let (headers, tip) = match result {
+        Ok(r) => r,
+        Err(e) => return internal_err(e),
+    };
+
+    match format {
+        RestFormat::Bin | RestFormat::Hex => {
+            let mut data = Vec::with_capacity(headers.len() * 80);
+            for (header, ..) in &headers {
+                data.extend_from_slice(&serialize(header));
+            }
+            if format == RestFormat::Bin {
+                bin_response(data)
+            } else {
+                hex_response(&data)
+            }
+        }
+        RestFormat::Json => json_response(json!(headers
+            .iter()
+            .map(|(header, n_tx, info, next)| header_to_json(header, info, tip, *next, Some(*n_tx)))
+            .collect::<Vec<_>>())),
+    }
+}
+
+/// GET /rest/headers/<count>/<hash>.<bin|hex|json>
+async fn headers_with_count(
+    State(state): State<AppState>,
+    Path((count, param)): Path<(String, String)>,
+) -> Response {
+    rest_headers(&state, &count, &param).await
+}
+
+/// GET /rest/headers/<hash>.<bin|hex|json>?count=<count>
+async fn headers_with_query(
+    State(state): State<AppState>,
+    Path(param): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let count = query.get("count").cloned().unwrap_or_else(|| "5".to_string());
+    rest_headers(&state, &count, &param).await
+}
+
+/// GET /rest/blockhashbyheight/<height>.<bin|hex|json>
+async fn blockhashbyheight(State(state): State<AppState>, Path(param): Path<String>) -> Response {
+    let (height_str, format) = parse_data_format(&param);
+    let Some(format) = format else { return format_not_found(false) };
+    let height = match height_str.parse::<i32>() {
+        Ok(h) if h >= 0 => h,
+        _ => return rest_err(StatusCode::BAD_REQUEST, format!("Invalid height: {}", height_str)),
+    };
+
+    let k = state.kernel.clone();
+    let result = blocking(move || {
+        let tip = k.get_height().map_err(|e| RpcError::internal(e.to_string()))?;
+        if height > tip {
+            return Ok(None);
+        }
+        Ok(k.get_block_hash(height).ok())
+    })
+    .await;
+
+    let hash = match result {
+        Ok(Some(hash)) => hash,
+        Ok(None) => return rest_err(StatusCode::NOT_FOUND, "Block height out of range"),
+        Err(e) => return internal_err(e),
+    };
+
+    match format {
+        RestFormat::Bin => bin_response(hash.to_byte_array().to_vec()),
+        RestFormat::Hex => ([(header::CONTENT_TYPE, "text/plain")], format!("{}\n", hash)).into_response(),
+        RestFormat::Json => json_response(json!({ "blockhash": hash.to_string() })),
+    }
+}
+
+// ============================================================================
+// Chain and mempool state (JSON only)
+// ============================================================================
+
+/// GET /rest/chaininfo.json
+async fn chaininfo(State(state): State<AppState>) -> Response {
+    match blockchain::getblockchaininfo(state, Params::positional(vec![])).await {
+        Ok(info) => json_response(info),
+        Err(e) => internal_err(e),
+    }
+}
+
+/// GET /rest/mempool/<info|contents>.json
+async fn mempool(
+    State(state): State<AppState>,
+    Path(param): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let (what, format) = parse_data_format(&param);
+    if format != Some(RestFormat::Json) {
+        return format_not_found(true);
+    }
+
+    let result = match what {
+        "info" => blockchain::getmempoolinfo(state, Params::positional(vec![])).await,
+        "contents" => {
+            let flag = |name: &str, default: bool| match query.get(name).map(String::as_str) {
+                None => Ok(default),
+                Some("true") => Ok(true),
+                Some("false") => Ok(false),
+                Some(_) => Err(rest_err(
+                    StatusCode::BAD_REQUEST,
+                    format!("The \"{}\" query parameter must be either \"true\" or \"false\".", name),
+                )),
+            };
+            let verbose = match flag("verbose", true) {
+                Ok(v) => v,
+                Err(resp) => return resp,
+            };
+            let mempool_sequence = match flag("mempool_sequence", false) {
+                Ok(v) => v,
+                Err(resp) => return resp,
+            };
+            if verbose && mempool_sequence {
+                return rest_err(
+                    StatusCode::BAD_REQUEST,
+                    "Verbose results cannot contain mempool sequence values. (hint: set \"verbose=false\")",
+                );
+            }
+            let params = Params::positional(vec![json!(verbose), json!(mempool_sequence)]);
+            blockchain::getrawmempool(state, params).await
+        }
+        _ => return rest_err(StatusCode::BAD_REQUEST, "Invalid URI format. Expected /rest/mempool/<info|contents>.json"),
+    };
+
+    match result {
+        Ok(value) => json_response(value),
+        Err(e) => internal_err(e),
+    }
+}
+
+// ============================================================================
+// UTXO lookups
+// ============================================================================
+
+/// Parse "[checkmempool/]<txid>-<n>/<txid>-<n>/...", with the format suffix already removed
+fn parse_outpoints(path: &str) -> Result<(bool, Vec<OutPoint>), Response> {
+    let mut parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+    let check_mempool = parts.first() == Some(&"checkmempool");
+    if check_mempool {
+        parts.remove(0);
+    }
+
+    let mut outpoints = Vec::with_capacity(parts.len());
+    for part in parts {
+        let parsed = part.split_once('-').and_then(|(txid, n)| {
+            let txid = Txid::from_str(txid).ok()?;
+            let n = n.parse::<u32>().ok()?;
+            Some(OutPoint::new(txid, n))
+        });
+        match parsed {
+            Some(outpoint) => outpoints.push(outpoint),
+            None => return Err(rest_err(StatusCode::BAD_REQUEST, "Parse error")),
+        }
+    }
+    Ok((check_mempool, outpoints))
+}
+
+fn max_outpoints_exceeded(tried: usize) -> Response {
+    rest_err(
+        StatusCode::BAD_REQUEST,
+        format!("Error: max outpoints exceeded (max: {}, tried: {})", MAX_GETUTXOS_OUTPOINTS, tried),
+    )
+}
+
+/// Binary request body: bool checkmempool followed by a vector of outpoints
+fn parse_outpoints_body(body: &[u8]) -> Result<(bool, Vec<OutPoint>), Response> {
+    if body.is_empty() {
+        return Err(rest_err(StatusCode::BAD_REQUEST, "Error: empty request"));
+    }
+    let check_mempool = body[0] != 0;
+    let mut data = &body[1..];
+    let parse_error = |_| rest_err(StatusCode::BAD_REQUEST, "Parse error");
+    let count = VarInt::consensus_decode(&mut data).map_err(parse_error)?.0 as usize;
+    if count > MAX_GETUTXOS_OUTPOINTS {
+        return Err(max_outpoints_exceeded(count));
+    }
+    let outpoints = (0..count)
+        .map(|_| OutPoint::consensus_decode(&mut data))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(parse_error)?;
+    Ok((check_mempool, outpoints))
+}
+
+/// Serialized getutxos reply (Bitcoin Core: CCoin with a dummy tx version)
+fn encode_getutxos(height: i32, tip: &BlockHash, bitmap: &[u8], coins: &[Coin]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&height.to_le_bytes());
+    buf.extend_from_slice(tip.as_byte_array());
+    VarInt(bitmap.len() as u64).consensus_encode(&mut buf).expect("vec write");
+    buf.extend_from_slice(bitmap);
+    VarInt(coins.len() as u64).consensus_encode(&mut buf).expect("vec write");
+    for coin in coins {
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf.extend_from_slice(&coin.height.to_le_bytes());
+        coin.output.consensus_encode(&mut buf).expect("vec write");
+    }
+    buf
+}
+
+/// GET|POST /rest/getutxos[/checkmempool]/<txid>-<n>/....<bin|hex|json>
+async fn getutxos(State(state): State<AppState>, Path(path): Path<String>, body: Bytes) -> Response {
+    let (path, format) = parse_data_format(&path);
+    let Some(format) = format else { return format_not_found(false) };
+
+    let (check_mempool, outpoints) = match parse_outpoints(path) {
+        Ok((check_mempool, outpoints)) if !outpoints.is_empty() => (check_mempool, outpoints),
+        Ok((check_mempool, _)) if !body.is_empty() && format != RestFormat::Json => {
+            let body = if format == RestFormat::Hex {
+                match hex::decode(String::from_utf8_lossy(&body).trim()) {
+                    Ok(b) => b,
+                    Err(_) => return rest_err(StatusCode::BAD_REQUEST, "Parse error"),
+                }
+            } else {
+                body.to_vec()
+            };
+            match parse_outpoints_body(&body) {
+                Ok((body_check, outpoints)) => (check_mempool || body_check, outpoints),
+                Err(resp) => return resp,
+            }
+        }
+        Ok(_) => return rest_err(StatusCode::BAD_REQUEST, "Error: empty request"),
+        Err(resp) => return resp,
+    };
+
+    if outpoints.is_empty() {
+        return rest_err(StatusCode::BAD_REQUEST, "Error: empty request");
+    }
+    if outpoints.len() > MAX_GETUTXOS_OUTPOINTS {
+        return max_outpoints_exceeded(outpoints.len());
+    }
+
+    let (height, tip) = state
+        .coins
+        .best_block()
+        .unwrap_or((-1, BlockHash::all_zeros()));
+
+    let mut bitmap = vec![0u8; outpoints.len().div_ceil(8)];
+    let mut bitmap_str = String::with_capacity(outpoints.len());
+    let mut coins = Vec::new();
+    for (i, outpoint) in outpoints.iter().enumerate() {
+        match lookup_coin(&state, outpoint, check_mempool) {
+            Ok(Some(coin)) => {
+                bitmap[i / 8] |= 1 << (i % 8);
+                bitmap_str.push('1');
+                coins.push(coin);
+            }
+            Ok(None) => bitmap_str.push('0'),
+            Err(e) => return internal_err(e),
+        }
+    }
+
+    match format {
+        RestFormat::Bin => bin_response(encode_getutxos(height, &tip, &bitmap, &coins)),
+        RestFormat::Hex => hex_response(&encode_getutxos(height, &tip, &bitmap, &coins)),
+        RestFormat::Json => {
+            let network = state.kernel.network();
+            let utxos: Vec<Value> = coins
+                .iter()
+                .map(|coin| {
+                    json!({
+                        "height": coin.height,
+                        "value": btc(coin.output.value.to_sat()),
+                        "scriptPubKey": script_pubkey_to_json(&coin.output.script_pubkey, network),
+                    })
+                })
+                .collect();
+            json_response(json!({
+                "chainHeight": height,
+                "chaintipHash": tip.to_string(),
+                "bitmap": bitmap_str,
+                "utxos": utxos,
+            }))
+        }
+    }
+}
+
+/// REST routes; mounted without authentication when `--rest` is set
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/rest/block/notxdetails/:param", get(block_notxdetails))
+        .route("/rest/block/:param", get(block))
+        .route("/rest/headers/:count/:param", get(headers_with_count))
+        .route("/rest/headers/:param", get(headers_with_query))
+        .route("/rest/blockhashbyheight/:param", get(blockhashbyheight))
+        .route("/rest/chaininfo.json", get(chaininfo))
+        .route("/rest/mempool/:param", get(mempool))
+        .route("/rest/getutxos/*path", get(getutxos).post(getutxos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_data_format() {
+        assert_eq!(parse_data_format("abcd.json"), ("abcd", Some(RestFormat::Json)));
+        assert_eq!(parse_data_format("abcd.bin"), ("abcd", Some(RestFormat::Bin)));
+        assert_eq!(parse_data_format("abcd.xml"), ("abcd", None));
+        assert_eq!(parse_data_format("abcd"), ("abcd", None));
+    }
+
+    #[test]
+    fn test_parse_outpoints() {
+        let txid = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+        let (check, outpoints) = parse_outpoints(&format!("checkmempool/{}-0/{}-3", txid, txid)).unwrap();
+        assert!(check);
+        assert_eq!(outpoints.len(), 2);
+        assert_eq!(outpoints[1].vout, 3);
+
+        let (check, outpoints) = parse_outpoints(&format!("{}-1", txid)).unwrap();
+        assert!(!check);
+        assert_eq!(outpoints[0].txid.to_string(), txid);
+
+        assert!(parse_outpoints("nothex-1").is_err());
+        assert!(parse_outpoints(&format!("{}", txid)).is_err());
+    }
+
+    #[test]
+    fn test_parse_outpoints_body() {
+        let outpoints = vec![OutPoint::new(Txid::all_zeros(), 5)];
+        let mut body = vec![1u8, 1u8];
+        body.extend_from_slice(&serialize(&outpoints[0]));
+        let (check, parsed) = parse_outpoints_body(&body).unwrap();
+        assert!(check);
+        assert_eq!(parsed, outpoints);
+    }
+
+    #[test]
+    fn test_encode_getutxos() {
+        let data = encode_getutxos(10, &BlockHash::all_zeros(), &[0b01], &[]);
+        // height + tip + bitmap (len, byte) + empty coin vector
+        assert_eq!(data.len(), 4 + 32 + 2 + 1);
+        assert_eq!(&data[..4], &10i32.to_le_bytes());
+        assert_eq!(data[36..], [1, 0b01, 0]);
+    }
+}
@@ -0,0 +1,561 @@
+// src/rpc/server.rs
+//! Bitcoin Core-compatible JSON-RPC dispatch.
+//!
+//! Every RPC method is registered once in an [`RpcTable`] with its argument names, so
+//! it can be invoked with positional or named parameters, individually or in batches,
+//! through `POST /` (what `bitcoin-cli` and python-bitcoinrpc use). The older
+//! per-method routes (`/getblockcount`, ...) are served by the same table.
+
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use super::auth::RpcUser;
+use super::protocol::*;
+use super::AppState;
+
+/// Handler signature shared by all RPC methods
+pub type RpcHandler = fn(AppState, Params) -> BoxFuture<'static, RpcResult>;
+
+/// A registered RPC method
+pub struct RpcCommand {
+    pub category: &'static str,
+    pub name: &'static str,
+    pub arg_names: &'static [&'static str],
+    pub handler: RpcHandler,
+}
+
+/// Method table (Bitcoin Core: CRPCTable)
+#[derive(Default)]
+pub struct RpcTable {
+    commands: BTreeMap<&'static str, RpcCommand>,
+}
+
+impl RpcTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(
+        &mut self,
+        category: &'static str,
+        name: &'static str,
+        arg_names: &'static [&'static str],
+        handler: RpcHandler,
+    ) {
+        self.commands.insert(name, RpcCommand { category, name, arg_names, handler });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RpcCommand> {
+        self.commands.get(name)
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &RpcCommand> {
+        self.commands.values()
+    }
+
+    /// Look up `method`, convert its params and run it
+    pub async fn execute(&self, state: AppState, method: &str, params: Value) -> RpcResult {
+        let cmd = self.get(method).ok_or_else(RpcError::method_not_found)?;
+        let params = Params::from_request(params, cmd.name, cmd.arg_names)?;
+        (cmd.handler)(state, params).await
+    }
+}
+
+/// Positional view of a request's parameters.
+///
+/// Named parameters are mapped onto positions using the method's argument names,
+/// the same way Bitcoin Core's `transformNamedArguments` does.
+#[derive(Debug, Clone, Default)]
+pub struct Params {
+    values: Vec<Value>,
+}
+
+impl Params {
+    pub fn positional(values: Vec<Value>) -> Self {
+        Self { values }
+    }
+
+    pub fn from_request(raw: Value, method: &str, arg_names: &[&str]) -> RpcResult<Self> {
+        let values = match raw {
+            Value::Null => Vec::new(),
+            Value::Array(values) => values,
+            Value::Object(map) => {
+                let mut values = Vec::new();
+                for (key, value) in map {
+                    let pos = arg_names
+                        .iter()
+                        .position(|name| *name == key)
+                        .ok_or_else(|| {
+                            RpcError::invalid_parameter(format!("Unknown named parameter {}", key))
+                        })?;
+                    if values.len() <= pos {
+                        values.resize(pos + 1, Value::Null);
+                    }
+                    values[pos] = value;
+                }
+                values
+            }
+            _ => {
+                return Err(RpcError::new(
+                    RPC_INVALID_REQUEST,
+                    "Params must be an array or object",
+                ))
+            }
+        };
+
+        if values.len() > arg_names.len() {
+            return Err(RpcError::misc(format!(
+                "{} takes at most {} argument(s), got {}",
+                method,
+                arg_names.len(),
+                values.len()
+            )));
+        }
+
+        Ok(Self { values })
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Raw parameter at `idx`; explicit `null` counts as omitted
+    pub fn get(&self, idx: usize) -> Option<&Value> {
+        self.values.get(idx).filter(|v| !v.is_null())
+    }
+
+    pub fn required<T: DeserializeOwned>(&self, idx: usize, name: &str) -> RpcResult<T> {
+        let value = self
+            .get(idx)
+            .ok_or_else(|| RpcError::invalid_parameter(format!("Missing required parameter '{}'", name)))?;
+        convert(value, name)
+    }
+
+    pub fn optional<T: DeserializeOwned>(&self, idx: usize, name: &str) -> RpcResult<Option<T>> {
+        self.get(idx).map(|value| convert(value, name)).transpose()
+    }
+
+    pub fn optional_or<T: DeserializeOwned>(&self, idx: usize, name: &str, default: T) -> RpcResult<T> {
+        Ok(self.optional(idx, name)?.unwrap_or(default))
+    }
+}
+
+fn convert<T: DeserializeOwned>(value: &Value, name: &str) -> RpcResult<T> {
+    serde_json::from_value(value.clone())
+        .map_err(|e| RpcError::type_error(format!("Invalid type for parameter '{}': {}", name, e)))
+}
+
+/// Parse a 64-character hex hash parameter (Bitcoin Core: ParseHashV)
+pub fn parse_hash<H: FromStr>(value: &str, name: &str) -> RpcResult<H> {
+    if value.len() != 64 {
+        return Err(RpcError::invalid_parameter(format!(
+            "{} must be of length 64 (not {}, for '{}')",
+            name,
+            value.len(),
+            value
+        )));
+    }
+    if !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(RpcError::invalid_parameter(format!(
+            "{} must be hexadecimal string (not '{}')",
+            name, value
+        )));
+    }
+    H::from_str(value).map_err(|_| RpcError::invalid_parameter(format!("{} is not a valid hash", name)))
+}
+
+/// Parse a BTC amount given as a JSON number or string into satoshis (Bitcoin Core: AmountFromValue)
+pub fn amount_from_value(value: &Value) -> RpcResult<u64> {
+    let text = match value {
+        // Avoid exponent notation (1e-5) from the float formatter
+        Value::Number(n) => match n.as_f64() {
+            Some(f) if n.to_string().contains(['e', 'E']) => format!("{:.8}", f),
+            _ => n.to_string(),
+        },
+        Value::String(s) => s.clone(),
+        _ => return Err(RpcError::type_error("Amount is not a number or string")),
+    };
+    if text.starts_with('-') {
+        return Err(RpcError::type_error("Amount out of range"));
+    }
+    let amount = bitcoin::Amount::from_str_in(&text, bitcoin::Denomination::Bitcoin)
+        .map_err(|_| RpcError::type_error("Invalid amount"))?;
+    if amount > bitcoin::Amount::MAX_MONEY {
+        return Err(RpcError::type_error("Amount out of range"));
+    }
+    Ok(amount.to_sat())
+}
+
+/// Run blocking kernel work off the async runtime
+pub async fn blocking<T, F>(f: F) -> RpcResult<T>
+where
+    F: FnOnce() -> RpcResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| RpcError::internal(format!("task failed: {}", e)))?
+}
+
+// ============================================================================
+// JSON-RPC request/response envelopes
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JsonRpcVersion {
+    Legacy,
+    V2,
+}
+
+struct JsonRpcRequest {
+    id: Value,
+    method: String,
+    params: Value,
+    version: JsonRpcVersion,
+    is_notification: bool,
+}
+
+impl JsonRpcRequest {
+    fn parse(value: Value) -> Result<Self, (Value, JsonRpcVersion, RpcError)> {
+        let Value::Object(mut obj) = value else {
+            return Err((
+                Value::Null,
+                JsonRpcVersion::Legacy,
+                RpcError::new(RPC_INVALID_REQUEST, "Invalid Request object"),
+            ));
+        };
+
+        let version = match obj.get("jsonrpc") {
+            Some(Value::String(v)) if v == "2.0" => JsonRpcVersion::V2,
+            _ => JsonRpcVersion::Legacy,
+        };
+        let is_notification = version == JsonRpcVersion::V2 && !obj.contains_key("id");
+        let id = obj.remove("id").unwrap_or(Value::Null);
+
+        let method = match obj.remove("method") {
+            Some(Value::String(m)) => m,
+            None => {
+                return Err((id, version, RpcError::new(RPC_INVALID_REQUEST, "Missing method")))
+            }
+            Some(_) => {
+                return Err((id, version, RpcError::new(RPC_INVALID_REQUEST, "Method must be a string")))
+            }
+        };
+
+        let params = match obj.remove("params") {
+            None | Some(Value::Null) => Value::Null,
+            Some(p @ (Value::Array(_) | Value::Object(_))) => p,
+            Some(_) => {
+                return Err((
+                    id,
+                    version,
+                    RpcError::new(RPC_INVALID_REQUEST, "Params must be an array or object"),
+                ))
+            }
+        };
+
+        Ok(Self { id, method, params, version, is_notification })
+    }
+}
+
+fn reply(version: JsonRpcVersion, result: RpcResult, id: Value) -> Value {
+    match (version, result) {
+        (JsonRpcVersion::Legacy, Ok(result)) => json!({ "result": result, "error": null, "id": id }),
+        (JsonRpcVersion::Legacy, Err(err)) => json!({ "result": null, "error": err.to_json(), "id": id }),
+        (JsonRpcVersion::V2, Ok(result)) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        (JsonRpcVersion::V2, Err(err)) => json!({ "jsonrpc": "2.0", "error": err.to_json(), "id": id }),
+    }
+}
+
+/// HTTP status for a legacy (JSON-RPC 1.0) error reply, as in Bitcoin Core's httprpc.cpp
+fn legacy_error_status(err: &RpcError) -> StatusCode {
+    match err.code {
+        RPC_INVALID_REQUEST => StatusCode::BAD_REQUEST,
+        RPC_METHOD_NOT_FOUND => StatusCode::NOT_FOUND,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn json_response(status: StatusCode, body: &Value) -> Response {
+    (
+        status,
+        [(header::CONTENT_TYPE, "application/json")],
+        format!("{}\n", body),
+    )
+        .into_response()
+}
+
+/// Execute one request object. Returns `None` for JSON-RPC 2.0 notifications.
+async fn execute_one(state: &AppState, value: Value) -> Option<(StatusCode, Value)> {
+    let req = match JsonRpcRequest::parse(value) {
+        Ok(req) => req,
+        Err((id, version, err)) => {
+            let status = match version {
+                JsonRpcVersion::Legacy => legacy_error_status(&err),
+                JsonRpcVersion::V2 => StatusCode::OK,
+            };
+            return Some((status, reply(version, Err(err), id)));
+        }
+    };
+
+    let table = state.rpc_table.clone();
+    let result = table.execute(state.clone(), &req.method, req.params).await;
+
+    if req.is_notification {
+        return None;
+    }
+
+    let status = match (&result, req.version) {
+        (Err(err), JsonRpcVersion::Legacy) => legacy_error_status(err),
+        _ => StatusCode::OK,
+    };
+    Some((status, reply(req.version, result, req.id)))
+}
+
+/// Whether `user` may call every method named in `request` (a request object or batch)
+fn is_request_allowed(state: &AppState, user: &str, request: &Value) -> bool {
+    let allowed = |item: &Value| match item.get("method").and_then(Value::as_str) {
+        Some(method) => state.auth.is_method_allowed(user, method),
+        None => true,
+    };
+    match request {
+        Value::Array(batch) => batch.iter().all(allowed),
+        other => allowed(other),
+    }
+}
+
+fn forbidden(user: &str) -> Response {
+    eprintln!("[rpc] RPC User {} not allowed to call method", user);
+    StatusCode::FORBIDDEN.into_response()
+}
+
+/// `POST /` - JSON-RPC 1.0/2.0 endpoint with batch support
+pub async fn handle_jsonrpc(
+    State(state): State<AppState>,
+    Extension(RpcUser(user)): Extension<RpcUser>,
+    body: Bytes,
+) -> Response {
+    let request: Value = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(_) => {
+            let err = RpcError::new(RPC_PARSE_ERROR, "Parse error");
+            return json_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &reply(JsonRpcVersion::Legacy, Err(err), Value::Null),
+            );
+        }
+    };
+
+    if !is_request_allowed(&state, &user, &request) {
+        return forbidden(&user);
+    }
+
+    match request {
+        Value::Array(batch) => {
+            if batch.is_empty() {
+                let err = RpcError::new(RPC_INVALID_REQUEST, "Invalid Request object");
+                return json_response(StatusCode::OK, &reply(JsonRpcVersion::V2, Err(err), Value::Null));
+            }
+            let mut replies = Vec::with_capacity(batch.len());
+            for item in batch {
+                if let Some((_, r)) = execute_one(&state, item).await {
+                    replies.push(r);
+                }
+            }
+            json_response(StatusCode::OK, &Value::Array(replies))
+        }
+        Value::Object(_) => match execute_one(&state, request).await {
+            Some((status, r)) => json_response(status, &r),
+            None => StatusCode::NO_CONTENT.into_response(),
+        },
+        _ => {
+            let err = RpcError::new(RPC_PARSE_ERROR, "Top-level object parse error");
+            json_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &reply(JsonRpcVersion::Legacy, Err(err), Value::Null),
+            )
+        }
+    }
+}
+
+/// `GET|POST /<method>` - per-method routes kept for existing scripts.
+/// The body, if any, is the params array or object.
+pub async fn handle_method_route(
+    State(state): State<AppState>,
+    Extension(RpcUser(user)): Extension<RpcUser>,
+    Path(method): Path<String>,
+    body: Bytes,
+) -> Response {
+    if !state.auth.is_method_allowed(&user, &method) {
+        return forbidden(&user);
+    }
+
+    let params = if body.iter().all(|b| b.is_ascii_whitespace()) {
+        Value::Null
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(v) => v,
+            Err(_) => {
+                let err = RpcError::new(RPC_PARSE_ERROR, "Parse error");
+                return json_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    &reply(JsonRpcVersion::Legacy, Err(err), Value::Null),
+                );
+            }
+        }
+    };
+
+    let table = state.rpc_table.clone();
+    let result = table.execute(state, &method, params).await;
+    let status = match &result {
+        Err(err) => legacy_error_status(err),
+        Ok(_) => StatusCode::OK,
+    };
+    json_response(status, &reply(JsonRpcVersion::Legacy, result, Value::Null))
+}
+
+// ============================================================================
+// Control RPCs
+// ============================================================================
+
+/// help ( "command" )
+pub async fn help(state: AppState, params: Params) -> RpcResult {
+    let command: Option<String> = params.optional(0, "command")?;
+
+    if let Some(name) = command {
+        let cmd = state
+            .rpc_table
+            .get(&name)
+            .ok_or_else(|| RpcError::misc(format!("help: unknown command: {}", name)))?;
+        let args: Vec<String> = cmd.arg_names.iter().map(|a| format!("\"{}\"", a)).collect();
+        return Ok(json!(format!("{} {}", cmd.name, args.join(" ")).trim_end().to_string()));
+    }
+
+    let mut out = String::new();
+    let mut last_category = "";
+    let mut commands: Vec<&RpcCommand> = state.rpc_table.commands().collect();
+    commands.sort_by_key(|c| (c.category, c.name));
+    for cmd in commands {
+        if cmd.category != last_category {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!("== {} ==\n", capitalize(cmd.category)));
+            last_category = cmd.category;
+        }
+        out.push_str(cmd.name);
+        for arg in cmd.arg_names {
+            out.push_str(&format!(" \"{}\"", arg));
+        }
+        out.push('\n');
+    }
+    Ok(json!(out.trim_end()))
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+pub fn register(table: &mut RpcTable) {
+    table.register("control", "help", &["command"], |s, p| Box::pin(help(s, p)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_params_mapping() {
+        let params = Params::from_request(
+            json!({ "verbosity": 2, "blockhash": "00" }),
+            "getblock",
+            &["blockhash", "verbosity"],
+        )
+        .unwrap();
+        assert_eq!(params.required::<String>(0, "blockhash").unwrap(), "00");
+        assert_eq!(params.required::<u8>(1, "verbosity").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_unknown_named_param() {
+        let err = Params::from_request(json!({ "foo": 1 }), "getblockhash", &["height"]).unwrap_err();
+        assert_eq!(err.code, RPC_INVALID_PARAMETER);
+    }
+
+    #[test]
+    fn test_too_many_params() {
+        let err = Params::from_request(json!([1, 2]), "getblockhash", &["height"]).unwrap_err();
+        assert_eq!(err.code, RPC_MISC_ERROR);
+    }
+
+    #[test]
+    fn test_null_treated_as_omitted() {
+        let params = Params::from_request(json!([null, true]), "x", &["a", "b"]).unwrap();
+        assert_eq!(params.optional::<u32>(0, "a").unwrap(), None);
+        assert!(params.required::<u32>(0, "a").is_err());
+        assert!(params.optional_or(1, "b", false).unwrap());
+    }
+
+    #[test]
+    fn test_parse_hash_errors() {
+        let err = parse_hash::<bitcoin::BlockHash>("abcd", "blockhash").unwrap_err();
+        assert_eq!(err.code, RPC_INVALID_PARAMETER);
+        let bad = "z".repeat(64);
+        assert!(parse_hash::<bitcoin::BlockHash>(&bad, "blockhash").is_err());
+        let ok = "0".repeat(64);
+        assert!(parse_hash::<bitcoin::BlockHash>(&ok, "blockhash").is_ok());
+    }
+
+    #[test]
+    fn test_amount_from_value() {
+        assert_eq!(amount_from_value(&json!(0.1)).unwrap(), 10_000_000);
+        assert_eq!(amount_from_value(&json!("0.00000001")).unwrap(), 1);
+        assert_eq!(amount_from_value(&json!(1e-5)).unwrap(), 1000);
+        assert_eq!(amount_from_value(&json!(0)).unwrap(), 0);
+        assert_eq!(amount_from_value(&json!(-1)).unwrap_err().message, "Amount out of range");
+        assert_eq!(amount_from_value(&json!(21_000_001)).unwrap_err().message, "Amount out of range");
+        assert_eq!(amount_from_value(&json!("0.000000001")).unwrap_err().message, "Invalid amount");
+        assert!(amount_from_value(&json!(true)).is_err());
+    }
+
+    #[test]
+    fn test_request_parsing() {
+        let req = JsonRpcRequest::parse(json!({ "jsonrpc": "2.0", "method": "getblockcount" })).ok().unwrap();
+        assert_eq!(req.version, JsonRpcVersion::V2);
+        assert!(req.is_notification);
+
+        let req = JsonRpcRequest::parse(json!({ "method": "getblockcount", "id": 1 })).ok().unwrap();
+        assert_eq!(req.version, JsonRpcVersion::Legacy);
+        assert_eq!(req.id, json!(1));
+
+        let (_, _, err) = JsonRpcRequest::parse(json!({ "method": 5, "id": 1 })).err().unwrap();
+        assert_eq!(err.code, RPC_INVALID_REQUEST);
+    }
+
+    #[test]
+    fn test_reply_shapes() {
+        let ok = reply(JsonRpcVersion::Legacy, Ok(json!(5)), json!("a"));
+        assert_eq!(ok, json!({ "result": 5, "error": null, "id": "a" }));
+
+        let err = reply(JsonRpcVersion::V2, Err(RpcError::method_not_found()), json!(1));
+        assert_eq!(err["error"]["code"], json!(-32601));
+        assert!(err.get("result").is_none());
+    }
+}
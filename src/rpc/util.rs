@@ -0,0 +1,211 @@
+// src/rpc/util.rs
+//! JSON encodings shared by the RPC and REST interfaces (Bitcoin Core: core_write.cpp)
+
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::{Address, Block, BlockHash, Network, Script, Transaction};
+use serde_json::{json, Value};
+
+use crate::kernel::BlockInfo;
+use crate::mempool::{standard, Mempool, MempoolEntry};
+
+/// Satoshis to a BTC amount
+pub fn btc(sats: u64) -> f64 {
+    sats as f64 / 100_000_000.0
+}
+
+/// Output type name as reported by Bitcoin Core
+pub fn script_type(script: &Script) -> &'static str {
+    standard::classify(script).as_str()
+}
+
+pub fn script_pubkey_to_json(script: &Script, network: Network) -> Value {
+    let mut obj = json!({
+        "asm": script.to_asm_string(),
+        "hex": hex::encode(script.as_bytes()),
+        "type": script_type(script),
+    });
+    if let Ok(address) = Address::from_script(script, network) {
+        obj["address"] = json!(address.to_string());
+    }
+    obj
+}
+
+/// decoderawtransaction-style JSON
+pub fn tx_to_json(tx: &Transaction, network: Network) -> Value {
+    let vin: Vec<Value> = tx
+        .input
+        .iter()
+        .map(|input| {
+            let mut obj = if tx.is_coinbase() {
+                json!({ "coinbase": hex::encode(input.script_sig.as_bytes()) })
+            } else {
+                json!({
+                    "txid": input.previous_output.txid.to_string(),
+                    "vout": input.previous_output.vout,
+                    "scriptSig": {
+                        "asm": input.script_sig.to_asm_string(),
+                        "hex": hex::encode(input.script_sig.as_bytes()),
+                    },
+                })
+            };
+            if !input.witness.is_empty() {
+                obj["txinwitness"] = json!(input.witness.iter().map(hex::encode).collect::<Vec<_>>());
+            }
+            obj["sequence"] = json!(input.sequence.0);
+            obj
+        })
+        .collect();
+
+    let vout: Vec<Value> = tx
+        .output
+        .iter()
+        .enumerate()
+        .map(|(n, output)| {
+            json!({
+                "value": btc(output.value.to_sat()),
+                "n": n,
+                "scriptPubKey": script_pubkey_to_json(&output.script_pubkey, network),
+            })
+        })
+        .collect();
+
+    json!({
+        "txid": tx.compute_txid().to_string(),
+        "hash": tx.compute_wtxid().to_string(),
+        "version": tx.version.0,
+        "size": tx.total_size(),
+        "vsize": tx.vsize(),
+        "weight": tx.weight().to_wu(),
+        "locktime": tx.lock_time.to_consensus_u32(),
+        "vin": vin,
+        "vout": vout,
+        "hex": serialize_hex(tx),
+    })
+}
+
+/// getblockheader-style JSON
+pub fn header_to_json(
+    header: &bitcoin::block::Header,
+    info: &BlockInfo,
+    tip_height: i32,
+    next: Option<BlockHash>,
+    n_tx: Option<usize>,
+) -> Value {
+    let confirmations = if info.in_active_chain { tip_height - info.height + 1 } else { -1 };
+    let mut obj = json!({
+        "hash": info.hash.to_string(),
+        "confirmations": confirmations,
+        "height": info.height,
+        "version": header.version.to_consensus(),
+        "versionHex": format!("{:08x}", header.version.to_consensus()),
+        "merkleroot": header.merkle_root.to_string(),
+        "time": header.time,
+        "nonce": header.nonce,
+        "bits": format!("{:08x}", header.bits.to_consensus()),
+        "difficulty": header.difficulty_float(),
+    });
+    if let Some(n_tx) = n_tx {
+        obj["nTx"] = json!(n_tx);
+    }
+    if let Some(prev) = info.prev {
+        obj["previousblockhash"] = json!(prev.to_string());
+    }
+    if let Some(next) = next {
+        obj["nextblockhash"] = json!(next.to_string());
+    }
+    obj
+}
+
+/// getblock-style JSON; `tx_details` selects verbosity 2 (full transactions) over 1 (txids)
+pub fn block_to_json(
+    block: &Block,
+    info: &BlockInfo,
+    tip_height: i32,
+    next: Option<BlockHash>,
+    network: Network,
+    tx_details: bool,
+) -> Value {
+    let mut obj = header_to_json(&block.header, info, tip_height, next, Some(block.txdata.len()));
+    obj["size"] = json!(block.total_size());
+    obj["strippedsize"] = json!(bitcoin::consensus::serialize(&block.header).len()
+        + block.txdata.iter().map(|tx| tx.base_size()).sum::<usize>()
+        + bitcoin::VarInt(block.txdata.len() as u64).size());
+    obj["weight"] = json!(block.weight().to_wu());
+    obj["tx"] = if tx_details {
+        json!(block.txdata.iter().map(|tx| tx_to_json(tx, network)).collect::<Vec<_>>())
+    } else {
+        json!(block.txdata.iter().map(|tx| tx.compute_txid().to_string()).collect::<Vec<_>>())
+    };
+    obj
+}
+
+/// getrawmempool verbose / getmempoolentry JSON
+pub fn mempool_entry_to_json(entry: &MempoolEntry, mempool: &Mempool) -> Value {
+    let time = entry
+        .time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let depends: Vec<String> = entry
+        .tx
+        .input
+        .iter()
+        .map(|input| input.previous_output.txid)
+        .filter(|txid| mempool.contains(txid))
+        .map(|txid| txid.to_string())
+        .collect();
+
+    json!({
+        "vsize": entry.vsize,
+        "weight": entry.tx.weight().to_wu(),
+        "time": time,
+        "height": entry.height,
+        "descendantcount": entry.descendant_count,
+        "descendantsize": entry.descendant_size,
+        "ancestorcount": entry.ancestor_count,
+        "ancestorsize": entry.ancestor_size,
+        "wtxid": entry.tx.compute_wtxid().to_string(),
+        "fees": {
+            "base": btc(entry.fee),
+            "modified": btc(entry.modified_fee()),
+            "ancestor": btc(entry.ancestor_fees),
+            "descendant": btc(entry.descendant_fees),
+        },
+        "depends": depends,
+        "spentby": entry.children.iter().map(|txid| txid.to_string()).collect::<Vec<_>>(),
+        "bip125-replaceable": entry.signals_replacement,
+        "unbroadcast": mempool.unbroadcast_txids().contains(&entry.txid),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::ScriptBuf;
+
+    #[test]
+    fn test_script_types() {
+        let genesis = genesis_block(Network::Bitcoin);
+        assert_eq!(script_type(&genesis.txdata[0].output[0].script_pubkey), "pubkey");
+        assert_eq!(script_type(&ScriptBuf::from(vec![0x51, 0x02, 0x4e, 0x73])), "anchor");
+        assert_eq!(script_type(&ScriptBuf::from(vec![0x6a])), "nulldata");
+        assert_eq!(script_type(&ScriptBuf::from(vec![0xff])), "nonstandard");
+    }
+
+    #[test]
+    fn test_genesis_block_json() {
+        let genesis = genesis_block(Network::Bitcoin);
+        let info = BlockInfo { hash: genesis.block_hash(), height: 0, prev: None, in_active_chain: true };
+        let obj = block_to_json(&genesis, &info, 10, None, Network::Bitcoin, false);
+        assert_eq!(obj["hash"], "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f");
+        assert_eq!(obj["confirmations"], 11);
+        assert_eq!(obj["nTx"], 1);
+        assert_eq!(obj["size"], 285);
+        assert_eq!(obj["strippedsize"], 285);
+        assert_eq!(obj["weight"], 1140);
+        assert_eq!(obj["bits"], "1d00ffff");
+        assert_eq!(obj["tx"][0], "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b");
+        assert!(obj.get("previousblockhash").is_none());
+    }
+}
@@ -0,0 +1,16 @@
+// src/rpc/zmq.rs
+use serde_json::json;
+
+use super::protocol::RpcResult;
+use super::server::{Params, RpcTable};
+use super::AppState;
+
+/// getzmqnotifications
+pub async fn getzmqnotifications(state: AppState, _params: Params) -> RpcResult {
+    let notifications = state.zmq.as_ref().map(|z| z.notifications()).unwrap_or_default();
+    Ok(json!(notifications))
+}
+
+pub fn register(table: &mut RpcTable) {
+    table.register("zmq", "getzmqnotifications", &[], |s, p| Box::pin(getzmqnotifications(s, p)));
+}
@@ -0,0 +1,352 @@
+//! Signet block signing (BIP 325). A signet block carries a solution to the
+//! network's challenge script in its coinbase witness commitment output:
+//! the scriptSig and witness of a virtual transaction spending the
+//! challenge, signed over the block header fields and a merkle root taken
+//! with the solution left out (Bitcoin Core: signet.cpp, contrib/signet/miner).
+//!
+//! Solutions come from a local key for the common single-signer
+//! challenges, or from any PSBT signer through `signet_psbt` and
+//! `block_from_psbt`.
+
+use bitcoin::blockdata::opcodes::all::{OP_CHECKMULTISIG, OP_RETURN};
+use bitcoin::consensus::{deserialize_partial, serialize};
+use bitcoin::hashes::Hash;
+use bitcoin::key::{Keypair, TapTweak};
+use bitcoin::psbt::{raw::ProprietaryKey, Psbt};
+use bitcoin::script::{Builder, Instruction, PushBytesBuf};
+use bitcoin::secp256k1::{Message, Secp256k1};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+use bitcoin::{
+    absolute, ecdsa, merkle_tree, taproot, transaction, Amount, Block, OutPoint, PrivateKey, Script, ScriptBuf,
+    Sequence, Transaction, TxIn, TxOut, Witness,
+};
+
+/// Marks the signet solution push in the witness commitment output
+pub const SIGNET_HEADER: [u8; 4] = [0xec, 0xc7, 0xda, 0xa2];
+
+/// Global PSBT field holding the block being signed: proprietary type
+/// with identifier "signet" and subtype 'b' (contrib/signet/miner)
+fn psbt_block_key() -> ProprietaryKey {
+    ProprietaryKey { prefix: b"signet".to_vec(), subtype: b'b', key: Vec::new() }
+}
+
+/// The virtual transactions whose spend proves a signet block
+#[derive(Debug, Clone)]
+pub struct SignetTxs {
+    /// Pays to the challenge; its scriptSig commits to the block
+    pub to_spend: Transaction,
+    /// Spends `to_spend`; the block's solution fills its input
+    pub to_sign: Transaction,
+}
+
+/// Last coinbase output that is a witness commitment (Bitcoin Core:
+/// GetWitnessCommitmentIndex)
+fn witness_commitment_index(coinbase: &Transaction) -> Option<usize> {
+    coinbase.output.iter().rposition(|o| {
+        let bytes = o.script_pubkey.as_bytes();
+        bytes.len() >= 38 && bytes[0] == OP_RETURN.to_u8() && bytes[1] == 0x24 && bytes[2..6] == [0xaa, 0x21, 0xa9, 0xed]
+    })
+}
+
+/// Cut the signet solution out of a witness commitment script, leaving
+/// the bare header push. Returns the new script and the solution, or
+/// None if there is no solution (Bitcoin Core: FetchAndClearCommitmentSection).
+fn clear_commitment_section(script: &Script) -> Option<(ScriptBuf, Vec<u8>)> {
+    let mut builder = Builder::new();
+    let mut solution = None;
+    for instruction in script.instructions() {
+        match instruction.ok()? {
+            Instruction::PushBytes(push) => {
+                let data = push.as_bytes();
+                if solution.is_none() && data.len() > SIGNET_HEADER.len() && data.starts_with(&SIGNET_HEADER) {
+                    solution = Some(data[SIGNET_HEADER.len()..].to_vec());
+                    builder = builder.push_slice(SIGNET_HEADER);
+                } else {
+                    builder = builder.push_slice(push);
+                }
+            }
+            Instruction::Op(op) => builder = builder.push_opcode(op),
+        }
+    }
+    solution.map(|solution| (builder.into_script(), solution))
+}
+
+/// The transactions `block` must satisfy `challenge` with, with any
+/// solution in the block filled in (Bitcoin Core: SignetTxs::Create).
+/// None if the block has no witness commitment or a malformed solution.
+pub fn signet_txs(block: &Block, challenge: &Script) -> Option<SignetTxs> {
+    let mut coinbase = block.txdata.first()?.clone();
+    let index = witness_commitment_index(&coinbase)?;
+
+    let mut to_sign = Transaction {
+        version: transaction::Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn { previous_output: OutPoint::null(), sequence: Sequence(0), ..Default::default() }],
+        output: vec![TxOut { value: Amount::ZERO, script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script() }],
+    };
+    // Without a solution the block must satisfy the challenge as is, e.g. OP_TRUE
+    if let Some((cleared, solution)) = clear_commitment_section(&coinbase.output[index].script_pubkey) {
+        coinbase.output[index].script_pubkey = cleared;
+        let (script_sig, used): (ScriptBuf, usize) = deserialize_partial(&solution).ok()?;
+        let (witness, rest): (Witness, usize) = deserialize_partial(&solution[used..]).ok()?;
+        if used + rest != solution.len() {
+            return None;
+        }
+        to_sign.input[0].script_sig = script_sig;
+        to_sign.input[0].witness = witness;
+    }
+
+    let leaves = std::iter::once(coinbase.compute_txid())
+        .chain(block.txdata[1..].iter().map(|tx| tx.compute_txid()))
+        .map(|txid| txid.to_raw_hash());
+    let merkle_root = merkle_tree::calculate_root(leaves)?;
+    let mut block_data = Vec::with_capacity(72);
+    block_data.extend_from_slice(&block.header.version.to_consensus().to_le_bytes());
+    block_data.extend_from_slice(block.header.prev_blockhash.as_byte_array());
+    block_data.extend_from_slice(merkle_root.as_byte_array());
+    block_data.extend_from_slice(&block.header.time.to_le_bytes());
+
+    let to_spend = Transaction {
+        version: transaction::Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: Builder::new()
+                .push_opcode(bitcoin::opcodes::OP_0)
+                .push_slice(PushBytesBuf::try_from(block_data).ok()?)
+                .into_script(),
+            sequence: Sequence(0),
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut { value: Amount::ZERO, script_pubkey: challenge.to_owned() }],
+    };
+    to_sign.input[0].previous_output = OutPoint::new(to_spend.compute_txid(), 0);
+    Some(SignetTxs { to_spend, to_sign })
+}
+
+/// `signet_txs` for `block` as it will be once solved: the signature
+/// commits to a coinbase holding an empty solution section
+pub fn unsigned_signet_txs(block: &Block, challenge: &Script) -> Option<SignetTxs> {
+    let mut block = block.clone();
+    set_solution_push(&mut block, SIGNET_HEADER.to_vec())?;
+    let mut txs = signet_txs(&block, challenge)?;
+    txs.to_sign.input[0].script_sig = ScriptBuf::new();
+    txs.to_sign.input[0].witness = Witness::new();
+    Some(txs)
+}
+
+/// Replace the solution push of the witness commitment with `data`
+fn set_solution_push(block: &mut Block, data: Vec<u8>) -> Option<()> {
+    let coinbase = block.txdata.first_mut()?;
+    let index = witness_commitment_index(coinbase)?;
+    let script = &coinbase.output[index].script_pubkey;
+    let mut builder = Builder::new();
+    for instruction in script.instructions() {
+        match instruction.ok()? {
+            Instruction::PushBytes(push) if push.as_bytes().starts_with(&SIGNET_HEADER) => {}
+            Instruction::PushBytes(push) => builder = builder.push_slice(push),
+            Instruction::Op(op) => builder = builder.push_opcode(op),
+        }
+    }
+    coinbase.output[index].script_pubkey = builder.push_slice(PushBytesBuf::try_from(data).ok()?).into_script();
+    Some(())
+}
+
+/// Put a solution into `block`'s witness commitment and fix up the merkle
+/// root. The proof of work has to be redone afterwards.
+pub fn add_solution(block: &mut Block, script_sig: &Script, witness: &Witness) -> Result<(), &'static str> {
+    let mut data = SIGNET_HEADER.to_vec();
+    data.extend(serialize(script_sig));
+    data.extend(serialize(witness));
+    set_solution_push(block, data).ok_or("block has no witness commitment or the solution is too large")?;
+    block.header.merkle_root = block.compute_merkle_root().ok_or("block has no transactions")?;
+    Ok(())
+}
+
+/// Sign `block` for `challenge` with `key` and add the solution. Handles
+/// P2PK, P2PKH, 1-of-n bare multisig, P2WPKH and key-path P2TR challenges;
+/// an OP_TRUE challenge needs no solution.
+pub fn sign_block(block: &mut Block, challenge: &Script, key: &PrivateKey) -> Result<(), &'static str> {
+    if challenge.as_bytes() == [bitcoin::opcodes::OP_TRUE.to_u8()] {
+        return Ok(());
+    }
+    let secp = Secp256k1::new();
+    let public = key.public_key(&secp);
+    let txs = unsigned_signet_txs(block, challenge).ok_or("block has no witness commitment")?;
+    let mut cache = SighashCache::new(&txs.to_sign);
+
+    let ecdsa_sig = |sighash: [u8; 32]| {
+        let signature = secp.sign_ecdsa(&Message::from_digest(sighash), &key.inner);
+        ecdsa::Signature { signature, sighash_type: EcdsaSighashType::All }
+    };
+    let legacy_sig = |cache: &SighashCache<&Transaction>| {
+        cache
+            .legacy_signature_hash(0, challenge, EcdsaSighashType::All.to_u32())
+            .map(|sighash| ecdsa_sig(sighash.to_byte_array()))
+            .map_err(|_| "cannot compute the signature hash")
+    };
+
+    let (script_sig, witness) = if *challenge == ScriptBuf::new_p2pk(&public) {
+        let sig = legacy_sig(&cache)?;
+        (Builder::new().push_slice(sig.serialize()).into_script(), Witness::new())
+    } else if *challenge == ScriptBuf::new_p2pkh(&public.pubkey_hash()) {
+        let sig = legacy_sig(&cache)?;
+        (Builder::new().push_slice(sig.serialize()).push_key(&public).into_script(), Witness::new())
+    } else if is_one_of_n_multisig(challenge, &public) {
+        let sig = legacy_sig(&cache)?;
+        (Builder::new().push_opcode(bitcoin::opcodes::OP_0).push_slice(sig.serialize()).into_script(), Witness::new())
+    } else if public.wpubkey_hash().is_ok_and(|hash| *challenge == ScriptBuf::new_p2wpkh(&hash)) {
+        let sighash = cache
+            .p2wpkh_signature_hash(0, challenge, Amount::ZERO, EcdsaSighashType::All)
+            .map_err(|_| "cannot compute the signature hash")?;
+        let sig = ecdsa_sig(sighash.to_byte_array());
+        (ScriptBuf::new(), Witness::p2wpkh(&sig, &public.inner))
+    } else if *challenge == ScriptBuf::new_p2tr(&secp, public.inner.x_only_public_key().0, None) {
+        let sighash = cache
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&txs.to_spend.output), TapSighashType::Default)
+            .map_err(|_| "cannot compute the signature hash")?;
+        let keypair = Keypair::from_secret_key(&secp, &key.inner).tap_tweak(&secp, None).to_keypair();
+        let signature = secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash.to_byte_array()), &keypair);
+        let sig = taproot::Signature { signature, sighash_type: TapSighashType::Default };
+        (ScriptBuf::new(), Witness::p2tr_key_spend(&sig))
+    } else {
+        return Err("the key does not match a signet challenge that can be signed locally; use a PSBT");
+    };
+    add_solution(block, &script_sig, &witness)
+}
+
+/// `OP_1 <key>... OP_n OP_CHECKMULTISIG` with `public` among the keys
+fn is_one_of_n_multisig(challenge: &Script, public: &bitcoin::PublicKey) -> bool {
+    let Ok(instructions) = challenge.instructions().collect::<Result<Vec<_>, _>>() else {
+        return false;
+    };
+    let [first, keys @ .., _, last] = instructions.as_slice() else {
+        return false;
+    };
+    *first == Instruction::Op(bitcoin::opcodes::all::OP_PUSHNUM_1)
+        && *last == Instruction::Op(OP_CHECKMULTISIG)
+        && keys.iter().any(|k| matches!(k, Instruction::PushBytes(b) if b.as_bytes() == public.to_bytes()))
+}
+
+/// PSBT a signer completes to solve `block` (contrib/signet/miner genpsbt):
+/// the transaction to sign, the one it spends, and the block itself
+pub fn signet_psbt(block: &Block, challenge: &Script) -> Result<Psbt, &'static str> {
+    let txs = unsigned_signet_txs(block, challenge).ok_or("block has no witness commitment")?;
+    let mut psbt = Psbt::from_unsigned_tx(txs.to_sign).map_err(|_| "cannot build the PSBT")?;
+    psbt.proprietary.insert(psbt_block_key(), serialize(block));
+    psbt.inputs[0].witness_utxo = Some(txs.to_spend.output[0].clone());
+    psbt.inputs[0].non_witness_utxo = Some(txs.to_spend);
+    psbt.inputs[0].sighash_type = Some(EcdsaSighashType::All.into());
+    Ok(psbt)
+}
+
+/// The block in a finalized signet PSBT with the signer's solution added
+/// (contrib/signet/miner solvepsbt)
+pub fn block_from_psbt(psbt: &Psbt, challenge: &Script) -> Result<Block, &'static str> {
+    let raw = psbt.proprietary.get(&psbt_block_key()).ok_or("PSBT does not contain a signet block")?;
+    let mut block: Block = bitcoin::consensus::deserialize(raw).map_err(|_| "signet block in the PSBT does not decode")?;
+    let expected = unsigned_signet_txs(&block, challenge).ok_or("block has no witness commitment")?;
+    if psbt.unsigned_tx != expected.to_sign {
+        return Err("PSBT does not sign this block for the signet challenge");
+    }
+
+    let input = psbt.inputs.first().ok_or("PSBT has no input")?;
+    let script_sig = input.final_script_sig.clone().unwrap_or_default();
+    let witness = input.final_script_witness.clone().unwrap_or_default();
+    if script_sig.is_empty() && witness.is_empty() {
+        return Err("PSBT is not signed and finalized");
+    }
+    add_solution(&mut block, &script_sig, &witness)?;
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mining;
+    use bitcoin::{BlockHash, CompactTarget, Network, NetworkKind, WPubkeyHash};
+
+    fn unsigned_block() -> Block {
+        let commitment = mining::witness_commitment_script(std::iter::empty::<&Transaction>());
+        let coinbase = mining::create_coinbase(1, 0, ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros()), Some(commitment));
+        let txdata = vec![coinbase];
+        let header = mining::block_header(BlockHash::all_zeros(), &txdata, 1_700_000_000, CompactTarget::from_consensus(0x1e0377ae));
+        Block { header, txdata }
+    }
+
+    fn key() -> PrivateKey {
+        PrivateKey::from_slice(&[7; 32], NetworkKind::Test).unwrap()
+    }
+
+    #[test]
+    fn test_solution_round_trip() {
+        let mut block = unsigned_block();
+        let public = key().public_key(&Secp256k1::new());
+        let challenge = ScriptBuf::new_p2wpkh(&public.wpubkey_hash().unwrap());
+        sign_block(&mut block, &challenge, &key()).unwrap();
+        assert!(block.check_merkle_root());
+        assert!(block.check_witness_commitment());
+
+        // The parsed solution spends the same transaction that was signed
+        let signed = signet_txs(&block, &challenge).unwrap();
+        let unsigned = unsigned_signet_txs(&block, &challenge).unwrap();
+        assert_eq!(signed.to_spend, unsigned.to_spend);
+        assert_eq!(signed.to_sign.input[0].witness.len(), 2);
+        assert_eq!(signed.to_sign.input[0].witness.nth(1).unwrap(), public.to_bytes());
+
+        // Signing again replaces the solution instead of adding one
+        sign_block(&mut block, &challenge, &key()).unwrap();
+        let solved = signet_txs(&block, &challenge).unwrap();
+        assert_eq!(solved.to_spend, unsigned.to_spend);
+    }
+
+    #[test]
+    fn test_local_signing_challenges() {
+        let secp = Secp256k1::new();
+        let public = key().public_key(&secp);
+        let other = PrivateKey::from_slice(&[8; 32], NetworkKind::Test).unwrap().public_key(&secp);
+        let multisig = Builder::new()
+            .push_opcode(bitcoin::opcodes::all::OP_PUSHNUM_1)
+            .push_key(&other)
+            .push_key(&public)
+            .push_opcode(bitcoin::opcodes::all::OP_PUSHNUM_2)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        for challenge in [
+            ScriptBuf::new_p2pk(&public),
+            ScriptBuf::new_p2pkh(&public.pubkey_hash()),
+            multisig,
+            ScriptBuf::new_p2tr(&secp, public.inner.x_only_public_key().0, None),
+        ] {
+            let mut block = unsigned_block();
+            sign_block(&mut block, &challenge, &key()).unwrap();
+            assert!(signet_txs(&block, &challenge).is_some());
+        }
+
+        let mut block = unsigned_block();
+        assert!(sign_block(&mut block, &ScriptBuf::new_p2pk(&other), &key()).is_err());
+        // The default signet challenge belongs to someone else
+        let default = crate::chainparams::default_signet_challenge();
+        assert!(sign_block(&mut block, &default, &key()).is_err());
+        assert_eq!(Network::Signet.magic(), crate::chainparams::signet_magic(&default));
+    }
+
+    #[test]
+    fn test_psbt_flow() {
+        let block = unsigned_block();
+        let public = key().public_key(&Secp256k1::new());
+        let challenge = ScriptBuf::new_p2pk(&public);
+        let mut psbt = signet_psbt(&block, &challenge).unwrap();
+        assert!(block_from_psbt(&psbt, &challenge).is_err());
+
+        // What a PSBT signer produces for the same block
+        let mut signed = block.clone();
+        sign_block(&mut signed, &challenge, &key()).unwrap();
+        let solution = signet_txs(&signed, &challenge).unwrap();
+        psbt.inputs[0].final_script_sig = Some(solution.to_sign.input[0].script_sig.clone());
+        assert_eq!(block_from_psbt(&psbt, &challenge).unwrap(), signed);
+
+        // A PSBT for another challenge is refused
+        assert!(block_from_psbt(&psbt, &ScriptBuf::from_bytes(vec![0x51])).is_err());
+    }
+}
@@ -0,0 +1,766 @@
+//! Stratum v1 job server for solo mining (built with the `mining` feature).
+//!
+//! Miners connect over TCP and speak newline-delimited JSON-RPC:
+//! `mining.subscribe`, `mining.authorize` and `mining.submit` requests, and
+//! `mining.set_difficulty` and `mining.notify` notifications. Jobs are
+//! built from the mempool's block template like getblocktemplate, each
+//! connection gets its own extranonce1, and a share that also meets the
+//! network target is submitted to the kernel as a block. A new job is
+//! pushed on every tip change, and when the mempool changed after
+//! `JOB_REFRESH_INTERVAL`.
+//!
+//! Signet blocks are signed over the coinbase and the time (BIP 325), so on
+//! signet the node signs every connection's coinbase with
+//! `--stratumsignetkey`, hands out an empty extranonce2 and refuses rolled
+//! timestamps.
+
+use anyhow::{anyhow, bail, Context, Result};
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::hashes::{sha256d, Hash, HashEngine};
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::{
+    Block, BlockHash, CompactTarget, PrivateKey, ScriptBuf, Target, Transaction, TxMerkleNode, Witness,
+};
+use futures::StreamExt;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use std::collections::{HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_util::codec::{FramedRead, LinesCodec};
+
+use crate::descriptor;
+use crate::kernel::Kernel;
+use crate::mempool::miner::BlockAssemblerOptions;
+use crate::mempool::Mempool;
+use crate::mining::{self, unix_time, MAX_FUTURE_BLOCK_TIME};
+use crate::p2p::control::P2PHandle;
+use crate::signet;
+
+/// Share difficulty when `--stratumdifficulty` is not given
+pub const DEFAULT_SHARE_DIFFICULTY: f64 = 1.0;
+
+/// Bytes of the per-connection extranonce1 and the miner's extranonce2
+const EXTRANONCE1_SIZE: usize = 4;
+const EXTRANONCE2_SIZE: usize = 4;
+
+/// How often the tip is checked for changes
+const TIP_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A job is rebuilt with new mempool transactions after this long
+const JOB_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Jobs on the current tip that shares may still be submitted for
+const MAX_JOBS: usize = 16;
+
+/// Longest request line a miner may send
+const MAX_LINE_LENGTH: usize = 16 * 1024;
+
+// Stratum error codes
+const ERR_OTHER: i64 = 20;
+const ERR_JOB_NOT_FOUND: i64 = 21;
+const ERR_DUPLICATE_SHARE: i64 = 22;
+const ERR_LOW_DIFFICULTY: i64 = 23;
+const ERR_UNAUTHORIZED: i64 = 24;
+const ERR_NOT_SUBSCRIBED: i64 = 25;
+
+/// `--stratum*` command line options
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct StratumArgs {
+    /// serve Stratum v1 mining jobs on <address>, e.g. 127.0.0.1:3333
+    #[arg(long, value_name = "ADDRESS")]
+    pub stratum: Option<String>,
+
+    /// coinbase destination of stratum blocks, as an address or a descriptor
+    #[arg(long, value_name = "ADDRESS")]
+    pub stratumaddress: Option<String>,
+
+    /// share difficulty given to miners (1 = 2^32 hashes per share)
+    #[arg(long, default_value_t = DEFAULT_SHARE_DIFFICULTY)]
+    pub stratumdifficulty: f64,
+
+    /// signet only: WIF key that signs stratum blocks for the signet challenge
+    #[arg(long, value_name = "WIF")]
+    pub stratumsignetkey: Option<String>,
+}
+
+/// Target of a share at pool difficulty `difficulty`: the difficulty-1
+/// target 0xffff * 2^208 divided by it, saturating at 2^256 - 1
+fn share_target(difficulty: f64) -> Target {
+    let quotient = 65535.0 / difficulty;
+    let exponent = quotient.log2().floor() as i32;
+    // quotient = mantissa * 2^(exponent - 52), with a 53-bit mantissa
+    let mantissa = (quotient / 2f64.powi(exponent - 52)) as u64;
+    let mut shift = exponent - 52 + 208;
+    if shift + 53 > 256 {
+        return Target::from_be_bytes([0xff; 32]);
+    }
+    let mantissa = if shift < 0 { mantissa >> (-shift).min(63) } else { mantissa };
+    shift = shift.max(0);
+
+    let mut bytes = [0u8; 32];
+    let wide = (mantissa as u128) << (shift % 8);
+    for i in 0..16 {
+        let pos = 31 - (shift / 8) as isize - i as isize;
+        if pos >= 0 {
+            bytes[pos as usize] = (wide >> (8 * i)) as u8;
+        }
+    }
+    Target::from_be_bytes(bytes)
+}
+
+fn hash_pair(left: &sha256d::Hash, right: &sha256d::Hash) -> sha256d::Hash {
+    let mut engine = sha256d::Hash::engine();
+    engine.input(left.as_byte_array());
+    engine.input(right.as_byte_array());
+    sha256d::Hash::from_engine(engine)
+}
+
+/// Hashes a miner combines with its coinbase hash, bottom up, to get the
+/// merkle root of a block whose other transactions have `txids`
+fn merkle_branch(txids: &[sha256d::Hash]) -> Vec<sha256d::Hash> {
+    let mut branch = Vec::new();
+    let mut level = txids.to_vec();
+    while let Some((first, rest)) = level.split_first() {
+        branch.push(*first);
+        // The coinbase's row has an even length once `first` is paired with
+        // it, so an odd remainder repeats its last hash
+        let mut rest = rest.to_vec();
+        if rest.len() % 2 == 1 {
+            rest.push(*rest.last().unwrap());
+        }
+        level = rest
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+    branch
+}
+
+fn merkle_root(coinbase_txid: sha256d::Hash, branch: &[sha256d::Hash]) -> TxMerkleNode {
+    let root = branch.iter().fold(coinbase_txid, |acc, hash| hash_pair(&acc, hash));
+    TxMerkleNode::from_raw_hash(root)
+}
+
+/// Previous block hash as mining.notify sends it: the internal byte order
+/// with each 32-bit word byte-swapped
+fn stratum_prevhash(hash: &BlockHash) -> String {
+    let swapped: Vec<u8> = hash.as_byte_array().chunks(4).flat_map(|word| word.iter().rev().copied()).collect();
+    hex::encode(swapped)
+}
+
+fn parse_u32_hex(s: &str) -> Option<u32> {
+    (s.len() == 8).then(|| u32::from_str_radix(s, 16).ok()).flatten()
+}
+
+/// Serialization of `coinbase` without witness, split around the extranonce
+/// that ends its scriptSig: (coinb1, coinb2)
+fn split_coinbase(coinbase: &Transaction, extranonce_len: usize) -> (Vec<u8>, Vec<u8>) {
+    let mut stripped = coinbase.clone();
+    stripped.input[0].witness = Witness::new();
+    let bytes = serialize(&stripped);
+    // version, input count, outpoint and the scriptSig length (always one byte)
+    let end = 4 + 1 + 36 + 1 + coinbase.input[0].script_sig.len();
+    let start = end - extranonce_len;
+    (bytes[..start].to_vec(), bytes[end..].to_vec())
+}
+
+/// Resolves once shutdown was requested, even before `stop` was subscribed
+async fn stopped(stop: &mut watch::Receiver<bool>) {
+    let _ = stop.wait_for(|stopped| *stopped).await;
+}
+
+/// Work for one block template
+struct Job {
+    id: String,
+    prev: BlockHash,
+    height: u32,
+    version: i32,
+    bits: CompactTarget,
+    time: u32,
+    /// Earliest time a share may carry (median time past + 1)
+    min_time: u32,
+    /// Coinbase ending its scriptSig with a zeroed extranonce
+    coinbase: Transaction,
+    txs: Vec<Transaction>,
+    merkle_branch: Vec<sha256d::Hash>,
+    /// Miners drop their other jobs (the tip changed)
+    clean: bool,
+    mempool_sequence: u64,
+    created: Instant,
+    /// Header hashes of the shares accepted for this job
+    shares: Mutex<HashSet<BlockHash>>,
+}
+
+impl Job {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        id: String,
+        prev: BlockHash,
+        height: u32,
+        time: u32,
+        min_time: u32,
+        bits: CompactTarget,
+        value: u64,
+        script_pubkey: ScriptBuf,
+        txs: Vec<Transaction>,
+        extranonce_len: usize,
+    ) -> Self {
+        let commitment = mining::witness_commitment_script(&txs);
+        let mut coinbase = mining::create_coinbase(height, value, script_pubkey, Some(commitment));
+        let extranonce = PushBytesBuf::try_from(vec![0u8; extranonce_len]).expect("short extranonce");
+        coinbase.input[0].script_sig = Builder::new().push_int(height as i64).push_slice(extranonce).into_script();
+        let txids: Vec<sha256d::Hash> = txs.iter().map(|tx| tx.compute_txid().to_raw_hash()).collect();
+        Self {
+            id,
+            prev,
+            height,
+            version: mining::BLOCK_VERSION,
+            bits,
+            time,
+            min_time,
+            coinbase,
+            merkle_branch: merkle_branch(&txids),
+            txs,
+            clean: true,
+            mempool_sequence: 0,
+            created: Instant::now(),
+            shares: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// The block for `coinbase`, with `time` and `nonce` in the header
+    fn block(&self, coinbase: Transaction, time: u32, nonce: u32) -> Block {
+        let txdata: Vec<Transaction> = std::iter::once(coinbase).chain(self.txs.iter().cloned()).collect();
+        let mut header = mining::block_header(self.prev, &txdata, time, self.bits);
+        header.nonce = nonce;
+        let mut block = Block { header, txdata };
+        mining::update_uncommitted_block_structures(&mut block);
+        block
+    }
+
+    /// Block a miner solved from coinb1 + extranonce1 + extranonce2 + coinb2
+    fn solved_block(&self, coinbase: &[u8], time: u32, nonce: u32) -> Option<Block> {
+        let coinbase: Transaction = deserialize(coinbase).ok()?;
+        let block = self.block(coinbase, time, nonce);
+        let root = merkle_root(block.txdata[0].compute_txid().to_raw_hash(), &self.merkle_branch);
+        (block.header.merkle_root == root).then_some(block)
+    }
+
+    fn notify_params(&self, coinb1: &[u8], coinb2: &[u8]) -> Value {
+        let branch: Vec<String> = self.merkle_branch.iter().map(|h| hex::encode(h.as_byte_array())).collect();
+        json!([
+            self.id,
+            stratum_prevhash(&self.prev),
+            hex::encode(coinb1),
+            hex::encode(coinb2),
+            branch,
+            format!("{:08x}", self.version as u32),
+            format!("{:08x}", self.bits.to_consensus()),
+            format!("{:08x}", self.time),
+            self.clean,
+        ])
+    }
+}
+
+/// Per-connection protocol state
+struct Connection {
+    addr: SocketAddr,
+    extranonce1: [u8; EXTRANONCE1_SIZE],
+    subscribed: bool,
+    workers: HashSet<String>,
+}
+
+struct Server {
+    kernel: Arc<Kernel>,
+    mempool: Arc<Mempool>,
+    p2p: Option<P2PHandle>,
+    script_pubkey: ScriptBuf,
+    difficulty: f64,
+    share_target: Target,
+    /// Signet challenge and the key that signs blocks for it
+    signer: Option<(ScriptBuf, PrivateKey)>,
+    /// Jobs on the current tip, newest last
+    jobs: Mutex<VecDeque<Arc<Job>>>,
+    job_tx: broadcast::Sender<Arc<Job>>,
+    next_job_id: AtomicU64,
+    next_extranonce1: AtomicU32,
+    stop: watch::Sender<bool>,
+    /// Dropped with the server, once every task holding it has exited
+    _running: mpsc::Sender<()>,
+}
+
+/// Running Stratum server; `shutdown` must be awaited before the kernel is dropped
+pub struct StratumServer {
+    server: Arc<Server>,
+    stopped: mpsc::Receiver<()>,
+}
+
+impl StratumServer {
+    /// Bind the listener and start following the tip. Returns None when
+    /// `--stratum` is not set.
+    pub async fn start(
+        args: &StratumArgs,
+        kernel: Arc<Kernel>,
+        mempool: Arc<Mempool>,
+        p2p: Option<P2PHandle>,
+    ) -> Result<Option<Self>> {
+        let Some(listen) = &args.stratum else {
+            return Ok(None);
+        };
+        let network = kernel.network();
+        let output = args.stratumaddress.as_deref().context("--stratum needs --stratumaddress for the coinbase")?;
+        let script_pubkey =
+            descriptor::output_script(output, network).ok_or_else(|| anyhow!("invalid --stratumaddress {}", output))?;
+        if !(args.stratumdifficulty > 0.0 && args.stratumdifficulty.is_finite()) {
+            bail!("--stratumdifficulty must be a positive number");
+        }
+
+        let signer = match (kernel.signet_challenge(), &args.stratumsignetkey) {
+            (Some(challenge), Some(wif)) => {
+                let key = PrivateKey::from_wif(wif).context("invalid --stratumsignetkey")?;
+                Some((challenge.to_owned(), key))
+            }
+            (Some(_), None) => bail!("--stratum on signet needs --stratumsignetkey to sign blocks"),
+            (None, Some(_)) => {
+                eprintln!("[stratum] --stratumsignetkey only applies to signet; ignoring");
+                None
+            }
+            (None, None) => None,
+        };
+
+        let (job_tx, _) = broadcast::channel(MAX_JOBS);
+        let (stop, _) = watch::channel(false);
+        let (running, stopped) = mpsc::channel(1);
+        let server = Arc::new(Server {
+            kernel,
+            mempool,
+            p2p,
+            script_pubkey,
+            difficulty: args.stratumdifficulty,
+            share_target: share_target(args.stratumdifficulty),
+            signer,
+            jobs: Mutex::new(VecDeque::new()),
+            job_tx,
+            next_job_id: AtomicU64::new(1),
+            next_extranonce1: AtomicU32::new(rand::random()),
+            stop,
+            _running: running,
+        });
+
+        // The first job also checks that the signet key solves the challenge
+        let job = {
+            let server = server.clone();
+            tokio::task::spawn_blocking(move || server.new_job(true)).await??
+        };
+        server.coinbase_halves(&job, &[0; EXTRANONCE1_SIZE]).map_err(|e| anyhow!("cannot sign signet blocks: {}", e))?;
+
+        let listener = TcpListener::bind(listen).await.with_context(|| format!("cannot bind stratum on {}", listen))?;
+        eprintln!(
+            "[stratum] serving jobs on {} (share difficulty {}, {} bytes of extranonce2)",
+            listen,
+            server.difficulty,
+            server.extranonce2_size()
+        );
+        tokio::spawn(server.clone().accept_loop(listener));
+        tokio::spawn(server.clone().follow_tip());
+
+        Ok(Some(Self { server, stopped }))
+    }
+
+    /// Close every connection and wait until no task holds the kernel
+    pub async fn shutdown(self) {
+        let Self { server, mut stopped } = self;
+        server.stop.send_replace(true);
+        drop(server);
+        let _ = stopped.recv().await;
+    }
+}
+
+impl Server {
+    fn extranonce2_size(&self) -> usize {
+        if self.signer.is_some() {
+            0
+        } else {
+            EXTRANONCE2_SIZE
+        }
+    }
+
+    fn current_job(&self) -> Option<Arc<Job>> {
+        self.jobs.lock().back().cloned()
+    }
+
+    /// Build a job from the mempool's template and announce it
+    fn new_job(&self, clean: bool) -> Result<Arc<Job>> {
+        let k = &self.kernel;
+        let tip = k.get_best_block_hash()?;
+        let template = self.mempool.get_block_template(BlockAssemblerOptions::default());
+        let time = unix_time().max(template.lock_time_cutoff + 1);
+        let bits = mining::next_work_required(k, &tip, time)?;
+        let value = mining::block_subsidy(template.height, k.network()) + template.fees;
+        let txs: Vec<Transaction> = template.txs.iter().map(|t| (*t.tx).clone()).collect();
+
+        let id = format!("{:x}", self.next_job_id.fetch_add(1, Ordering::SeqCst));
+        let extranonce_len = EXTRANONCE1_SIZE + self.extranonce2_size();
+        let job = Job {
+            clean,
+            mempool_sequence: self.mempool.sequence(),
+            ..Job::new(
+                id,
+                tip,
+                template.height,
+                time,
+                template.lock_time_cutoff + 1,
+                bits,
+                value,
+                self.script_pubkey.clone(),
+                txs,
+                extranonce_len,
+            )
+        };
+        let job = Arc::new(job);
+
+        {
+            let mut jobs = self.jobs.lock();
+            if clean {
+                jobs.clear();
+            }
+            if jobs.len() == MAX_JOBS {
+                jobs.pop_front();
+            }
+            jobs.push_back(job.clone());
+        }
+        eprintln!("[stratum] job {} at height {} with {} transactions", job.id, job.height, job.txs.len());
+        let _ = self.job_tx.send(job.clone());
+        Ok(job)
+    }
+
+    /// coinb1 and coinb2 for a connection: the job's own, or on signet the
+    /// halves of the coinbase signed with the connection's extranonce1
+    fn coinbase_halves(&self, job: &Job, extranonce1: &[u8]) -> Result<(Vec<u8>, Vec<u8>), &'static str> {
+        let extranonce_len = EXTRANONCE1_SIZE + self.extranonce2_size();
+        let Some((challenge, key)) = &self.signer else {
+            return Ok(split_coinbase(&job.coinbase, extranonce_len));
+        };
+        let mut coinbase = job.coinbase.clone();
+        let script_sig = coinbase.input[0].script_sig.as_bytes().to_vec();
+        let start = script_sig.len() - extranonce_len;
+        coinbase.input[0].script_sig = ScriptBuf::from_bytes([&script_sig[..start], extranonce1].concat());
+        let mut block = job.block(coinbase, job.time, 0);
+        signet::sign_block(&mut block, challenge, key)?;
+        Ok(split_coinbase(&block.txdata[0], extranonce_len))
+    }
+
+    /// Check a mining.submit share. Returns the block when it also meets
+    /// the network target.
+    fn check_share(&self, conn: &Connection, params: &[Value]) -> Result<Option<Block>, (i64, &'static str)> {
+        let param = |i: usize| params.get(i).and_then(Value::as_str);
+        let (Some(worker), Some(job_id), Some(extranonce2), Some(time), Some(nonce)) =
+            (param(0), param(1), param(2), param(3), param(4))
+        else {
+            return Err((ERR_OTHER, "Invalid parameters"));
+        };
+        if !conn.workers.contains(worker) {
+            return Err((ERR_UNAUTHORIZED, "Unauthorized worker"));
+        }
+        let job = self
+            .jobs
+            .lock()
+            .iter()
+            .find(|job| job.id == job_id)
+            .cloned()
+            .ok_or((ERR_JOB_NOT_FOUND, "Job not found"))?;
+        let extranonce2 = hex::decode(extranonce2)
+            .ok()
+            .filter(|e| e.len() == self.extranonce2_size())
+            .ok_or((ERR_OTHER, "Invalid extranonce2 size"))?;
+        let time = parse_u32_hex(time).ok_or((ERR_OTHER, "Invalid ntime"))?;
+        let nonce = parse_u32_hex(nonce).ok_or((ERR_OTHER, "Invalid nonce"))?;
+        let time_ok = if self.signer.is_some() {
+            time == job.time
+        } else {
+            time >= job.min_time && time <= unix_time().saturating_add(MAX_FUTURE_BLOCK_TIME)
+        };
+        if !time_ok {
+            return Err((ERR_OTHER, "ntime out of range"));
+        }
+
+        let (coinb1, coinb2) = self.coinbase_halves(&job, &conn.extranonce1).map_err(|e| (ERR_OTHER, e))?;
+        let coinbase = [&coinb1[..], &conn.extranonce1[..], &extranonce2[..], &coinb2[..]].concat();
+        let block = job.solved_block(&coinbase, time, nonce).ok_or((ERR_OTHER, "Invalid coinbase"))?;
+        let hash = block.block_hash();
+        let is_block = block.header.target().is_met_by(hash);
+        if !is_block && !self.share_target.is_met_by(hash) {
+            return Err((ERR_LOW_DIFFICULTY, "Low difficulty share"));
+        }
+        if !job.shares.lock().insert(hash) {
+            return Err((ERR_DUPLICATE_SHARE, "Duplicate share"));
+        }
+        Ok(is_block.then_some(block))
+    }
+
+    async fn accept_loop(self: Arc<Self>, listener: TcpListener) {
+        let mut stop = self.stop.subscribe();
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, addr)) => {
+                        tokio::spawn(self.clone().serve(stream, addr));
+                    }
+                    Err(e) => eprintln!("[stratum] accept failed: {}", e),
+                },
+                _ = stopped(&mut stop) => break,
+            }
+        }
+    }
+
+    /// Push a new job when the tip changes, or when the mempool changed
+    /// since a job older than JOB_REFRESH_INTERVAL
+    async fn follow_tip(self: Arc<Self>) {
+        let mut stop = self.stop.subscribe();
+        let mut interval = tokio::time::interval(TIP_POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = stopped(&mut stop) => break,
+            }
+            let (Some(job), Ok(tip)) = (self.current_job(), self.kernel.get_best_block_hash()) else {
+                continue;
+            };
+            let clean = tip != job.prev;
+            let stale = job.created.elapsed() >= JOB_REFRESH_INTERVAL && self.mempool.sequence() != job.mempool_sequence;
+            if !clean && !stale {
+                continue;
+            }
+            let server = self.clone();
+            match tokio::task::spawn_blocking(move || server.new_job(clean)).await {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => eprintln!("[stratum] cannot build a job: {:#}", e),
+                Err(e) => eprintln!("[stratum] job task failed: {}", e),
+            }
+        }
+    }
+
+    async fn serve(self: Arc<Self>, stream: TcpStream, addr: SocketAddr) {
+        let extranonce1 = self.next_extranonce1.fetch_add(1, Ordering::SeqCst).to_be_bytes();
+        let mut conn = Connection { addr, extranonce1, subscribed: false, workers: HashSet::new() };
+        eprintln!("[stratum] {} connected", addr);
+        match self.run_connection(stream, &mut conn).await {
+            Ok(()) => eprintln!("[stratum] {} disconnected", addr),
+            Err(e) => eprintln!("[stratum] {} disconnected: {:#}", addr, e),
+        }
+    }
+
+    async fn run_connection(&self, stream: TcpStream, conn: &mut Connection) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = FramedRead::new(reader, LinesCodec::new_with_max_length(MAX_LINE_LENGTH));
+        let mut jobs = self.job_tx.subscribe();
+        let mut stop = self.stop.subscribe();
+
+        loop {
+            let messages = tokio::select! {
+                line = lines.next() => match line {
+                    Some(line) => self.handle_line(conn, &line?).await,
+                    None => return Ok(()),
+                },
+                job = jobs.recv() => match job {
+                    Ok(job) if conn.subscribed => self.notify(conn, &job).into_iter().collect(),
+                    Ok(_) => continue,
+                    // Only the newest job matters
+                    Err(broadcast::error::RecvError::Lagged(_)) => match self.current_job() {
+                        Some(job) if conn.subscribed => self.notify(conn, &job).into_iter().collect(),
+                        _ => continue,
+                    },
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+                _ = stopped(&mut stop) => return Ok(()),
+            };
+            for message in messages {
+                writer.write_all(format!("{}\n", message).as_bytes()).await?;
+            }
+        }
+    }
+
+    fn notify(&self, conn: &Connection, job: &Job) -> Option<Value> {
+        match self.coinbase_halves(job, &conn.extranonce1) {
+            Ok((coinb1, coinb2)) => {
+                Some(json!({ "id": null, "method": "mining.notify", "params": job.notify_params(&coinb1, &coinb2) }))
+            }
+            Err(e) => {
+                eprintln!("[stratum] cannot sign job {} for {}: {}", job.id, conn.addr, e);
+                None
+            }
+        }
+    }
+
+    /// Answer one request line; returns the messages to send
+    async fn handle_line(&self, conn: &mut Connection, line: &str) -> Vec<Value> {
+        let Ok(request) = serde_json::from_str::<Value>(line) else {
+            return vec![json!({ "id": null, "result": null, "error": [ERR_OTHER, "Parse error", null] })];
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = request.get("method").and_then(Value::as_str).unwrap_or_default();
+        let params = request.get("params").and_then(Value::as_array).cloned().unwrap_or_default();
+
+        let mut messages = Vec::new();
+        let result = match method {
+            "mining.subscribe" => {
+                conn.subscribed = true;
+                let subscription = hex::encode(conn.extranonce1);
+                Ok(json!([
+                    [["mining.set_difficulty", subscription], ["mining.notify", subscription]],
+                    hex::encode(conn.extranonce1),
+                    self.extranonce2_size(),
+                ]))
+            }
+            "mining.authorize" => match params.first().and_then(Value::as_str) {
+                Some(worker) => {
+                    eprintln!("[stratum] {} authorized worker {}", conn.addr, worker);
+                    conn.workers.insert(worker.to_string());
+                    Ok(json!(true))
+                }
+                None => Err((ERR_OTHER, "Invalid parameters")),
+            },
+            // No extensions (BIP 310): version rolling is not offered
+            "mining.configure" => Ok(json!({})),
+            "mining.submit" if !conn.subscribed => Err((ERR_NOT_SUBSCRIBED, "Not subscribed")),
+            "mining.submit" => match self.check_share(conn, &params) {
+                Ok(None) => Ok(json!(true)),
+                Ok(Some(block)) => self.submit_block(conn, &params, block).await,
+                Err(e) => Err(e),
+            },
+            _ => Err((ERR_OTHER, "Method not found")),
+        };
+        messages.push(match result {
+            Ok(result) => json!({ "id": id, "result": result, "error": null }),
+            Err((code, message)) => json!({ "id": id, "result": null, "error": [code, message, null] }),
+        });
+
+        // A new subscriber gets its difficulty and the current job right away
+        if method == "mining.subscribe" {
+            messages.push(json!({ "id": null, "method": "mining.set_difficulty", "params": [self.difficulty] }));
+            messages.extend(self.current_job().and_then(|job| self.notify(conn, &job)));
+        }
+        messages
+    }
+
+    /// Hand a solved block to the kernel through the same path as submitblock
+    async fn submit_block(&self, conn: &Connection, params: &[Value], block: Block) -> Result<Value, (i64, &'static str)> {
+        let hash = block.block_hash();
+        let worker = params.first().and_then(Value::as_str).unwrap_or_default();
+        eprintln!("[stratum] block {} found by {} ({})", hash, worker, conn.addr);
+
+        let (kernel, mempool, p2p) = (self.kernel.clone(), self.mempool.clone(), self.p2p.clone());
+        let result =
+            tokio::task::spawn_blocking(move || mining::submit_block(&kernel, &mempool, p2p.as_ref(), block)).await;
+        match result {
+            Ok(Ok(None)) => Ok(json!(true)),
+            Ok(Ok(Some(reason))) => {
+                eprintln!("[stratum] block {} was not accepted: {}", hash, reason);
+                Err((ERR_OTHER, reason))
+            }
+            Ok(Err(e)) => {
+                eprintln!("[stratum] block {} submission failed: {:#}", hash, e);
+                Err((ERR_OTHER, "Block submission failed"))
+            }
+            Err(_) => Err((ERR_OTHER, "Block submission failed")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{merkle_tree, Amount, Network, OutPoint, TxIn, TxOut, Txid, WPubkeyHash};
+
+    fn regtest_job(txs: Vec<Transaction>) -> Job {
+        let genesis = bitcoin::constants::genesis_block(Network::Regtest);
+        let payout = ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros());
+        let time = genesis.header.time + 1;
+        Job::new("1".into(), genesis.block_hash(), 1, time, time, genesis.header.bits, 50_0000_0000, payout, txs, 8)
+    }
+
+    fn spend(n: u8) -> Transaction {
+        Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([n; 32]), 0),
+                witness: Witness::from_slice(&[vec![n; 72], vec![2u8; 33]]),
+                ..Default::default()
+            }],
+            output: vec![TxOut { value: Amount::from_sat(10_000), script_pubkey: ScriptBuf::new() }],
+        }
+    }
+
+    #[test]
+    fn test_share_target() {
+        let mut diff1 = [0u8; 32];
+        diff1[4] = 0xff;
+        diff1[5] = 0xff;
+        assert_eq!(share_target(1.0), Target::from_be_bytes(diff1));
+
+        let mut half = [0u8; 32];
+        half[4] = 0x7f;
+        half[5] = 0xff;
+        half[6] = 0x80;
+        assert_eq!(share_target(2.0), Target::from_be_bytes(half));
+
+        // Regtest blocks are at difficulty 4.7e-10
+        let regtest = Target::from_compact(CompactTarget::from_consensus(0x207fffff));
+        assert!(share_target(1e-9) < regtest);
+        assert!(share_target(1e-10) > regtest);
+        assert_eq!(share_target(1e-30), Target::from_be_bytes([0xff; 32]));
+    }
+
+    #[test]
+    fn test_merkle_branch() {
+        for n in 0..8u8 {
+            let coinbase = sha256d::Hash::hash(&[0xcb]);
+            let txids: Vec<sha256d::Hash> = (0..n).map(|i| sha256d::Hash::hash(&[i])).collect();
+            let expected = merkle_tree::calculate_root(std::iter::once(coinbase).chain(txids.iter().copied())).unwrap();
+            assert_eq!(merkle_root(coinbase, &merkle_branch(&txids)).to_raw_hash(), expected, "{} transactions", n);
+        }
+    }
+
+    #[test]
+    fn test_prevhash_word_order() {
+        let hash = BlockHash::from_byte_array(core::array::from_fn(|i| i as u8));
+        assert!(stratum_prevhash(&hash).starts_with("0302010007060504"));
+    }
+
+    #[test]
+    fn test_mine_job() {
+        let job = regtest_job(vec![spend(1), spend(2), spend(3)]);
+        let (coinb1, coinb2) = split_coinbase(&job.coinbase, 8);
+        let extranonce1 = [0xde, 0xad, 0xbe, 0xef];
+
+        // What a miner does with mining.notify
+        let mut found = None;
+        for extranonce2 in 0u32.. {
+            let coinbase = [&coinb1[..], &extranonce1, &extranonce2.to_be_bytes(), &coinb2].concat();
+            let root = merkle_root(sha256d::Hash::hash(&coinbase), &job.merkle_branch);
+            let mut header = mining::block_header(job.prev, &[], job.time, job.bits);
+            header.merkle_root = root;
+            let mut tries = 1_000;
+            if mining::grind_header(&mut header, &mut tries) {
+                found = Some((coinbase, header.nonce, header.block_hash()));
+                break;
+            }
+        }
+        let (coinbase, nonce, hash) = found.unwrap();
+
+        let block = job.solved_block(&coinbase, job.time, nonce).unwrap();
+        assert_eq!(block.block_hash(), hash);
+        assert_eq!(mining::check_block(&block, true), Ok(()));
+        let script_sig = block.txdata[0].input[0].script_sig.as_bytes();
+        assert_eq!(&script_sig[script_sig.len() - 8..][..4], &extranonce1);
+        assert_eq!(job.solved_block(&coinbase[1..], job.time, nonce).map(|b| b.block_hash()), None);
+    }
+}
@@ -0,0 +1,339 @@
+//! ZeroMQ notifications (Bitcoin Core: zmq/zmqpublishnotifier.cpp).
+//!
+//! Publishes `hashblock`, `rawblock`, `hashtx`, `rawtx` and `sequence`
+//! messages on ZMTP PUB sockets configured with `--zmqpub<topic>=tcp://...`.
+//! Each message has three parts: topic, body and a 4-byte little-endian
+//! per-topic sequence number, exactly like Bitcoin Core, so existing
+//! subscribers (lnd, indexers) work unchanged.
+
+pub mod zmtp;
+
+use anyhow::{anyhow, Context, Result};
+use bitcoin::consensus::serialize;
+use bitcoin::hashes::Hash;
+use bitcoin::{Block, BlockHash, Transaction};
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::index::BlockIndex;
+use crate::kernel::Kernel;
+use crate::mempool::{Mempool, MempoolEvent, RemovalReason};
+use zmtp::PubSocket;
+
+/// Default per-subscriber outbound message limit (Bitcoin Core: DEFAULT_ZMQ_SNDHWM)
+pub const DEFAULT_ZMQ_SNDHWM: usize = 1000;
+
+/// `--zmqpub*` command line options
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct ZmqArgs {
+    /// publish block hashes on <address>, e.g. tcp://127.0.0.1:28332
+    #[arg(long, value_name = "ADDRESS")]
+    pub zmqpubhashblock: Option<String>,
+
+    /// publish transaction hashes (mempool and block) on <address>
+    #[arg(long, value_name = "ADDRESS")]
+    pub zmqpubhashtx: Option<String>,
+
+    /// publish raw blocks on <address>
+    #[arg(long, value_name = "ADDRESS")]
+    pub zmqpubrawblock: Option<String>,
+
+    /// publish raw transactions (mempool and block) on <address>
+    #[arg(long, value_name = "ADDRESS")]
+    pub zmqpubrawtx: Option<String>,
+
+    /// publish block connect/disconnect and mempool add/remove events on <address>
+    #[arg(long, value_name = "ADDRESS")]
+    pub zmqpubsequence: Option<String>,
+
+    /// outbound message high water mark for hashblock
+    #[arg(long, default_value_t = DEFAULT_ZMQ_SNDHWM)]
+    pub zmqpubhashblockhwm: usize,
+
+    /// outbound message high water mark for hashtx
+    #[arg(long, default_value_t = DEFAULT_ZMQ_SNDHWM)]
+    pub zmqpubhashtxhwm: usize,
+
+    /// outbound message high water mark for rawblock
+    #[arg(long, default_value_t = DEFAULT_ZMQ_SNDHWM)]
+    pub zmqpubrawblockhwm: usize,
+
+    /// outbound message high water mark for rawtx
+    #[arg(long, default_value_t = DEFAULT_ZMQ_SNDHWM)]
+    pub zmqpubrawtxhwm: usize,
+
+    /// outbound message high water mark for sequence
+    #[arg(long, default_value_t = DEFAULT_ZMQ_SNDHWM)]
+    pub zmqpubsequencehwm: usize,
+}
+
+impl ZmqArgs {
+    /// Configured (topic, address, hwm) triples, in getzmqnotifications order
+    fn publishers(&self) -> Vec<(Topic, String, usize)> {
+        [
+            (Topic::HashBlock, &self.zmqpubhashblock, self.zmqpubhashblockhwm),
+            (Topic::HashTx, &self.zmqpubhashtx, self.zmqpubhashtxhwm),
+            (Topic::RawBlock, &self.zmqpubrawblock, self.zmqpubrawblockhwm),
+            (Topic::RawTx, &self.zmqpubrawtx, self.zmqpubrawtxhwm),
+            (Topic::Sequence, &self.zmqpubsequence, self.zmqpubsequencehwm),
+        ]
+        .into_iter()
+        .filter_map(|(topic, address, hwm)| address.clone().map(|a| (topic, a, hwm)))
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Topic {
+    HashBlock,
+    HashTx,
+    RawBlock,
+    RawTx,
+    Sequence,
+}
+
+impl Topic {
+    fn name(&self) -> &'static str {
+        match self {
+            Topic::HashBlock => "hashblock",
+            Topic::HashTx => "hashtx",
+            Topic::RawBlock => "rawblock",
+            Topic::RawTx => "rawtx",
+            Topic::Sequence => "sequence",
+        }
+    }
+}
+
+/// Entry of getzmqnotifications
+#[derive(Debug, Clone, Serialize)]
+pub struct ZmqNotification {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub address: String,
+    pub hwm: usize,
+}
+
+struct Publisher {
+    topic: Topic,
+    address: String,
+    hwm: usize,
+    socket: Arc<PubSocket>,
+    sequence: AtomicU32,
+}
+
+/// Resolve "tcp://host:port"; "*" binds all interfaces
+async fn resolve_address(address: &str) -> Result<SocketAddr> {
+    let hostport = address
+        .strip_prefix("tcp://")
+        .ok_or_else(|| anyhow!("unsupported ZMQ address {} (only tcp:// is supported)", address))?;
+    let hostport = match hostport.strip_prefix("*:") {
+        Some(port) => format!("0.0.0.0:{}", port),
+        None => hostport.to_string(),
+    };
+    let mut addrs = tokio::net::lookup_host(hostport)
+        .await
+        .with_context(|| format!("cannot resolve ZMQ address {}", address))?;
+    addrs.next().ok_or_else(|| anyhow!("cannot resolve ZMQ address {}", address))
+}
+
+/// Hash bytes in display order, as Bitcoin Core sends them
+fn reversed(bytes: [u8; 32]) -> [u8; 32] {
+    let mut out = bytes;
+    out.reverse();
+    out
+}
+
+/// `sequence` topic body: hash, label and, for mempool events, the mempool sequence
+fn sequence_body(hash: [u8; 32], label: u8, mempool_sequence: Option<u64>) -> Vec<u8> {
+    let mut body = Vec::with_capacity(41);
+    body.extend_from_slice(&reversed(hash));
+    body.push(label);
+    if let Some(seq) = mempool_sequence {
+        body.extend_from_slice(&seq.to_le_bytes());
+    }
+    body
+}
+
+/// Publishes chain and mempool events to ZMQ subscribers
+pub struct ZmqNotifier {
+    publishers: Vec<Publisher>,
+    /// Last block notified; blocks follow the active chain like an index
+    best: RwLock<Option<(i32, BlockHash)>>,
+}
+
+impl ZmqNotifier {
+    /// Bind every configured publisher. Returns None when no `--zmqpub*` option is set.
+    pub async fn start(args: &ZmqArgs, kernel: &Kernel) -> Result<Option<Arc<Self>>> {
+        let configured = args.publishers();
+        if configured.is_empty() {
+            return Ok(None);
+        }
+
+        // Topics configured with the same address share one socket
+        let mut sockets: HashMap<String, Arc<PubSocket>> = HashMap::new();
+        let mut publishers = Vec::with_capacity(configured.len());
+        for (topic, address, hwm) in configured {
+            let socket = match sockets.get(&address) {
+                Some(socket) => socket.clone(),
+                None => {
+                    let addr = resolve_address(&address).await?;
+                    let socket = PubSocket::bind(addr, hwm)
+                        .await
+                        .with_context(|| format!("cannot bind ZMQ socket on {}", address))?;
+                    sockets.insert(address.clone(), socket.clone());
+                    socket
+                }
+            };
+            eprintln!("[zmq] publishing {} on {}", topic.name(), address);
+            publishers.push(Publisher { topic, address, hwm, socket, sequence: AtomicU32::new(0) });
+        }
+
+        // Only notify blocks connected from now on
+        let best = match kernel.get_height()? {
+            height if height >= 0 => Some((height, kernel.get_best_block_hash()?)),
+            _ => None,
+        };
+
+        Ok(Some(Arc::new(Self { publishers, best: RwLock::new(best) })))
+    }
+
+    pub fn notifications(&self) -> Vec<ZmqNotification> {
+        self.publishers
+            .iter()
+            .map(|p| ZmqNotification {
+                kind: format!("pub{}", p.topic.name()),
+                address: p.address.clone(),
+                hwm: p.hwm,
+            })
+            .collect()
+    }
+
+    fn publish(&self, topic: Topic, body: &[u8]) {
+        for p in self.publishers.iter().filter(|p| p.topic == topic) {
+            let sequence = p.sequence.fetch_add(1, Ordering::SeqCst).to_le_bytes();
+            p.socket.publish(&[topic.name().as_bytes(), body, &sequence]);
+        }
+    }
+
+    fn has_topic(&self, topic: Topic) -> bool {
+        self.publishers.iter().any(|p| p.topic == topic)
+    }
+
+    fn notify_transaction(&self, tx: &Transaction) {
+        if self.has_topic(Topic::HashTx) {
+            self.publish(Topic::HashTx, &reversed(tx.compute_txid().to_byte_array()));
+        }
+        if self.has_topic(Topic::RawTx) {
+            self.publish(Topic::RawTx, &serialize(tx));
+        }
+    }
+
+    fn notify_mempool_event(&self, event: &MempoolEvent) {
+        match event {
+            MempoolEvent::Added { tx, sequence } => {
+                self.notify_transaction(tx);
+                let body = sequence_body(tx.compute_txid().to_byte_array(), b'A', Some(*sequence));
+                self.publish(Topic::Sequence, &body);
+            }
+            // Transactions mined in a block are reported by the block's 'C' event
+            MempoolEvent::Removed { reason: RemovalReason::Block, .. } => {}
+            MempoolEvent::Removed { tx, sequence, .. } => {
+                let body = sequence_body(tx.compute_txid().to_byte_array(), b'R', Some(*sequence));
+                self.publish(Topic::Sequence, &body);
+            }
+        }
+    }
+
+    /// Forward mempool additions and removals until the mempool is dropped
+    pub fn spawn_mempool_listener(self: &Arc<Self>, mempool: &Mempool) -> tokio::task::JoinHandle<()> {
+        let mut events = mempool.subscribe();
+        let notifier = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => notifier.notify_mempool_event(&event),
+                    Err(RecvError::Lagged(n)) => eprintln!("[zmq] missed {} mempool events", n),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
+
+impl BlockIndex for ZmqNotifier {
+    fn name(&self) -> &'static str {
+        "zmq"
+    }
+
+    fn best_block(&self) -> Option<(i32, BlockHash)> {
+        *self.best.read()
+    }
+
+    fn connect_block(&self, height: i32, block: &Block) -> Result<()> {
+        let hash = block.block_hash();
+        for tx in &block.txdata {
+            self.notify_transaction(tx);
+        }
+        self.publish(Topic::Sequence, &sequence_body(hash.to_byte_array(), b'C', None));
+        self.publish(Topic::HashBlock, &reversed(hash.to_byte_array()));
+        if self.has_topic(Topic::RawBlock) {
+            self.publish(Topic::RawBlock, &serialize(block));
+        }
+        *self.best.write() = Some((height, hash));
+        Ok(())
+    }
+
+    fn disconnect_block(&self, height: i32, block: &Block) -> Result<()> {
+        for tx in &block.txdata {
+            self.notify_transaction(tx);
+        }
+        let hash = block.block_hash();
+        self.publish(Topic::Sequence, &sequence_body(hash.to_byte_array(), b'D', None));
+        *self.best.write() = if height > 0 { Some((height - 1, block.header.prev_blockhash)) } else { None };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_body() {
+        let mut hash = [0u8; 32];
+        hash[0] = 0xAA;
+        let body = sequence_body(hash, b'A', Some(7));
+        assert_eq!(body.len(), 41);
+        assert_eq!(body[31], 0xAA);
+        assert_eq!(body[32], b'A');
+        assert_eq!(&body[33..], &7u64.to_le_bytes());
+
+        assert_eq!(sequence_body(hash, b'C', None).len(), 33);
+    }
+
+    #[test]
+    fn test_configured_publishers() {
+        let args = ZmqArgs {
+            zmqpubrawtx: Some("tcp://127.0.0.1:28333".into()),
+            zmqpubhashblock: Some("tcp://127.0.0.1:28332".into()),
+            zmqpubhashblockhwm: 5,
+            ..Default::default()
+        };
+        let publishers = args.publishers();
+        assert_eq!(publishers.len(), 2);
+        assert_eq!(publishers[0], (Topic::HashBlock, "tcp://127.0.0.1:28332".to_string(), 5));
+        assert_eq!(publishers[1].0, Topic::RawTx);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_address() {
+        assert_eq!(resolve_address("tcp://127.0.0.1:28332").await.unwrap(), "127.0.0.1:28332".parse().unwrap());
+        assert_eq!(resolve_address("tcp://*:28332").await.unwrap(), "0.0.0.0:28332".parse().unwrap());
+        assert!(resolve_address("ipc:///tmp/zmq").await.is_err());
+    }
+}
@@ -0,0 +1,415 @@
+//! Minimal ZMTP 3.x PUB socket (https://rfc.zeromq.org/spec/23/ and /37/).
+//!
+//! Only what a publisher needs: the NULL security handshake, READY
+//! exchange, subscription tracking and multipart message framing. Any
+//! libzmq SUB socket (pyzmq, lnd, bitcoin-zmq clients) can connect to it.
+
+use anyhow::{anyhow, bail, Result};
+use parking_lot::{Mutex, RwLock};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+const FLAG_MORE: u8 = 0x01;
+const FLAG_LONG: u8 = 0x02;
+const FLAG_COMMAND: u8 = 0x04;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest frame a subscriber may send us (subscriptions and commands are tiny)
+const MAX_INBOUND_FRAME: u64 = 64 * 1024;
+
+/// 64-byte ZMTP 3.1 greeting for the NULL mechanism, as server=0
+fn greeting() -> [u8; 64] {
+    let mut g = [0u8; 64];
+    g[0] = 0xFF;
+    g[8] = 0x01;
+    g[9] = 0x7F;
+    g[10] = 3; // major
+    g[11] = 1; // minor
+    g[12..16].copy_from_slice(b"NULL");
+    g
+}
+
+/// Encode one frame
+fn encode_frame(buf: &mut Vec<u8>, flags: u8, body: &[u8]) {
+    if body.len() > 255 {
+        buf.push(flags | FLAG_LONG);
+        buf.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    } else {
+        buf.push(flags);
+        buf.push(body.len() as u8);
+    }
+    buf.extend_from_slice(body);
+}
+
+/// Encode a multipart message
+pub fn encode_message(parts: &[&[u8]]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(parts.iter().map(|p| p.len() + 9).sum());
+    for (i, part) in parts.iter().enumerate() {
+        let flags = if i + 1 < parts.len() { FLAG_MORE } else { 0 };
+        encode_frame(&mut buf, flags, part);
+    }
+    buf
+}
+
+/// Encode a command frame: name length, name, data
+fn encode_command(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(1 + name.len() + data.len());
+    body.push(name.len() as u8);
+    body.extend_from_slice(name.as_bytes());
+    body.extend_from_slice(data);
+    let mut buf = Vec::new();
+    encode_frame(&mut buf, FLAG_COMMAND, &body);
+    buf
+}
+
+/// READY command metadata: name length (1 byte), name, value length (4 bytes BE), value
+fn encode_metadata(props: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (name, value) in props {
+        data.push(name.len() as u8);
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        data.extend_from_slice(value);
+    }
+    data
+}
+
+fn parse_metadata(mut data: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut props = Vec::new();
+    while !data.is_empty() {
+        let name_len = data[0] as usize;
+        if data.len() < 1 + name_len + 4 {
+            bail!("truncated metadata");
+        }
+        let name = String::from_utf8_lossy(&data[1..1 + name_len]).to_string();
+        data = &data[1 + name_len..];
+        let value_len = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+        if data.len() < 4 + value_len {
+            bail!("truncated metadata value");
+        }
+        props.push((name, data[4..4 + value_len].to_vec()));
+        data = &data[4 + value_len..];
+    }
+    Ok(props)
+}
+
+/// Split a command body into its name and data
+fn parse_command(body: &[u8]) -> Result<(&[u8], &[u8])> {
+    let name_len = *body.first().ok_or_else(|| anyhow!("empty command"))? as usize;
+    if body.len() < 1 + name_len {
+        bail!("truncated command");
+    }
+    Ok((&body[1..1 + name_len], &body[1 + name_len..]))
+}
+
+struct Frame {
+    flags: u8,
+    body: Vec<u8>,
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(r: &mut R) -> Result<Frame> {
+    let flags = r.read_u8().await?;
+    let size = if flags & FLAG_LONG != 0 { r.read_u64().await? } else { r.read_u8().await? as u64 };
+    if size > MAX_INBOUND_FRAME {
+        bail!("frame too large ({} bytes)", size);
+    }
+    let mut body = vec![0u8; size as usize];
+    r.read_exact(&mut body).await?;
+    Ok(Frame { flags, body })
+}
+
+/// Greeting and READY exchange; the peer must be a SUB socket
+async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S) -> Result<()> {
+    stream.write_all(&greeting()).await?;
+
+    let mut peer = [0u8; 64];
+    stream.read_exact(&mut peer).await?;
+    if peer[0] != 0xFF || peer[9] & 0x01 != 0x01 {
+        bail!("invalid ZMTP signature");
+    }
+    if peer[10] < 3 {
+        bail!("unsupported ZMTP version {}.{}", peer[10], peer[11]);
+    }
+    if &peer[12..16] != b"NULL" || peer[16] != 0 {
+        bail!("unsupported security mechanism");
+    }
+
+    let ready = encode_command("READY", &encode_metadata(&[("Socket-Type", b"PUB")]));
+    stream.write_all(&ready).await?;
+
+    let frame = read_frame(stream).await?;
+    if frame.flags & FLAG_COMMAND == 0 {
+        bail!("expected READY command");
+    }
+    let (name, data) = parse_command(&frame.body)?;
+    if name != b"READY" {
+        bail!("expected READY, got {}", String::from_utf8_lossy(name));
+    }
+    let socket_type = parse_metadata(data)?
+        .into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("Socket-Type"))
+        .map(|(_, value)| String::from_utf8_lossy(&value).to_string())
+        .ok_or_else(|| anyhow!("READY without Socket-Type"))?;
+    if socket_type != "SUB" && socket_type != "XSUB" {
+        bail!("incompatible socket type {}", socket_type);
+    }
+    Ok(())
+}
+
+/// Track subscription changes and answer heartbeats until the subscriber disconnects
+async fn read_subscriptions<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    subscriptions: &RwLock<Subscriptions>,
+    pong_tx: &mpsc::Sender<Vec<u8>>,
+) -> Result<()> {
+    loop {
+        let frame = read_frame(reader).await?;
+        if frame.flags & FLAG_COMMAND != 0 {
+            let (name, data) = parse_command(&frame.body)?;
+            match name {
+                // ZMTP 3.1 subscriptions
+                b"SUBSCRIBE" => subscriptions.write().subscribe(data),
+                b"CANCEL" => subscriptions.write().cancel(data),
+                // Heartbeat: echo the context back in a PONG
+                b"PING" if data.len() >= 2 => {
+                    let _ = pong_tx.try_send(encode_command("PONG", &data[2..]));
+                }
+                _ => {}
+            }
+        } else {
+            // ZMTP 3.0 subscriptions: 0x01 subscribe, 0x00 cancel
+            match frame.body.split_first() {
+                Some((1, topic)) => subscriptions.write().subscribe(topic),
+                Some((0, topic)) => subscriptions.write().cancel(topic),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Topic prefixes a subscriber asked for
+#[derive(Default)]
+struct Subscriptions(Vec<Vec<u8>>);
+
+impl Subscriptions {
+    fn subscribe(&mut self, topic: &[u8]) {
+        self.0.push(topic.to_vec());
+    }
+
+    fn cancel(&mut self, topic: &[u8]) {
+        if let Some(pos) = self.0.iter().position(|t| t == topic) {
+            self.0.remove(pos);
+        }
+    }
+
+    fn matches(&self, topic: &[u8]) -> bool {
+        self.0.iter().any(|prefix| topic.starts_with(prefix))
+    }
+}
+
+struct Subscriber {
+    id: u64,
+    subscriptions: Arc<RwLock<Subscriptions>>,
+    queue: mpsc::Sender<Arc<Vec<u8>>>,
+}
+
+/// A bound PUB socket
+pub struct PubSocket {
+    address: SocketAddr,
+    hwm: usize,
+    subscribers: Mutex<Vec<Subscriber>>,
+    next_id: AtomicU64,
+}
+
+impl PubSocket {
+    /// Bind and start accepting subscribers. `hwm` is the per-subscriber
+    /// queue limit; messages beyond it are dropped, as with ZMQ_SNDHWM.
+    pub async fn bind(address: SocketAddr, hwm: usize) -> Result<Arc<Self>> {
+        let listener = TcpListener::bind(address).await?;
+        let socket = Arc::new(Self {
+            address: listener.local_addr()?,
+            hwm: hwm.max(1),
+            subscribers: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(0),
+        });
+
+        let s = socket.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        let s = s.clone();
+                        tokio::spawn(async move {
+                            let address = s.address;
+                            if let Err(e) = s.serve(stream).await {
+                                eprintln!("[zmq] subscriber {} on {}: {:#}", peer, address, e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        eprintln!("[zmq] accept error on {}: {}", s.address, e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        });
+
+        Ok(socket)
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Queue a multipart message for every subscriber whose subscription matches the first part
+    pub fn publish(&self, parts: &[&[u8]]) {
+        let topic = parts.first().copied().unwrap_or_default();
+        let mut message: Option<Arc<Vec<u8>>> = None;
+
+        self.subscribers.lock().retain(|sub| {
+            if !sub.subscriptions.read().matches(topic) {
+                return !sub.queue.is_closed();
+            }
+            let message = message.get_or_insert_with(|| Arc::new(encode_message(parts))).clone();
+            match sub.queue.try_send(message) {
+                Ok(()) => true,
+                // High-water mark reached: drop the message for this subscriber
+                Err(mpsc::error::TrySendError::Full(_)) => true,
+                Err(mpsc::error::TrySendError::Closed(_)) => false,
+            }
+        });
+    }
+
+    async fn serve(self: Arc<Self>, mut stream: TcpStream) -> Result<()> {
+        stream.set_nodelay(true)?;
+        tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake(&mut stream))
+            .await
+            .map_err(|_| anyhow!("handshake timed out"))??;
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let subscriptions = Arc::new(RwLock::new(Subscriptions::default()));
+        let (queue, mut rx) = mpsc::channel::<Arc<Vec<u8>>>(self.hwm);
+        self.subscribers.lock().push(Subscriber { id, subscriptions: subscriptions.clone(), queue });
+
+        let (mut reader, mut writer) = stream.into_split();
+        let (pong_tx, mut pong_rx) = mpsc::channel::<Vec<u8>>(4);
+
+        let read_loop = read_subscriptions(&mut reader, &subscriptions, &pong_tx);
+
+        let write_loop = async {
+            loop {
+                tokio::select! {
+                    msg = rx.recv() => match msg {
+                        Some(msg) => writer.write_all(&msg).await?,
+                        None => break,
+                    },
+                    Some(pong) = pong_rx.recv() => writer.write_all(&pong).await?,
+                }
+            }
+            Ok::<(), anyhow::Error>(())
+        };
+
+        let result = tokio::select! {
+            r = read_loop => r,
+            r = write_loop => r,
+        };
+
+        self.subscribers.lock().retain(|sub| sub.id != id);
+        match result {
+            // A subscriber closing its socket is not an error
+            Err(e) if e.downcast_ref::<std::io::Error>().map(|e| e.kind()) == Some(std::io::ErrorKind::UnexpectedEof) => {
+                Ok(())
+            }
+            r => r,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_encoding() {
+        let msg = encode_message(&[b"hashtx", &[0xAB; 32], &1u32.to_le_bytes()]);
+        assert_eq!(&msg[..2], &[FLAG_MORE, 6]);
+        assert_eq!(&msg[2..8], b"hashtx");
+        assert_eq!(&msg[8..10], &[FLAG_MORE, 32]);
+        assert_eq!(&msg[42..], &[0, 4, 1, 0, 0, 0]);
+
+        let long = encode_message(&[&[0u8; 300]]);
+        assert_eq!(long[0], FLAG_LONG);
+        assert_eq!(&long[1..9], &300u64.to_be_bytes());
+        assert_eq!(long.len(), 9 + 300);
+    }
+
+    #[test]
+    fn test_ready_metadata_roundtrip() {
+        let cmd = encode_command("READY", &encode_metadata(&[("Socket-Type", b"PUB")]));
+        assert_eq!(cmd[0], FLAG_COMMAND);
+        let (name, data) = parse_command(&cmd[2..]).unwrap();
+        assert_eq!(name, b"READY");
+        let props = parse_metadata(data).unwrap();
+        assert_eq!(props, vec![("Socket-Type".to_string(), b"PUB".to_vec())]);
+    }
+
+    #[test]
+    fn test_subscription_prefix_match() {
+        let mut subs = Subscriptions::default();
+        assert!(!subs.matches(b"hashblock"));
+        subs.subscribe(b"hash");
+        assert!(subs.matches(b"hashblock"));
+        assert!(!subs.matches(b"rawtx"));
+        subs.subscribe(b"");
+        assert!(subs.matches(b"rawtx"));
+        subs.cancel(b"");
+        assert!(!subs.matches(b"rawtx"));
+    }
+
+    #[tokio::test]
+    async fn test_pub_to_sub() {
+        let socket = PubSocket::bind("127.0.0.1:0".parse().unwrap(), 10).await.unwrap();
+        let mut sub = TcpStream::connect(socket.local_addr()).await.unwrap();
+
+        // Act as a ZMTP 3.0 SUB client
+        sub.write_all(&greeting()).await.unwrap();
+        let mut peer = [0u8; 64];
+        sub.read_exact(&mut peer).await.unwrap();
+        assert_eq!(&peer[12..16], b"NULL");
+        let ready = read_frame(&mut sub).await.unwrap();
+        assert_eq!(ready.flags, FLAG_COMMAND);
+        sub.write_all(&encode_command("READY", &encode_metadata(&[("Socket-Type", b"SUB")])))
+            .await
+            .unwrap();
+        let mut subscribe = Vec::new();
+        encode_frame(&mut subscribe, 0, b"\x01hashblock");
+        sub.write_all(&subscribe).await.unwrap();
+
+        // Wait for the subscription to be registered
+        for _ in 0..100 {
+            let subscribed = socket.subscribers.lock().iter().any(|s| s.subscriptions.read().matches(b"hashblock"));
+            if subscribed {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        socket.publish(&[b"rawtx", b"ignored", &[0, 0, 0, 0]]);
+        socket.publish(&[b"hashblock", &[7u8; 32], &[5, 0, 0, 0]]);
+
+        let topic = read_frame(&mut sub).await.unwrap();
+        assert_eq!(topic.body, b"hashblock");
+        assert_eq!(topic.flags, FLAG_MORE);
+        let body = read_frame(&mut sub).await.unwrap();
+        assert_eq!(body.body, vec![7u8; 32]);
+        let seq = read_frame(&mut sub).await.unwrap();
+        assert_eq!(seq.flags, 0);
+        assert_eq!(seq.body, vec![5, 0, 0, 0]);
+    }
+}